@@ -0,0 +1,192 @@
+/// Roster of registered accounts and created characters
+///
+/// Players register their account by proving they own its wallet, create
+/// characters for it ahead of
+/// playing and pick one of them when joining the game. The roster keeps
+/// every character, including those not in the game, and validates the
+/// names of new ones.
+use std::collections::HashMap;
+use uuid::Uuid;
+use crate::types::Player;
+use crate::wallet::challenge_message;
+
+/// Shortest character name allowed
+pub const MIN_NAME_LEN: usize = 3;
+/// Longest character name allowed
+pub const MAX_NAME_LEN: usize = 16;
+/// Most characters a single account may have
+pub const MAX_CHARACTERS_PER_ACCOUNT: usize = 5;
+/// Words character names may not contain
+const BLOCKED_NAMES: &str = include_str!("../data/blocked_names.txt");
+
+/// Checks whether a name is allowed for a new character
+///
+/// Names are 3 to 16 ASCII letters, digits, underscores or hyphens,
+/// starting with a letter, and none of their words is a blocked word.
+/// Words are separated by underscores, hyphens, digits and capitals, so
+/// "TheAdmin" is refused while "Badminton" is not.
+///
+/// # Returns
+/// The reason the name is refused, if it is
+pub fn check_name(name: &str) -> Result<(), String> {
+    let length = name.chars().count();
+    if !(MIN_NAME_LEN..=MAX_NAME_LEN).contains(&length) {
+        return Err(format!("Names are {} to {} characters long", MIN_NAME_LEN, MAX_NAME_LEN));
+    }
+    if !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return Err("Names start with a letter".to_string());
+    }
+    if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        return Err("Names only contain letters, digits, underscores and hyphens".to_string());
+    }
+    let words = words_of(name);
+    let blocked = BLOCKED_NAMES.lines()
+        .map(str::trim)
+        .filter(|word| !word.is_empty() && !word.starts_with('#'))
+        .any(|blocked| words.iter().any(|word| word.eq_ignore_ascii_case(blocked)));
+    if blocked {
+        return Err("This name is not allowed".to_string());
+    }
+    Ok(())
+}
+
+/// Splits a name into its words at underscores, hyphens, digits and at
+/// capitals following a lowercase letter
+fn words_of(name: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    let mut start = None;
+    let mut previous = None;
+    for (index, c) in name.char_indices() {
+        let splits = !c.is_ascii_alphabetic()
+            || (c.is_ascii_uppercase() && previous.is_some_and(|p: char| p.is_ascii_lowercase()));
+        if splits {
+            if let Some(begin) = start.take() {
+                words.push(&name[begin..index]);
+            }
+        }
+        if c.is_ascii_alphabetic() && start.is_none() {
+            start = Some(index);
+        }
+        previous = Some(c);
+    }
+    if let Some(begin) = start {
+        words.push(&name[begin..]);
+    }
+    words
+}
+
+/// Registered accounts and the characters created so far, in or out of the game
+#[derive(Default)]
+pub struct Roster {
+    /// Secret key of each registered account, keyed by wallet address
+    accounts: HashMap<String, Uuid>,
+    /// Challenge each account must sign to be given a key, keyed by wallet address
+    challenges: HashMap<String, String>,
+    /// Characters as they were when last saved, keyed by player ID
+    characters: HashMap<Uuid, Player>,
+}
+
+impl Roster {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Issues a new single-use challenge for an account, replacing any
+    /// challenge it was issued before
+    ///
+    /// # Returns
+    /// The message the account's wallet must sign
+    pub fn challenge(&mut self, account: &str) -> String {
+        let message = challenge_message(account, &Uuid::new_v4().simple().to_string());
+        self.challenges.insert(account.to_string(), message.clone());
+        message
+    }
+
+    /// Takes the pending challenge of an account, which can only be answered once
+    pub fn take_challenge(&mut self, account: &str) -> Option<String> {
+        self.challenges.remove(account)
+    }
+
+    /// Gives an account a new secret key, replacing the one it had
+    ///
+    /// # Returns
+    /// The new key of the account
+    pub fn register(&mut self, account: &str) -> Uuid {
+        let key = Uuid::new_v4();
+        self.accounts.insert(account.to_string(), key);
+        key
+    }
+
+    /// Checks whether a key is the secret key of a registered account
+    pub fn is_key_of(&self, account: &str, key: Uuid) -> bool {
+        self.accounts.get(account) == Some(&key)
+    }
+
+    /// Checks whether a character already goes by a name, ignoring case
+    pub fn is_taken(&self, name: &str) -> bool {
+        self.characters.values().any(|character| character.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Gets a character by ID
+    pub fn get(&self, id: Uuid) -> Option<&Player> {
+        self.characters.get(&id)
+    }
+
+    /// Lists the IDs of an account's characters, in order of name
+    pub fn of_account(&self, account: &str) -> Vec<Uuid> {
+        let mut characters: Vec<&Player> = self.characters.values()
+            .filter(|character| character.wallet_address == account)
+            .collect();
+        characters.sort_by(|a, b| a.name.cmp(&b.name));
+        characters.into_iter().map(|character| character.id).collect()
+    }
+
+    /// Adds a new character or saves the progress of an existing one
+    pub fn save(&mut self, character: Player) {
+        self.characters.insert(character.id, character);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accounts_only_open_with_their_latest_key() {
+        let mut roster = Roster::new();
+        let key = roster.register("0xabc");
+        assert!(roster.is_key_of("0xabc", key));
+        assert!(!roster.is_key_of("0xabc", Uuid::new_v4()));
+        assert!(!roster.is_key_of("0xdef", key));
+        let rotated = roster.register("0xabc");
+        assert!(roster.is_key_of("0xabc", rotated) && !roster.is_key_of("0xabc", key));
+
+        let challenge = roster.challenge("0xabc");
+        assert_ne!(roster.challenge("0xabc"), challenge);
+        assert!(roster.take_challenge("0xabc").is_some());
+        assert!(roster.take_challenge("0xabc").is_none());
+    }
+
+    #[test]
+    fn names_are_checked_for_length_charset_and_blocked_words() {
+        assert!(check_name("Aria").is_ok());
+        assert!(check_name("Sir_Lance-3").is_ok());
+        assert!(check_name("Al").is_err());
+        assert!(check_name("Bartholomew_the_Great").is_err());
+        assert!(check_name("3vil").is_err());
+        assert!(check_name("Zoë").is_err());
+        assert!(check_name("Dark Lord").is_err());
+        assert!(check_name("TheAdmin").is_err());
+        assert!(check_name("ShitLord").is_err());
+        assert!(check_name("admin_42").is_err());
+        assert!(check_name("Mod-Admin").is_err());
+    }
+
+    #[test]
+    fn blocked_words_inside_other_words_are_allowed() {
+        assert!(check_name("Badminton").is_ok());
+        assert!(check_name("Dickens").is_ok());
+        assert!(check_name("Pissarro").is_ok());
+        assert_eq!(words_of("TheAdmin_of-3Kings"), ["The", "Admin", "of", "Kings"]);
+    }
+}
@@ -0,0 +1,620 @@
+/// Behavior trees driving monsters and other NPCs
+///
+/// Trees are plain data, read from the bestiary together with the kinds of
+/// NPC using them, so designers can add and tune monsters without
+/// recompiling. Every tick each NPC's tree is run from its root: composite
+/// nodes pick which of their children run, conditions look at the NPC and
+/// its surroundings, and actions walk it around the dungeon grid or attack.
+/// NPCs navigate with the floor's path cache and collide with walls the way
+/// players do. Random choices are drawn from the floor's seeded RNG, so NPCs
+/// behave the same way for the same seed and the same players.
+use std::collections::{HashMap, VecDeque};
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+use serde::Deserialize;
+use uuid::Uuid;
+use crate::core::game::collision::{Collision, Stride};
+use crate::core::game::fov::line_of_sight;
+use crate::core::game::pathfinding::PathCache;
+use crate::types::{Dungeon, Npc, Position, TileType};
+
+/// Attempts at finding a floor tile to wander to before giving up for a tick
+const WANDER_ATTEMPTS: usize = 10;
+
+/// Result of running a node for one tick
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// The node did what it is meant to do
+    Success,
+    /// The node could not do it
+    Failure,
+    /// The node is still at it and continues on the next tick
+    Running,
+}
+
+/// Node of a behavior tree
+///
+/// Ranges are in tiles and measured along the longer axis, so the eight
+/// tiles around an NPC are at range 1.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "node", rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum Behavior {
+    /// Runs its children in order until one succeeds or is running
+    Selector { children: Vec<Behavior> },
+    /// Runs its children in order until one fails or is running
+    Sequence { children: Vec<Behavior> },
+    /// Succeeds with a probability between 0 and 1
+    Chance { probability: f64 },
+    /// Succeeds while the NPC's health is below a fraction of its maximum
+    HealthBelow { fraction: f64 },
+    /// Makes the closest player in line of sight the NPC's target; fails
+    /// and forgets the target when no player is in sight
+    SeePlayer { range: i32 },
+    /// Succeeds when the target is within range
+    TargetInRange { range: i32 },
+    /// Attacks the target at most once per cooldown, running while it lasts
+    Attack { cooldown_ms: u64 },
+    /// Walks up to the target, succeeding once next to it
+    Chase,
+    /// Walks away from the target, failing when cornered
+    Flee,
+    /// Walks to a random room and rests there up to a pause before moving on
+    Patrol { pause_ms: u64 },
+    /// Strolls between random tiles near where the NPC spawned, resting up
+    /// to a pause at each
+    Wander { radius: i32, pause_ms: u64 },
+    /// Does nothing and succeeds
+    Idle,
+}
+
+/// What the NPCs of a floor perceive during a tick
+pub struct Surroundings<'a> {
+    /// Layout of the floor
+    pub dungeon: &'a Dungeon,
+    /// Tile geometry used for movement
+    pub collision: &'a Collision,
+    /// Players on the floor and their positions, sorted by ID
+    pub players: &'a [(Uuid, Position)],
+    /// Current tick
+    pub tick: u64,
+    /// Simulation ticks per second
+    pub tick_rate: u32,
+    /// Distance a player covers in one second, which NPC speeds are relative to
+    pub speed: i32,
+    /// Pace of NPCs under haste, slow or stun, in percent of their normal
+    /// speed; NPCs missing from it move at their normal speed
+    pub paces: &'a HashMap<Uuid, i32>,
+}
+
+/// Effect of an NPC's tick on the world around it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Act {
+    /// The NPC moved
+    Moved(Uuid),
+    /// The NPC wants to walk through the closed door on a tile
+    OpenDoor(i32, i32),
+    /// The NPC attacks a player
+    Attack { npc: Uuid, target: Uuid },
+}
+
+/// What an NPC is walking somewhere for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Purpose {
+    Chase,
+    Flee,
+    Patrol,
+    Wander,
+}
+
+/// Tile an NPC is walking to and the tiles left on the way
+#[derive(Debug, Clone)]
+struct Plan {
+    purpose: Purpose,
+    goal: (i32, i32),
+    route: VecDeque<(i32, i32)>,
+}
+
+/// What an NPC remembers between ticks
+#[derive(Debug, Clone)]
+pub struct Brain {
+    /// Index of the NPC's kind in the bestiary
+    pub kind: usize,
+    /// Walking speed in percent of a player's
+    speed: u32,
+    /// Tile the NPC spawned on
+    home: (i32, i32),
+    /// Player the NPC is after
+    target: Option<Uuid>,
+    /// Where the NPC is walking to
+    plan: Option<Plan>,
+    /// Tick until which the NPC rests after patrolling or wandering somewhere
+    resting_until: u64,
+    /// Tick from which the NPC can attack again
+    ready_at: u64,
+    /// Distance carried over between the ticks the NPC walks
+    stride: Stride,
+}
+
+impl Brain {
+    /// Creates the memory of a newly spawned NPC
+    ///
+    /// # Arguments
+    /// * `kind` - Index of the NPC's kind in the bestiary
+    /// * `speed` - Walking speed in percent of a player's
+    /// * `home` - Tile the NPC spawned on
+    pub fn new(kind: usize, speed: u32, home: (i32, i32)) -> Self {
+        Self {
+            kind,
+            speed,
+            home,
+            target: None,
+            plan: None,
+            resting_until: 0,
+            ready_at: 0,
+            stride: Stride::default(),
+        }
+    }
+
+    /// Runs an NPC's behavior tree for one tick
+    ///
+    /// The NPC walks at most once per tick, however many movement nodes run.
+    ///
+    /// # Arguments
+    /// * `behavior` - Root of the NPC's behavior tree
+    /// * `npc` - The NPC, moved in place
+    /// * `world` - What the NPC perceives
+    /// * `paths` - Path cache of the NPC's floor
+    /// * `rng` - Source of every random choice
+    ///
+    /// # Returns
+    /// What the NPC did that affects the world around it
+    pub fn think(&mut self, behavior: &Behavior, npc: &mut Npc, world: &Surroundings, paths: &mut PathCache, rng: &mut ChaCha8Rng) -> Vec<Act> {
+        let mut mind = Mind { brain: self, npc, world, paths, rng, acts: Vec::new(), walked: false };
+        mind.run(behavior);
+        mind.acts
+    }
+}
+
+/// Distance between two tiles along the longer axis
+fn distance(a: (i32, i32), b: (i32, i32)) -> i32 {
+    (a.0 - b.0).abs().max((a.1 - b.1).abs())
+}
+
+fn status(success: bool) -> Status {
+    if success { Status::Success } else { Status::Failure }
+}
+
+/// An NPC thinking during one tick
+struct Mind<'a, 'w> {
+    brain: &'a mut Brain,
+    npc: &'a mut Npc,
+    world: &'a Surroundings<'w>,
+    paths: &'a mut PathCache,
+    rng: &'a mut ChaCha8Rng,
+    acts: Vec<Act>,
+    /// Whether the NPC already walked this tick
+    walked: bool,
+}
+
+impl Mind<'_, '_> {
+    fn run(&mut self, node: &Behavior) -> Status {
+        match node {
+            Behavior::Selector { children } => children.iter()
+                .map(|child| self.run(child))
+                .find(|status| *status != Status::Failure)
+                .unwrap_or(Status::Failure),
+            Behavior::Sequence { children } => children.iter()
+                .map(|child| self.run(child))
+                .find(|status| *status != Status::Success)
+                .unwrap_or(Status::Success),
+            Behavior::Chance { probability } => status(self.rng.gen_bool(probability.clamp(0.0, 1.0))),
+            Behavior::HealthBelow { fraction } => {
+                status((self.npc.health as f64) < self.npc.max_health as f64 * fraction)
+            }
+            Behavior::SeePlayer { range } => self.see_player(*range),
+            Behavior::TargetInRange { range } => {
+                let here = self.here();
+                status(self.target().is_some_and(|(_, tile)| distance(here, tile) <= *range))
+            }
+            Behavior::Attack { cooldown_ms } => self.attack(*cooldown_ms),
+            Behavior::Chase => self.chase(),
+            Behavior::Flee => self.flee(),
+            Behavior::Patrol { pause_ms } => self.patrol(*pause_ms),
+            Behavior::Wander { radius, pause_ms } => self.wander(*radius, *pause_ms),
+            Behavior::Idle => Status::Success,
+        }
+    }
+
+    /// Tile the NPC stands on
+    fn here(&self) -> (i32, i32) {
+        self.world.collision.tile_of(self.npc.position)
+    }
+
+    /// Finds the NPC's target among the players on the floor
+    ///
+    /// # Returns
+    /// The target's ID and tile, or None when it has none or the target left
+    fn target(&self) -> Option<(Uuid, (i32, i32))> {
+        let target = self.brain.target?;
+        self.world.players.iter()
+            .find(|(id, _)| *id == target)
+            .map(|(id, position)| (*id, self.world.collision.tile_of(*position)))
+    }
+
+    /// Converts a duration into ticks at the current tick rate
+    fn ticks(&self, ms: u64) -> u64 {
+        (ms * self.world.tick_rate as u64).div_ceil(1000)
+    }
+
+    fn resting(&self) -> bool {
+        self.world.tick < self.brain.resting_until
+    }
+
+    fn see_player(&mut self, range: i32) -> Status {
+        let here = self.here();
+        let Surroundings { dungeon, collision, players, .. } = self.world;
+        let seen = players.iter()
+            .map(|(id, position)| (*id, collision.tile_of(*position)))
+            .filter(|(_, tile)| distance(here, *tile) <= range && line_of_sight(dungeon, here, *tile))
+            .min_by_key(|(_, tile)| distance(here, *tile));
+        self.brain.target = seen.map(|(id, _)| id);
+        status(seen.is_some())
+    }
+
+    fn attack(&mut self, cooldown_ms: u64) -> Status {
+        let Some((target, _)) = self.target() else {
+            return Status::Failure;
+        };
+        if self.world.tick < self.brain.ready_at {
+            return Status::Running;
+        }
+        self.brain.ready_at = self.world.tick + self.ticks(cooldown_ms);
+        self.acts.push(Act::Attack { npc: self.npc.id, target });
+        Status::Success
+    }
+
+    fn chase(&mut self) -> Status {
+        let Some((_, tile)) = self.target() else {
+            return Status::Failure;
+        };
+        if distance(self.here(), tile) <= 1 {
+            self.brain.plan = None;
+            return Status::Success;
+        }
+        match self.head_for(Purpose::Chase, tile) {
+            Status::Success => Status::Running,
+            status => status,
+        }
+    }
+
+    fn flee(&mut self) -> Status {
+        let Some((_, threat)) = self.target() else {
+            return Status::Failure;
+        };
+        let fleeing = self.brain.plan.as_ref()
+            .is_some_and(|plan| plan.purpose == Purpose::Flee && !plan.route.is_empty());
+        if !fleeing {
+            // Step onto the neighbouring tile furthest from the threat
+            let here = self.here();
+            let dungeon = self.world.dungeon;
+            let open = |x: i32, y: i32| x >= 0 && y >= 0 && !dungeon.get_tile(x as usize, y as usize).is_solid();
+            let away = |(x, y): (i32, i32)| (x - threat.0).pow(2) + (y - threat.1).pow(2);
+            let step = (-1..=1)
+                .flat_map(|dy| (-1..=1).map(move |dx| (here.0 + dx, here.1 + dy)))
+                .filter(|&(x, y)| open(x, y) && open(x, here.1) && open(here.0, y))
+                .max_by_key(|tile| away(*tile))
+                .filter(|tile| away(*tile) > away(here));
+            let Some(step) = step else {
+                self.brain.plan = None;
+                return Status::Failure;
+            };
+            self.brain.plan = Some(Plan { purpose: Purpose::Flee, goal: step, route: VecDeque::from([step]) });
+        }
+        match self.walk() {
+            Status::Success => Status::Running,
+            status => status,
+        }
+    }
+
+    fn patrol(&mut self, pause_ms: u64) -> Status {
+        if self.resting() {
+            return Status::Running;
+        }
+        let goal = match &self.brain.plan {
+            Some(plan) if plan.purpose == Purpose::Patrol => plan.goal,
+            _ => {
+                let rooms = &self.world.dungeon.rooms;
+                if rooms.is_empty() {
+                    return Status::Failure;
+                }
+                rooms[self.rng.gen_range(0..rooms.len())].center()
+            }
+        };
+        let arrived = self.head_for(Purpose::Patrol, goal);
+        self.rest_on(arrived, pause_ms)
+    }
+
+    fn wander(&mut self, radius: i32, pause_ms: u64) -> Status {
+        if self.resting() {
+            return Status::Running;
+        }
+        let goal = match &self.brain.plan {
+            Some(plan) if plan.purpose == Purpose::Wander => plan.goal,
+            _ => {
+                let (radius, (x, y)) = (radius.max(0), self.brain.home);
+                let dungeon = self.world.dungeon;
+                let rng = &mut *self.rng;
+                let goal = (0..WANDER_ATTEMPTS)
+                    .map(|_| (x + rng.gen_range(-radius..=radius), y + rng.gen_range(-radius..=radius)))
+                    .find(|&(x, y)| x >= 0 && y >= 0 && dungeon.get_tile(x as usize, y as usize) == TileType::Floor);
+                let Some(goal) = goal else {
+                    return Status::Failure;
+                };
+                goal
+            }
+        };
+        let arrived = self.head_for(Purpose::Wander, goal);
+        self.rest_on(arrived, pause_ms)
+    }
+
+    /// Starts resting for a random part of a pause once the NPC arrived
+    fn rest_on(&mut self, arrived: Status, pause_ms: u64) -> Status {
+        if arrived != Status::Success {
+            return arrived;
+        }
+        let pause = self.ticks(pause_ms);
+        self.brain.resting_until = self.world.tick + self.rng.gen_range(0..=pause);
+        Status::Running
+    }
+
+    /// Walks towards a tile, finding a path to it unless already walking there
+    fn head_for(&mut self, purpose: Purpose, goal: (i32, i32)) -> Status {
+        let planned = self.brain.plan.as_ref()
+            .is_some_and(|plan| plan.purpose == purpose && plan.goal == goal);
+        if !planned {
+            let Some(path) = self.paths.find(self.world.dungeon, self.here(), goal) else {
+                self.brain.plan = None;
+                return Status::Failure;
+            };
+            self.brain.plan = Some(Plan { purpose, goal, route: path.iter().copied().collect() });
+        }
+        self.walk()
+    }
+
+    /// Moves the NPC one tick's worth of distance along its plan
+    ///
+    /// The NPC heads for the center of each tile of the route in turn and
+    /// waits in front of closed doors until they are opened. The plan is
+    /// dropped once its last tile is reached or when the way is blocked.
+    fn walk(&mut self) -> Status {
+        let Some(mut plan) = self.brain.plan.take() else {
+            return Status::Failure;
+        };
+        if self.walked {
+            self.brain.plan = Some(plan);
+            return Status::Running;
+        }
+        self.walked = true;
+
+        let Surroundings { dungeon, collision, tick_rate, speed, paces, .. } = self.world;
+        let pace = paces.get(&self.npc.id).copied().unwrap_or(100);
+        let distance = *speed as i64 * self.brain.speed as i64 * pace as i64;
+        let mut budget = self.brain.stride.advance(distance, *tick_rate as i64 * 100 * 100);
+        let from = self.npc.position;
+        let mut position = from;
+        let mut blocked = false;
+        while let Some(&(x, y)) = plan.route.front() {
+            let target = collision.tile_center(x, y);
+            if position == target {
+                plan.route.pop_front();
+                continue;
+            }
+            if budget == 0 {
+                break;
+            }
+            if dungeon.get_tile(x as usize, y as usize) == TileType::Door {
+                self.acts.push(Act::OpenDoor(x, y));
+                break;
+            }
+            let dx = (target.x - position.x).clamp(-budget, budget);
+            let dy = (target.y - position.y).clamp(-budget, budget);
+            let next = collision.sweep(dungeon, position, dx, dy);
+            if next == position {
+                blocked = true;
+                break;
+            }
+            budget -= (next.x - position.x).abs().max((next.y - position.y).abs());
+            position = next;
+        }
+
+        if position != from {
+            self.npc.position = position;
+            self.acts.push(Act::Moved(self.npc.id));
+        }
+        if blocked {
+            Status::Failure
+        } else if plan.route.is_empty() {
+            Status::Success
+        } else {
+            self.brain.plan = Some(plan);
+            Status::Running
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use crate::types::{Room, Stats};
+
+    /// Two rooms joined by a door at (10, 5)
+    fn two_rooms() -> Dungeon {
+        let mut dungeon = Dungeon::solid(21, 11, 0);
+        dungeon.add_room(Room::new(1, 1, 9, 9));
+        dungeon.add_room(Room::new(11, 1, 9, 9));
+        dungeon.set_tile(10, 5, TileType::OpenDoor);
+        dungeon
+    }
+
+    fn npc_at(collision: &Collision, x: i32, y: i32) -> Npc {
+        Npc {
+            id: Uuid::from_u128(1),
+            name: "Goblin".to_string(),
+            hostile: true,
+            position: collision.tile_center(x, y),
+            health: 20,
+            max_health: 20,
+            stats: Stats { damage: 4, defense: 1, ..Default::default() },
+            experience: 20,
+            loot: Vec::new(),
+        }
+    }
+
+    fn tree(json: &str) -> Behavior {
+        serde_json::from_str(json).unwrap()
+    }
+
+    /// Runs a tree for a number of ticks with a single player standing still
+    fn simulate(behavior: &Behavior, dungeon: &Dungeon, npc: &mut Npc, player: Option<(i32, i32)>, ticks: u64) -> Vec<Act> {
+        let collision = Collision::default();
+        let players: Vec<(Uuid, Position)> = player.into_iter()
+            .map(|(x, y)| (Uuid::from_u128(2), collision.tile_center(x, y)))
+            .collect();
+        let mut brain = Brain::new(0, 100, collision.tile_of(npc.position));
+        let (mut paths, mut rng) = (PathCache::new(), ChaCha8Rng::seed_from_u64(7));
+        let mut acts = Vec::new();
+        for tick in 1..=ticks {
+            let world = Surroundings { dungeon, collision: &collision, players: &players, tick, tick_rate: 30, speed: 150, paces: &HashMap::new() };
+            acts.extend(brain.think(behavior, npc, &world, &mut paths, &mut rng));
+        }
+        acts
+    }
+
+    const HUNTER: &str = r#"{ "node": "selector", "children": [
+        { "node": "sequence", "children": [
+            { "node": "healthBelow", "fraction": 0.25 },
+            { "node": "seePlayer", "range": 12 },
+            { "node": "flee" }
+        ] },
+        { "node": "sequence", "children": [
+            { "node": "seePlayer", "range": 12 },
+            { "node": "selector", "children": [
+                { "node": "sequence", "children": [
+                    { "node": "targetInRange", "range": 1 },
+                    { "node": "attack", "cooldownMs": 1000 }
+                ] },
+                { "node": "chase" }
+            ] }
+        ] },
+        { "node": "idle" }
+    ] }"#;
+
+    #[test]
+    fn monsters_chase_players_in_sight_and_attack_in_range() {
+        let collision = Collision::default();
+        let dungeon = two_rooms();
+        let hunter = tree(HUNTER);
+
+        let mut npc = npc_at(&collision, 3, 5);
+        let acts = simulate(&hunter, &dungeon, &mut npc, Some((15, 5)), 90);
+        let (x, _) = collision.tile_of(npc.position);
+        assert!((14..=16).contains(&x), "stopped at {}", x);
+        // One attack per second once next to the player
+        let attacks = acts.iter().filter(|act| matches!(act, Act::Attack { .. })).count();
+        assert_eq!(attacks, 3);
+
+        // Out of sight, it stays put
+        let mut npc = npc_at(&collision, 3, 5);
+        let acts = simulate(&hunter, &dungeon, &mut npc, Some((15, 1)), 30);
+        assert!(acts.is_empty());
+        assert_eq!(npc.position, collision.tile_center(3, 5));
+    }
+
+    #[test]
+    fn hurt_monsters_flee() {
+        let collision = Collision::default();
+        let dungeon = two_rooms();
+        let mut npc = npc_at(&collision, 5, 5);
+        npc.health = 3;
+
+        simulate(&tree(HUNTER), &dungeon, &mut npc, Some((3, 5)), 30);
+        let (x, _) = collision.tile_of(npc.position);
+        assert!(x > 5, "fled to {}", x);
+    }
+
+    #[test]
+    fn npcs_wait_at_closed_doors_until_they_are_opened() {
+        let collision = Collision::default();
+        let mut dungeon = two_rooms();
+        dungeon.set_tile(10, 5, TileType::Door);
+        let wander = tree(r#"{ "node": "wander", "radius": 0, "pauseMs": 60000 }"#);
+        let mut npc = npc_at(&collision, 8, 5);
+        let mut brain = Brain::new(0, 100, (8, 5));
+        brain.plan = Some(Plan {
+            purpose: Purpose::Wander,
+            goal: (12, 5),
+            route: VecDeque::from([(9, 5), (10, 5), (11, 5), (12, 5)]),
+        });
+
+        let (mut paths, mut rng) = (PathCache::new(), ChaCha8Rng::seed_from_u64(1));
+        let mut acts = Vec::new();
+        for tick in 1..=10 {
+            if tick == 6 {
+                dungeon.set_tile(10, 5, TileType::OpenDoor);
+            }
+            let world = Surroundings { dungeon: &dungeon, collision: &collision, players: &[], tick, tick_rate: 30, speed: 150, paces: &HashMap::new() };
+            acts.push(brain.think(&wander, &mut npc, &world, &mut paths, &mut rng));
+            if tick == 5 {
+                assert_eq!(collision.tile_of(npc.position), (9, 5));
+                assert_eq!(acts[4], vec![Act::OpenDoor(10, 5)]);
+            }
+        }
+        assert_eq!(npc.position, collision.tile_center(12, 5));
+    }
+
+    #[test]
+    fn wandering_stays_near_home() {
+        let collision = Collision::default();
+        let mut dungeon = Dungeon::solid(40, 40, 0);
+        dungeon.add_room(Room::new(1, 1, 38, 38));
+        let wander = tree(r#"{ "node": "wander", "radius": 3, "pauseMs": 200 }"#);
+        let mut npc = npc_at(&collision, 20, 20);
+
+        let mut tiles = std::collections::HashSet::new();
+        let mut brain = Brain::new(0, 100, (20, 20));
+        let (mut paths, mut rng) = (PathCache::new(), ChaCha8Rng::seed_from_u64(3));
+        for tick in 1..=600 {
+            let world = Surroundings { dungeon: &dungeon, collision: &collision, players: &[], tick, tick_rate: 30, speed: 150, paces: &HashMap::new() };
+            brain.think(&wander, &mut npc, &world, &mut paths, &mut rng);
+            let tile = collision.tile_of(npc.position);
+            assert!(distance(tile, (20, 20)) <= 3, "wandered off to {:?}", tile);
+            tiles.insert(tile);
+        }
+        assert!(tiles.len() > 3);
+    }
+
+    #[test]
+    fn behavior_is_deterministic_for_a_seed() {
+        let collision = Collision::default();
+        let mut dungeon = Dungeon::solid(40, 12, 0);
+        dungeon.add_room(Room::new(1, 1, 10, 10));
+        dungeon.add_room(Room::new(20, 1, 10, 10));
+        dungeon.create_corridor((6, 6), (25, 6), true);
+        let patrol = tree(r#"{ "node": "selector", "children": [
+            { "node": "sequence", "children": [
+                { "node": "chance", "probability": 0.5 }, { "node": "wander", "radius": 4, "pauseMs": 300 }
+            ] },
+            { "node": "patrol", "pauseMs": 500 }
+        ] }"#);
+
+        let run = || {
+            let mut npc = npc_at(&collision, 5, 5);
+            let acts = simulate(&patrol, &dungeon, &mut npc, None, 300);
+            (npc.position, acts.len())
+        };
+        let (position, moves) = run();
+        assert_eq!(run(), (position, moves));
+        assert!(moves > 0);
+    }
+}
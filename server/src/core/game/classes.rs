@@ -0,0 +1,173 @@
+/// Character classes picked when creating a character
+///
+/// A class sets a new character's base attributes, the items they start
+/// with in their inventory and the skills on their hotbar. The classes
+/// themselves are data, read from a [`ClassBook`] file.
+use std::collections::BTreeMap;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::core::game::skills::{SkillBook, HOTBAR_SLOTS};
+use crate::types::{Attributes, EquipmentSlot, Item, ItemType, Rarity, Requirements, Stats};
+
+/// Class book used unless another one is configured
+const BUILTIN_CLASSES: &str = include_str!("../../../data/classes.json");
+
+/// Item a character of a class starts with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StartingItem {
+    /// Display name of the item
+    pub name: String,
+    /// Type of the item
+    pub item_type: ItemType,
+    /// Rarity of the item
+    pub rarity: Rarity,
+    /// Value in game currency
+    pub value: i32,
+    /// Stats of the item
+    pub stats: Stats,
+    /// Equipment slots the item fits
+    #[serde(default)]
+    pub slots: Vec<EquipmentSlot>,
+    /// Whether the item takes both hands
+    #[serde(default)]
+    pub two_handed: bool,
+    /// What a player needs to equip the item
+    #[serde(default)]
+    pub requirements: Requirements,
+}
+
+impl StartingItem {
+    /// Creates a new copy of the item
+    pub fn create(&self) -> Item {
+        Item {
+            id: Uuid::new_v4(),
+            name: self.name.clone(),
+            item_type: self.item_type.clone(),
+            rarity: self.rarity.clone(),
+            value: self.value,
+            attributes: Vec::new(),
+            stats: self.stats.clone(),
+            stack_size: 1,
+            slots: self.slots.clone(),
+            two_handed: self.two_handed,
+            requirements: self.requirements.clone(),
+        }
+    }
+}
+
+/// Class a character can be created with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CharacterClass {
+    /// Display name
+    pub name: String,
+    /// Short description shown while picking a class
+    #[serde(default)]
+    pub description: String,
+    /// Base attributes of a new character
+    pub attributes: Attributes,
+    /// Items put in the inventory of a new character
+    #[serde(default)]
+    pub equipment: Vec<StartingItem>,
+    /// Skills bound to the hotbar of a new character, slot by slot
+    #[serde(default)]
+    pub skills: Vec<String>,
+}
+
+impl CharacterClass {
+    /// Builds the hotbar of a new character of this class
+    ///
+    /// Skills missing from the skill book leave their slot empty.
+    ///
+    /// # Returns
+    /// `HOTBAR_SLOTS` slots, the first ones holding the class's skills
+    pub fn starting_hotbar(&self, book: &SkillBook) -> Vec<Option<String>> {
+        let mut hotbar: Vec<Option<String>> = self.skills.iter()
+            .map(|name| Some(name.clone()).filter(|name| book.skills.contains_key(name)))
+            .collect();
+        hotbar.resize(HOTBAR_SLOTS, None);
+        hotbar
+    }
+}
+
+/// Every class characters can be created with
+///
+/// Read from a JSON data file, so classes can be added and tuned without
+/// recompiling the server.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ClassBook {
+    /// Classes, keyed by the name characters are created with
+    pub classes: BTreeMap<String, CharacterClass>,
+}
+
+impl Default for ClassBook {
+    fn default() -> Self {
+        Self::parse(BUILTIN_CLASSES).expect("the built-in class book is valid")
+    }
+}
+
+impl ClassBook {
+    /// Parses a class book from JSON
+    ///
+    /// Fails when it has no class, or when a class has more starting skills
+    /// than the hotbar has slots.
+    pub fn parse(value: &str) -> Result<Self, serde_json::Error> {
+        let book: Self = serde_json::from_str(value)?;
+        if book.classes.is_empty() {
+            return Err(serde::de::Error::custom("there must be at least one class"));
+        }
+        if let Some(class) = book.classes.values().find(|class| class.skills.len() > HOTBAR_SLOTS) {
+            return Err(serde::de::Error::custom(format!("{} starts with more skills than the hotbar has slots", class.name)));
+        }
+        Ok(book)
+    }
+}
+
+/// Reads the class book from the file named by the `CLASSES_PATH` environment variable
+///
+/// # Returns
+/// The class book in the file, or the built-in one when unset or invalid
+pub fn classes_from_env() -> ClassBook {
+    let Ok(path) = std::env::var("CLASSES_PATH") else {
+        return ClassBook::default();
+    };
+    std::fs::read_to_string(&path)
+        .map_err(|e| e.to_string())
+        .and_then(|value| ClassBook::parse(&value).map_err(|e| e.to_string()))
+        .unwrap_or_else(|e| {
+            warn!("Ignoring invalid class book {}: {}", path, e);
+            ClassBook::default()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_classes_start_with_known_skills_and_distinct_attributes() {
+        let classes = ClassBook::default();
+        let skills = SkillBook::default();
+        for class in classes.classes.values() {
+            let hotbar = class.starting_hotbar(&skills);
+            assert_eq!(hotbar.len(), HOTBAR_SLOTS);
+            assert_eq!(hotbar.iter().flatten().count(), class.skills.len(), "{} has unknown skills", class.name);
+        }
+        let warrior = &classes.classes["warrior"].attributes;
+        let mage = &classes.classes["mage"].attributes;
+        assert!(warrior.strength > mage.strength && mage.intelligence > warrior.intelligence);
+    }
+
+    #[test]
+    fn class_books_are_validated() {
+        assert!(ClassBook::parse(r#"{ "classes": {} }"#).is_err());
+        let class = |skills: &str| format!(
+            r#"{{ "classes": {{ "monk": {{ "name": "Monk", "attributes": {{ "strength": 10, "dexterity": 10, "intelligence": 10 }}, "skills": {} }} }} }}"#,
+            skills
+        );
+        assert!(ClassBook::parse(&class(r#"["mend"]"#)).is_ok());
+        assert!(ClassBook::parse(&class(&format!("{:?}", vec!["mend"; HOTBAR_SLOTS + 1]))).is_err());
+    }
+}
@@ -0,0 +1,239 @@
+/// Collision of players against dungeon walls
+///
+/// Players are axis-aligned boxes centered on their position. Movement is
+/// swept one world unit at a time, first along x and then along y, so a
+/// box stops flush against the first wall in its way instead of skipping
+/// over thin walls, and a diagonal move into a wall keeps sliding along it.
+use crate::types::{Dungeon, Position};
+
+/// Tile size used when `TILE_SIZE` is not set
+pub const DEFAULT_TILE_SIZE: i32 = 5;
+/// Smallest accepted tile size
+pub const MIN_TILE_SIZE: i32 = 2;
+/// Largest accepted tile size
+pub const MAX_TILE_SIZE: i32 = 256;
+
+/// Reads the tile size from the `TILE_SIZE` environment variable
+///
+/// # Returns
+/// The configured size clamped to `MIN_TILE_SIZE..=MAX_TILE_SIZE`,
+/// or `DEFAULT_TILE_SIZE` when unset or invalid
+pub fn tile_size_from_env() -> i32 {
+    std::env::var("TILE_SIZE")
+        .ok()
+        .and_then(|size| size.parse::<i32>().ok())
+        .map(|size| size.clamp(MIN_TILE_SIZE, MAX_TILE_SIZE))
+        .unwrap_or(DEFAULT_TILE_SIZE)
+}
+
+/// Tile geometry and player bounding box used for collision
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Collision {
+    /// Side length of a tile in world units
+    pub tile_size: i32,
+    /// Half the side length of a player's bounding box in world units
+    pub half_extent: i32,
+}
+
+impl Default for Collision {
+    fn default() -> Self {
+        Self::new(DEFAULT_TILE_SIZE)
+    }
+}
+
+impl Collision {
+    /// Creates the collision geometry for a tile size
+    ///
+    /// Players are 80% of a tile wide, so they fit through one-tile
+    /// corridors and doorways with a little room to spare.
+    pub fn new(tile_size: i32) -> Self {
+        let tile_size = tile_size.max(MIN_TILE_SIZE);
+        Self {
+            tile_size,
+            half_extent: (tile_size * 2 / 5).max(1),
+        }
+    }
+
+    /// Converts a world position to tile coordinates
+    pub fn tile_of(&self, position: Position) -> (i32, i32) {
+        (position.x.div_euclid(self.tile_size), position.y.div_euclid(self.tile_size))
+    }
+
+    /// Gets the world position at the center of a tile
+    pub fn tile_center(&self, x: i32, y: i32) -> Position {
+        Position::new(x * self.tile_size + self.tile_size / 2, y * self.tile_size + self.tile_size / 2)
+    }
+
+    /// Checks whether a player's box centered on a position touches no wall
+    /// or closed door
+    ///
+    /// Tiles outside the dungeon count as walls.
+    pub fn fits(&self, dungeon: &Dungeon, position: Position) -> bool {
+        // The box covers [position - half_extent, position + half_extent)
+        let (min_x, min_y) = self.tile_of(Position::new(position.x - self.half_extent, position.y - self.half_extent));
+        let (max_x, max_y) = self.tile_of(Position::new(position.x + self.half_extent - 1, position.y + self.half_extent - 1));
+        (min_y..=max_y).all(|y| {
+            (min_x..=max_x).all(|x| x >= 0 && y >= 0 && !dungeon.get_tile(x as usize, y as usize).is_solid())
+        })
+    }
+
+    /// Moves a player's box as far as walls allow
+    ///
+    /// # Arguments
+    /// * `dungeon` - Dungeon the player is in
+    /// * `from` - Current position
+    /// * `dx` - Distance to move along x in world units
+    /// * `dy` - Distance to move along y in world units
+    ///
+    /// # Returns
+    /// The position reached, which is `from` when the move is fully blocked
+    pub fn sweep(&self, dungeon: &Dungeon, from: Position, dx: i32, dy: i32) -> Position {
+        let mut position = from;
+        for (distance, along_x) in [(dx, true), (dy, false)] {
+            let unit = distance.signum();
+            for _ in 0..distance.abs() {
+                let next = if along_x {
+                    Position::new(position.x + unit, position.y)
+                } else {
+                    Position::new(position.x, position.y + unit)
+                };
+                if !self.fits(dungeon, next) {
+                    break;
+                }
+                position = next;
+            }
+        }
+        position
+    }
+}
+
+/// Fraction of a world unit carried over from one tick to the next
+///
+/// Speeds rarely divide evenly into the ticks of a second. Moving only the
+/// whole units of each tick's share and carrying the rest over keeps the
+/// distance covered in a second exact at any tick rate.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Stride {
+    /// Distance covered but not moved yet, in `1 / per` of a unit
+    remainder: i64,
+}
+
+impl Stride {
+    /// Takes one tick's share of a speed
+    ///
+    /// # Arguments
+    /// * `distance` - Distance to cover per tick, in `1 / per` of a unit
+    /// * `per` - Fractions of a unit `distance` is counted in
+    ///
+    /// # Returns
+    /// The whole units to move this tick
+    pub fn advance(&mut self, distance: i64, per: i64) -> i32 {
+        self.remainder += distance.max(0);
+        let units = self.remainder / per;
+        self.remainder %= per;
+        units as i32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Room, TileType};
+
+    /// Room of 6x6 tiles at (2, 2) with a one-tile wall on its right and
+    /// another room behind it
+    fn walled_rooms() -> Dungeon {
+        let mut dungeon = Dungeon::solid(16, 10, 0);
+        dungeon.add_room(Room::new(2, 2, 6, 6));
+        dungeon.add_room(Room::new(9, 2, 5, 6));
+        dungeon
+    }
+
+    #[test]
+    fn boxes_stop_flush_against_walls() {
+        let collision = Collision::default();
+        let dungeon = walled_rooms();
+        let start = collision.tile_center(6, 4);
+
+        // The wall column starts at x = 40 and the box reaches 2 units right
+        let end = collision.sweep(&dungeon, start, 10, 0);
+        assert_eq!(end, Position::new(38, start.y));
+        assert!(!collision.fits(&dungeon, Position::new(39, start.y)));
+    }
+
+    #[test]
+    fn large_steps_do_not_tunnel_through_thin_walls() {
+        let collision = Collision::default();
+        let dungeon = walled_rooms();
+        let start = collision.tile_center(7, 4);
+        let behind = collision.tile_center(9, 4);
+        assert!(collision.fits(&dungeon, behind));
+
+        let end = collision.sweep(&dungeon, start, behind.x - start.x, 0);
+        assert_eq!(end.x, 38);
+    }
+
+    #[test]
+    fn diagonal_moves_slide_along_walls() {
+        let collision = Collision::default();
+        let dungeon = walled_rooms();
+        let start = Position::new(38, collision.tile_center(6, 4).y);
+
+        let end = collision.sweep(&dungeon, start, 5, 5);
+        assert_eq!(end, Position::new(38, start.y + 5));
+
+        // Into the corner: both axes blocked
+        let corner = Position::new(38, 38);
+        assert_eq!(collision.sweep(&dungeon, corner, 5, 5), corner);
+    }
+
+    #[test]
+    fn one_tile_corridors_fit_players() {
+        let collision = Collision::default();
+        let mut dungeon = Dungeon::solid(12, 5, 0);
+        dungeon.create_corridor((1, 2), (10, 2), true);
+
+        let start = collision.tile_center(1, 2);
+        assert!(collision.fits(&dungeon, start));
+        assert_eq!(collision.sweep(&dungeon, start, 0, -5), start);
+        assert_eq!(collision.sweep(&dungeon, start, 60, 0).x, collision.tile_center(10, 2).x + 1);
+    }
+
+    #[test]
+    fn closed_doors_block_until_opened() {
+        let collision = Collision::default();
+        let mut dungeon = Dungeon::solid(12, 5, 0);
+        dungeon.create_corridor((1, 2), (10, 2), true);
+        dungeon.set_tile(5, 2, TileType::Door);
+
+        let start = collision.tile_center(3, 2);
+        let blocked = collision.sweep(&dungeon, start, 30, 0);
+        assert_eq!(blocked.x, 5 * collision.tile_size - collision.half_extent);
+
+        dungeon.set_tile(5, 2, TileType::OpenDoor);
+        assert_eq!(collision.sweep(&dungeon, start, 30, 0).x, start.x + 30);
+    }
+
+    #[test]
+    fn geometry_scales_with_tile_size() {
+        let small = Collision::new(5);
+        let large = Collision::new(32);
+        let dungeon = walled_rooms();
+
+        for collision in [small, large] {
+            let start = collision.tile_center(6, 4);
+            let end = collision.sweep(&dungeon, start, collision.tile_size * 3, 0);
+            assert_eq!(end.x, 8 * collision.tile_size - collision.half_extent);
+            assert_eq!(collision.tile_of(end), (7, 4));
+        }
+        assert!(!large.fits(&dungeon, Position::new(-1, 100)));
+    }
+
+    #[test]
+    fn strides_carry_fractions_of_units_over() {
+        let mut stride = Stride::default();
+        let steps: Vec<i32> = (0..4).map(|_| stride.advance(150, 60)).collect();
+        assert_eq!(steps, [2, 3, 2, 3]);
+        assert_eq!((0..45).map(|_| stride.advance(150, 45)).sum::<i32>(), 150);
+    }
+}
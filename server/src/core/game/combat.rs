@@ -0,0 +1,268 @@
+/// Damage formulas and attack rolls
+///
+/// A fighter's combat stats are its base attributes plus the bonuses of
+/// everything it has equipped. Melee attacks hit harder with strength and
+/// ranged ones with dexterity; dexterity also decides how often attacks
+/// land and how often they are critical. Armor soaks up a share of the
+/// damage that shrinks as more of it is stacked, and at least one point of
+/// damage always gets through. Spells grow with intelligence instead and
+/// ignore armor. Every roll is drawn from a seeded RNG.
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use crate::types::{AttackKind, AttackOutcome, Npc, Player};
+
+/// Attributes of a new character; NPCs have no attributes of their own and
+/// are as nimble as a new character
+pub const BASE_ATTRIBUTE: i32 = 10;
+/// Chance to hit an equally nimble target, in percent
+const BASE_HIT_PERCENT: i32 = 85;
+/// Lowest chance to hit, however nimble the target
+const MIN_HIT_PERCENT: i32 = 50;
+/// Highest chance to hit, however clumsy the target
+const MAX_HIT_PERCENT: i32 = 95;
+/// Chance of a critical hit without dexterity, in percent
+const BASE_CRIT_PERCENT: i32 = 5;
+/// Highest chance of a critical hit
+const MAX_CRIT_PERCENT: i32 = 50;
+/// Damage of a critical hit, in percent of a normal one
+const CRIT_DAMAGE_PERCENT: i32 = 150;
+/// Spread of damage rolls around the attack power, in percent
+const DAMAGE_SPREAD_PERCENT: i32 = 20;
+/// Armor that halves incoming damage
+const ARMOR_HALVING: i32 = 20;
+
+/// Stats a fighter brings into combat, equipment included
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CombatStats {
+    /// Damage of the fighter's weapons
+    pub damage: i32,
+    /// Armor reducing incoming damage
+    pub armor: i32,
+    /// Strength, raising melee damage
+    pub strength: i32,
+    /// Dexterity, raising ranged damage, accuracy and critical hits
+    pub dexterity: i32,
+    /// Intelligence, raising magic
+    pub intelligence: i32,
+}
+
+impl CombatStats {
+    /// Derives a player's stats from their attributes and equipment
+    pub fn of_player(player: &Player) -> Self {
+        let base = Self {
+            damage: 0,
+            armor: 0,
+            strength: player.attributes.strength,
+            dexterity: player.attributes.dexterity,
+            intelligence: player.attributes.intelligence,
+        };
+        player.inventory.equipment().values().fold(base, |total, item| Self {
+            damage: total.damage + item.stats.damage,
+            armor: total.armor + item.stats.defense,
+            strength: total.strength + item.stats.strength,
+            dexterity: total.dexterity + item.stats.dexterity,
+            intelligence: total.intelligence + item.stats.intelligence + item.stats.magic,
+        })
+    }
+
+    /// Derives an NPC's stats from its template's stats
+    pub fn of_npc(npc: &Npc) -> Self {
+        Self {
+            damage: npc.stats.damage,
+            armor: npc.stats.defense,
+            strength: 0,
+            dexterity: BASE_ATTRIBUTE,
+            intelligence: npc.stats.magic,
+        }
+    }
+
+    /// Damage an attack of a kind deals on average, before armor
+    pub fn power(&self, kind: AttackKind) -> i32 {
+        let attribute = match kind {
+            AttackKind::Melee => self.strength,
+            AttackKind::Ranged => self.dexterity,
+        };
+        (self.damage + attribute / 2).max(1)
+    }
+
+    /// Adds another set of stats, e.g. those granted by a buff
+    pub fn plus(self, other: CombatStats) -> Self {
+        Self {
+            damage: self.damage + other.damage,
+            armor: self.armor + other.armor,
+            strength: self.strength + other.strength,
+            dexterity: self.dexterity + other.dexterity,
+            intelligence: self.intelligence + other.intelligence,
+        }
+    }
+
+    /// Subtracts another set of stats, e.g. those taken by a debuff
+    pub fn minus(self, other: CombatStats) -> Self {
+        self.plus(other.negated())
+    }
+
+    /// Flips the sign of every stat
+    fn negated(self) -> Self {
+        Self {
+            damage: -self.damage,
+            armor: -self.armor,
+            strength: -self.strength,
+            dexterity: -self.dexterity,
+            intelligence: -self.intelligence,
+        }
+    }
+}
+
+/// Reduces damage by armor
+///
+/// # Returns
+/// The damage that gets through, at least 1
+pub fn mitigate(damage: i32, armor: i32) -> i32 {
+    (damage * ARMOR_HALVING / (ARMOR_HALVING + armor.max(0))).max(1)
+}
+
+/// Rolls whether an attack hits, whether it is critical and how much
+/// damage it deals
+///
+/// # Arguments
+/// * `attacker` - Stats of the attacker
+/// * `defender` - Stats of the target
+/// * `kind` - How the attack is delivered
+/// * `rng` - Source of the rolls
+pub fn roll_attack(attacker: &CombatStats, defender: &CombatStats, kind: AttackKind, rng: &mut ChaCha8Rng) -> AttackOutcome {
+    let hit_chance = (BASE_HIT_PERCENT + (attacker.dexterity - defender.dexterity) / 2)
+        .clamp(MIN_HIT_PERCENT, MAX_HIT_PERCENT);
+    if rng.gen_range(0..100) >= hit_chance {
+        return AttackOutcome::Miss;
+    }
+
+    let spread = rng.gen_range(100 - DAMAGE_SPREAD_PERCENT..=100 + DAMAGE_SPREAD_PERCENT);
+    let mut damage = attacker.power(kind) * spread / 100;
+    let crit_chance = (BASE_CRIT_PERCENT + attacker.dexterity / 5).clamp(0, MAX_CRIT_PERCENT);
+    let critical = rng.gen_range(0..100) < crit_chance;
+    if critical {
+        damage = damage * CRIT_DAMAGE_PERCENT / 100;
+    }
+    AttackOutcome::Hit { damage: mitigate(damage, defender.armor), critical }
+}
+
+/// Rolls the damage or healing of a spell
+///
+/// # Arguments
+/// * `power` - Base amount of the spell
+/// * `scaling_percent` - Share of the caster's intelligence added to it
+/// * `caster` - Stats of the caster
+/// * `rng` - Source of the roll
+pub fn roll_spell(power: i32, scaling_percent: i32, caster: &CombatStats, rng: &mut ChaCha8Rng) -> i32 {
+    let spread = rng.gen_range(100 - DAMAGE_SPREAD_PERCENT..=100 + DAMAGE_SPREAD_PERCENT);
+    ((power + caster.intelligence.max(0) * scaling_percent / 100) * spread / 100).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use uuid::Uuid;
+    use crate::types::{EquipmentSlot, Item, ItemType, Position, Rarity, Requirements, Stats};
+
+    fn gear(name: &str, stats: Stats) -> Item {
+        Item {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            item_type: ItemType::Weapon,
+            rarity: Rarity::Common,
+            value: 0,
+            attributes: Vec::new(),
+            stats,
+            stack_size: 1,
+            slots: vec![EquipmentSlot::MainHand, EquipmentSlot::Chest],
+            two_handed: false,
+            requirements: Requirements::default(),
+        }
+    }
+
+    fn wear(player: &mut Player, slot: EquipmentSlot, item: Item) {
+        let id = item.id;
+        player.inventory.add_item(item);
+        player.inventory.equip_item(id, slot).unwrap();
+    }
+
+    #[test]
+    fn equipment_adds_to_base_attributes() {
+        let mut player = Player::new(Uuid::nil(), "knight".to_string(), Position::new(0, 0));
+        let unarmed = CombatStats::of_player(&player);
+        assert_eq!(unarmed.strength, BASE_ATTRIBUTE);
+        assert_eq!(unarmed.power(AttackKind::Melee), BASE_ATTRIBUTE / 2);
+
+        wear(&mut player, EquipmentSlot::MainHand, gear("Sword", Stats { damage: 6, strength: 4, ..Default::default() }));
+        wear(&mut player, EquipmentSlot::Chest, gear("Mail", Stats { defense: 5, dexterity: -2, ..Default::default() }));
+        let armed = CombatStats::of_player(&player);
+        assert_eq!(armed, CombatStats {
+            damage: 6,
+            armor: 5,
+            strength: 14,
+            dexterity: 8,
+            intelligence: BASE_ATTRIBUTE,
+        });
+        assert_eq!(armed.power(AttackKind::Melee), 13);
+        assert_eq!(armed.power(AttackKind::Ranged), 10);
+    }
+
+    #[test]
+    fn armor_has_diminishing_returns() {
+        assert_eq!(mitigate(40, 0), 40);
+        assert_eq!(mitigate(40, ARMOR_HALVING), 20);
+        assert_eq!(mitigate(40, 3 * ARMOR_HALVING), 10);
+        assert_eq!(mitigate(2, 1000), 1);
+        assert_eq!(mitigate(40, -5), 40);
+    }
+
+    #[test]
+    fn dexterity_lands_more_hits_and_crits() {
+        let defender = CombatStats { dexterity: BASE_ATTRIBUTE, ..Default::default() };
+        let clumsy = CombatStats { damage: 10, dexterity: 0, ..Default::default() };
+        let nimble = CombatStats { damage: 10, dexterity: 60, ..Default::default() };
+        let mut rng = ChaCha8Rng::seed_from_u64(5);
+        let tally = |attacker: &CombatStats, rng: &mut ChaCha8Rng| {
+            let outcomes: Vec<AttackOutcome> = (0..2000)
+                .map(|_| roll_attack(attacker, &defender, AttackKind::Melee, rng))
+                .collect();
+            let hits = outcomes.iter().filter(|outcome| **outcome != AttackOutcome::Miss).count();
+            let crits = outcomes.iter().filter(|outcome| matches!(outcome, AttackOutcome::Hit { critical: true, .. })).count();
+            (hits, crits)
+        };
+
+        let (clumsy_hits, clumsy_crits) = tally(&clumsy, &mut rng);
+        let (nimble_hits, nimble_crits) = tally(&nimble, &mut rng);
+        assert!(clumsy_hits > 1500 && nimble_hits > clumsy_hits, "{} vs {}", clumsy_hits, nimble_hits);
+        assert!(nimble_crits > 2 * clumsy_crits, "{} vs {}", clumsy_crits, nimble_crits);
+    }
+
+    #[test]
+    fn damage_stays_within_the_spread() {
+        let attacker = CombatStats { damage: 20, ..Default::default() };
+        let defender = CombatStats::default();
+        let mut rng = ChaCha8Rng::seed_from_u64(9);
+        for _ in 0..500 {
+            match roll_attack(&attacker, &defender, AttackKind::Melee, &mut rng) {
+                AttackOutcome::Hit { damage, critical: false } => assert!((16..=24).contains(&damage), "{}", damage),
+                AttackOutcome::Hit { damage, critical: true } => assert!((24..=36).contains(&damage), "{}", damage),
+                AttackOutcome::Miss => {}
+            }
+        }
+    }
+
+    #[test]
+    fn spells_grow_with_intelligence() {
+        let mut rng = ChaCha8Rng::seed_from_u64(3);
+        let novice = CombatStats { intelligence: BASE_ATTRIBUTE, ..Default::default() };
+        let sage = novice.plus(CombatStats { intelligence: 30, ..Default::default() });
+        assert_eq!(sage.minus(novice).intelligence, 30);
+        for _ in 0..200 {
+            assert!((16..=24).contains(&roll_spell(10, 100, &novice, &mut rng)));
+            assert!((40..=60).contains(&roll_spell(10, 100, &sage, &mut rng)));
+        }
+    }
+}
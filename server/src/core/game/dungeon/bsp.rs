@@ -0,0 +1,150 @@
+/// Binary space partitioning
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+use serde::{Serialize, Deserialize};
+use crate::types::{Dungeon, Room, TileType};
+use super::{add_doors, DungeonAlgorithm};
+
+/// Recursively splits the map into leaves, puts a room in each leaf and
+/// connects sibling subtrees, giving evenly spread, fully connected rooms
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct BspPartition {
+    /// Smallest side of a leaf in tiles; smaller values give more, tighter rooms
+    pub min_leaf_size: i32,
+    /// Smallest room side in tiles
+    pub min_room_size: i32,
+    /// Wall tiles kept between a room and the edge of its leaf
+    pub room_padding: i32,
+    /// Chance that a doorway gets a door
+    pub door_chance: f64,
+}
+
+impl Default for BspPartition {
+    fn default() -> Self {
+        Self {
+            min_leaf_size: 12,
+            min_room_size: 4,
+            room_padding: 1,
+            door_chance: 0.5,
+        }
+    }
+}
+
+impl BspPartition {
+    /// Harder variant for a deeper floor: smaller leaves behind more doors
+    pub fn at_depth(&self, depth: u32) -> Self {
+        let depth = depth.min(10) as i32;
+        Self {
+            min_leaf_size: (self.min_leaf_size - depth / 2).max(self.min_room_size + 2 * self.room_padding),
+            door_chance: (self.door_chance + 0.05 * depth as f64).min(1.0),
+            ..self.clone()
+        }
+    }
+
+    /// Fills a partition with rooms
+    ///
+    /// # Returns
+    /// A floor tile inside the partition to connect it to its sibling
+    fn build(&self, area: Room, dungeon: &mut Dungeon, rng: &mut ChaCha8Rng) -> (i32, i32) {
+        let min_leaf = self.min_leaf_size.max(self.min_room_size.max(1) + 2 * self.room_padding.max(0));
+        let can_split_x = area.width >= 2 * min_leaf;
+        let can_split_y = area.height >= 2 * min_leaf;
+
+        let split_x = match (can_split_x, can_split_y) {
+            (false, false) => return self.place_room(area, dungeon, rng),
+            (true, false) => true,
+            (false, true) => false,
+            // Prefer cutting across the long side to avoid thin leaves
+            (true, true) if area.width * 4 > area.height * 5 => true,
+            (true, true) if area.height * 4 > area.width * 5 => false,
+            (true, true) => rng.gen_bool(0.5),
+        };
+
+        let (first, second) = if split_x {
+            let cut = rng.gen_range(min_leaf..=area.width - min_leaf);
+            (
+                Room::new(area.x, area.y, cut, area.height),
+                Room::new(area.x + cut, area.y, area.width - cut, area.height),
+            )
+        } else {
+            let cut = rng.gen_range(min_leaf..=area.height - min_leaf);
+            (
+                Room::new(area.x, area.y, area.width, cut),
+                Room::new(area.x, area.y + cut, area.width, area.height - cut),
+            )
+        };
+
+        let a = self.build(first, dungeon, rng);
+        let b = self.build(second, dungeon, rng);
+        dungeon.create_corridor(a, b, rng.gen_bool(0.5));
+        if rng.gen_bool(0.5) { a } else { b }
+    }
+
+    /// Places a randomly sized room inside a leaf
+    fn place_room(&self, leaf: Room, dungeon: &mut Dungeon, rng: &mut ChaCha8Rng) -> (i32, i32) {
+        let padding = self.room_padding.max(0);
+        let max_width = leaf.width - 2 * padding;
+        let max_height = leaf.height - 2 * padding;
+        if max_width < 1 || max_height < 1 {
+            // Too cramped for a room; leave a single floor tile to connect through
+            let center = leaf.center();
+            dungeon.set_tile(center.0, center.1, TileType::Floor);
+            return center;
+        }
+
+        let width = rng.gen_range(self.min_room_size.clamp(1, max_width)..=max_width);
+        let height = rng.gen_range(self.min_room_size.clamp(1, max_height)..=max_height);
+        let x = leaf.x + padding + rng.gen_range(0..=max_width - width);
+        let y = leaf.y + padding + rng.gen_range(0..=max_height - height);
+        let room = Room::new(x, y, width, height);
+        let center = room.center();
+        dungeon.add_room(room);
+        center
+    }
+}
+
+impl DungeonAlgorithm for BspPartition {
+    fn carve(&self, dungeon: &mut Dungeon, rng: &mut ChaCha8Rng) {
+        let (w, h) = (dungeon.width as i32, dungeon.height as i32);
+        if w < 3 || h < 3 {
+            return;
+        }
+        // Keep the outer ring solid
+        self.build(Room::new(1, 1, w - 2, h - 2), dungeon, rng);
+        add_doors(dungeon, rng, self.door_chance);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn leaves_get_separate_rooms() {
+        let mut dungeon = Dungeon::solid(100, 100, 3);
+        BspPartition::default().carve(&mut dungeon, &mut ChaCha8Rng::seed_from_u64(3));
+
+        // A 98x98 area split into leaves of at least 12 tiles
+        assert!(dungeon.rooms.len() >= 16, "only {} rooms", dungeon.rooms.len());
+        for (index, room) in dungeon.rooms.iter().enumerate() {
+            assert!(room.x >= 2 && room.y >= 2 && room.x + room.width <= 98 && room.y + room.height <= 98);
+            assert!(dungeon.rooms[index + 1..].iter().all(|other| {
+                room.x + room.width <= other.x || other.x + other.width <= room.x
+                    || room.y + room.height <= other.y || other.y + other.height <= room.y
+            }));
+        }
+    }
+
+    #[test]
+    fn smaller_leaves_make_tighter_levels() {
+        let count = |min_leaf_size| {
+            let mut dungeon = Dungeon::solid(100, 100, 1);
+            let layout = BspPartition { min_leaf_size, ..Default::default() };
+            layout.carve(&mut dungeon, &mut ChaCha8Rng::seed_from_u64(1));
+            dungeon.rooms.len()
+        };
+        assert!(count(6) > count(20));
+    }
+}
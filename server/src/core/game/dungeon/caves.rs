@@ -0,0 +1,104 @@
+/// Cellular-automata caves
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+use serde::{Serialize, Deserialize};
+use crate::types::{Dungeon, TileType};
+use super::DungeonAlgorithm;
+
+/// Fills the map with random noise and smooths it into organic caves
+///
+/// Caves have no rooms or doors.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CellularCaves {
+    /// Chance that a tile starts out as wall
+    pub fill_chance: f64,
+    /// Number of smoothing passes
+    pub iterations: u32,
+    /// Walls among the 8 neighbours at which a floor tile turns to wall;
+    /// an existing wall survives with one fewer
+    pub wall_threshold: u32,
+}
+
+impl Default for CellularCaves {
+    fn default() -> Self {
+        Self {
+            fill_chance: 0.45,
+            iterations: 5,
+            wall_threshold: 5,
+        }
+    }
+}
+
+impl CellularCaves {
+    /// Harder variant for a deeper floor: narrower, more broken-up caves
+    pub fn at_depth(&self, depth: u32) -> Self {
+        Self {
+            fill_chance: (self.fill_chance + 0.01 * depth.min(5) as f64).min(1.0),
+            ..self.clone()
+        }
+    }
+}
+
+impl DungeonAlgorithm for CellularCaves {
+    fn carve(&self, dungeon: &mut Dungeon, rng: &mut ChaCha8Rng) {
+        let (w, h) = (dungeon.width, dungeon.height);
+        if w < 3 || h < 3 {
+            return;
+        }
+        let border = |x: usize, y: usize| x == 0 || y == 0 || x == w - 1 || y == h - 1;
+
+        let fill_chance = self.fill_chance.clamp(0.0, 1.0);
+        let mut walls: Vec<bool> = (0..w * h)
+            .map(|index| border(index % w, index / w) || rng.gen_bool(fill_chance))
+            .collect();
+
+        for _ in 0..self.iterations {
+            let previous = walls.clone();
+            for y in 1..h - 1 {
+                for x in 1..w - 1 {
+                    let neighbours = (y - 1..=y + 1)
+                        .flat_map(|ny| (x - 1..=x + 1).map(move |nx| (nx, ny)))
+                        .filter(|&(nx, ny)| (nx, ny) != (x, y) && previous[ny * w + nx])
+                        .count() as u32;
+                    let threshold = if previous[y * w + x] {
+                        self.wall_threshold.saturating_sub(1)
+                    } else {
+                        self.wall_threshold
+                    };
+                    walls[y * w + x] = neighbours >= threshold;
+                }
+            }
+        }
+
+        for (tile, wall) in dungeon.tiles.iter_mut().zip(walls) {
+            *tile = if wall { TileType::Wall } else { TileType::Floor };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    fn floor_ratio(layout: &CellularCaves) -> f64 {
+        let mut dungeon = Dungeon::solid(80, 60, 9);
+        layout.carve(&mut dungeon, &mut ChaCha8Rng::seed_from_u64(9));
+        assert!((0..80).all(|x| dungeon.get_tile(x, 0) == TileType::Wall && dungeon.get_tile(x, 59) == TileType::Wall));
+        dungeon.tiles.iter().filter(|tile| **tile == TileType::Floor).count() as f64 / dungeon.tiles.len() as f64
+    }
+
+    #[test]
+    fn smoothing_produces_open_caves() {
+        let ratio = floor_ratio(&CellularCaves::default());
+        assert!(ratio > 0.3 && ratio < 0.8, "floor ratio {}", ratio);
+    }
+
+    #[test]
+    fn denser_fill_means_less_floor() {
+        let sparse = floor_ratio(&CellularCaves { fill_chance: 0.40, ..Default::default() });
+        let dense = floor_ratio(&CellularCaves { fill_chance: 0.55, ..Default::default() });
+        assert!(dense < sparse);
+    }
+}
@@ -0,0 +1,86 @@
+/// Drunkard's walk tunnels
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+use serde::{Serialize, Deserialize};
+use crate::types::{Dungeon, TileType};
+use super::DungeonAlgorithm;
+
+/// Carves winding, always-connected tunnels by walking randomly from the
+/// center of the map until enough of it is floor
+///
+/// Low coverage gives tight, maze-like levels. Tunnels have no rooms or doors.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct DrunkardsWalk {
+    /// Fraction of the interior to turn into floor
+    pub coverage: f64,
+    /// Steps allowed per interior tile before giving up on the coverage
+    pub max_steps_per_tile: u32,
+}
+
+impl Default for DrunkardsWalk {
+    fn default() -> Self {
+        Self {
+            coverage: 0.35,
+            max_steps_per_tile: 25,
+        }
+    }
+}
+
+impl DrunkardsWalk {
+    /// Harder variant for a deeper floor: sparser, more maze-like tunnels
+    pub fn at_depth(&self, depth: u32) -> Self {
+        Self {
+            coverage: (self.coverage - 0.02 * depth.min(10) as f64).max(self.coverage.min(0.15)),
+            ..self.clone()
+        }
+    }
+}
+
+impl DungeonAlgorithm for DrunkardsWalk {
+    fn carve(&self, dungeon: &mut Dungeon, rng: &mut ChaCha8Rng) {
+        let (w, h) = (dungeon.width as i32, dungeon.height as i32);
+        if w < 3 || h < 3 {
+            return;
+        }
+
+        let interior = ((w - 2) * (h - 2)) as usize;
+        let target = ((interior as f64 * self.coverage.clamp(0.0, 1.0)) as usize).max(1);
+        let max_steps = interior * self.max_steps_per_tile.max(1) as usize;
+
+        let (mut x, mut y) = (w / 2, h / 2);
+        dungeon.set_tile(x, y, TileType::Floor);
+        let mut carved = 1;
+
+        for _ in 0..max_steps {
+            if carved >= target {
+                break;
+            }
+            let (dx, dy) = [(1, 0), (-1, 0), (0, 1), (0, -1)][rng.gen_range(0..4)];
+            // Stay off the outer ring
+            x = (x + dx).clamp(1, w - 2);
+            y = (y + dy).clamp(1, h - 2);
+            if dungeon.get_tile(x as usize, y as usize) == TileType::Wall {
+                dungeon.set_tile(x, y, TileType::Floor);
+                carved += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn walks_until_coverage_is_reached() {
+        let mut dungeon = Dungeon::solid(60, 40, 4);
+        DrunkardsWalk { coverage: 0.25, ..Default::default() }
+            .carve(&mut dungeon, &mut ChaCha8Rng::seed_from_u64(4));
+
+        let floor = dungeon.tiles.iter().filter(|tile| **tile == TileType::Floor).count();
+        assert_eq!(floor, (58.0 * 38.0 * 0.25) as usize);
+        assert!((0..60).all(|x| dungeon.get_tile(x, 0) == TileType::Wall && dungeon.get_tile(x, 39) == TileType::Wall));
+    }
+}
@@ -0,0 +1,117 @@
+/// Locked doors and the keys that open them
+///
+/// A door is only locked when it closes off a dead end, such as a side
+/// room, so a locked door never stands between the spawn and the stairs
+/// or a portal. The key of each lock is left on a floor tile that can be
+/// reached without going through any locked door.
+use std::collections::VecDeque;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+use uuid::Builder;
+use crate::types::{Dungeon, TileType};
+
+/// Locks some of the doors of a floor and decides where their keys lie
+///
+/// # Arguments
+/// * `dungeon` - Floor with its stairs and portal already in place
+/// * `rng` - Generator the doors, key IDs and key tiles are drawn from
+/// * `count` - Most doors to lock
+///
+/// # Returns
+/// The number of doors locked
+pub fn place_locks(dungeon: &mut Dungeon, rng: &mut ChaCha8Rng, count: usize) -> usize {
+    let Some(spawn) = dungeon.spawn_tile() else {
+        return 0;
+    };
+    let mut doors: Vec<(i32, i32)> = (0..dungeon.tiles.len())
+        .filter(|index| dungeon.tiles[*index] == TileType::Door)
+        .map(|index| ((index % dungeon.width) as i32, (index / dungeon.width) as i32))
+        .collect();
+    doors.shuffle(rng);
+
+    let mut placed = 0;
+    for (x, y) in doors {
+        if placed == count {
+            break;
+        }
+        let before = reachable(dungeon, spawn, None);
+        let after = reachable(dungeon, spawn, Some((x, y)));
+        let mut cut_off = (0..dungeon.tiles.len()).filter(|index| before[*index] && !after[*index]);
+        let guards_only_a_dead_end = cut_off.clone().next().is_some()
+            && !cut_off.any(|index| {
+                matches!(dungeon.tiles[index], TileType::StairsUp | TileType::StairsDown | TileType::Portal)
+            });
+        if !guards_only_a_dead_end {
+            continue;
+        }
+
+        let key_tiles: Vec<usize> = (0..dungeon.tiles.len())
+            .filter(|index| after[*index] && dungeon.tiles[*index] == TileType::Floor)
+            .collect();
+        let Some(&key_tile) = key_tiles.choose(rng) else {
+            continue;
+        };
+        let key = Builder::from_random_bytes(rng.gen()).into_uuid();
+        let key_at = ((key_tile % dungeon.width) as i32, (key_tile / dungeon.width) as i32);
+        if dungeon.lock_door(x, y, key, key_at) {
+            placed += 1;
+        }
+    }
+    placed
+}
+
+/// Marks the tiles that can be walked to from a tile without going through
+/// a locked door or the blocked tile
+fn reachable(dungeon: &Dungeon, start: (i32, i32), blocked: Option<(i32, i32)>) -> Vec<bool> {
+    let walkable = |x: i32, y: i32| {
+        x >= 0 && y >= 0 && Some((x, y)) != blocked
+            && !matches!(dungeon.get_tile(x as usize, y as usize), TileType::Wall | TileType::LockedDoor)
+    };
+    let mut reached = vec![false; dungeon.tiles.len()];
+    if !walkable(start.0, start.1) {
+        return reached;
+    }
+    let mut queue = VecDeque::from([start]);
+    reached[start.1 as usize * dungeon.width + start.0 as usize] = true;
+    while let Some((x, y)) = queue.pop_front() {
+        for (nx, ny) in [(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)] {
+            if walkable(nx, ny) && !reached[ny as usize * dungeon.width + nx as usize] {
+                reached[ny as usize * dungeon.width + nx as usize] = true;
+                queue.push_back((nx, ny));
+            }
+        }
+    }
+    reached
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use crate::types::Room;
+
+    #[test]
+    fn only_dead_ends_are_locked_and_keys_lie_outside_them() {
+        // Spawn room joined to the stairs room, with a side room off the corridor
+        let mut dungeon = Dungeon::solid(40, 20, 0);
+        dungeon.add_room(Room::new(2, 2, 6, 6));
+        dungeon.add_room(Room::new(30, 2, 6, 6));
+        dungeon.add_room(Room::new(16, 12, 6, 4));
+        dungeon.create_corridor((5, 5), (33, 5), true);
+        dungeon.create_corridor((18, 5), (18, 12), false);
+        dungeon.set_tile(18, 11, TileType::Door);
+        dungeon.set_tile(28, 5, TileType::Door);
+        dungeon.set_tile(33, 5, TileType::StairsDown);
+
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        assert_eq!(place_locks(&mut dungeon, &mut rng, 2), 1);
+        assert_eq!(dungeon.get_tile(18, 11), TileType::LockedDoor);
+        assert_eq!(dungeon.get_tile(28, 5), TileType::Door);
+
+        let lock = dungeon.locks[0];
+        let outside = reachable(&dungeon, dungeon.spawn_tile().unwrap(), None);
+        assert!(outside[lock.key_at.1 as usize * dungeon.width + lock.key_at.0 as usize]);
+        assert_eq!(dungeon.door_key(18, 11), Some(lock.key));
+    }
+}
@@ -0,0 +1,427 @@
+/// Seeded procedural dungeon generation
+///
+/// Layouts are driven entirely by an explicit seed: the same seed and
+/// layout settings always produce the same tiles, so a layout a player
+/// reported can be rebuilt from the seed and layout returned by
+/// `/api/game/dungeon`.
+///
+/// Each generation algorithm implements [`DungeonAlgorithm`]; a
+/// [`DungeonLayout`] picks one together with its parameters and can be
+/// written as JSON, e.g. `{"algorithm": "cellularCaves", "fillChance": 0.5}`.
+pub mod bsp;
+pub mod caves;
+pub mod drunkard;
+pub mod locks;
+pub mod rooms;
+pub mod stairs;
+pub mod validate;
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Serialize, Deserialize};
+use log::warn;
+use crate::types::{Dungeon, TileType};
+use self::bsp::BspPartition;
+use self::caves::CellularCaves;
+use self::drunkard::DrunkardsWalk;
+use self::rooms::RoomsAndCorridors;
+
+/// Seeds tried before giving up on a layout that cannot be repaired
+const MAX_GENERATION_ATTEMPTS: u64 = 8;
+
+/// Offset between the seeds of consecutive floors of a dungeon stack
+const FLOOR_SEED_STRIDE: u64 = 1_000_003;
+
+/// Most locked doors on a floor or instance
+const LOCKS_PER_FLOOR: usize = 2;
+
+/// Mixed into a dungeon's seed to seed the placement of its locks
+const LOCK_SEED_SALT: u64 = 0x10c4_5eed;
+
+/// Largest seed handed out by [`random_seed`]
+///
+/// Larger seeds work, but JavaScript clients cannot represent them exactly.
+pub const MAX_SAFE_SEED: u64 = (1 << 53) - 1;
+
+/// Picks a fresh seed for a new dungeon
+pub fn random_seed() -> u64 {
+    rand::thread_rng().gen_range(0..=MAX_SAFE_SEED)
+}
+
+/// Derives the seed of a floor from the seed of the dungeon stack
+///
+/// # Arguments
+/// * `seed` - Seed of the top floor
+/// * `depth` - Depth of the floor, 0 being the top floor
+pub fn floor_seed(seed: u64, depth: u32) -> u64 {
+    seed.wrapping_add(depth as u64 * FLOOR_SEED_STRIDE) & MAX_SAFE_SEED
+}
+
+/// Reads the dungeon seed from the `DUNGEON_SEED` environment variable
+///
+/// # Returns
+/// The configured seed, or a random one when unset or invalid
+pub fn seed_from_env() -> u64 {
+    std::env::var("DUNGEON_SEED")
+        .ok()
+        .and_then(|seed| seed.parse::<u64>().ok())
+        .unwrap_or_else(random_seed)
+}
+
+/// Reads the dungeon layout from the `DUNGEON_LAYOUT` environment variable
+///
+/// The variable holds either a layout as JSON or just an algorithm name,
+/// e.g. `bsp`, to use that algorithm's default parameters.
+///
+/// # Returns
+/// The configured layout, or the default one when unset or invalid
+pub fn layout_from_env() -> DungeonLayout {
+    let Ok(value) = std::env::var("DUNGEON_LAYOUT") else {
+        return DungeonLayout::default();
+    };
+    DungeonLayout::parse(&value).unwrap_or_else(|e| {
+        warn!("Ignoring invalid DUNGEON_LAYOUT: {}", e);
+        DungeonLayout::default()
+    })
+}
+
+/// Algorithm carving a dungeon layout
+pub trait DungeonAlgorithm {
+    /// Carves the layout into a dungeon made only of walls
+    ///
+    /// Every random choice must be drawn from `rng` so that layouts can be
+    /// reproduced from their seed. Rooms, if the algorithm has any, are
+    /// added to `dungeon.rooms`.
+    fn carve(&self, dungeon: &mut Dungeon, rng: &mut ChaCha8Rng);
+}
+
+/// Generation algorithm of a dungeon together with its parameters
+///
+/// Missing parameters take the algorithm's defaults.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "algorithm", rename_all = "camelCase")]
+pub enum DungeonLayout {
+    /// Random rooms joined by L-shaped corridors
+    RoomsAndCorridors(RoomsAndCorridors),
+    /// Rooms in the leaves of a binary space partition
+    Bsp(BspPartition),
+    /// Organic caves smoothed by cellular automata
+    CellularCaves(CellularCaves),
+    /// Winding tunnels dug by a random walk
+    DrunkardsWalk(DrunkardsWalk),
+}
+
+impl Default for DungeonLayout {
+    fn default() -> Self {
+        Self::RoomsAndCorridors(RoomsAndCorridors::default())
+    }
+}
+
+impl DungeonLayout {
+    /// Parses a layout from JSON or from a bare algorithm name
+    pub fn parse(value: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(value)
+            .or_else(|_| serde_json::from_value(serde_json::json!({ "algorithm": value.trim() })))
+    }
+
+    /// Gets the harder variant of this layout used for a deeper floor
+    ///
+    /// # Arguments
+    /// * `depth` - Depth of the floor, 0 being the top floor
+    pub fn at_depth(&self, depth: u32) -> Self {
+        match self {
+            Self::RoomsAndCorridors(algorithm) => Self::RoomsAndCorridors(algorithm.at_depth(depth)),
+            Self::Bsp(algorithm) => Self::Bsp(algorithm.at_depth(depth)),
+            Self::CellularCaves(algorithm) => Self::CellularCaves(algorithm.at_depth(depth)),
+            Self::DrunkardsWalk(algorithm) => Self::DrunkardsWalk(algorithm.at_depth(depth)),
+        }
+    }
+
+    /// Gets the algorithm implementing this layout
+    pub fn algorithm(&self) -> &dyn DungeonAlgorithm {
+        match self {
+            Self::RoomsAndCorridors(algorithm) => algorithm,
+            Self::Bsp(algorithm) => algorithm,
+            Self::CellularCaves(algorithm) => algorithm,
+            Self::DrunkardsWalk(algorithm) => algorithm,
+        }
+    }
+}
+
+/// Generates dungeons with a configurable layout
+#[derive(Debug, Clone, Default)]
+pub struct DungeonGenerator {
+    layout: DungeonLayout,
+}
+
+impl DungeonGenerator {
+    pub fn new(layout: DungeonLayout) -> Self {
+        Self { layout }
+    }
+
+    /// Generates a dungeon from a seed
+    ///
+    /// Every layout is validated and repaired so that the whole map is
+    /// reachable from the spawn. A layout that cannot be repaired is
+    /// rejected and the following seed is tried instead; the seed stored on
+    /// the dungeon is the one that produced it.
+    ///
+    /// # Arguments
+    /// * `width` - Width of the dungeon in tiles
+    /// * `height` - Height of the dungeon in tiles
+    /// * `seed` - Seed driving every random choice
+    ///
+    /// # Returns
+    /// The generated dungeon, or the single-room layout of `Dungeon::new`
+    /// when no seed produced a usable layout for an area this size
+    pub fn generate(&self, width: usize, height: usize, seed: u64) -> Dungeon {
+        for attempt in 0..MAX_GENERATION_ATTEMPTS {
+            let attempt_seed = seed.wrapping_add(attempt);
+            let mut dungeon = self.carve(width, height, attempt_seed);
+            let report = validate::repair(&mut dungeon);
+            if report.is_valid() {
+                return dungeon;
+            }
+            warn!("Rejected dungeon from seed {}: {:?}", attempt_seed, report);
+        }
+
+        let mut dungeon = Dungeon { seed, ..Dungeon::new(width, height) };
+        validate::repair(&mut dungeon);
+        dungeon
+    }
+
+    /// Generates one floor of a dungeon stack, stairs and locked doors included
+    ///
+    /// Deeper floors use the harder variant of the layout from
+    /// [`DungeonLayout::at_depth`] and a seed derived with [`floor_seed`].
+    ///
+    /// # Arguments
+    /// * `width` - Width of the floor in tiles
+    /// * `height` - Height of the floor in tiles
+    /// * `seed` - Seed of the top floor
+    /// * `depth` - Depth of the floor, 0 being the top floor
+    pub fn generate_floor(&self, width: usize, height: usize, seed: u64, depth: u32) -> Dungeon {
+        let generator = DungeonGenerator::new(self.layout.at_depth(depth));
+        let mut dungeon = generator.generate(width, height, floor_seed(seed, depth));
+        dungeon.depth = depth;
+        stairs::place_stairs(&mut dungeon, depth > 0);
+        if depth == 0 {
+            stairs::place_portal(&mut dungeon);
+        }
+        Self::lock_doors(&mut dungeon);
+        dungeon
+    }
+
+    /// Generates the private dungeon of a party instance
+    ///
+    /// Instances are a single floor with a portal next to the spawn leading
+    /// back out, and locked doors like any floor.
+    ///
+    /// # Arguments
+    /// * `width` - Width of the instance in tiles
+    /// * `height` - Height of the instance in tiles
+    /// * `seed` - Seed driving every random choice
+    pub fn generate_instance(&self, width: usize, height: usize, seed: u64) -> Dungeon {
+        let mut dungeon = self.generate(width, height, seed);
+        stairs::place_portal(&mut dungeon);
+        Self::lock_doors(&mut dungeon);
+        dungeon
+    }
+
+    /// Locks up to `LOCKS_PER_FLOOR` doors closing off dead ends, with keys
+    /// lying elsewhere on the floor
+    fn lock_doors(dungeon: &mut Dungeon) {
+        let mut rng = ChaCha8Rng::seed_from_u64(dungeon.seed ^ LOCK_SEED_SALT);
+        locks::place_locks(dungeon, &mut rng, LOCKS_PER_FLOOR);
+    }
+
+    /// Runs the layout's algorithm on a dungeon made only of walls
+    fn carve(&self, width: usize, height: usize, seed: u64) -> Dungeon {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        let mut dungeon = Dungeon::solid(width, height, seed);
+        self.layout.algorithm().carve(&mut dungeon, &mut rng);
+        dungeon.layout = Some(self.layout.clone());
+        dungeon
+    }
+}
+
+/// Puts doors in some of the doorways where corridors enter rooms
+///
+/// A doorway is a floor tile just outside a room's edge with walls on both
+/// sides along that edge.
+///
+/// # Arguments
+/// * `dungeon` - Dungeon whose rooms get doors
+/// * `rng` - Generator the door rolls are drawn from
+/// * `chance` - Chance that a doorway gets a door
+pub fn add_doors(dungeon: &mut Dungeon, rng: &mut ChaCha8Rng, chance: f64) {
+    let chance = chance.clamp(0.0, 1.0);
+    let tile = |dungeon: &Dungeon, x: i32, y: i32| {
+        if x < 0 || y < 0 {
+            TileType::Wall
+        } else {
+            dungeon.get_tile(x as usize, y as usize)
+        }
+    };
+
+    let rooms = dungeon.rooms.clone();
+    for room in &rooms {
+        let mut doorways = Vec::new();
+        for x in room.x..room.x + room.width {
+            doorways.push((x, room.y - 1, true));
+            doorways.push((x, room.y + room.height, true));
+        }
+        for y in room.y..room.y + room.height {
+            doorways.push((room.x - 1, y, false));
+            doorways.push((room.x + room.width, y, false));
+        }
+
+        for (x, y, along_x) in doorways {
+            let (side_a, side_b) = if along_x {
+                (tile(dungeon, x - 1, y), tile(dungeon, x + 1, y))
+            } else {
+                (tile(dungeon, x, y - 1), tile(dungeon, x, y + 1))
+            };
+            if tile(dungeon, x, y) == TileType::Floor
+                && side_a == TileType::Wall
+                && side_b == TileType::Wall
+                && rng.gen_bool(chance)
+            {
+                dungeon.set_tile(x, y, TileType::Door);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// FNV-1a hash of the tile bytes, stable across platforms and runs
+    fn tile_hash(dungeon: &Dungeon) -> u64 {
+        dungeon.tiles.iter().fold(0xcbf29ce484222325, |hash, tile| {
+            (hash ^ *tile as u64).wrapping_mul(0x100000001b3)
+        })
+    }
+
+    fn tile_bytes(dungeon: &Dungeon) -> Vec<u8> {
+        dungeon.tiles.iter().map(|tile| *tile as u8).collect()
+    }
+
+    #[test]
+    fn same_seed_produces_identical_tiles() {
+        let generator = DungeonGenerator::default();
+        let first = generator.generate(100, 100, 42);
+        let second = generator.generate(100, 100, 42);
+        assert_eq!(tile_bytes(&first), tile_bytes(&second));
+        assert_eq!(first.seed, 42);
+
+        let other = generator.generate(100, 100, 43);
+        assert_ne!(tile_bytes(&first), tile_bytes(&other));
+    }
+
+    #[test]
+    fn golden_layouts() {
+        let generator = DungeonGenerator::default();
+        for (seed, rooms, hash) in GOLDEN {
+            let dungeon = generator.generate(100, 100, seed);
+            assert_eq!(dungeon.rooms.len(), rooms, "room count for seed {}", seed);
+            assert_eq!(tile_hash(&dungeon), hash, "tile hash for seed {}", seed);
+        }
+    }
+
+    fn all_layouts() -> Vec<DungeonLayout> {
+        vec![
+            DungeonLayout::RoomsAndCorridors(RoomsAndCorridors::default()),
+            DungeonLayout::Bsp(BspPartition::default()),
+            DungeonLayout::CellularCaves(CellularCaves::default()),
+            DungeonLayout::DrunkardsWalk(DrunkardsWalk::default()),
+        ]
+    }
+
+    #[test]
+    fn every_layout_is_reproducible() {
+        for layout in all_layouts() {
+            let generator = DungeonGenerator::new(layout.clone());
+            let first = generator.generate(80, 60, 99);
+            assert_eq!(tile_bytes(&first), tile_bytes(&generator.generate(80, 60, 99)), "{:?}", layout);
+            assert_ne!(tile_bytes(&first), tile_bytes(&generator.generate(80, 60, 100)), "{:?}", layout);
+            assert_eq!(first.layout.as_ref(), Some(&layout));
+        }
+    }
+
+    #[test]
+    fn layouts_parse_from_json_or_name() {
+        assert_eq!(
+            DungeonLayout::parse("bsp").unwrap(),
+            DungeonLayout::Bsp(BspPartition::default())
+        );
+        assert_eq!(
+            DungeonLayout::parse(r#"{"algorithm": "cellularCaves", "fillChance": 0.5}"#).unwrap(),
+            DungeonLayout::CellularCaves(CellularCaves { fill_chance: 0.5, ..Default::default() })
+        );
+        assert!(DungeonLayout::parse("labyrinth").is_err());
+
+        let json = serde_json::to_value(DungeonLayout::DrunkardsWalk(DrunkardsWalk::default())).unwrap();
+        assert_eq!(json["algorithm"], "drunkardsWalk");
+        assert_eq!(json["coverage"], 0.35);
+    }
+
+    #[test]
+    fn doors_only_on_room_edges() {
+        let dungeon = DungeonGenerator::default().generate(100, 100, 7);
+        for (index, tile) in dungeon.tiles.iter().enumerate() {
+            let (x, y) = ((index % dungeon.width) as i32, (index / dungeon.width) as i32);
+            if *tile == TileType::Door {
+                assert!(dungeon.room_at(x, y).is_none(), "door inside a room at {},{}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn every_layout_is_fully_connected() {
+        for layout in all_layouts() {
+            for seed in 0..5 {
+                let dungeon = DungeonGenerator::new(layout.clone()).generate(100, 100, seed);
+                let report = validate::validate(&dungeon);
+                assert!(report.is_valid(), "{:?} seed {}: {:?}", layout, seed, report);
+            }
+        }
+    }
+
+    #[test]
+    fn floors_are_linked_by_stairs_and_get_harder() {
+        let generator = DungeonGenerator::default();
+        let top = generator.generate_floor(100, 100, 42, 0);
+        let deeper = generator.generate_floor(100, 100, 42, 4);
+
+        assert_eq!(top.find_tile(TileType::StairsUp), None);
+        assert!(top.find_tile(TileType::StairsDown).is_some());
+        assert!(top.find_tile(TileType::Portal).is_some());
+        assert_eq!(deeper.find_tile(TileType::Portal), None);
+        assert!(deeper.find_tile(TileType::StairsUp).is_some());
+        assert!(deeper.find_tile(TileType::StairsDown).is_some());
+        assert!(validate::validate(&deeper).is_valid());
+
+        assert_eq!((top.depth, deeper.depth), (0, 4));
+        assert_eq!(top.seed, 42);
+        assert_ne!(top.tiles, deeper.tiles);
+        assert!(deeper.rooms.len() > top.rooms.len());
+        assert_eq!(deeper.tiles, generator.generate_floor(100, 100, 42, 4).tiles);
+    }
+
+    #[test]
+    fn tiny_areas_fall_back_to_a_single_room() {
+        let dungeon = DungeonGenerator::default().generate(8, 8, 5);
+        assert_eq!(dungeon.seed, 5);
+        assert_eq!(dungeon.rooms.len(), 1);
+    }
+
+    /// Seed, room count and tile hash of known layouts; a change here means
+    /// existing seeds no longer reproduce the layouts players reported
+    const GOLDEN: [(u64, usize, u64); 3] = [
+        (1, 15, 0x3d4c798e24a44764),
+        (42, 15, 0x638ab55fc361ed25),
+        (1337, 15, 0x2cf77dc37a201326),
+    ];
+}
@@ -0,0 +1,109 @@
+/// Random rectangular rooms joined by L-shaped corridors
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+use serde::{Serialize, Deserialize};
+use crate::types::{Dungeon, Room};
+use super::{add_doors, DungeonAlgorithm};
+
+/// Places non-overlapping rooms at random and connects each one to the
+/// previously placed room
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RoomsAndCorridors {
+    /// Smallest room side in tiles
+    pub min_room_size: i32,
+    /// Largest room side in tiles
+    pub max_room_size: i32,
+    /// Number of rooms to place
+    pub max_rooms: i32,
+    /// Chance that a doorway gets a door
+    pub door_chance: f64,
+}
+
+impl Default for RoomsAndCorridors {
+    fn default() -> Self {
+        Self {
+            min_room_size: 6,
+            max_room_size: 14,
+            max_rooms: 15,
+            door_chance: 0.5,
+        }
+    }
+}
+
+impl RoomsAndCorridors {
+    /// Harder variant for a deeper floor: more, smaller rooms behind more doors
+    pub fn at_depth(&self, depth: u32) -> Self {
+        let depth = depth.min(10) as i32;
+        Self {
+            max_room_size: (self.max_room_size - depth / 2).max(self.min_room_size),
+            max_rooms: self.max_rooms + 2 * depth,
+            door_chance: (self.door_chance + 0.05 * depth as f64).min(1.0),
+            ..self.clone()
+        }
+    }
+}
+
+impl DungeonAlgorithm for RoomsAndCorridors {
+    fn carve(&self, dungeon: &mut Dungeon, rng: &mut ChaCha8Rng) {
+        let (w, h) = (dungeon.width as i32, dungeon.height as i32);
+        let min_room_size = self.min_room_size.max(1);
+        let max_room_size = self.max_room_size.min(w - 3).min(h - 3);
+        if max_room_size < min_room_size {
+            return;
+        }
+
+        // Give up after a few failed placements per room
+        for _ in 0..self.max_rooms.max(0) * 3 {
+            if dungeon.rooms.len() >= self.max_rooms as usize {
+                break;
+            }
+
+            let room_width = rng.gen_range(min_room_size..=max_room_size);
+            let room_height = rng.gen_range(min_room_size..=max_room_size);
+            let x = rng.gen_range(1..w - room_width - 1);
+            let y = rng.gen_range(1..h - room_height - 1);
+            let new_room = Room::new(x, y, room_width, room_height);
+
+            if dungeon.rooms.iter().any(|room| room.intersects(&new_room)) {
+                continue;
+            }
+
+            // Connect the room to the previous one
+            if let Some(prev_room) = dungeon.rooms.last() {
+                let from = prev_room.center();
+                dungeon.create_corridor(from, new_room.center(), rng.gen_bool(0.5));
+            }
+            dungeon.add_room(new_room);
+        }
+
+        add_doors(dungeon, rng, self.door_chance);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn rooms_do_not_overlap_and_stay_inside() {
+        let mut dungeon = Dungeon::solid(100, 100, 7);
+        RoomsAndCorridors::default().carve(&mut dungeon, &mut ChaCha8Rng::seed_from_u64(7));
+
+        assert!(!dungeon.rooms.is_empty());
+        for (index, room) in dungeon.rooms.iter().enumerate() {
+            assert!(room.x >= 1 && room.y >= 1);
+            assert!(room.x + room.width < 99 && room.y + room.height < 99);
+            assert!(dungeon.rooms[index + 1..].iter().all(|other| !other.intersects(room)));
+        }
+    }
+
+    #[test]
+    fn impossible_sizes_carve_nothing() {
+        let mut dungeon = Dungeon::solid(8, 8, 0);
+        let layout = RoomsAndCorridors { min_room_size: 20, ..Default::default() };
+        layout.carve(&mut dungeon, &mut ChaCha8Rng::seed_from_u64(0));
+        assert!(dungeon.rooms.is_empty());
+    }
+}
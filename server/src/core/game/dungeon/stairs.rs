@@ -0,0 +1,109 @@
+/// Stairs linking the floors of a dungeon stack, and portals leading into
+/// and out of party instances
+use std::collections::VecDeque;
+use crate::types::{Dungeon, TileType};
+
+/// Puts the stairs of a floor in place
+///
+/// The up stairs replace the spawn tile, so players arriving from above
+/// land where a fresh floor would spawn them. The down stairs go on the
+/// walkable tile furthest from the spawn, which never cuts off any other
+/// part of the floor.
+///
+/// # Arguments
+/// * `dungeon` - Validated floor to put the stairs on
+/// * `up` - Whether the floor has a floor above it
+///
+/// # Returns
+/// Whether the down stairs could be placed
+pub fn place_stairs(dungeon: &mut Dungeon, up: bool) -> bool {
+    let Some(spawn) = dungeon.spawn_tile() else {
+        return false;
+    };
+    let furthest = walk_order(dungeon, spawn).last().copied();
+
+    if up {
+        dungeon.set_tile(spawn.0, spawn.1, TileType::StairsUp);
+    }
+    match furthest {
+        Some((x, y)) if (x, y) != spawn => dungeon.set_tile(x, y, TileType::StairsDown),
+        _ => false,
+    }
+}
+
+/// Puts a portal on the floor tile closest to the spawn
+///
+/// # Arguments
+/// * `dungeon` - Validated floor or instance to put the portal on
+///
+/// # Returns
+/// The portal's tile, or None when there is no floor tile besides the spawn
+pub fn place_portal(dungeon: &mut Dungeon) -> Option<(i32, i32)> {
+    let spawn = dungeon.spawn_tile()?;
+    let (x, y) = walk_order(dungeon, spawn)
+        .into_iter()
+        .skip(1)
+        .find(|&(x, y)| dungeon.get_tile(x as usize, y as usize) == TileType::Floor)?;
+    dungeon.set_tile(x, y, TileType::Portal);
+    Some((x, y))
+}
+
+/// Lists the walkable tiles reachable from a tile, nearest first
+fn walk_order(dungeon: &Dungeon, start: (i32, i32)) -> Vec<(i32, i32)> {
+    let walkable = |x: i32, y: i32| x >= 0 && y >= 0 && dungeon.get_tile(x as usize, y as usize) != TileType::Wall;
+    if !walkable(start.0, start.1) {
+        return Vec::new();
+    }
+
+    let mut seen = vec![false; dungeon.tiles.len()];
+    let mut order = Vec::new();
+    let mut queue = VecDeque::from([start]);
+    seen[start.1 as usize * dungeon.width + start.0 as usize] = true;
+    while let Some((x, y)) = queue.pop_front() {
+        order.push((x, y));
+        for (nx, ny) in [(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)] {
+            if walkable(nx, ny) && !seen[ny as usize * dungeon.width + nx as usize] {
+                seen[ny as usize * dungeon.width + nx as usize] = true;
+                queue.push_back((nx, ny));
+            }
+        }
+    }
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Room;
+
+    #[test]
+    fn down_stairs_go_to_the_far_end() {
+        let mut dungeon = Dungeon::solid(40, 12, 0);
+        dungeon.add_room(Room::new(2, 2, 6, 6));
+        dungeon.add_room(Room::new(30, 2, 6, 6));
+        dungeon.create_corridor((5, 5), (33, 5), true);
+
+        assert!(place_stairs(&mut dungeon, true));
+        assert_eq!(dungeon.get_tile(5, 5), TileType::StairsUp);
+        assert_eq!(dungeon.find_tile(TileType::StairsDown), Some((35, 2)));
+    }
+
+    #[test]
+    fn portals_go_next_to_the_spawn() {
+        let mut dungeon = Dungeon::solid(20, 20, 0);
+        dungeon.add_room(Room::new(4, 4, 6, 6));
+
+        assert_eq!(place_portal(&mut dungeon), Some((8, 7)));
+        assert_eq!(dungeon.get_tile(8, 7), TileType::Portal);
+    }
+
+    #[test]
+    fn single_tile_floors_have_no_down_stairs() {
+        let mut dungeon = Dungeon::solid(5, 5, 0);
+        dungeon.set_tile(2, 2, TileType::Floor);
+
+        assert!(!place_stairs(&mut dungeon, false));
+        assert_eq!(place_portal(&mut dungeon), None);
+        assert_eq!(dungeon.get_tile(2, 2), TileType::Floor);
+    }
+}
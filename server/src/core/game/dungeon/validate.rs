@@ -0,0 +1,309 @@
+/// Post-generation connectivity validation and repair
+///
+/// [`validate`] flood-fills the walkable tiles from the spawn point and
+/// reports every room, door and tile that cannot be reached, as well as doors
+/// that do not sit in a one-tile gap between two walls. [`repair`] fixes what
+/// it can by digging corridors from isolated areas to the reachable part of
+/// the map and turning misplaced doors into floor.
+use std::collections::VecDeque;
+use serde::Serialize;
+use crate::types::{Dungeon, TileType};
+
+/// Outcome of validating a dungeon
+#[derive(Debug, Clone, PartialEq, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidationReport {
+    /// Tile the flood fill started from, or None when nothing is walkable
+    pub spawn: Option<(i32, i32)>,
+    /// Number of floor and door tiles
+    pub walkable_tiles: usize,
+    /// Number of walkable tiles reachable from the spawn
+    pub reachable_tiles: usize,
+    /// Indices of rooms with no tile reachable from the spawn
+    pub unreachable_rooms: Vec<usize>,
+    /// Doors that cannot be reached from the spawn
+    pub unreachable_doors: Vec<(i32, i32)>,
+    /// Doors that do not sit between two walls on a chokepoint
+    pub misplaced_doors: Vec<(i32, i32)>,
+    /// Corridors dug by `repair` to connect isolated areas
+    pub corridors_added: usize,
+    /// Misplaced doors `repair` turned into floor
+    pub doors_removed: usize,
+}
+
+impl ValidationReport {
+    /// Checks whether every walkable tile is reachable and every door is
+    /// on a chokepoint
+    pub fn is_valid(&self) -> bool {
+        self.spawn.is_some()
+            && self.reachable_tiles == self.walkable_tiles
+            && self.unreachable_rooms.is_empty()
+            && self.unreachable_doors.is_empty()
+            && self.misplaced_doors.is_empty()
+    }
+}
+
+/// Tiles of a dungeon with walkability lookups by signed coordinates
+struct Grid<'a> {
+    dungeon: &'a Dungeon,
+}
+
+impl Grid<'_> {
+    fn tile(&self, x: i32, y: i32) -> TileType {
+        if x < 0 || y < 0 {
+            TileType::Wall
+        } else {
+            self.dungeon.get_tile(x as usize, y as usize)
+        }
+    }
+
+    fn walkable(&self, x: i32, y: i32) -> bool {
+        self.tile(x, y) != TileType::Wall
+    }
+
+    fn index(&self, x: i32, y: i32) -> usize {
+        y as usize * self.dungeon.width + x as usize
+    }
+
+    fn coords(&self, index: usize) -> (i32, i32) {
+        ((index % self.dungeon.width) as i32, (index / self.dungeon.width) as i32)
+    }
+
+    /// Marks every walkable tile connected to the start tiles
+    fn flood(&self, starts: impl IntoIterator<Item = (i32, i32)>) -> Vec<bool> {
+        let mut reached = vec![false; self.dungeon.tiles.len()];
+        let mut queue = VecDeque::new();
+        for (x, y) in starts {
+            if self.walkable(x, y) && !reached[self.index(x, y)] {
+                reached[self.index(x, y)] = true;
+                queue.push_back((x, y));
+            }
+        }
+        while let Some((x, y)) = queue.pop_front() {
+            for (nx, ny) in neighbours(x, y) {
+                if self.walkable(nx, ny) && !reached[self.index(nx, ny)] {
+                    reached[self.index(nx, ny)] = true;
+                    queue.push_back((nx, ny));
+                }
+            }
+        }
+        reached
+    }
+
+    /// Checks whether a door fills a one-tile gap: walls on two opposite
+    /// sides and walkable tiles on the other two
+    fn is_chokepoint(&self, x: i32, y: i32) -> bool {
+        let wall = |x, y| !self.walkable(x, y);
+        let across_x = self.walkable(x - 1, y) && self.walkable(x + 1, y) && wall(x, y - 1) && wall(x, y + 1);
+        let across_y = self.walkable(x, y - 1) && self.walkable(x, y + 1) && wall(x - 1, y) && wall(x + 1, y);
+        across_x || across_y
+    }
+}
+
+fn neighbours(x: i32, y: i32) -> [(i32, i32); 4] {
+    [(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)]
+}
+
+/// Checks that the whole dungeon is reachable from its spawn point
+///
+/// # Arguments
+/// * `dungeon` - Dungeon to check
+///
+/// # Returns
+/// Report listing everything that is unreachable or misplaced
+pub fn validate(dungeon: &Dungeon) -> ValidationReport {
+    let grid = Grid { dungeon };
+    let spawn = dungeon.spawn_tile().filter(|&(x, y)| grid.walkable(x, y));
+    let reached = grid.flood(spawn);
+
+    let mut report = ValidationReport {
+        spawn,
+        ..Default::default()
+    };
+    for (index, tile) in dungeon.tiles.iter().enumerate() {
+        if *tile == TileType::Wall {
+            continue;
+        }
+        report.walkable_tiles += 1;
+        if reached[index] {
+            report.reachable_tiles += 1;
+        }
+        if tile.is_door() {
+            let (x, y) = grid.coords(index);
+            if !reached[index] {
+                report.unreachable_doors.push((x, y));
+            }
+            if !grid.is_chokepoint(x, y) {
+                report.misplaced_doors.push((x, y));
+            }
+        }
+    }
+    report.unreachable_rooms = dungeon.rooms.iter()
+        .enumerate()
+        .filter(|(_, room)| {
+            !(room.y..room.y + room.height)
+                .flat_map(|y| (room.x..room.x + room.width).map(move |x| (x, y)))
+                .any(|(x, y)| grid.walkable(x, y) && reached[grid.index(x, y)])
+        })
+        .map(|(index, _)| index)
+        .collect();
+    report
+}
+
+/// Repairs a dungeon so that it passes validation where possible
+///
+/// Misplaced doors become floor, and every area that cannot be reached
+/// from the spawn is joined to the reachable part by the shortest corridor
+/// that stays off the outer ring of the map.
+///
+/// # Arguments
+/// * `dungeon` - Dungeon to repair in place
+///
+/// # Returns
+/// Report of the repaired dungeon, including what was changed
+pub fn repair(dungeon: &mut Dungeon) -> ValidationReport {
+    let mut report = validate(dungeon);
+    let mut corridors_added = 0;
+    let doors_removed = report.misplaced_doors.len();
+
+    for &(x, y) in &report.misplaced_doors {
+        dungeon.set_tile(x, y, TileType::Floor);
+    }
+
+    if report.spawn.is_some() {
+        while let Some(path) = connecting_corridor(dungeon) {
+            for (x, y) in path {
+                dungeon.set_tile(x, y, TileType::Floor);
+            }
+            corridors_added += 1;
+        }
+    }
+
+    if corridors_added > 0 || doors_removed > 0 {
+        report = validate(dungeon);
+    }
+    report.corridors_added = corridors_added;
+    report.doors_removed = doors_removed;
+    report
+}
+
+/// Finds the shortest run of walls to dig to join one unreachable area to
+/// the area around the spawn
+///
+/// # Returns
+/// The wall tiles to turn into floor, or None when everything is reachable
+fn connecting_corridor(dungeon: &Dungeon) -> Option<Vec<(i32, i32)>> {
+    let grid = Grid { dungeon };
+    let reached = grid.flood(dungeon.spawn_tile());
+    let isolated = dungeon.tiles.iter()
+        .enumerate()
+        .position(|(index, tile)| *tile != TileType::Wall && !reached[index])?;
+    let area = grid.flood([grid.coords(isolated)]);
+
+    // Breadth-first search from the whole isolated area through anything
+    // but the outer ring until the reachable area is hit
+    let (w, h) = (dungeon.width as i32, dungeon.height as i32);
+    let mut previous: Vec<Option<usize>> = vec![None; dungeon.tiles.len()];
+    let mut seen = area.clone();
+    let mut queue: VecDeque<usize> = area.iter()
+        .enumerate()
+        .filter_map(|(index, in_area)| in_area.then_some(index))
+        .collect();
+
+    while let Some(index) = queue.pop_front() {
+        let (x, y) = grid.coords(index);
+        for (nx, ny) in neighbours(x, y) {
+            if nx < 1 || ny < 1 || nx >= w - 1 || ny >= h - 1 {
+                continue;
+            }
+            let next = grid.index(nx, ny);
+            if seen[next] {
+                continue;
+            }
+            seen[next] = true;
+            previous[next] = Some(index);
+
+            if reached[next] {
+                let mut path = Vec::new();
+                let mut cursor = previous[next];
+                while let Some(step) = cursor.filter(|step| !area[*step]) {
+                    path.push(grid.coords(step));
+                    cursor = previous[step];
+                }
+                return Some(path);
+            }
+            queue.push_back(next);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Room;
+
+    /// Two rooms side by side with a solid wall between them
+    fn split_dungeon() -> Dungeon {
+        let mut dungeon = Dungeon::solid(30, 12, 0);
+        dungeon.add_room(Room::new(2, 2, 8, 8));
+        dungeon.add_room(Room::new(18, 2, 8, 8));
+        dungeon
+    }
+
+    #[test]
+    fn reports_unreachable_rooms_and_doors() {
+        let mut dungeon = split_dungeon();
+        dungeon.set_tile(22, 10, TileType::Door);
+
+        let report = validate(&dungeon);
+        assert_eq!(report.spawn, Some((6, 6)));
+        assert_eq!(report.walkable_tiles, 129);
+        assert_eq!(report.reachable_tiles, 64);
+        assert_eq!(report.unreachable_rooms, vec![1]);
+        assert_eq!(report.unreachable_doors, vec![(22, 10)]);
+        assert!(!report.is_valid());
+    }
+
+    #[test]
+    fn flags_doors_that_are_not_chokepoints() {
+        let mut dungeon = split_dungeon();
+        dungeon.create_corridor((6, 6), (22, 6), true);
+        // In the middle of a room, and at the end of a dead end
+        dungeon.set_tile(4, 4, TileType::Door);
+        dungeon.set_tile(14, 6, TileType::Door);
+        dungeon.set_tile(6, 1, TileType::Door);
+
+        let report = validate(&dungeon);
+        assert_eq!(report.reachable_tiles, report.walkable_tiles);
+        assert_eq!(report.misplaced_doors, vec![(6, 1), (4, 4)]);
+    }
+
+    #[test]
+    fn repair_digs_the_shortest_corridor_and_clears_bad_doors() {
+        let mut dungeon = split_dungeon();
+        dungeon.set_tile(4, 4, TileType::Door);
+
+        let report = repair(&mut dungeon);
+        assert!(report.is_valid(), "{:?}", report);
+        assert_eq!(report.corridors_added, 1);
+        assert_eq!(report.doors_removed, 1);
+        // The rooms are 8 walls apart
+        assert_eq!(report.walkable_tiles, 128 + 8);
+        assert_eq!(dungeon.get_tile(4, 4), TileType::Floor);
+    }
+
+    #[test]
+    fn nothing_walkable_cannot_be_repaired() {
+        let mut dungeon = Dungeon::solid(10, 10, 0);
+        let report = repair(&mut dungeon);
+        assert_eq!(report.spawn, None);
+        assert!(!report.is_valid());
+    }
+
+    #[test]
+    fn fixed_layout_is_valid() {
+        let report = validate(&Dungeon::new(100, 100));
+        assert!(report.is_valid(), "{:?}", report);
+    }
+}
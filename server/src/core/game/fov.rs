@@ -0,0 +1,212 @@
+/// Field of view over dungeon tiles
+///
+/// Uses recursive shadowcasting: each of the eight octants around the
+/// viewer is scanned row by row, and every solid tile casts a shadow that
+/// narrows the slopes still visible in the rows behind it. Walls and closed
+/// doors are solid; they are seen themselves but hide what lies behind them.
+use std::collections::HashSet;
+use crate::types::Dungeon;
+
+/// Transforms mapping the first octant onto each of the eight octants
+const OCTANTS: [(i32, i32, i32, i32); 8] = [
+    (1, 0, 0, 1),
+    (0, 1, 1, 0),
+    (0, -1, 1, 0),
+    (-1, 0, 0, 1),
+    (-1, 0, 0, -1),
+    (0, -1, -1, 0),
+    (0, 1, -1, 0),
+    (1, 0, 0, -1),
+];
+
+/// Tiles visible from one tile of a dungeon
+#[derive(Debug, Clone, Default)]
+pub struct FieldOfView {
+    /// Tile the view was computed from
+    pub origin: (i32, i32),
+    /// Tile revision of the dungeon the view was computed in
+    pub revision: u64,
+    /// Visible tiles inside the dungeon, including the origin
+    pub visible: HashSet<(i32, i32)>,
+}
+
+impl FieldOfView {
+    /// Computes what can be seen from a tile
+    ///
+    /// # Arguments
+    /// * `dungeon` - Dungeon to look around in
+    /// * `origin` - Tile of the viewer
+    /// * `radius` - Sight distance in tiles
+    pub fn compute(dungeon: &Dungeon, origin: (i32, i32), radius: i32) -> Self {
+        let mut view = Self {
+            origin,
+            revision: dungeon.revision,
+            visible: HashSet::new(),
+        };
+        view.reveal(dungeon, origin);
+        for octant in OCTANTS {
+            view.cast(dungeon, radius, 1, 1.0, 0.0, octant);
+        }
+        view
+    }
+
+    /// Checks whether the view is out of date after the viewer moved to a
+    /// tile or the dungeon's tiles changed
+    pub fn is_stale(&self, dungeon: &Dungeon, origin: (i32, i32)) -> bool {
+        self.origin != origin || self.revision != dungeon.revision
+    }
+
+    /// Checks whether a tile is visible
+    pub fn sees(&self, tile: (i32, i32)) -> bool {
+        self.visible.contains(&tile)
+    }
+
+    fn reveal(&mut self, dungeon: &Dungeon, (x, y): (i32, i32)) {
+        if x >= 0 && y >= 0 && (x as usize) < dungeon.width && (y as usize) < dungeon.height {
+            self.visible.insert((x, y));
+        }
+    }
+
+    /// Scans the rows of an octant between two slopes, starting at a distance
+    fn cast(&mut self, dungeon: &Dungeon, radius: i32, row: i32, mut start: f64, end: f64, octant: (i32, i32, i32, i32)) {
+        if start < end {
+            return;
+        }
+        let (xx, xy, yx, yy) = octant;
+        let mut next_start = start;
+        for distance in row..=radius {
+            let mut blocked = false;
+            let dy = -distance;
+            for dx in -distance..=0 {
+                let left_slope = (dx as f64 - 0.5) / (dy as f64 + 0.5);
+                let right_slope = (dx as f64 + 0.5) / (dy as f64 - 0.5);
+                if start < right_slope {
+                    continue;
+                }
+                if end > left_slope {
+                    break;
+                }
+
+                let tile = (self.origin.0 + dx * xx + dy * xy, self.origin.1 + dx * yx + dy * yy);
+                if dx * dx + dy * dy <= radius * radius {
+                    self.reveal(dungeon, tile);
+                }
+                let solid = tile.0 < 0 || tile.1 < 0 || dungeon.get_tile(tile.0 as usize, tile.1 as usize).is_solid();
+                if blocked {
+                    if solid {
+                        next_start = right_slope;
+                    } else {
+                        blocked = false;
+                        start = next_start;
+                    }
+                } else if solid && distance < radius {
+                    blocked = true;
+                    self.cast(dungeon, radius, distance + 1, start, left_slope, octant);
+                    next_start = right_slope;
+                }
+            }
+            if blocked {
+                break;
+            }
+        }
+    }
+}
+
+/// Checks whether nothing solid stands on the straight line between two tiles
+///
+/// Cheaper than a full field of view when only a few tiles matter, e.g. for
+/// NPCs looking out for players. The line is traced with Bresenham's
+/// algorithm; the two tiles themselves may be solid.
+pub fn line_of_sight(dungeon: &Dungeon, from: (i32, i32), to: (i32, i32)) -> bool {
+    let (dx, dy) = ((to.0 - from.0).abs(), -(to.1 - from.1).abs());
+    let (sx, sy) = ((to.0 - from.0).signum(), (to.1 - from.1).signum());
+    let (mut x, mut y) = from;
+    let mut error = dx + dy;
+    loop {
+        let doubled = 2 * error;
+        if doubled >= dy {
+            error += dy;
+            x += sx;
+        }
+        if doubled <= dx {
+            error += dx;
+            y += sy;
+        }
+        if (x, y) == to {
+            return true;
+        }
+        if x < 0 || y < 0 || dungeon.get_tile(x as usize, y as usize).is_solid() {
+            return false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Room, TileType};
+
+    #[test]
+    fn open_rooms_are_seen_up_to_the_radius() {
+        let mut dungeon = Dungeon::solid(30, 30, 0);
+        dungeon.add_room(Room::new(1, 1, 28, 28));
+
+        let view = FieldOfView::compute(&dungeon, (15, 15), 5);
+        assert!(view.sees((15, 15)));
+        assert!(view.sees((20, 15)));
+        assert!(view.sees((18, 19)));
+        assert!(!view.sees((21, 15)));
+        assert!(!view.sees((19, 19)));
+    }
+
+    #[test]
+    fn walls_hide_what_is_behind_them() {
+        // Two rooms split by a wall column at x = 10
+        let mut dungeon = Dungeon::solid(21, 11, 0);
+        dungeon.add_room(Room::new(1, 1, 9, 9));
+        dungeon.add_room(Room::new(11, 1, 9, 9));
+
+        let view = FieldOfView::compute(&dungeon, (5, 5), 20);
+        assert!(view.sees((10, 5)));
+        assert!(!view.sees((11, 5)));
+        assert!(!view.sees((15, 3)));
+        assert!(!view.sees((-1, 5)));
+    }
+
+    #[test]
+    fn closed_doors_block_sight_until_opened() {
+        let mut dungeon = Dungeon::solid(21, 11, 0);
+        dungeon.add_room(Room::new(1, 1, 9, 9));
+        dungeon.add_room(Room::new(11, 1, 9, 9));
+        dungeon.set_tile(10, 5, TileType::Door);
+
+        let closed = FieldOfView::compute(&dungeon, (8, 5), 20);
+        assert!(closed.sees((10, 5)));
+        assert!(!closed.sees((12, 5)));
+
+        dungeon.set_tile(10, 5, TileType::OpenDoor);
+        assert!(closed.is_stale(&dungeon, (8, 5)));
+        let open = FieldOfView::compute(&dungeon, (8, 5), 20);
+        assert!(open.sees((12, 5)));
+        assert!(open.sees((15, 5)));
+        assert!(!open.sees((15, 1)));
+    }
+
+    #[test]
+    fn lines_of_sight_stop_at_solid_tiles() {
+        let mut dungeon = Dungeon::solid(21, 11, 0);
+        dungeon.add_room(Room::new(1, 1, 9, 9));
+        dungeon.add_room(Room::new(11, 1, 9, 9));
+        dungeon.set_tile(10, 5, TileType::Door);
+
+        assert!(line_of_sight(&dungeon, (2, 2), (8, 7)));
+        assert!(line_of_sight(&dungeon, (8, 7), (2, 2)));
+        assert!(line_of_sight(&dungeon, (4, 4), (4, 4)));
+        assert!(line_of_sight(&dungeon, (8, 5), (10, 5)));
+        assert!(!line_of_sight(&dungeon, (8, 5), (12, 5)));
+
+        dungeon.set_tile(10, 5, TileType::OpenDoor);
+        assert!(line_of_sight(&dungeon, (8, 5), (12, 5)));
+        assert!(!line_of_sight(&dungeon, (8, 3), (12, 3)));
+    }
+}
@@ -0,0 +1,368 @@
+/// Slot-based inventories and the equipment players wear
+///
+/// A player carries up to `INVENTORY_SLOTS` stacks of items. Consumables
+/// and quest items of the same kind stack in one slot; everything else
+/// takes a slot of its own. Weapons and armor move from the inventory into
+/// one of the equipment slots they fit when equipped, and back when
+/// unequipped. A two-handed weapon is wielded in the main hand and keeps
+/// the off hand empty. Items may require a level, a class or attributes of
+/// the player equipping them.
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use uuid::Uuid;
+use crate::types::{EquipmentSlot, Item, Player, Requirements};
+
+/// Number of slots in an inventory
+pub const INVENTORY_SLOTS: usize = 30;
+
+/// Reason an item could not be equipped or unequipped
+#[derive(Debug, Clone, PartialEq, Error, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum EquipError {
+    /// The item is not in the inventory
+    #[error("You do not have that item")]
+    NotCarried,
+    /// The item cannot be worn or wielded at all
+    #[error("{item} cannot be equipped")]
+    NotEquippable { item: String },
+    /// The item does not fit the slot
+    #[error("{item} does not fit there")]
+    WrongSlot { item: String, slots: Vec<EquipmentSlot> },
+    /// The player's level is too low
+    #[error("You need to be level {required}")]
+    LevelTooLow { required: i32 },
+    /// The player's class may not use the item
+    #[error("Only {} can use that", .classes.join(", "))]
+    WrongClass { classes: Vec<String> },
+    /// One of the player's base attributes is too low
+    #[error("You need {required} {attribute}")]
+    AttributeTooLow { attribute: String, required: i32 },
+    /// The slot to unequip is empty
+    #[error("Nothing is equipped there")]
+    NothingEquipped,
+    /// The inventory has no room for the items to take off
+    #[error("Inventory is full")]
+    InventoryFull,
+}
+
+impl Requirements {
+    /// Checks whether a player meets the requirements
+    ///
+    /// Attributes are compared before equipment bonuses, so an item cannot
+    /// meet its own requirements.
+    pub fn check(&self, player: &Player) -> Result<(), EquipError> {
+        if player.level < self.level {
+            return Err(EquipError::LevelTooLow { required: self.level });
+        }
+        if !self.classes.is_empty() && !player.class.as_ref().is_some_and(|class| self.classes.contains(class)) {
+            return Err(EquipError::WrongClass { classes: self.classes.clone() });
+        }
+        let attributes = [
+            ("strength", player.attributes.strength, self.strength),
+            ("dexterity", player.attributes.dexterity, self.dexterity),
+            ("intelligence", player.attributes.intelligence, self.intelligence),
+        ];
+        match attributes.into_iter().find(|(_, value, required)| value < required) {
+            Some((attribute, _, required)) => Err(EquipError::AttributeTooLow { attribute: attribute.to_string(), required }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Items a player carries and wears
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Inventory {
+    /// Items carried, slot by slot
+    slots: Vec<Option<Item>>,
+    /// Items worn or wielded, by slot
+    equipment: HashMap<EquipmentSlot, Item>,
+}
+
+impl Default for Inventory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Inventory {
+    pub fn new() -> Self {
+        Self {
+            slots: vec![None; INVENTORY_SLOTS],
+            equipment: HashMap::new(),
+        }
+    }
+
+    /// Puts an item in the inventory, on a stack of the same kind if it has one
+    ///
+    /// # Returns
+    /// The item back when there is no room for it
+    pub fn add_item(&mut self, item: Item) -> Option<Item> {
+        if item.is_stackable() {
+            if let Some(stack) = self.slots.iter_mut().flatten().find(|stack| stack.stacks_with(&item)) {
+                stack.stack_size += item.stack_size;
+                return None;
+            }
+        }
+        match self.slots.iter_mut().find(|slot| slot.is_none()) {
+            Some(slot) => {
+                *slot = Some(item);
+                None
+            }
+            None => Some(item),
+        }
+    }
+
+    /// Takes items off a stack in the inventory
+    ///
+    /// # Arguments
+    /// * `id` - UUID of the stack
+    /// * `amount` - Number of items to take
+    ///
+    /// # Returns
+    /// The items taken, or None when the inventory holds fewer of them
+    pub fn remove_item(&mut self, id: Uuid, amount: u32) -> Option<Item> {
+        let slot = self.slots.iter_mut().find(|slot| slot.as_ref().is_some_and(|item| item.id == id))?;
+        let stack = slot.as_mut()?;
+        if amount == 0 || amount > stack.stack_size {
+            return None;
+        }
+        if amount == stack.stack_size {
+            return slot.take();
+        }
+        stack.stack_size -= amount;
+        Some(Item { id: Uuid::new_v4(), stack_size: amount, ..stack.clone() })
+    }
+
+    /// Gets a stack in the inventory by ID
+    pub fn get_item(&self, id: Uuid) -> Option<&Item> {
+        self.items().find(|item| item.id == id)
+    }
+
+    /// Iterates over the stacks in the inventory
+    pub fn items(&self) -> impl Iterator<Item = &Item> {
+        self.slots.iter().flatten()
+    }
+
+    /// Moves a weapon or armor from the inventory into an equipment slot it fits
+    ///
+    /// Whatever it displaces goes back into the inventory: the item already
+    /// in the slot, the off-hand item when wielding a two-handed weapon, or
+    /// the two-handed weapon when equipping the off hand. Nothing changes
+    /// when the inventory has no room for them.
+    ///
+    /// # Arguments
+    /// * `id` - UUID of the item
+    /// * `slot` - Equipment slot to put it in
+    ///
+    /// # Returns
+    /// Result indicating success or the reason the item stays put
+    pub fn equip_item(&mut self, id: Uuid, slot: EquipmentSlot) -> Result<(), EquipError> {
+        let item = self.get_item(id).ok_or(EquipError::NotCarried)?;
+        if !item.is_equippable() {
+            return Err(EquipError::NotEquippable { item: item.name.clone() });
+        }
+        if !item.slots.contains(&slot) || (item.two_handed && slot != EquipmentSlot::MainHand) {
+            return Err(EquipError::WrongSlot { item: item.name.clone(), slots: item.slots.clone() });
+        }
+
+        let mut displaced = vec![slot];
+        if item.two_handed {
+            displaced.push(EquipmentSlot::OffHand);
+        }
+        let main_hand_two_handed = self.equipment.get(&EquipmentSlot::MainHand).is_some_and(|weapon| weapon.two_handed);
+        if slot == EquipmentSlot::OffHand && main_hand_two_handed {
+            displaced.push(EquipmentSlot::MainHand);
+        }
+        let returning = displaced.iter().filter(|slot| self.equipment.contains_key(slot)).count();
+        let freed = usize::from(item.stack_size == 1);
+        if returning > self.free_slots() + freed {
+            return Err(EquipError::InventoryFull);
+        }
+
+        let item = self.remove_item(id, 1).ok_or(EquipError::NotCarried)?;
+        for slot in displaced {
+            if let Some(old) = self.equipment.remove(&slot) {
+                self.add_item(old);
+            }
+        }
+        self.equipment.insert(slot, item);
+        Ok(())
+    }
+
+    /// Moves the item in an equipment slot back into the inventory
+    ///
+    /// # Returns
+    /// Result indicating success or the reason the item stays equipped
+    pub fn unequip_item(&mut self, slot: EquipmentSlot) -> Result<(), EquipError> {
+        if !self.equipment.contains_key(&slot) {
+            return Err(EquipError::NothingEquipped);
+        }
+        if self.free_slots() == 0 {
+            return Err(EquipError::InventoryFull);
+        }
+        if let Some(item) = self.equipment.remove(&slot) {
+            self.add_item(item);
+        }
+        Ok(())
+    }
+
+    /// Counts the empty inventory slots
+    fn free_slots(&self) -> usize {
+        self.slots.iter().filter(|slot| slot.is_none()).count()
+    }
+
+    /// Gets the items worn or wielded, by slot
+    pub fn equipment(&self) -> &HashMap<EquipmentSlot, Item> {
+        &self.equipment
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{ItemType, Position, Rarity, Stats};
+    use EquipmentSlot::*;
+
+    fn item(name: &str, item_type: ItemType) -> Item {
+        Item {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            item_type,
+            rarity: Rarity::Common,
+            value: 1,
+            attributes: Vec::new(),
+            stats: Stats::default(),
+            stack_size: 1,
+            slots: Vec::new(),
+            two_handed: false,
+            requirements: Requirements::default(),
+        }
+    }
+
+    fn gear(name: &str, slots: &[EquipmentSlot]) -> Item {
+        Item { slots: slots.to_vec(), ..item(name, ItemType::Weapon) }
+    }
+
+    fn carrying(items: &[&Item]) -> Inventory {
+        let mut inventory = Inventory::new();
+        for item in items {
+            assert!(inventory.add_item((*item).clone()).is_none());
+        }
+        inventory
+    }
+
+    #[test]
+    fn consumables_stack_and_equipment_does_not() {
+        let mut inventory = Inventory::new();
+        let potion = item("Healing Potion", ItemType::Consumable);
+        assert!(inventory.add_item(potion.clone()).is_none());
+        assert!(inventory.add_item(item("Healing Potion", ItemType::Consumable)).is_none());
+        assert!(inventory.add_item(item("Sword", ItemType::Weapon)).is_none());
+        assert!(inventory.add_item(item("Sword", ItemType::Weapon)).is_none());
+        assert_eq!(inventory.items().count(), 3);
+        assert_eq!(inventory.get_item(potion.id).unwrap().stack_size, 2);
+
+        let taken = inventory.remove_item(potion.id, 1).unwrap();
+        assert_ne!(taken.id, potion.id);
+        assert_eq!(inventory.get_item(potion.id).unwrap().stack_size, 1);
+        assert!(inventory.remove_item(potion.id, 2).is_none());
+        assert!(inventory.remove_item(potion.id, 1).is_some());
+        assert!(inventory.get_item(potion.id).is_none());
+    }
+
+    #[test]
+    fn full_inventories_hand_items_back() {
+        let mut inventory = Inventory::new();
+        assert!(inventory.add_item(item("Rock", ItemType::Quest)).is_none());
+        for index in 1..INVENTORY_SLOTS {
+            assert!(inventory.add_item(item(&format!("Helm {}", index), ItemType::Armor)).is_none());
+        }
+        let extra = item("Shield", ItemType::Armor);
+        assert_eq!(inventory.add_item(extra.clone()).map(|item| item.id), Some(extra.id));
+        assert!(inventory.add_item(item("Rock", ItemType::Quest)).is_none());
+        assert!(inventory.add_item(item("Pebble", ItemType::Quest)).is_some());
+    }
+
+    #[test]
+    fn equipping_swaps_items_between_the_inventory_and_slots() {
+        let (dagger, axe, potion) = (gear("Dagger", &[MainHand, OffHand]), gear("Axe", &[MainHand]), item("Potion", ItemType::Consumable));
+        let mut inventory = carrying(&[&dagger, &axe, &potion]);
+
+        assert_eq!(inventory.equip_item(potion.id, MainHand), Err(EquipError::NotEquippable { item: "Potion".to_string() }));
+        inventory.equip_item(dagger.id, MainHand).unwrap();
+        inventory.equip_item(axe.id, MainHand).unwrap();
+        assert_eq!(inventory.equipment()[&MainHand].id, axe.id);
+        assert!(inventory.get_item(dagger.id).is_some() && inventory.get_item(axe.id).is_none());
+        assert_eq!(inventory.equip_item(axe.id, MainHand), Err(EquipError::NotCarried));
+
+        inventory.unequip_item(MainHand).unwrap();
+        assert!(inventory.equipment().is_empty());
+        assert_eq!(inventory.unequip_item(MainHand), Err(EquipError::NothingEquipped));
+        assert_eq!(inventory.items().count(), 3);
+    }
+
+    #[test]
+    fn items_only_fit_the_slots_they_declare() {
+        let (sword, ring, plate) = (gear("Sword", &[MainHand]), gear("Ring", &[Ring1, Ring2]), gear("Plate", &[Chest]));
+        let mut inventory = carrying(&[&sword, &ring, &plate]);
+
+        assert!(matches!(inventory.equip_item(sword.id, Feet), Err(EquipError::WrongSlot { .. })));
+        assert!(matches!(inventory.equip_item(plate.id, Ring2), Err(EquipError::WrongSlot { .. })));
+        inventory.equip_item(ring.id, Ring2).unwrap();
+        inventory.equip_item(plate.id, Chest).unwrap();
+        assert_eq!(inventory.equipment().len(), 2);
+    }
+
+    #[test]
+    fn two_handed_weapons_take_both_hands() {
+        let greatsword = Item { two_handed: true, ..gear("Greatsword", &[MainHand]) };
+        let (mace, shield) = (gear("Mace", &[MainHand]), gear("Shield", &[OffHand]));
+        let mut inventory = carrying(&[&greatsword, &mace, &shield]);
+        inventory.equip_item(mace.id, MainHand).unwrap();
+        inventory.equip_item(shield.id, OffHand).unwrap();
+
+        inventory.equip_item(greatsword.id, MainHand).unwrap();
+        assert_eq!(inventory.equipment().len(), 1);
+        assert!(inventory.get_item(mace.id).is_some() && inventory.get_item(shield.id).is_some());
+
+        inventory.equip_item(shield.id, OffHand).unwrap();
+        assert_eq!(inventory.equipment().keys().collect::<Vec<_>>(), [&OffHand]);
+        assert!(inventory.get_item(greatsword.id).is_some());
+    }
+
+    #[test]
+    fn full_inventories_keep_equipment_on() {
+        let (mace, shield) = (gear("Mace", &[MainHand]), gear("Shield", &[OffHand]));
+        let greatsword = Item { two_handed: true, ..gear("Greatsword", &[MainHand]) };
+        let mut inventory = carrying(&[&mace, &shield]);
+        inventory.equip_item(mace.id, MainHand).unwrap();
+        inventory.equip_item(shield.id, OffHand).unwrap();
+        inventory.add_item(greatsword.clone());
+        while inventory.free_slots() > 0 {
+            inventory.add_item(item("Junk", ItemType::Armor));
+        }
+
+        assert_eq!(inventory.unequip_item(OffHand), Err(EquipError::InventoryFull));
+        assert_eq!(inventory.equip_item(greatsword.id, MainHand), Err(EquipError::InventoryFull));
+        assert_eq!(inventory.equipment().len(), 2);
+        assert!(inventory.get_item(greatsword.id).is_some());
+    }
+
+    #[test]
+    fn requirements_check_level_class_and_attributes() {
+        let mut player = Player::new(Uuid::nil(), "hero".to_string(), Position::new(0, 0));
+        player.class = Some("warrior".to_string());
+        assert!(Requirements::default().check(&player).is_ok());
+        let level = Requirements { level: 3, ..Default::default() };
+        assert_eq!(level.check(&player), Err(EquipError::LevelTooLow { required: 3 }));
+        let classes = Requirements { classes: vec!["mage".to_string(), "cleric".to_string()], ..Default::default() };
+        assert!(matches!(classes.check(&player), Err(EquipError::WrongClass { .. })));
+        let strength = Requirements { strength: player.attributes.strength + 1, ..Default::default() };
+        assert_eq!(strength.check(&player), Err(EquipError::AttributeTooLow { attribute: "strength".to_string(), required: strength.strength }));
+        player.level = 3;
+        player.class = Some("cleric".to_string());
+        player.attributes.strength += 1;
+        assert!(level.check(&player).is_ok() && classes.check(&player).is_ok() && strength.check(&player).is_ok());
+    }
+}
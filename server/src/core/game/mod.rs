@@ -0,0 +1,13 @@
+pub mod ai;
+pub mod classes;
+pub mod collision;
+pub mod combat;
+pub mod dungeon;
+pub mod fov;
+pub mod inventory;
+pub mod npcs;
+pub mod pathfinding;
+pub mod progression;
+pub mod quests;
+pub mod skills;
+pub mod status;
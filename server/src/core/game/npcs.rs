@@ -0,0 +1,528 @@
+/// Monsters and other NPCs, and the spawners that keep floors populated
+///
+/// Every room of a floor except the one players spawn in gets a spawner.
+/// A spawner keeps a number of NPCs alive that grows with the room's size
+/// and the floor's depth, and brings each one that dies back after a
+/// respawn delay. Which NPCs appear, how strong they are and what they drop
+/// depends on the depth; spawns are drawn from an RNG seeded by the floor's
+/// seed, so a floor is populated the same way every time it is generated.
+/// The kinds of NPC and the behavior trees driving them are data, read
+/// from a [`Bestiary`] file.
+use std::collections::HashMap;
+use std::sync::Arc;
+use log::warn;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::Deserialize;
+use uuid::{Builder, Uuid};
+use crate::core::game::ai::{Act, Behavior, Brain, Surroundings};
+use crate::core::game::collision::Collision;
+use crate::core::game::pathfinding::PathCache;
+use crate::types::{Dungeon, EquipmentSlot, Item, ItemType, Npc, Position, Rarity, Requirements, Stats, TileType};
+
+/// Floor area in tiles per NPC kept alive in a room
+const ROOM_AREA_PER_NPC: i32 = 30;
+/// Most NPCs a single room's spawner keeps alive
+const MAX_NPCS_PER_ROOM: usize = 5;
+/// Extra strength of NPCs per floor of depth, in percent
+const DEPTH_SCALING_PERCENT: i32 = 15;
+/// Attempts at finding a free tile in a room before a spawn is postponed
+const SPAWN_ATTEMPTS: usize = 10;
+/// Mixed into a floor's seed to seed its spawns
+const SPAWN_SEED_SALT: u64 = 0x5eed_5a17;
+/// Bestiary used unless another one is configured
+const BUILTIN_BESTIARY: &str = include_str!("../../../data/bestiary.json");
+
+/// Item an NPC may drop
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LootEntry {
+    /// Display name of the item
+    pub name: String,
+    /// Type of the item
+    pub item_type: ItemType,
+    /// Rarity of the item
+    pub rarity: Rarity,
+    /// Value in game currency
+    pub value: i32,
+    /// Stats of the item
+    pub stats: Stats,
+    /// Equipment slots the item fits
+    #[serde(default)]
+    pub slots: Vec<EquipmentSlot>,
+    /// Whether the item takes both hands
+    #[serde(default)]
+    pub two_handed: bool,
+    /// What a player needs to equip the item
+    #[serde(default)]
+    pub requirements: Requirements,
+    /// Chance of dropping, between 0 and 1
+    pub chance: f64,
+}
+
+/// Kind of NPC that can be spawned
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NpcTemplate {
+    /// Display name
+    pub name: String,
+    /// Whether the NPC fights players
+    pub hostile: bool,
+    /// Health at the top floor
+    pub health: i32,
+    /// Experience awarded for killing the NPC at the top floor
+    #[serde(default)]
+    pub experience: i32,
+    /// Combat stats at the top floor
+    pub stats: Stats,
+    /// Shallowest floor the NPC appears on
+    pub min_depth: u32,
+    /// Relative likelihood of being picked among the NPCs of a floor
+    pub weight: u32,
+    /// Walking speed in percent of a player's
+    pub speed: u32,
+    /// Name of the behavior tree driving the NPC
+    pub behavior: String,
+    /// Items the NPC may drop
+    #[serde(default)]
+    pub loot: Vec<LootEntry>,
+}
+
+/// Every kind of NPC and the behavior trees driving them
+///
+/// Read from a JSON data file, so kinds of NPC can be added and tuned
+/// without recompiling the server.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Bestiary {
+    /// Behavior trees, keyed by the name kinds of NPC refer to them by
+    pub behaviors: HashMap<String, Behavior>,
+    /// Every kind of NPC, from the weakest to the strongest
+    pub npcs: Vec<NpcTemplate>,
+}
+
+impl Default for Bestiary {
+    fn default() -> Self {
+        Self::parse(BUILTIN_BESTIARY).expect("the built-in bestiary is valid")
+    }
+}
+
+impl Bestiary {
+    /// Parses a bestiary from JSON
+    ///
+    /// Fails when a kind of NPC refers to a behavior tree that is not defined.
+    pub fn parse(value: &str) -> Result<Self, serde_json::Error> {
+        let bestiary: Self = serde_json::from_str(value)?;
+        if let Some(template) = bestiary.npcs.iter().find(|template| !bestiary.behaviors.contains_key(&template.behavior)) {
+            return Err(serde::de::Error::custom(format!(
+                "{} uses the undefined behavior {}", template.name, template.behavior
+            )));
+        }
+        Ok(bestiary)
+    }
+
+    /// Gets the behavior tree driving a kind of NPC
+    ///
+    /// # Arguments
+    /// * `kind` - Index of the kind in `npcs`
+    pub fn behavior_of(&self, kind: usize) -> Option<&Behavior> {
+        self.behaviors.get(&self.npcs.get(kind)?.behavior)
+    }
+}
+
+/// Reads the bestiary from the file named by the `BESTIARY_PATH` environment variable
+///
+/// # Returns
+/// The bestiary in the file, or the built-in one when unset or invalid
+pub fn bestiary_from_env() -> Bestiary {
+    let Ok(path) = std::env::var("BESTIARY_PATH") else {
+        return Bestiary::default();
+    };
+    std::fs::read_to_string(&path)
+        .map_err(|e| e.to_string())
+        .and_then(|value| Bestiary::parse(&value).map_err(|e| e.to_string()))
+        .unwrap_or_else(|e| {
+            warn!("Ignoring invalid bestiary {}: {}", path, e);
+            Bestiary::default()
+        })
+}
+
+impl NpcTemplate {
+    /// Creates an NPC of this kind, scaled to a floor's depth
+    ///
+    /// # Arguments
+    /// * `id` - UUID of the NPC
+    /// * `position` - Where the NPC spawns, in world space
+    /// * `depth` - Depth of the floor it spawns on
+    /// * `rng` - Source of the loot rolls
+    pub fn spawn(&self, id: Uuid, position: Position, depth: u32, rng: &mut ChaCha8Rng) -> Npc {
+        let scale = |value: i32| value * (100 + DEPTH_SCALING_PERCENT * depth as i32) / 100;
+        let health = scale(self.health);
+        Npc {
+            id,
+            name: self.name.clone(),
+            hostile: self.hostile,
+            position,
+            health,
+            max_health: health,
+            stats: Stats {
+                damage: scale(self.stats.damage),
+                defense: scale(self.stats.defense),
+                magic: scale(self.stats.magic),
+                ..Default::default()
+            },
+            experience: scale(self.experience),
+            loot: self.roll_loot(rng),
+        }
+    }
+
+    /// Rolls which items of the loot table an NPC of this kind will drop
+    fn roll_loot(&self, rng: &mut ChaCha8Rng) -> Vec<Item> {
+        let mut loot = Vec::new();
+        for entry in &self.loot {
+            if rng.gen_bool(entry.chance) {
+                loot.push(Item {
+                    id: random_id(rng),
+                    name: entry.name.clone(),
+                    item_type: entry.item_type.clone(),
+                    rarity: entry.rarity.clone(),
+                    value: entry.value,
+                    attributes: Vec::new(),
+                    stats: entry.stats.clone(),
+                    stack_size: 1,
+                    slots: entry.slots.clone(),
+                    two_handed: entry.two_handed,
+                    requirements: entry.requirements.clone(),
+                });
+            }
+        }
+        loot
+    }
+}
+
+/// Draws a UUID from a seeded RNG
+fn random_id(rng: &mut ChaCha8Rng) -> Uuid {
+    Builder::from_random_bytes(rng.gen()).into_uuid()
+}
+
+/// Keeps the NPCs of one room alive
+#[derive(Debug, Clone)]
+pub struct Spawner {
+    /// Index of the room in the floor's room list
+    pub room: usize,
+    /// Number of NPCs kept alive
+    pub capacity: usize,
+    /// NPCs spawned here that are still alive
+    pub alive: Vec<Uuid>,
+    /// Ticks at which the next NPCs are due to spawn
+    pub pending: Vec<u64>,
+}
+
+/// NPCs living on one floor and the spawners that keep it populated
+#[derive(Debug)]
+pub struct Population {
+    /// Living NPCs, keyed by ID
+    pub npcs: HashMap<Uuid, Npc>,
+    /// What each living NPC remembers between ticks
+    brains: HashMap<Uuid, Brain>,
+    /// One spawner per room, except the room players spawn in
+    pub spawners: Vec<Spawner>,
+    /// Kinds of NPC that can spawn
+    bestiary: Arc<Bestiary>,
+    /// Depth of the floor
+    depth: u32,
+    /// Source of spawn positions, kinds, loot and every choice NPCs make
+    rng: ChaCha8Rng,
+}
+
+impl Population {
+    /// Places the spawners of a floor without spawning anything yet
+    ///
+    /// # Arguments
+    /// * `dungeon` - Layout of the floor
+    /// * `bestiary` - Kinds of NPC that can spawn
+    pub fn new(dungeon: &Dungeon, bestiary: &Arc<Bestiary>) -> Self {
+        let depth = dungeon.depth;
+        let spawners = dungeon.rooms.iter()
+            .enumerate()
+            .skip(1)
+            .map(|(room, bounds)| {
+                let by_area = (bounds.width * bounds.height / ROOM_AREA_PER_NPC) as usize;
+                Spawner {
+                    room,
+                    capacity: (by_area + depth as usize / 3).clamp(1, MAX_NPCS_PER_ROOM),
+                    alive: Vec::new(),
+                    pending: Vec::new(),
+                }
+            })
+            .collect();
+        Self {
+            npcs: HashMap::new(),
+            brains: HashMap::new(),
+            spawners,
+            bestiary: bestiary.clone(),
+            depth,
+            rng: ChaCha8Rng::seed_from_u64(dungeon.seed ^ SPAWN_SEED_SALT),
+        }
+    }
+
+    /// Advances the spawners by one tick
+    ///
+    /// NPCs that are no longer in `npcs` count as dead and are scheduled to
+    /// respawn; empty slots are filled at once, e.g. on the first tick.
+    ///
+    /// # Arguments
+    /// * `dungeon` - Layout of the floor
+    /// * `collision` - Tile geometry used to place NPCs
+    /// * `tick` - Current tick
+    /// * `respawn_ticks` - Delay before a dead NPC comes back
+    ///
+    /// # Returns
+    /// IDs of the NPCs spawned this tick
+    pub fn advance(&mut self, dungeon: &Dungeon, collision: &Collision, tick: u64, respawn_ticks: u64) -> Vec<Uuid> {
+        self.brains.retain(|id, _| self.npcs.contains_key(id));
+        let mut spawned = Vec::new();
+        for index in 0..self.spawners.len() {
+            let spawner = &mut self.spawners[index];
+            let before = spawner.alive.len();
+            spawner.alive.retain(|id| self.npcs.contains_key(id));
+            for _ in spawner.alive.len()..before {
+                spawner.pending.push(tick + respawn_ticks);
+            }
+            while spawner.alive.len() + spawner.pending.len() < spawner.capacity {
+                spawner.pending.push(tick);
+            }
+
+            let due = spawner.pending.iter().filter(|at| **at <= tick).count();
+            spawner.pending.retain(|at| *at > tick);
+            for _ in 0..due {
+                match self.spawn(dungeon, collision, index) {
+                    Some(id) => spawned.push(id),
+                    None => self.spawners[index].pending.push(tick + respawn_ticks),
+                }
+            }
+        }
+        spawned
+    }
+
+    /// Spawns an NPC on a free floor tile of a spawner's room
+    ///
+    /// # Returns
+    /// ID of the NPC, or None when no free tile was found
+    fn spawn(&mut self, dungeon: &Dungeon, collision: &Collision, spawner: usize) -> Option<Uuid> {
+        let room = dungeon.rooms.get(self.spawners[spawner].room)?;
+        let kind = self.pick_kind()?;
+        let taken: Vec<(i32, i32)> = self.npcs.values()
+            .map(|npc| collision.tile_of(npc.position))
+            .collect();
+        let tile = (0..SPAWN_ATTEMPTS)
+            .map(|_| (
+                self.rng.gen_range(room.x..room.x + room.width),
+                self.rng.gen_range(room.y..room.y + room.height),
+            ))
+            .find(|&(x, y)| {
+                x >= 0 && y >= 0
+                    && dungeon.get_tile(x as usize, y as usize) == TileType::Floor
+                    && !taken.contains(&(x, y))
+            })?;
+
+        let id = random_id(&mut self.rng);
+        let template = &self.bestiary.npcs[kind];
+        let npc = template.spawn(id, collision.tile_center(tile.0, tile.1), self.depth, &mut self.rng);
+        self.brains.insert(id, Brain::new(kind, template.speed, tile));
+        self.npcs.insert(id, npc);
+        self.spawners[spawner].alive.push(id);
+        Some(id)
+    }
+
+    /// Picks the kind of the next NPC among those found at the floor's depth
+    ///
+    /// # Returns
+    /// Index of the kind in the bestiary, or None when no kind is found this deep
+    fn pick_kind(&mut self) -> Option<usize> {
+        let candidates: Vec<(usize, u32)> = self.bestiary.npcs.iter()
+            .enumerate()
+            .filter(|(_, template)| template.min_depth <= self.depth)
+            .map(|(kind, template)| (kind, template.weight))
+            .collect();
+        let total: u32 = candidates.iter().map(|(_, weight)| weight).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut roll = self.rng.gen_range(0..total);
+        candidates.into_iter()
+            .find(|(_, weight)| {
+                if roll < *weight {
+                    true
+                } else {
+                    roll -= weight;
+                    false
+                }
+            })
+            .map(|(kind, _)| kind)
+    }
+
+    /// Runs the behavior tree of every NPC for one tick
+    ///
+    /// NPCs think in order of their IDs, so the choices they draw from the
+    /// floor's RNG are the same on every run. Stunned NPCs do nothing.
+    ///
+    /// # Arguments
+    /// * `world` - What the NPCs perceive
+    /// * `paths` - Path cache of the floor
+    ///
+    /// # Returns
+    /// What the NPCs did that affects the world around them
+    pub fn think(&mut self, world: &Surroundings, paths: &mut PathCache) -> Vec<Act> {
+        let mut ids: Vec<Uuid> = self.npcs.keys().copied().collect();
+        ids.sort();
+        let mut acts = Vec::new();
+        for id in ids {
+            if world.paces.get(&id) == Some(&0) {
+                continue;
+            }
+            let (Some(npc), Some(brain)) = (self.npcs.get_mut(&id), self.brains.get_mut(&id)) else {
+                continue;
+            };
+            if let Some(behavior) = self.bestiary.behavior_of(brain.kind) {
+                acts.extend(brain.think(behavior, npc, world, paths, &mut self.rng));
+            }
+        }
+        acts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Room;
+
+    fn bestiary() -> Arc<Bestiary> {
+        Arc::new(Bestiary::default())
+    }
+
+    fn three_rooms(depth: u32) -> Dungeon {
+        let mut dungeon = Dungeon::solid(40, 12, 9);
+        dungeon.depth = depth;
+        dungeon.add_room(Room::new(1, 1, 8, 8));
+        dungeon.add_room(Room::new(12, 1, 10, 10));
+        dungeon.add_room(Room::new(25, 1, 4, 4));
+        dungeon
+    }
+
+    #[test]
+    fn rooms_other_than_the_spawn_room_are_populated() {
+        let dungeon = three_rooms(0);
+        let collision = Collision::default();
+        let mut population = Population::new(&dungeon, &bestiary());
+        assert_eq!(population.spawners.iter().map(|spawner| spawner.room).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(population.spawners[0].capacity, 3);
+        assert_eq!(population.spawners[1].capacity, 1);
+
+        let spawned = population.advance(&dungeon, &collision, 1, 100);
+        assert_eq!(spawned.len(), 4);
+        for npc in population.npcs.values() {
+            let (x, y) = collision.tile_of(npc.position);
+            assert!(dungeon.room_at(x, y).is_some_and(|room| room > 0), "{} spawned at {},{}", npc.name, x, y);
+        }
+        assert!(population.advance(&dungeon, &collision, 2, 100).is_empty());
+    }
+
+    #[test]
+    fn dead_npcs_respawn_after_the_delay() {
+        let dungeon = three_rooms(0);
+        let collision = Collision::default();
+        let mut population = Population::new(&dungeon, &bestiary());
+        population.advance(&dungeon, &collision, 1, 100);
+
+        let victim = population.spawners[1].alive[0];
+        population.npcs.remove(&victim);
+        assert!(population.advance(&dungeon, &collision, 10, 100).is_empty());
+        assert!(population.advance(&dungeon, &collision, 109, 100).is_empty());
+        assert_eq!(population.advance(&dungeon, &collision, 110, 100).len(), 1);
+        assert_eq!(population.npcs.len(), 4);
+    }
+
+    #[test]
+    fn deeper_floors_hold_more_and_stronger_npcs() {
+        let collision = Collision::default();
+        let top = three_rooms(0);
+        let deep = three_rooms(9);
+        let mut shallow = Population::new(&top, &bestiary());
+        let mut deeper = Population::new(&deep, &bestiary());
+        shallow.advance(&top, &collision, 1, 100);
+        deeper.advance(&deep, &collision, 1, 100);
+
+        assert!(deeper.npcs.len() > shallow.npcs.len());
+        let bestiary = bestiary();
+        assert!(shallow.npcs.values().all(|npc| bestiary.npcs.iter().any(|t| t.name == npc.name && t.min_depth == 0)));
+        let template = &bestiary.npcs[0];
+        let rat = template.spawn(Uuid::nil(), Position::new(0, 0), 9, &mut ChaCha8Rng::seed_from_u64(0));
+        assert!(rat.max_health > template.health);
+        assert!(rat.stats.damage > template.stats.damage);
+    }
+
+    #[test]
+    fn populations_are_deterministic_per_seed() {
+        let dungeon = three_rooms(3);
+        let collision = Collision::default();
+        let layout = |population: &Population| {
+            let mut npcs: Vec<_> = population.npcs.values()
+                .map(|npc| (npc.id, npc.name.clone(), npc.position.x, npc.position.y, npc.loot.len()))
+                .collect();
+            npcs.sort();
+            npcs
+        };
+
+        let mut first = Population::new(&dungeon, &bestiary());
+        let mut second = Population::new(&dungeon, &bestiary());
+        first.advance(&dungeon, &collision, 1, 100);
+        second.advance(&dungeon, &collision, 1, 100);
+        assert_eq!(layout(&first), layout(&second));
+    }
+
+    #[test]
+    fn npcs_think_the_same_way_for_the_same_seed() {
+        let dungeon = three_rooms(3);
+        let collision = Collision::default();
+        let player = collision.tile_center(15, 5);
+        let run = || {
+            let (mut population, mut paths) = (Population::new(&dungeon, &bestiary()), PathCache::new());
+            let mut acts = Vec::new();
+            for tick in 1..=200 {
+                population.advance(&dungeon, &collision, tick, 100);
+                let players = [(Uuid::nil(), player)];
+                let world = Surroundings { dungeon: &dungeon, collision: &collision, players: &players, tick, tick_rate: 30, speed: 150, paces: &HashMap::new() };
+                acts.extend(population.think(&world, &mut paths));
+            }
+            let mut positions: Vec<_> = population.npcs.values().map(|npc| (npc.id, npc.position.x, npc.position.y)).collect();
+            positions.sort();
+            (positions, acts)
+        };
+
+        let (positions, acts) = run();
+        assert!(acts.iter().any(|act| matches!(act, Act::Moved(_))));
+        assert_eq!(run(), (positions, acts));
+    }
+
+    #[test]
+    fn bestiaries_are_read_from_data() {
+        let bestiary = Bestiary::default();
+        assert!((0..bestiary.npcs.len()).all(|kind| bestiary.behavior_of(kind).is_some()));
+
+        let custom = Bestiary::parse(r#"{
+            "behaviors": { "lazy": { "node": "idle" } },
+            "npcs": [{ "name": "Slime", "hostile": true, "health": 5, "stats": { "damage": 1, "defense": 0, "magic": 0 },
+                       "minDepth": 0, "weight": 1, "speed": 20, "behavior": "lazy" }]
+        }"#).unwrap();
+        assert_eq!(custom.npcs[0].name, "Slime");
+        assert!(custom.npcs[0].loot.is_empty());
+        assert_eq!(custom.behavior_of(0), Some(&Behavior::Idle));
+
+        let undefined = Bestiary::parse(r#"{
+            "behaviors": {},
+            "npcs": [{ "name": "Slime", "hostile": true, "health": 5, "stats": { "damage": 1, "defense": 0, "magic": 0 },
+                       "minDepth": 0, "weight": 1, "speed": 20, "behavior": "lazy" }]
+        }"#);
+        assert!(undefined.is_err());
+    }
+}
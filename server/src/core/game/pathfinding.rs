@@ -0,0 +1,243 @@
+/// A* pathfinding over dungeon tiles
+///
+/// Paths move in eight directions but never cut the corner of a wall, so a
+/// player's box can always follow them. Closed doors cost extra to walk
+/// through, as they have to be opened on the way, which makes paths prefer
+/// open passages of similar length; locked doors are impassable. Found
+/// paths are kept in a [`PathCache`] until the dungeon's tiles change.
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::sync::Arc;
+use crate::types::{Dungeon, TileType};
+
+/// Cost of a straight step
+const STRAIGHT_COST: u32 = 10;
+/// Cost of a diagonal step, about `STRAIGHT_COST * sqrt(2)`
+const DIAGONAL_COST: u32 = 14;
+/// Extra cost of stepping onto a closed door
+pub const DOOR_COST: u32 = 20;
+/// Paths kept by a cache before it starts over
+const PATH_CACHE_CAPACITY: usize = 512;
+
+/// Tile coordinates
+type Tile = (i32, i32);
+
+/// Tiles from the tile after the start up to and including the goal
+pub type Path = Arc<[(i32, i32)]>;
+
+/// Gets the extra cost of stepping onto a tile
+///
+/// # Returns
+/// The cost, or None when the tile cannot be walked on
+fn tile_cost(dungeon: &Dungeon, x: i32, y: i32) -> Option<u32> {
+    if x < 0 || y < 0 {
+        return None;
+    }
+    match dungeon.get_tile(x as usize, y as usize) {
+        TileType::Wall | TileType::LockedDoor => None,
+        TileType::Door => Some(DOOR_COST),
+        _ => Some(0),
+    }
+}
+
+/// Estimates the cost between two tiles, assuming nothing is in the way
+fn octile_distance(a: (i32, i32), b: (i32, i32)) -> u32 {
+    let dx = a.0.abs_diff(b.0);
+    let dy = a.1.abs_diff(b.1);
+    STRAIGHT_COST * dx.max(dy) + (DIAGONAL_COST - STRAIGHT_COST) * dx.min(dy)
+}
+
+/// Finds the cheapest path between two tiles
+///
+/// # Arguments
+/// * `dungeon` - Dungeon to search
+/// * `start` - Tile to start from
+/// * `goal` - Tile to reach
+///
+/// # Returns
+/// The tiles to walk through after `start`, ending with `goal`, or None
+/// when the goal cannot be reached
+pub fn find_path(dungeon: &Dungeon, start: (i32, i32), goal: (i32, i32)) -> Option<Vec<(i32, i32)>> {
+    tile_cost(dungeon, start.0, start.1)?;
+    tile_cost(dungeon, goal.0, goal.1)?;
+    if start == goal {
+        return Some(Vec::new());
+    }
+
+    let index = |(x, y): (i32, i32)| y as usize * dungeon.width + x as usize;
+    let mut cost = vec![u32::MAX; dungeon.tiles.len()];
+    let mut previous: Vec<Option<(i32, i32)>> = vec![None; dungeon.tiles.len()];
+    let mut open = BinaryHeap::new();
+    cost[index(start)] = 0;
+    open.push(Reverse((octile_distance(start, goal), 0, start)));
+
+    while let Some(Reverse((_, spent, (x, y)))) = open.pop() {
+        if (x, y) == goal {
+            let mut path = vec![goal];
+            let mut cursor = goal;
+            while let Some(step) = previous[index(cursor)].filter(|step| *step != start) {
+                path.push(step);
+                cursor = step;
+            }
+            path.reverse();
+            return Some(path);
+        }
+        if spent > cost[index((x, y))] {
+            continue;
+        }
+
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                if dx == 0 && dy == 0 {
+                    continue;
+                }
+                let next = (x + dx, y + dy);
+                let Some(extra) = tile_cost(dungeon, next.0, next.1) else {
+                    continue;
+                };
+                let diagonal = dx != 0 && dy != 0;
+                // Do not squeeze past the corner of a wall
+                if diagonal && (tile_cost(dungeon, x + dx, y).is_none() || tile_cost(dungeon, x, y + dy).is_none()) {
+                    continue;
+                }
+
+                let step = if diagonal { DIAGONAL_COST } else { STRAIGHT_COST };
+                let total = spent + step + extra;
+                if total < cost[index(next)] {
+                    cost[index(next)] = total;
+                    previous[index(next)] = Some((x, y));
+                    open.push(Reverse((total + octile_distance(next, goal), total, next)));
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Paths found in one dungeon, dropped whenever its tiles change
+#[derive(Debug, Default)]
+pub struct PathCache {
+    /// Tile revision of the dungeon the cached paths were found in
+    revision: u64,
+    /// Path, or None when unreachable, for each start and goal
+    paths: HashMap<(Tile, Tile), Option<Path>>,
+}
+
+impl PathCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Finds the cheapest path between two tiles, reusing earlier results
+    ///
+    /// # Arguments
+    /// * `dungeon` - Dungeon to search, always the same one for a cache
+    /// * `start` - Tile to start from
+    /// * `goal` - Tile to reach
+    ///
+    /// # Returns
+    /// Same as [`find_path`]
+    pub fn find(&mut self, dungeon: &Dungeon, start: (i32, i32), goal: (i32, i32)) -> Option<Path> {
+        if self.revision != dungeon.revision || self.paths.len() >= PATH_CACHE_CAPACITY {
+            self.invalidate();
+            self.revision = dungeon.revision;
+        }
+        self.paths.entry((start, goal))
+            .or_insert_with(|| find_path(dungeon, start, goal).map(Path::from))
+            .clone()
+    }
+
+    /// Drops every cached path, e.g. when the dungeon is replaced
+    pub fn invalidate(&mut self) {
+        self.paths.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Room;
+
+    fn open_room() -> Dungeon {
+        let mut dungeon = Dungeon::solid(12, 12, 0);
+        dungeon.add_room(Room::new(1, 1, 10, 10));
+        dungeon
+    }
+
+    #[test]
+    fn open_rooms_are_crossed_diagonally() {
+        let path = find_path(&open_room(), (2, 2), (6, 6)).unwrap();
+        assert_eq!(path, vec![(3, 3), (4, 4), (5, 5), (6, 6)]);
+        assert_eq!(find_path(&open_room(), (2, 2), (2, 2)), Some(Vec::new()));
+    }
+
+    #[test]
+    fn paths_do_not_cut_wall_corners() {
+        let mut dungeon = open_room();
+        for y in 1..6 {
+            dungeon.set_tile(5, y, TileType::Wall);
+        }
+
+        let path = find_path(&dungeon, (4, 2), (6, 2)).unwrap();
+        assert!(path.contains(&(5, 6)));
+        let mut previous = (4, 2);
+        for &(x, y) in &path {
+            if x != previous.0 && y != previous.1 {
+                assert_ne!(dungeon.get_tile(x as usize, previous.1 as usize), TileType::Wall);
+                assert_ne!(dungeon.get_tile(previous.0 as usize, y as usize), TileType::Wall);
+            }
+            previous = (x, y);
+        }
+    }
+
+    #[test]
+    fn doors_are_avoided_when_an_open_passage_is_close_by() {
+        // Two rooms joined by a door and, right next to it, an open gap
+        let mut dungeon = Dungeon::solid(20, 12, 0);
+        dungeon.add_room(Room::new(1, 1, 8, 10));
+        dungeon.add_room(Room::new(10, 1, 8, 10));
+        dungeon.set_tile(9, 4, TileType::Door);
+        dungeon.set_tile(9, 5, TileType::Floor);
+
+        let path = find_path(&dungeon, (7, 4), (11, 4)).unwrap();
+        assert!(path.contains(&(9, 5)));
+        assert!(!path.contains(&(9, 4)));
+
+        // Walking around is not worth it when the gap is far away
+        dungeon.set_tile(9, 5, TileType::Wall);
+        dungeon.set_tile(9, 10, TileType::Floor);
+        let path = find_path(&dungeon, (7, 4), (11, 4)).unwrap();
+        assert!(path.contains(&(9, 4)));
+    }
+
+    #[test]
+    fn unreachable_goals_have_no_path() {
+        let mut dungeon = open_room();
+        for y in 1..11 {
+            dungeon.set_tile(5, y, TileType::Wall);
+        }
+        assert_eq!(find_path(&dungeon, (2, 2), (8, 8)), None);
+        assert_eq!(find_path(&dungeon, (2, 2), (5, 5)), None);
+        assert_eq!(find_path(&dungeon, (2, 2), (-1, 5)), None);
+
+        dungeon.set_tile(5, 8, TileType::LockedDoor);
+        assert_eq!(find_path(&dungeon, (2, 2), (8, 8)), None);
+        dungeon.set_tile(5, 8, TileType::OpenDoor);
+        assert!(find_path(&dungeon, (2, 2), (8, 8)).is_some());
+    }
+
+    #[test]
+    fn cached_paths_are_dropped_when_tiles_change() {
+        let mut dungeon = open_room();
+        let mut cache = PathCache::new();
+
+        let first = cache.find(&dungeon, (2, 2), (8, 2)).unwrap();
+        let again = cache.find(&dungeon, (2, 2), (8, 2)).unwrap();
+        assert!(Arc::ptr_eq(&first, &again));
+
+        dungeon.set_tile(5, 2, TileType::Wall);
+        let rerouted = cache.find(&dungeon, (2, 2), (8, 2)).unwrap();
+        assert!(!rerouted.contains(&(5, 2)));
+        assert!(!Arc::ptr_eq(&first, &rerouted));
+    }
+}
@@ -0,0 +1,211 @@
+/// Character progression: experience, levels and stat points
+///
+/// Players gain experience by killing NPCs and completing quests. Reaching
+/// the total experience a level requires raises the player's level, which
+/// grants stat points to spend on attributes and refills their health and
+/// mana. Maximum health grows with level and strength, maximum mana with
+/// level and intelligence. The experience table and growth rates are data,
+/// read from a [`Progression`] file.
+use log::warn;
+use serde::Deserialize;
+use crate::types::{Attributes, Player};
+
+/// Progression used unless another one is configured
+const BUILTIN_PROGRESSION: &str = include_str!("../../../data/progression.json");
+
+/// How a maximum grows with a character's level and one of their attributes
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Growth {
+    /// Value before level and attribute
+    pub base: i32,
+    /// Added per level
+    pub per_level: i32,
+    /// Added per point of the attribute
+    pub per_attribute: i32,
+}
+
+impl Growth {
+    /// Computes the maximum at a level and attribute value
+    fn at(self, level: i32, attribute: i32) -> i32 {
+        (self.base + self.per_level * level + self.per_attribute * attribute).max(1)
+    }
+}
+
+/// Experience table and growth rates of characters
+///
+/// Read from a JSON data file, so the leveling curve can be tuned without
+/// recompiling the server.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Progression {
+    /// Total experience needed to reach each level, level 1 first; its
+    /// length is the highest level
+    pub experience: Vec<i32>,
+    /// Stat points granted per level gained
+    pub stat_points_per_level: i32,
+    /// Growth of maximum health, with strength
+    pub health: Growth,
+    /// Growth of maximum mana, with intelligence
+    pub mana: Growth,
+}
+
+impl Default for Progression {
+    fn default() -> Self {
+        Self::parse(BUILTIN_PROGRESSION).expect("the built-in progression is valid")
+    }
+}
+
+impl Progression {
+    /// Parses a progression from JSON
+    ///
+    /// Fails when the experience table is empty, does not start at 0 or does
+    /// not strictly increase.
+    pub fn parse(value: &str) -> Result<Self, serde_json::Error> {
+        let progression: Self = serde_json::from_str(value)?;
+        if progression.experience.first() != Some(&0) {
+            return Err(serde::de::Error::custom("the experience table must start at 0"));
+        }
+        if progression.experience.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err(serde::de::Error::custom("the experience table must strictly increase"));
+        }
+        Ok(progression)
+    }
+
+    /// Finds the level a total of experience reaches
+    pub fn level_for(&self, exp: i32) -> i32 {
+        self.experience.iter().filter(|needed| **needed <= exp).count() as i32
+    }
+
+    /// Gets the total experience needed to reach the level after one
+    ///
+    /// # Returns
+    /// The experience of the highest level once it is reached
+    pub fn next_level_at(&self, level: i32) -> i32 {
+        self.experience[(level.max(1) as usize).min(self.experience.len() - 1)]
+    }
+
+    /// Computes the maximum health of a character
+    pub fn max_health(&self, level: i32, attributes: &Attributes) -> i32 {
+        self.health.at(level, attributes.strength)
+    }
+
+    /// Computes the maximum mana of a character, before equipment bonuses
+    pub fn max_mana(&self, level: i32, attributes: &Attributes) -> i32 {
+        self.mana.at(level, attributes.intelligence)
+    }
+
+    /// Updates a player's maximum health, mana and experience from their
+    /// level and attributes
+    ///
+    /// Current health and mana are lowered to the new maximums if needed.
+    pub fn recalculate(&self, player: &mut Player) {
+        player.max_health = self.max_health(player.level, &player.attributes);
+        player.max_mana = self.max_mana(player.level, &player.attributes);
+        player.max_exp = self.next_level_at(player.level);
+        player.health = player.health.min(player.max_health);
+        player.mana = player.mana.min(player.mana_capacity());
+    }
+
+    /// Gives a player experience, leveling them up as far as it reaches
+    ///
+    /// Every level gained grants stat points; a player who levels up is
+    /// brought back to full health and mana. Experience stops accruing at
+    /// the highest level.
+    ///
+    /// # Arguments
+    /// * `player` - Player gaining the experience
+    /// * `amount` - Experience points gained
+    ///
+    /// # Returns
+    /// The number of levels gained
+    pub fn award(&self, player: &mut Player, amount: i32) -> i32 {
+        let cap = self.experience[self.experience.len() - 1];
+        player.exp = player.exp.saturating_add(amount.max(0)).min(cap);
+        let gained = (self.level_for(player.exp) - player.level).max(0);
+        if gained > 0 {
+            player.level += gained;
+            player.stat_points += gained * self.stat_points_per_level;
+            self.recalculate(player);
+            player.health = player.max_health;
+            player.mana = player.mana_capacity();
+        }
+        gained
+    }
+}
+
+/// Reads the progression from the file named by the `PROGRESSION_PATH` environment variable
+///
+/// # Returns
+/// The progression in the file, or the built-in one when unset or invalid
+pub fn progression_from_env() -> Progression {
+    let Ok(path) = std::env::var("PROGRESSION_PATH") else {
+        return Progression::default();
+    };
+    std::fs::read_to_string(&path)
+        .map_err(|e| e.to_string())
+        .and_then(|value| Progression::parse(&value).map_err(|e| e.to_string()))
+        .unwrap_or_else(|e| {
+            warn!("Ignoring invalid progression {}: {}", path, e);
+            Progression::default()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+    use crate::types::Position;
+
+    fn new_player(progression: &Progression) -> Player {
+        let mut player = Player::new(Uuid::nil(), "hero".to_string(), Position::new(0, 0));
+        progression.recalculate(&mut player);
+        player
+    }
+
+    #[test]
+    fn new_characters_start_at_level_one() {
+        let progression = Progression::default();
+        let player = new_player(&progression);
+        assert_eq!(player.level, 1);
+        assert_eq!(player.max_exp, progression.experience[1]);
+        assert_eq!((player.max_health, player.max_mana), (100, 100));
+        assert_eq!((player.health, player.mana), (100, 100));
+    }
+
+    #[test]
+    fn experience_levels_up_and_grants_stat_points() {
+        let progression = Progression::parse(r#"{
+            "experience": [0, 100, 300],
+            "statPointsPerLevel": 2,
+            "health": { "base": 10, "perLevel": 10, "perAttribute": 1 },
+            "mana": { "base": 0, "perLevel": 5, "perAttribute": 2 }
+        }"#).unwrap();
+        let mut player = new_player(&progression);
+        player.health = 5;
+
+        assert_eq!(progression.award(&mut player, 99), 0);
+        assert_eq!((player.level, player.stat_points, player.health), (1, 0, 5));
+        assert_eq!(progression.award(&mut player, 250), 2);
+        assert_eq!((player.level, player.stat_points), (3, 4));
+        assert_eq!(player.max_health, 10 + 30 + player.attributes.strength);
+        assert_eq!(player.health, player.max_health);
+        assert_eq!(player.mana, 15 + 2 * player.attributes.intelligence);
+
+        assert_eq!(progression.award(&mut player, 1000), 0);
+        assert_eq!((player.level, player.exp, player.max_exp), (3, 300, 300));
+    }
+
+    #[test]
+    fn experience_tables_are_validated() {
+        let table = |experience: &str| format!(r#"{{
+            "experience": {}, "statPointsPerLevel": 1,
+            "health": {{ "base": 1, "perLevel": 1, "perAttribute": 1 }},
+            "mana": {{ "base": 1, "perLevel": 1, "perAttribute": 1 }}
+        }}"#, experience);
+        assert!(Progression::parse(&table("[0, 10, 20]")).is_ok());
+        assert!(Progression::parse(&table("[]")).is_err());
+        assert!(Progression::parse(&table("[5, 10]")).is_err());
+        assert!(Progression::parse(&table("[0, 10, 10]")).is_err());
+    }
+}
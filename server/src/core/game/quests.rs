@@ -0,0 +1,171 @@
+/// Quests players complete for experience
+///
+/// Every quest is a standing bounty on one kind of NPC: players make
+/// progress by killing NPCs of that kind and complete the quest once, when
+/// they reach its goal, gaining its experience. The quests themselves are
+/// data, read from a [`QuestBook`] file.
+use std::collections::{BTreeMap, BTreeSet};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+/// Quest book used unless another one is configured
+const BUILTIN_QUESTS: &str = include_str!("../../../data/quests.json");
+
+/// Bounty on a kind of NPC
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Quest {
+    /// Display name
+    pub name: String,
+    /// Short description shown in the quest log
+    #[serde(default)]
+    pub description: String,
+    /// Name of the NPCs to kill, as in the bestiary
+    pub target: String,
+    /// How many of them to kill
+    pub count: u32,
+    /// Experience gained on completion
+    pub experience: i32,
+}
+
+/// Every quest players can complete
+///
+/// Read from a JSON data file, so quests can be added and tuned without
+/// recompiling the server.
+#[derive(Debug, Clone, Deserialize)]
+pub struct QuestBook {
+    /// Quests, keyed by the name they are tracked under
+    pub quests: BTreeMap<String, Quest>,
+}
+
+impl Default for QuestBook {
+    fn default() -> Self {
+        Self::parse(BUILTIN_QUESTS).expect("the built-in quest book is valid")
+    }
+}
+
+impl QuestBook {
+    /// Parses a quest book from JSON
+    ///
+    /// Fails when a quest asks for no kills or gives no experience.
+    pub fn parse(value: &str) -> Result<Self, serde_json::Error> {
+        let book: Self = serde_json::from_str(value)?;
+        if let Some(quest) = book.quests.values().find(|quest| quest.count == 0 || quest.experience <= 0) {
+            return Err(serde::de::Error::custom(format!("{} must ask for kills and give experience", quest.name)));
+        }
+        Ok(book)
+    }
+}
+
+/// Progress of a quest after a kill counted toward it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuestProgress {
+    /// Name the quest is tracked under
+    pub quest: String,
+    /// Kills counted so far
+    pub progress: u32,
+    /// Kills the quest asks for
+    pub goal: u32,
+}
+
+impl QuestProgress {
+    /// Whether the kill completed the quest
+    pub fn is_complete(&self) -> bool {
+        self.progress >= self.goal
+    }
+}
+
+/// A player's progress on the quests of a quest book
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuestLog {
+    /// Kills counted toward each quest not completed yet
+    progress: BTreeMap<String, u32>,
+    /// Quests completed
+    completed: BTreeSet<String>,
+}
+
+impl QuestLog {
+    /// Counts a kill toward every quest on the kind of NPC killed
+    ///
+    /// # Arguments
+    /// * `book` - Quests that can be progressed
+    /// * `target` - Name of the NPC killed
+    ///
+    /// # Returns
+    /// The progress of each quest the kill counted toward; quests reaching
+    /// their goal are completed
+    pub fn record_kill(&mut self, book: &QuestBook, target: &str) -> Vec<QuestProgress> {
+        let mut progressed = Vec::new();
+        for (name, quest) in &book.quests {
+            if quest.target != target || self.is_completed(name) {
+                continue;
+            }
+            let kills = self.progress.entry(name.clone()).or_default();
+            *kills += 1;
+            let progress = QuestProgress { quest: name.clone(), progress: *kills, goal: quest.count };
+            if progress.is_complete() {
+                self.progress.remove(name);
+                self.completed.insert(name.clone());
+            }
+            progressed.push(progress);
+        }
+        progressed
+    }
+
+    /// Checks whether a quest was completed
+    pub fn is_completed(&self, quest: &str) -> bool {
+        self.completed.contains(quest)
+    }
+}
+
+/// Reads the quest book from the file named by the `QUESTS_PATH` environment variable
+///
+/// # Returns
+/// The quest book in the file, or the built-in one when unset or invalid
+pub fn quests_from_env() -> QuestBook {
+    let Ok(path) = std::env::var("QUESTS_PATH") else {
+        return QuestBook::default();
+    };
+    std::fs::read_to_string(&path)
+        .map_err(|e| e.to_string())
+        .and_then(|value| QuestBook::parse(&value).map_err(|e| e.to_string()))
+        .unwrap_or_else(|e| {
+            warn!("Ignoring invalid quest book {}: {}", path, e);
+            QuestBook::default()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kills_progress_quests_on_their_target_until_completed() {
+        let book = QuestBook::parse(r#"{ "quests": {
+            "pests": { "name": "Pests", "target": "Giant Rat", "count": 2, "experience": 40 },
+            "bats": { "name": "Bats", "target": "Cave Bat", "count": 1, "experience": 10 }
+        } }"#).unwrap();
+        let mut log = QuestLog::default();
+
+        assert!(log.record_kill(&book, "Goblin").is_empty());
+        let first = log.record_kill(&book, "Giant Rat");
+        assert_eq!(first, [QuestProgress { quest: "pests".to_string(), progress: 1, goal: 2 }]);
+        assert!(!first[0].is_complete());
+        assert!(log.record_kill(&book, "Giant Rat")[0].is_complete());
+        assert!(log.is_completed("pests") && !log.is_completed("bats"));
+        assert!(log.record_kill(&book, "Giant Rat").is_empty());
+    }
+
+    #[test]
+    fn quest_books_are_validated() {
+        assert!(QuestBook::default().quests.values().all(|quest| quest.count > 0));
+        let quest = |count: u32, experience: i32| format!(
+            r#"{{ "quests": {{ "q": {{ "name": "Q", "target": "Goblin", "count": {}, "experience": {} }} }} }}"#,
+            count, experience
+        );
+        assert!(QuestBook::parse(&quest(3, 50)).is_ok());
+        assert!(QuestBook::parse(&quest(0, 50)).is_err());
+        assert!(QuestBook::parse(&quest(3, 0)).is_err());
+    }
+}
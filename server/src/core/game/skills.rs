@@ -0,0 +1,217 @@
+/// Skills and spells players cast with mana
+///
+/// A skill costs mana and, once cast, has to cool down before it can be
+/// cast again; skills with a cast time only take effect after it, and are
+/// interrupted when the caster moves. Skills aim at the caster, at another
+/// entity or at a tile of the ground, and may affect everything within an
+/// area around that point. Players bind the skills they want to use to the
+/// slots of their hotbar. The skills themselves are data, read from a
+/// [`SkillBook`] file.
+use std::collections::HashMap;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use crate::core::game::status::Status;
+
+/// Number of slots on a player's hotbar
+pub const HOTBAR_SLOTS: usize = 10;
+/// Skill book used unless another one is configured
+const BUILTIN_SKILLS: &str = include_str!("../../../data/skills.json");
+
+/// What a skill is aimed at
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Targeting {
+    /// The caster themselves
+    Caster,
+    /// Another player or an NPC, in range and in line of sight
+    Entity,
+    /// A tile of the ground, in range and in line of sight
+    Ground,
+}
+
+/// Tiles a skill affects around the point it is aimed at
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(tag = "shape", rename_all = "camelCase")]
+pub enum Area {
+    /// Only the entity aimed at, or whoever stands on the tile aimed at
+    #[default]
+    Single,
+    /// Every tile within a radius in tiles
+    Circle { radius: i32 },
+}
+
+impl Area {
+    /// Checks whether the area around a point covers a tile
+    ///
+    /// # Arguments
+    /// * `center` - Tile the skill is aimed at
+    /// * `tile` - Tile to check
+    pub fn covers(self, center: (i32, i32), tile: (i32, i32)) -> bool {
+        let (dx, dy) = (tile.0 - center.0, tile.1 - center.1);
+        match self {
+            Area::Single => dx == 0 && dy == 0,
+            Area::Circle { radius } => dx * dx + dy * dy <= radius * radius,
+        }
+    }
+}
+
+/// What a skill does to the entities it affects
+///
+/// Damage and harmful status effects only affect NPCs; heals and helpful
+/// status effects only affect players. Damage and heals grow by a share of
+/// the caster's intelligence.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "effect", rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum Effect {
+    /// Deal damage; spells ignore armor
+    Damage {
+        power: i32,
+        #[serde(default)]
+        scaling_percent: i32,
+    },
+    /// Restore health, up to the maximum
+    Heal {
+        power: i32,
+        #[serde(default)]
+        scaling_percent: i32,
+    },
+    /// Put a status effect on the entity for a while
+    Status { status: Status, duration_ms: u64 },
+    /// Move the caster to the tile aimed at
+    Teleport,
+}
+
+impl Effect {
+    /// Whether the effect is meant for NPCs rather than players
+    pub fn is_hostile(self) -> bool {
+        match self {
+            Effect::Damage { .. } => true,
+            Effect::Status { status, .. } => status.is_harmful(),
+            Effect::Heal { .. } | Effect::Teleport => false,
+        }
+    }
+}
+
+/// Skill players can cast
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Skill {
+    /// Display name
+    pub name: String,
+    /// Mana spent when the skill takes effect
+    pub mana_cost: i32,
+    /// Time after the skill takes effect before it can be cast again
+    pub cooldown_ms: u64,
+    /// Time between starting the cast and the skill taking effect
+    #[serde(default)]
+    pub cast_ms: u64,
+    /// Reach in tiles, measured along the longer axis
+    #[serde(default)]
+    pub range: i32,
+    /// What the skill is aimed at
+    pub targeting: Targeting,
+    /// Tiles affected around the point aimed at
+    #[serde(default)]
+    pub area: Area,
+    /// What the skill does
+    pub effect: Effect,
+}
+
+/// Every skill players can cast
+///
+/// Read from a JSON data file, so skills can be added and tuned without
+/// recompiling the server.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SkillBook {
+    /// Skills, keyed by the name hotbars refer to them by
+    pub skills: HashMap<String, Skill>,
+    /// Skills bound to the hotbar of a new player, slot by slot
+    #[serde(default)]
+    pub hotbar: Vec<String>,
+}
+
+impl Default for SkillBook {
+    fn default() -> Self {
+        Self::parse(BUILTIN_SKILLS).expect("the built-in skill book is valid")
+    }
+}
+
+impl SkillBook {
+    /// Parses a skill book from JSON
+    ///
+    /// Fails when a teleport is not aimed at the ground, or when the
+    /// starting hotbar has too many slots or refers to an undefined skill.
+    pub fn parse(value: &str) -> Result<Self, serde_json::Error> {
+        let book: Self = serde_json::from_str(value)?;
+        if let Some(skill) = book.skills.values().find(|skill| skill.effect == Effect::Teleport && skill.targeting != Targeting::Ground) {
+            return Err(serde::de::Error::custom(format!("{} teleports without aiming at the ground", skill.name)));
+        }
+        if book.hotbar.len() > HOTBAR_SLOTS {
+            return Err(serde::de::Error::custom(format!("the hotbar has only {} slots", HOTBAR_SLOTS)));
+        }
+        if let Some(name) = book.hotbar.iter().find(|name| !book.skills.contains_key(*name)) {
+            return Err(serde::de::Error::custom(format!("the hotbar uses the undefined skill {}", name)));
+        }
+        Ok(book)
+    }
+
+    /// Builds the hotbar of a new player
+    ///
+    /// # Returns
+    /// `HOTBAR_SLOTS` slots, the first ones holding the starting skills
+    pub fn starting_hotbar(&self) -> Vec<Option<String>> {
+        let mut hotbar: Vec<Option<String>> = self.hotbar.iter().cloned().map(Some).collect();
+        hotbar.resize(HOTBAR_SLOTS, None);
+        hotbar
+    }
+}
+
+/// Reads the skill book from the file named by the `SKILLS_PATH` environment variable
+///
+/// # Returns
+/// The skill book in the file, or the built-in one when unset or invalid
+pub fn skills_from_env() -> SkillBook {
+    let Ok(path) = std::env::var("SKILLS_PATH") else {
+        return SkillBook::default();
+    };
+    std::fs::read_to_string(&path)
+        .map_err(|e| e.to_string())
+        .and_then(|value| SkillBook::parse(&value).map_err(|e| e.to_string()))
+        .unwrap_or_else(|e| {
+            warn!("Ignoring invalid skill book {}: {}", path, e);
+            SkillBook::default()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_builtin_skill_book_fills_the_hotbar() {
+        let book = SkillBook::default();
+        let hotbar = book.starting_hotbar();
+        assert_eq!(hotbar.len(), HOTBAR_SLOTS);
+        assert!(hotbar.iter().flatten().all(|name| book.skills.contains_key(name)));
+        assert!(hotbar[0].is_some());
+    }
+
+    #[test]
+    fn skill_books_are_validated() {
+        let blink = r#"{ "name": "Blink", "manaCost": 10, "cooldownMs": 0, "range": 5, "targeting": "entity", "effect": { "effect": "teleport" } }"#;
+        assert!(SkillBook::parse(&format!(r#"{{ "skills": {{ "blink": {} }} }}"#, blink)).is_err());
+        let blink = blink.replace("entity", "ground");
+        assert!(SkillBook::parse(&format!(r#"{{ "skills": {{ "blink": {} }}, "hotbar": ["blink"] }}"#, blink)).is_ok());
+        assert!(SkillBook::parse(&format!(r#"{{ "skills": {{ "blink": {} }}, "hotbar": ["dash"] }}"#, blink)).is_err());
+    }
+
+    #[test]
+    fn areas_cover_tiles_around_their_center() {
+        assert!(Area::Single.covers((3, 4), (3, 4)));
+        assert!(!Area::Single.covers((3, 4), (4, 4)));
+        let circle = Area::Circle { radius: 2 };
+        assert!(circle.covers((3, 4), (5, 4)));
+        assert!(circle.covers((3, 4), (4, 5)));
+        assert!(!circle.covers((3, 4), (5, 6)));
+    }
+}
@@ -0,0 +1,325 @@
+/// Status effects on players and NPCs
+///
+/// Poison and regeneration change health every interval, haste and slow
+/// change how fast an entity moves, stun keeps it from moving, attacking
+/// and casting, shields absorb damage before it reaches health, and buffs
+/// and debuffs raise or lower combat stats on top of base stats and
+/// equipment. Every effect wears off after its duration.
+///
+/// Applying a status an entity already has follows that status's stacking
+/// rule: poison stacks up to `MAX_POISON_STACKS` times, a stun never
+/// shortens a longer one, and every other status replaces the one applied
+/// by the same source, while those of other sources stack.
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::core::game::combat::CombatStats;
+use crate::types::{ActiveStatus, StatusKind};
+
+/// Most poison effects one entity can carry at a time
+const MAX_POISON_STACKS: usize = 5;
+/// Slowest pace of a moving entity, in percent of its normal speed
+const MIN_PACE_PERCENT: i32 = 25;
+/// Fastest pace of an entity, in percent of its normal speed
+const MAX_PACE_PERCENT: i32 = 300;
+
+/// Status effect and its strength
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum Status {
+    /// Lose health every interval
+    Poison { damage: i32, interval_ms: u64 },
+    /// Regain health every interval
+    Regeneration { health: i32, interval_ms: u64 },
+    /// Move faster by a percentage
+    Haste { percent: i32 },
+    /// Move slower by a percentage
+    Slow { percent: i32 },
+    /// Neither move, attack nor cast
+    Stun,
+    /// Absorb an amount of damage
+    Shield { amount: i32 },
+    /// Raise combat stats
+    Buff { stats: CombatStats },
+    /// Lower combat stats
+    Debuff { stats: CombatStats },
+}
+
+/// How a status combines with the same status already on an entity
+enum Stacking {
+    /// Replaces the one applied by the same source
+    Refresh,
+    /// Adds another, dropping the one closest to wearing off past a limit
+    Stack(usize),
+    /// Keeps whichever lasts longer
+    Longest,
+}
+
+impl Status {
+    /// Kind of the status, as shown to clients
+    pub fn kind(self) -> StatusKind {
+        match self {
+            Status::Poison { .. } => StatusKind::Poison,
+            Status::Regeneration { .. } => StatusKind::Regeneration,
+            Status::Haste { .. } => StatusKind::Haste,
+            Status::Slow { .. } => StatusKind::Slow,
+            Status::Stun => StatusKind::Stun,
+            Status::Shield { .. } => StatusKind::Shield,
+            Status::Buff { .. } => StatusKind::Buff,
+            Status::Debuff { .. } => StatusKind::Debuff,
+        }
+    }
+
+    /// Whether the status hurts whoever has it
+    pub fn is_harmful(self) -> bool {
+        matches!(self, Status::Poison { .. } | Status::Slow { .. } | Status::Stun | Status::Debuff { .. })
+    }
+
+    /// Time between the health changes of a periodic status
+    fn interval_ms(self) -> Option<u64> {
+        match self {
+            Status::Poison { interval_ms, .. } | Status::Regeneration { interval_ms, .. } => Some(interval_ms),
+            _ => None,
+        }
+    }
+
+    /// Rule for applying the status to an entity that already has it
+    fn stacking(self) -> Stacking {
+        match self {
+            Status::Poison { .. } => Stacking::Stack(MAX_POISON_STACKS),
+            Status::Stun => Stacking::Longest,
+            _ => Stacking::Refresh,
+        }
+    }
+}
+
+/// Status effect on an entity, counting down
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ActiveEffect {
+    /// The effect, with what is left of a shield
+    pub status: Status,
+    /// Entity that applied it, credited with kills by poison
+    pub source: Uuid,
+    /// Tick at which it wears off
+    pub expires: u64,
+    /// Ticks between health changes of a periodic effect
+    interval: u64,
+    /// Tick of the next health change of a periodic effect
+    next: u64,
+}
+
+/// Health change caused by a periodic status effect
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pulse {
+    /// Entity that applied the effect
+    pub source: Uuid,
+    /// Health gained, or lost when negative
+    pub health: i32,
+}
+
+/// Status effects on one entity
+#[derive(Debug, Clone, Default)]
+pub struct StatusEffects {
+    effects: Vec<ActiveEffect>,
+}
+
+impl StatusEffects {
+    /// Puts a status effect on the entity, following the status's stacking rule
+    ///
+    /// # Arguments
+    /// * `status` - Effect to apply
+    /// * `source` - Entity applying it
+    /// * `tick` - Current tick
+    /// * `duration` - Ticks until it wears off
+    /// * `tick_rate` - Simulation ticks per second, to time periodic effects
+    pub fn apply(&mut self, status: Status, source: Uuid, tick: u64, duration: u64, tick_rate: u32) {
+        let interval = status.interval_ms()
+            .map(|ms| (ms * tick_rate as u64).div_ceil(1000).max(1))
+            .unwrap_or(0);
+        let effect = ActiveEffect { status, source, expires: tick + duration, interval, next: tick + interval };
+        let kind = status.kind();
+        match status.stacking() {
+            Stacking::Refresh => {
+                self.effects.retain(|other| other.status.kind() != kind || other.source != source);
+            }
+            Stacking::Stack(limit) => {
+                while self.effects.iter().filter(|other| other.status.kind() == kind).count() >= limit.max(1) {
+                    let soonest = self.effects.iter()
+                        .enumerate()
+                        .filter(|(_, other)| other.status.kind() == kind)
+                        .min_by_key(|(_, other)| other.expires)
+                        .map(|(index, _)| index);
+                    if let Some(index) = soonest {
+                        self.effects.remove(index);
+                    }
+                }
+            }
+            Stacking::Longest => {
+                if self.effects.iter().any(|other| other.status.kind() == kind && other.expires >= effect.expires) {
+                    return;
+                }
+                self.effects.retain(|other| other.status.kind() != kind);
+            }
+        }
+        self.effects.push(effect);
+    }
+
+    /// Checks whether the entity has no status effects left
+    pub fn is_empty(&self) -> bool {
+        self.effects.is_empty()
+    }
+
+    /// Checks whether the entity is stunned
+    pub fn is_stunned(&self) -> bool {
+        self.effects.iter().any(|effect| effect.status == Status::Stun)
+    }
+
+    /// Applies buffs and debuffs to combat stats
+    pub fn modify(&self, stats: CombatStats) -> CombatStats {
+        self.effects.iter().fold(stats, |stats, effect| match effect.status {
+            Status::Buff { stats: buff } => stats.plus(buff),
+            Status::Debuff { stats: debuff } => stats.minus(debuff),
+            _ => stats,
+        })
+    }
+
+    /// Movement speed under haste and slow
+    ///
+    /// # Returns
+    /// Percent of the normal speed, 0 when stunned
+    pub fn pace_percent(&self) -> i32 {
+        if self.is_stunned() {
+            return 0;
+        }
+        let pace = self.effects.iter().fold(100, |pace, effect| match effect.status {
+            Status::Haste { percent } => pace + percent,
+            Status::Slow { percent } => pace - percent,
+            _ => pace,
+        });
+        pace.clamp(MIN_PACE_PERCENT, MAX_PACE_PERCENT)
+    }
+
+    /// Lets shields soak up damage, oldest first; depleted shields break
+    ///
+    /// # Returns
+    /// The damage that gets through
+    pub fn absorb(&mut self, mut damage: i32) -> i32 {
+        for effect in &mut self.effects {
+            if let Status::Shield { amount } = &mut effect.status {
+                let absorbed = (*amount).min(damage).max(0);
+                *amount -= absorbed;
+                damage -= absorbed;
+            }
+        }
+        self.effects.retain(|effect| !matches!(effect.status, Status::Shield { amount } if amount <= 0));
+        damage
+    }
+
+    /// Advances the effects to a tick, wearing off those that expired
+    ///
+    /// # Returns
+    /// Health changes of the periodic effects due at the tick; effects
+    /// expiring at the tick still have their last one
+    pub fn advance(&mut self, tick: u64) -> Vec<Pulse> {
+        let mut pulses = Vec::new();
+        for effect in &mut self.effects {
+            if effect.interval == 0 || effect.next > tick {
+                continue;
+            }
+            effect.next += effect.interval;
+            match effect.status {
+                Status::Poison { damage, .. } => pulses.push(Pulse { source: effect.source, health: -damage }),
+                Status::Regeneration { health, .. } => pulses.push(Pulse { source: effect.source, health }),
+                _ => {}
+            }
+        }
+        self.effects.retain(|effect| effect.expires > tick);
+        pulses
+    }
+
+    /// Summarizes the effects by kind, for clients to draw as icons
+    pub fn icons(&self) -> Vec<ActiveStatus> {
+        let mut icons: Vec<ActiveStatus> = Vec::new();
+        for effect in &self.effects {
+            let kind = effect.status.kind();
+            match icons.iter_mut().find(|icon| icon.kind == kind) {
+                Some(icon) => {
+                    icon.stacks = icon.stacks.saturating_add(1);
+                    icon.expires = icon.expires.max(effect.expires);
+                }
+                None => icons.push(ActiveStatus { kind, stacks: 1, expires: effect.expires }),
+            }
+        }
+        icons.sort_by_key(|icon| icon.kind);
+        icons
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POISON: Status = Status::Poison { damage: 3, interval_ms: 1000 };
+
+    #[test]
+    fn poison_stacks_up_to_a_limit() {
+        let mut effects = StatusEffects::default();
+        for tick in 0..(MAX_POISON_STACKS as u64 + 2) {
+            effects.apply(POISON, Uuid::new_v4(), tick, 300, 30);
+        }
+        let icons = effects.icons();
+        assert_eq!(icons.len(), 1);
+        assert_eq!(icons[0].stacks as usize, MAX_POISON_STACKS);
+        assert_eq!(icons[0].expires, MAX_POISON_STACKS as u64 + 1 + 300);
+    }
+
+    #[test]
+    fn other_statuses_refresh_per_source_and_stuns_keep_the_longest() {
+        let (mage, priest) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut effects = StatusEffects::default();
+        effects.apply(Status::Haste { percent: 50 }, mage, 0, 100, 30);
+        effects.apply(Status::Haste { percent: 20 }, mage, 10, 100, 30);
+        assert_eq!(effects.pace_percent(), 120);
+        effects.apply(Status::Slow { percent: 100 }, priest, 10, 100, 30);
+        assert_eq!(effects.pace_percent(), MIN_PACE_PERCENT);
+
+        effects.apply(Status::Stun, mage, 10, 90, 30);
+        effects.apply(Status::Stun, priest, 20, 30, 30);
+        assert_eq!(effects.pace_percent(), 0);
+        let stun = effects.icons().into_iter().find(|icon| icon.kind == StatusKind::Stun).unwrap();
+        assert_eq!((stun.stacks, stun.expires), (1, 100));
+    }
+
+    #[test]
+    fn periodic_effects_pulse_until_they_wear_off() {
+        let (poisoner, healer) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut effects = StatusEffects::default();
+        effects.apply(POISON, poisoner, 0, 90, 30);
+        effects.apply(Status::Regeneration { health: 2, interval_ms: 500 }, healer, 0, 60, 30);
+
+        let mut health = 0;
+        for tick in 1..=120 {
+            health += effects.advance(tick).iter().map(|pulse| pulse.health).sum::<i32>();
+        }
+        assert_eq!(health, -3 * 3 + 2 * 4);
+        assert!(effects.is_empty());
+    }
+
+    #[test]
+    fn shields_absorb_damage_until_they_break() {
+        let mut effects = StatusEffects::default();
+        effects.apply(Status::Shield { amount: 10 }, Uuid::new_v4(), 0, 300, 30);
+        assert_eq!(effects.absorb(4), 0);
+        assert_eq!(effects.absorb(10), 4);
+        assert!(effects.is_empty());
+        assert_eq!(effects.absorb(5), 5);
+    }
+
+    #[test]
+    fn buffs_and_debuffs_apply_on_top_of_stats() {
+        let mut effects = StatusEffects::default();
+        let stats = CombatStats { damage: 5, strength: 10, ..Default::default() };
+        effects.apply(Status::Buff { stats: CombatStats { damage: 3, ..Default::default() } }, Uuid::new_v4(), 0, 30, 30);
+        effects.apply(Status::Debuff { stats: CombatStats { strength: 4, ..Default::default() } }, Uuid::new_v4(), 0, 30, 30);
+        assert_eq!(effects.modify(stats), CombatStats { damage: 8, strength: 6, ..Default::default() });
+    }
+}
@@ -74,12 +74,6 @@ impl Player {
         self.last_active = SystemTime::now();
     }
 
-    pub fn add_experience(&mut self, exp: u32) {
-        self.experience += exp;
-        // Simple leveling formula: level = sqrt(experience / 100)
-        self.level = ((self.experience as f32 / 100.0).sqrt() as u32).max(1);
-    }
-
     pub fn update_wallet(&mut self, balance: f64) {
         self.wallet.balance = balance;
         self.last_active = SystemTime::now();
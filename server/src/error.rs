@@ -2,6 +2,7 @@
 use thiserror::Error;
 use actix_web::{HttpResponse, ResponseError};
 use serde_json::json;
+use crate::core::game::inventory::EquipError;
 
 /// Represents all possible errors that can occur in the game server
 /// 
@@ -13,6 +14,18 @@ pub enum GameError {
     #[error("Player not found")]
     PlayerNotFound,
     
+    /// Error when a requested character has not been created
+    #[error("Character not found")]
+    CharacterNotFound,
+    
+    /// Error when a requested dungeon floor has not been generated
+    #[error("Floor not found")]
+    FloorNotFound,
+    
+    /// Error when a requested party instance is not live
+    #[error("Instance not found")]
+    InstanceNotFound,
+    
     /// Error when an invalid movement is attempted
     /// 
     /// # Arguments
@@ -20,6 +33,34 @@ pub enum GameError {
     #[error("Invalid movement: {0}")]
     InvalidMovement(String),
     
+    /// Error when an action such as an attack is not allowed right now
+    /// 
+    /// # Arguments
+    /// * String - Description of why the action was refused
+    #[error("Invalid action: {0}")]
+    InvalidAction(String),
+    
+    /// Error when a request is not made on behalf of the account it acts for
+    /// 
+    /// # Arguments
+    /// * String - Description of why the request was refused
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+    
+    /// Error when a character name is refused
+    /// 
+    /// # Arguments
+    /// * String - Description of why the name was refused
+    #[error("Invalid name: {0}")]
+    InvalidName(String),
+    
+    /// Error when an item cannot be equipped or unequipped
+    /// 
+    /// # Arguments
+    /// * EquipError - Why the equipment stays as it is
+    #[error("Invalid equipment change: {0}")]
+    InvalidEquipment(#[from] EquipError),
+    
     /// Error when a position is outside valid bounds or in an invalid tile
     /// 
     /// # Arguments
@@ -27,13 +68,6 @@ pub enum GameError {
     #[error("Invalid position: {0}")]
     InvalidPosition(String),
     
-    /// Error when a database operation fails
-    /// 
-    /// # Arguments
-    /// * String - Description of the database error
-    #[error("Database error: {0}")]
-    DatabaseError(String),
-    
     /// Error for unexpected internal server issues
     /// 
     /// # Arguments
@@ -57,19 +91,50 @@ impl ResponseError for GameError {
                     "error": "Player not found"
                 }))
             }
+            GameError::CharacterNotFound => {
+                HttpResponse::NotFound().json(json!({
+                    "error": "Character not found"
+                }))
+            }
+            GameError::FloorNotFound => {
+                HttpResponse::NotFound().json(json!({
+                    "error": "Floor not found"
+                }))
+            }
+            GameError::InstanceNotFound => {
+                HttpResponse::NotFound().json(json!({
+                    "error": "Instance not found"
+                }))
+            }
             GameError::InvalidMovement(msg) => {
                 HttpResponse::BadRequest().json(json!({
                     "error": format!("Invalid movement: {}", msg)
                 }))
             }
-            GameError::InvalidPosition(msg) => {
+            GameError::InvalidAction(msg) => {
                 HttpResponse::BadRequest().json(json!({
-                    "error": format!("Invalid position: {}", msg)
+                    "error": format!("Invalid action: {}", msg)
                 }))
             }
-            GameError::DatabaseError(msg) => {
-                HttpResponse::InternalServerError().json(json!({
-                    "error": format!("Database error: {}", msg)
+            GameError::Unauthorized(msg) => {
+                HttpResponse::Unauthorized().json(json!({
+                    "error": format!("Unauthorized: {}", msg)
+                }))
+            }
+            GameError::InvalidName(msg) => {
+                HttpResponse::BadRequest().json(json!({
+                    "error": format!("Invalid name: {}", msg)
+                }))
+            }
+            GameError::InvalidEquipment(reason) => {
+                HttpResponse::BadRequest().json(json!({
+                    "error": format!("Invalid equipment change: {}", reason),
+                    "reason": reason
+                }))
+            }
+            GameError::InvalidPosition(msg) => {
+                HttpResponse::BadRequest().json(json!({
+                    "error": format!("Invalid position: {}", msg)
                 }))
             }
             GameError::InternalError(msg) => {
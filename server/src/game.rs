@@ -1,12 +1,246 @@
 /// Game state management module
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use log::info;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
 use uuid::Uuid;
 use crate::types::*;
 use crate::error::GameError;
-use rand::Rng;
+use crate::protocol::ServerMessage;
+use crate::snapshot::{EntitySnapshot, WorldSnapshot};
+use crate::interest::{InterestManager, VIEW_RADIUS};
+use crate::core::game::ai::{Act, Surroundings};
+use crate::characters::{check_name, Roster, MAX_CHARACTERS_PER_ACCOUNT};
+use crate::core::game::classes::{ClassBook, StartingItem};
+use crate::core::game::collision::{Collision, Stride, DEFAULT_TILE_SIZE};
+use crate::core::game::combat::{roll_attack, roll_spell, CombatStats};
+use crate::core::game::dungeon::{DungeonGenerator, DungeonLayout};
+use crate::core::game::fov::{line_of_sight, FieldOfView};
+use crate::core::game::inventory::EquipError;
+use crate::core::game::npcs::{Bestiary, Population};
+use crate::core::game::pathfinding::PathCache;
+use crate::core::game::progression::Progression;
+use crate::core::game::quests::QuestBook;
+use crate::core::game::skills::{Area, Effect, Skill, SkillBook, Targeting, HOTBAR_SLOTS};
+use crate::core::game::status::{Status, StatusEffects};
+use crate::sessions::SessionRegistry;
+use crate::wallet::{is_address, recover_signer};
+use crate::ws::WsGameSession;
+use actix::Addr;
 
+/// Distance a player covers per tick at the reference tick rate, in world
+/// units at the default tile size
 const PLAYER_SPEED: i32 = 5;
 
+/// Size in tiles of the shared dungeon
+const DUNGEON_SIZE: usize = 100;
+
+/// Size in tiles of a party instance's dungeon
+const INSTANCE_SIZE: usize = 60;
+
+/// How long an empty instance is kept for party members to reconnect
+const INSTANCE_GRACE_SECS: u64 = 60;
+
+/// Distance in tiles within which players can use doors
+const INTERACT_REACH: i32 = 1;
+
+/// Tick rate at which a player moves exactly `PLAYER_SPEED` units per tick
+const REFERENCE_TICK_RATE: u32 = 30;
+
+/// How long a dead NPC stays gone before its spawner brings it back
+const RESPAWN_SECS: u64 = 30;
+
+/// How long a dead player waits before coming back to life
+const PLAYER_RESPAWN_SECS: u64 = 5;
+
+/// Mana every player regains per second, before intelligence
+const MANA_REGEN_PER_SEC: i32 = 2;
+
+/// Intelligence per extra point of mana regained per second
+const INTELLIGENCE_PER_MANA_REGEN: i32 = 5;
+
+/// Mixed into the top floor's seed to seed attack rolls
+const COMBAT_SEED_SALT: u64 = 0xc0_ba7;
+
+/// How long a movement input keeps applying without being refreshed
+const INPUT_TIMEOUT_MS: u64 = 250;
+
+/// Latest movement input received from a player
+#[derive(Debug, Clone, Copy)]
+struct BufferedInput {
+    /// Held movement keys
+    movement: Movement,
+    /// Tick during which the input arrived
+    received_tick: u64,
+}
+
+/// Skill a player started casting that has yet to take effect
+#[derive(Debug, Clone)]
+struct Cast {
+    /// Name of the skill in the skill book
+    skill: String,
+    /// Entity the skill is aimed at, for skills aimed at entities
+    target: Option<Uuid>,
+    /// Position the skill is aimed at, for skills aimed at the ground
+    position: Option<Position>,
+    /// Tick at which the skill takes effect
+    finishes: u64,
+}
+
+/// One floor of the dungeon stack together with the players' view of it
+struct Floor {
+    /// Layout of the floor
+    dungeon: Dungeon,
+    /// Which entities each player on the floor can see
+    interest: InterestManager,
+    /// Tiles each player on the floor can currently see
+    sight: HashMap<Uuid, FieldOfView>,
+    /// Tiles each player has seen on the floor, as they last saw them; kept
+    /// after they leave so a character's map survives reconnects
+    explored: HashMap<Uuid, Vec<Option<TileType>>>,
+    /// Paths recently found on the floor
+    paths: PathCache,
+    /// Monsters and NPCs on the floor and their spawners
+    population: Population,
+    /// Items lying on the floor and the tiles they lie on, keyed by item ID
+    ground: HashMap<Uuid, ((i32, i32), Item)>,
+}
+
+impl Floor {
+    fn new(dungeon: Dungeon, bestiary: &Arc<Bestiary>) -> Self {
+        let mut floor = Self {
+            population: Population::new(&dungeon, bestiary),
+            dungeon,
+            interest: InterestManager::new(VIEW_RADIUS),
+            sight: HashMap::new(),
+            explored: HashMap::new(),
+            paths: PathCache::new(),
+            ground: HashMap::new(),
+        };
+        floor.drop_keys();
+        floor
+    }
+
+    /// Leaves the keys of the floor's locked doors on the tiles the layout
+    /// put them on
+    fn drop_keys(&mut self) {
+        for lock in &self.dungeon.locks {
+            self.ground.insert(lock.key, (lock.key_at, lock.key_item()));
+        }
+    }
+
+    /// Replaces the floor's layout, dropping everything tied to the old one
+    ///
+    /// Players stay on the floor but forget what they saw of it; NPCs and
+    /// items on the ground are removed, the new layout's keys are dropped
+    /// and its spawners populate it on the next tick.
+    fn replace(&mut self, dungeon: Dungeon, bestiary: &Arc<Bestiary>) {
+        self.dungeon = dungeon;
+        self.repopulate(bestiary);
+        self.paths.invalidate();
+        self.sight.clear();
+        self.explored.clear();
+        self.ground.clear();
+        self.drop_keys();
+    }
+
+    /// Removes every NPC and starts populating the floor from a bestiary
+    fn repopulate(&mut self, bestiary: &Arc<Bestiary>) {
+        for id in self.population.npcs.keys() {
+            self.interest.remove(*id);
+        }
+        self.population = Population::new(&self.dungeon, bestiary);
+    }
+
+    /// Spawns the NPCs due this tick and starts tracking them
+    fn advance(&mut self, collision: &Collision, tick: u64, respawn_ticks: u64) {
+        for id in self.population.advance(&self.dungeon, collision, tick, respawn_ticks) {
+            if let Some(npc) = self.population.npcs.get(&id) {
+                self.interest.update_position(id, npc.position);
+            }
+        }
+    }
+
+    /// Runs the AI of every NPC on the floor for one tick
+    ///
+    /// # Arguments
+    /// * `collision` - Tile geometry used for movement
+    /// * `players` - Players on the floor and their positions, sorted by ID
+    /// * `tick` - Current tick
+    /// * `tick_rate` - Simulation ticks per second
+    /// * `speed` - Distance a player covers in one second
+    /// * `paces` - Pace of the NPCs under haste, slow or stun, in percent
+    ///
+    /// # Returns
+    /// What the NPCs did beyond moving, which is already tracked
+    fn think(&mut self, collision: &Collision, players: &[(Uuid, Position)], tick: u64, tick_rate: u32, speed: i32, paces: &HashMap<Uuid, i32>) -> Vec<Act> {
+        let Floor { dungeon, interest, paths, population, .. } = self;
+        let world = Surroundings { dungeon, collision, players, tick, tick_rate, speed, paces };
+        let mut acts = population.think(&world, paths);
+        acts.retain(|act| match act {
+            Act::Moved(id) => {
+                if let Some(npc) = population.npcs.get(id) {
+                    interest.update_position(*id, npc.position);
+                }
+                false
+            }
+            _ => true,
+        });
+        acts
+    }
+
+    /// Checks whether a player on the floor can currently see a tile
+    fn sees(&self, id: Uuid, tile: (i32, i32)) -> bool {
+        self.sight.get(&id).is_some_and(|view| view.sees(tile))
+    }
+
+    /// Builds a player's map of the floor from what they have seen
+    fn map_for(&self, id: Uuid) -> DungeonView {
+        let dungeon = &self.dungeon;
+        DungeonView {
+            width: dungeon.width,
+            height: dungeon.height,
+            depth: dungeon.depth,
+            tiles: self.explored.get(&id)
+                .cloned()
+                .unwrap_or_else(|| vec![None; dungeon.tiles.len()]),
+        }
+    }
+}
+
+/// Private dungeon spun up for a party
+struct Instance {
+    /// The instance's single floor
+    floor: Floor,
+    /// IDs of the party members allowed back in after reconnecting
+    members: HashSet<Uuid>,
+    /// Players currently inside
+    players: HashSet<Uuid>,
+    /// Tick at which the instance was created
+    created_tick: u64,
+    /// Tick at which the last player left, or None while players are inside
+    empty_since: Option<u64>,
+}
+
+/// Where in the game world a player is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Location {
+    /// Party instance, or None for the shared dungeon
+    instance: Option<Uuid>,
+    /// Depth of the floor, always 0 inside an instance
+    depth: u32,
+}
+
+impl Location {
+    /// Top floor of the shared dungeon
+    const TOP: Location = Location { instance: None, depth: 0 };
+
+    fn of(player: &Player) -> Self {
+        Self { instance: player.instance, depth: player.floor }
+    }
+}
+
 /// Central game state manager
 /// 
 /// Manages all game state including players, dungeon layout, and game world.
@@ -14,25 +248,181 @@ const PLAYER_SPEED: i32 = 5;
 pub struct GameState {
     /// Map of player IDs to their corresponding player data
     players: HashMap<Uuid, Player>,
-    /// Current dungeon layout and configuration
-    dungeon: Dungeon,
-    subscribers: Vec<Box<dyn Fn(&str) + Send>>,
+    /// Floors generated so far, indexed by depth; the top floor always exists
+    floors: Vec<Floor>,
+    /// Seed of the top floor, from which the seeds of deeper floors are derived
+    seed: u64,
+    /// Live party instances, keyed by instance ID
+    instances: HashMap<Uuid, Instance>,
+    /// Generator used to build and rebuild the dungeon
+    dungeon_generator: DungeonGenerator,
+    /// Websocket sessions of joined players, keyed by player ID
+    sessions: SessionRegistry,
+    /// Number of simulation ticks processed so far
+    tick: u64,
+    /// Simulation ticks per second
+    tick_rate: u32,
+    /// Latest movement input of each player, applied once per tick
+    inputs: HashMap<Uuid, BufferedInput>,
+    /// Tiles left to walk through by players moving to a destination
+    routes: HashMap<Uuid, VecDeque<(i32, i32)>>,
+    /// Distance each moving player carries over between ticks
+    strides: HashMap<Uuid, Stride>,
+    /// Player each player waiting on a portal waits for to take them along
+    portal_waits: HashMap<Uuid, Uuid>,
+    /// Tile size and player bounding box used for movement
+    collision: Collision,
+    /// Kinds of NPC populating the floors
+    bestiary: Arc<Bestiary>,
+    /// Tick from which each player who attacked can attack again
+    cooldowns: HashMap<Uuid, u64>,
+    /// Tick at which each dead player comes back to life
+    dead: HashMap<Uuid, u64>,
+    /// Skills players can cast
+    skills: SkillBook,
+    /// Tick from which a player can cast a skill again, keyed by player and skill name
+    skill_cooldowns: HashMap<(Uuid, String), u64>,
+    /// Skills being cast, keyed by caster
+    casts: HashMap<Uuid, Cast>,
+    /// Status effects on players and NPCs, keyed by entity ID
+    effects: HashMap<Uuid, StatusEffects>,
+    /// Experience table and growth rates of players
+    progression: Progression,
+    /// Classes characters can be created with
+    classes: ClassBook,
+    /// Quests players complete for experience
+    quests: QuestBook,
+    /// Characters created so far, in or out of the game
+    roster: Roster,
+    /// Source of attack and spell rolls
+    rng: ChaCha8Rng,
 }
 
 impl GameState {
     /// Creates a new game state with an empty player list and generated dungeon
-    /// 
+    ///
+    /// # Arguments
+    /// * `dungeon_generator` - Generator of the dungeon layout
+    /// * `seed` - Seed of the dungeon layout
+    ///
     /// # Returns
-    /// A new GameState instance with a 100x100 procedurally generated dungeon
-    pub fn new() -> Self {
+    /// A new GameState instance whose top floor is a 100x100 procedurally
+    /// generated dungeon; deeper floors are generated as players reach them
+    pub fn new(dungeon_generator: DungeonGenerator, seed: u64) -> Self {
+        let dungeon = dungeon_generator.generate_floor(DUNGEON_SIZE, DUNGEON_SIZE, seed, 0);
+        Self { seed, ..Self::with_dungeon(dungeon_generator, dungeon) }
+    }
+
+    /// Creates a new game state around an existing dungeon
+    ///
+    /// # Arguments
+    /// * `dungeon_generator` - Generator used when the dungeon is regenerated
+    /// * `dungeon` - Layout of the top floor
+    pub fn with_dungeon(dungeon_generator: DungeonGenerator, dungeon: Dungeon) -> Self {
+        let bestiary = Arc::new(Bestiary::default());
+        let rng = ChaCha8Rng::seed_from_u64(dungeon.seed ^ COMBAT_SEED_SALT);
         Self {
             players: HashMap::new(),
-            dungeon: Dungeon::new(100, 100), // Create a 100x100 dungeon
-            subscribers: Vec::new(),
+            seed: dungeon.seed,
+            floors: vec![Floor::new(dungeon, &bestiary)],
+            instances: HashMap::new(),
+            dungeon_generator,
+            sessions: SessionRegistry::new(),
+            tick: 0,
+            tick_rate: REFERENCE_TICK_RATE,
+            inputs: HashMap::new(),
+            routes: HashMap::new(),
+            strides: HashMap::new(),
+            portal_waits: HashMap::new(),
+            collision: Collision::default(),
+            bestiary,
+            cooldowns: HashMap::new(),
+            dead: HashMap::new(),
+            skills: SkillBook::default(),
+            skill_cooldowns: HashMap::new(),
+            casts: HashMap::new(),
+            effects: HashMap::new(),
+            progression: Progression::default(),
+            classes: ClassBook::default(),
+            quests: QuestBook::default(),
+            roster: Roster::new(),
+            rng,
+        }
+    }
+
+    /// Sets the number of simulation ticks per second
+    ///
+    /// Movement speed is scaled so players cover the same distance per
+    /// second regardless of the tick rate.
+    pub fn set_tick_rate(&mut self, tick_rate: u32) {
+        self.tick_rate = tick_rate.max(1);
+    }
+
+    /// Sets the side length of a tile in world units
+    ///
+    /// Must be called before players join, as positions are not rescaled.
+    /// Movement speed and player size scale with the tile size.
+    pub fn set_tile_size(&mut self, tile_size: i32) {
+        self.collision = Collision::new(tile_size);
+    }
+
+    /// Sets the kinds of NPC populating the floors
+    ///
+    /// NPCs already spawned are removed; the floors are populated from the
+    /// new bestiary on the next tick.
+    pub fn set_bestiary(&mut self, bestiary: Bestiary) {
+        self.bestiary = Arc::new(bestiary);
+        let instances = self.instances.values_mut().map(|instance| &mut instance.floor);
+        for floor in self.floors.iter_mut().chain(instances) {
+            floor.repopulate(&self.bestiary);
+        }
+    }
+
+    /// Sets the skills players can cast
+    ///
+    /// Players who already joined keep their hotbars; skills that no longer
+    /// exist in the new skill book cannot be cast.
+    pub fn set_skills(&mut self, skills: SkillBook) {
+        self.skills = skills;
+    }
+
+    /// Sets the classes characters can be created with
+    ///
+    /// Characters created before keep their attributes, items and hotbar.
+    pub fn set_classes(&mut self, classes: ClassBook) {
+        self.classes = classes;
+    }
+
+    /// Gets the classes characters can be created with
+    pub fn classes(&self) -> &ClassBook {
+        &self.classes
+    }
+
+    /// Sets the quests players complete for experience
+    ///
+    /// Players keep their progress on quests that still exist in the new
+    /// quest book.
+    pub fn set_quests(&mut self, quests: QuestBook) {
+        self.quests = quests;
+    }
+
+    /// Sets the experience table and growth rates of players
+    ///
+    /// The maximum health, mana and experience of players who already
+    /// joined are recalculated; their levels are kept.
+    pub fn set_progression(&mut self, progression: Progression) {
+        self.progression = progression;
+        for player in self.players.values_mut() {
+            self.progression.recalculate(player);
         }
     }
 
-    /// Adds a new player to the game
+    /// Adds a new guest player, without a class, to the top floor of the game
+    ///
+    /// Guest names follow the same rules as character names and may not be
+    /// taken by a character or a player in the game. A player whose ID
+    /// belongs to the party of a live instance, e.g. after reconnecting, is
+    /// put back into that instance instead.
     /// 
     /// # Arguments
     /// * `id` - UUID of the player
@@ -40,23 +430,270 @@ impl GameState {
     /// 
     /// # Returns
     /// The newly created Player instance
-    pub fn add_player(&mut self, id: Uuid, name: String) -> Player {
-        // Start players in the center of the dungeon
-        let position = Position::new(
-            (self.dungeon.width / 2) as i32,
-            (self.dungeon.height / 2) as i32,
-        );
-        let player = Player::new(id, name, position);
+    pub fn add_player(&mut self, id: Uuid, name: String) -> Result<Player, GameError> {
+        self.check_available(&name)?;
+        let mut player = Player::new(id, name, self.spawn_position());
+        player.hotbar = self.skills.starting_hotbar();
+        self.progression.recalculate(&mut player);
+        player.health = player.max_health;
+        player.mana = player.mana_capacity();
+        Ok(self.enter(player))
+    }
+
+    /// Checks whether a new character or guest may go by a name
+    ///
+    /// # Returns
+    /// `GameError::InvalidName` when the name breaks the naming rules or
+    /// belongs to a character or a player in the game
+    fn check_available(&self, name: &str) -> Result<(), GameError> {
+        check_name(name).map_err(GameError::InvalidName)?;
+        if self.roster.is_taken(name) || self.players.values().any(|player| player.name.eq_ignore_ascii_case(name)) {
+            return Err(GameError::InvalidName("This name is taken".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Issues the challenge a wallet must sign to register its account
+    ///
+    /// # Arguments
+    /// * `account` - Wallet address of the account
+    ///
+    /// # Returns
+    /// The message to sign; only the latest challenge of an account can be
+    /// answered, and only once
+    pub fn account_challenge(&mut self, account: &str) -> Result<String, GameError> {
+        if !is_address(account) {
+            return Err(GameError::InvalidAction("Accounts are wallet addresses".to_string()));
+        }
+        Ok(self.roster.challenge(account))
+    }
+
+    /// Registers an account characters can be created for, once its wallet
+    /// signed the account's challenge
+    ///
+    /// Registering again gives the account a new key; the old one stops working.
+    ///
+    /// # Arguments
+    /// * `account` - Wallet address of the account
+    /// * `signature` - Signature of the challenge by the wallet, as hex
+    ///
+    /// # Returns
+    /// The secret key requests acting for the account must present
+    pub fn register_account(&mut self, account: &str, signature: &str) -> Result<Uuid, GameError> {
+        let challenge = self.roster.take_challenge(account)
+            .ok_or_else(|| GameError::InvalidAction("Request a challenge to sign first".to_string()))?;
+        let signer = recover_signer(&challenge, signature).map_err(GameError::Unauthorized)?;
+        if !signer.eq_ignore_ascii_case(account) {
+            return Err(GameError::Unauthorized("The challenge was not signed by this wallet".to_string()));
+        }
+        Ok(self.roster.register(account))
+    }
+
+    /// Checks that a request acting for an account presents its key
+    ///
+    /// # Arguments
+    /// * `account` - Wallet address of the account
+    /// * `key` - Key presented with the request
+    pub fn authenticate(&self, account: &str, key: Uuid) -> Result<(), GameError> {
+        if !self.roster.is_key_of(account, key) {
+            return Err(GameError::Unauthorized("Wrong key for this account".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Creates a character for an account, ready to join the game
+    ///
+    /// # Arguments
+    /// * `account` - Wallet address of the account the character belongs to
+    /// * `name` - Display name, unique among characters and players in the game
+    /// * `class` - Name of the class in the class book
+    ///
+    /// # Returns
+    /// The new character, with the attributes, inventory and hotbar of its class
+    pub fn create_character(&mut self, account: &str, name: &str, class: &str) -> Result<Player, GameError> {
+        let template = self.classes.classes.get(class)
+            .ok_or_else(|| GameError::InvalidAction(format!("Unknown class {}", class)))?;
+        self.check_available(name)?;
+        if account.trim().is_empty() {
+            return Err(GameError::InvalidAction("Characters must belong to an account".to_string()));
+        }
+        if self.roster.of_account(account).len() >= MAX_CHARACTERS_PER_ACCOUNT {
+            return Err(GameError::InvalidAction(format!("Accounts have at most {} characters", MAX_CHARACTERS_PER_ACCOUNT)));
+        }
+
+        let mut character = Player::new(Uuid::new_v4(), name.to_string(), self.spawn_position());
+        character.class = Some(class.to_string());
+        character.attributes = template.attributes;
+        for item in template.equipment.iter().map(StartingItem::create) {
+            character.inventory.add_item(item);
+        }
+        character.hotbar = template.starting_hotbar(&self.skills);
+        character.wallet_address = account.to_string();
+        self.progression.recalculate(&mut character);
+        character.health = character.max_health;
+        character.mana = character.mana_capacity();
+        self.roster.save(character.clone());
+        Ok(character)
+    }
+
+    /// Lists the characters of an account, as they are in the game or were
+    /// when they last left it
+    pub fn characters(&self, account: &str) -> Vec<&Player> {
+        self.roster.of_account(account)
+            .into_iter()
+            .filter_map(|id| self.players.get(&id).or(self.roster.get(id)))
+            .collect()
+    }
+
+    /// Brings a created character into the game
+    ///
+    /// The character keeps their level, attributes, items and hotbar from
+    /// the last time they played; a character who left while dead comes
+    /// back to life.
+    ///
+    /// # Arguments
+    /// * `id` - UUID of the character, which becomes their player ID
+    /// * `account` - Wallet address of the account joining, which must own the character
+    ///
+    /// # Returns
+    /// The character as they entered the game
+    pub fn join_character(&mut self, id: Uuid, account: &str) -> Result<Player, GameError> {
+        let mut character = self.roster.get(id).cloned().ok_or(GameError::CharacterNotFound)?;
+        if character.wallet_address != account {
+            return Err(GameError::Unauthorized("This character belongs to another account".to_string()));
+        }
+        if self.players.contains_key(&id) {
+            return Err(GameError::InvalidAction("This character is already in the game".to_string()));
+        }
+        self.progression.recalculate(&mut character);
+        if character.health <= 0 {
+            character.health = character.max_health;
+            character.mana = character.mana_capacity();
+        }
+        character.position = self.spawn_position();
+        character.floor = 0;
+        character.instance = None;
+        Ok(self.enter(character))
+    }
+
+    /// Puts a player standing on the spawn of the top floor into the game,
+    /// or back into the live instance of their party
+    fn enter(&mut self, player: Player) -> Player {
+        let (id, position) = (player.id, player.position);
+        let rejoin = self.instances.iter()
+            .find(|(_, instance)| instance.members.contains(&id))
+            .map(|(instance_id, instance)| (*instance_id, self.spawn_position_in(&instance.floor.dungeon)));
+
         self.players.insert(id, player.clone());
-        player
+        self.floors[0].interest.update_position(id, position);
+        self.floors[0].interest.add_observer(id);
+
+        match rejoin {
+            Some((instance, position)) => {
+                self.move_to(id, Location { instance: Some(instance), depth: 0 }, position);
+                self.players[&id].clone()
+            }
+            None => player,
+        }
+    }
+
+    /// Finds where new players start
+    ///
+    /// # Returns
+    /// The spawn tile of the top floor, or its center if it has none
+    fn spawn_position(&self) -> Position {
+        self.spawn_position_in(&self.floors[0].dungeon)
+    }
+
+    /// Finds where players start in a dungeon
+    ///
+    /// # Returns
+    /// The center of the dungeon's spawn tile, or of the dungeon if it has none
+    fn spawn_position_in(&self, dungeon: &Dungeon) -> Position {
+        let (tile_x, tile_y) = dungeon.spawn_tile()
+            .unwrap_or(((dungeon.width / 2) as i32, (dungeon.height / 2) as i32));
+        self.collision.tile_center(tile_x, tile_y)
+    }
+
+    /// Gets a floor of the shared dungeon or of an instance
+    fn floor(&self, at: Location) -> Option<&Floor> {
+        match at.instance {
+            Some(instance) => self.instances.get(&instance).map(|instance| &instance.floor),
+            None => self.floors.get(at.depth as usize),
+        }
+    }
+
+    /// Gets a floor of the shared dungeon or of an instance for modification
+    fn floor_mut(&mut self, at: Location) -> Option<&mut Floor> {
+        match at.instance {
+            Some(instance) => self.instances.get_mut(&instance).map(|instance| &mut instance.floor),
+            None => self.floors.get_mut(at.depth as usize),
+        }
+    }
+
+    /// Finds the players on the same floor who can see a player and marks it
+    /// visible to them, going by what they could see at the last tick
+    ///
+    /// Used to announce a player who just joined; the players found will not
+    /// get an `EntityEntered` event for them on the next tick.
+    ///
+    /// # Arguments
+    /// * `id` - UUID of the player to reveal
+    ///
+    /// # Returns
+    /// IDs of the players that can see them
+    pub fn reveal_player(&mut self, id: Uuid) -> Vec<Uuid> {
+        let Some(at) = self.players.get(&id).map(Location::of) else {
+            return Vec::new();
+        };
+        let tile = self.collision.tile_of(self.players[&id].position);
+        let Some(floor) = self.floor_mut(at) else {
+            return Vec::new();
+        };
+        let observers: Vec<Uuid> = floor.interest.observers_of(id)
+            .into_iter()
+            .filter(|observer| floor.sees(*observer, tile))
+            .collect();
+        for observer in &observers {
+            floor.interest.mark_visible(*observer, id);
+        }
+        observers
     }
 
     /// Removes a player from the game
+    ///
+    /// An instance left empty is kept for `INSTANCE_GRACE_SECS` so the party
+    /// can reconnect.
     /// 
     /// # Arguments
     /// * `id` - UUID of the player to remove
-    pub fn remove_player(&mut self, id: Uuid) {
-        self.players.remove(&id);
+    ///
+    /// # Returns
+    /// IDs of the players that could see them
+    pub fn remove_player(&mut self, id: Uuid) -> Vec<Uuid> {
+        self.inputs.remove(&id);
+        self.routes.remove(&id);
+        self.strides.remove(&id);
+        self.portal_waits.retain(|waiting, leader| *waiting != id && *leader != id);
+        self.cooldowns.remove(&id);
+        self.dead.remove(&id);
+        self.casts.remove(&id);
+        self.effects.remove(&id);
+        self.skill_cooldowns.retain(|(caster, _), _| *caster != id);
+        let Some(player) = self.players.remove(&id) else {
+            return Vec::new();
+        };
+        let at = Location::of(&player);
+        if self.roster.get(id).is_some() {
+            self.roster.save(player);
+        }
+        self.vacate_instance(at.instance, id);
+        self.floor_mut(at)
+            .map(|floor| {
+                floor.sight.remove(&id);
+                floor.interest.remove(id)
+            })
+            .unwrap_or_default()
     }
 
     /// Retrieves a player by their ID
@@ -70,223 +707,2623 @@ impl GameState {
         self.players.get(&id)
     }
 
-    /// Gets a list of all players currently in the game
-    /// 
-    /// # Returns
-    /// Vector of references to all players
-    pub fn get_players(&self) -> Vec<&Player> {
-        self.players.values().collect()
+    /// Buffers a player's movement input for the next ticks
+    ///
+    /// Only the latest input is kept; it is applied once per tick until it
+    /// is replaced or goes `INPUT_TIMEOUT_MS` without being refreshed.
+    /// Holding any key stops a walk to a destination and interrupts a cast.
+    ///
+    /// # Arguments
+    /// * `id` - UUID of the player to move
+    /// * `movement` - Held movement keys
+    pub fn queue_movement(&mut self, id: Uuid, movement: Movement) {
+        if self.players.contains_key(&id) {
+            if movement.up || movement.down || movement.left || movement.right {
+                self.routes.remove(&id);
+                self.interrupt_cast(id);
+            }
+            self.inputs.insert(id, BufferedInput { movement, received_tick: self.tick });
+        }
     }
 
-    /// Updates a player's position if the new position is valid
-    /// 
+    /// Starts walking a player to a position on their floor
+    ///
+    /// The path is found once and then walked over the following ticks at
+    /// the player's normal speed; it is abandoned when the player presses a
+    /// movement key, changes floor, or runs into something in the way.
+    /// Walking off interrupts a cast.
+    ///
     /// # Arguments
     /// * `id` - UUID of the player to move
-    /// * `movement` - Movement direction and magnitude
-    /// 
+    /// * `target` - Destination in world space
+    ///
     /// # Returns
-    /// Result indicating success or failure with error details
-    pub fn update_player_movement(&mut self, id: Uuid, movement: Movement) {
-        if let Some(player) = self.players.get_mut(&id) {
-            let mut new_pos = player.position;
+    /// Centers of the tiles the player will walk through, ending with the
+    /// destination's tile
+    pub fn move_player_to(&mut self, id: Uuid, target: Position) -> Result<Vec<Position>, GameError> {
+        let player = self.players.get(&id).ok_or(GameError::PlayerNotFound)?;
+        if self.dead.contains_key(&id) {
+            return Err(GameError::InvalidAction("You are dead".to_string()));
+        }
+        let at = Location::of(player);
+        let start = self.collision.tile_of(player.position);
+        let goal = self.collision.tile_of(target);
+
+        let floor = self.floor_mut(at).ok_or(GameError::PlayerNotFound)?;
+        let path = floor.paths.find(&floor.dungeon, start, goal)
+            .ok_or_else(|| GameError::InvalidMovement("No path to target".to_string()))?;
+
+        if path.is_empty() {
+            self.routes.remove(&id);
+        } else {
+            self.routes.insert(id, path.iter().copied().collect());
+            self.interrupt_cast(id);
+        }
+        Ok(path.iter().map(|&(x, y)| self.collision.tile_center(x, y)).collect())
+    }
+
+    /// Advances the simulation by one tick
+    ///
+    /// Brings back players whose respawn delay is over, applies every living
+    /// player's buffered input once, lets casts whose time is up take
+    /// effect, applies status effects and wears off expired ones,
+    /// regenerates mana once a second, spawns NPCs and runs their AI, and tears down instances that
+    /// stayed empty for longer than the grace period.
+    ///
+    /// # Returns
+    /// Snapshot of the world at the end of the tick
+    pub fn advance_tick(&mut self) -> WorldSnapshot {
+        self.tick += 1;
+
+        let ttl = (INPUT_TIMEOUT_MS * self.tick_rate as u64).div_ceil(1000).max(1);
+        let tick = self.tick;
+        self.inputs.retain(|_, input| tick - input.received_tick <= ttl);
+
+        self.respawn_players();
+        let inputs: Vec<(Uuid, Movement)> = self.inputs.iter()
+            .filter(|(id, _)| !self.dead.contains_key(id))
+            .map(|(id, input)| (*id, input.movement))
+            .collect();
+        for (id, movement) in inputs {
+            self.apply_movement(id, movement);
+        }
+        self.finish_casts();
+        self.pulse_effects();
+        if tick.is_multiple_of(self.tick_rate as u64) {
+            self.regenerate_mana();
+        }
+
+        let respawn_ticks = RESPAWN_SECS * self.tick_rate as u64;
+        let collision = self.collision;
+        let instances = self.instances.values_mut().map(|instance| &mut instance.floor);
+        for floor in self.floors.iter_mut().chain(instances) {
+            floor.advance(&collision, tick, respawn_ticks);
+        }
+        self.run_npcs();
+        let walking: Vec<Uuid> = self.routes.keys().copied().collect();
+        for id in walking {
+            self.follow_route(id);
+        }
 
-            // Calculate new position based on movement
-            if movement.up {
-                new_pos.y -= PLAYER_SPEED;
+        let grace = INSTANCE_GRACE_SECS * self.tick_rate as u64;
+        self.instances.retain(|id, instance| {
+            let expired = instance.empty_since.is_some_and(|since| tick - since >= grace);
+            if expired {
+                info!("Tearing down empty instance {}", id);
             }
-            if movement.down {
-                new_pos.y += PLAYER_SPEED;
+            !expired
+        });
+
+        self.snapshot()
+    }
+
+    /// Runs the AI of the NPCs on every floor and instance for one tick
+    ///
+    /// NPCs open the closed doors they walk up to and attack the living
+    /// players they are after, at the pace their status effects allow.
+    fn run_npcs(&mut self) {
+        let mut players: Vec<(Location, Uuid, Position)> = self.players.values()
+            .filter(|player| !self.dead.contains_key(&player.id))
+            .map(|player| (Location::of(player), player.id, player.position))
+            .collect();
+        players.sort_by_key(|(_, id, _)| *id);
+        let mut locations: Vec<Location> = (0..self.floors.len() as u32)
+            .map(|depth| Location { instance: None, depth })
+            .collect();
+        locations.extend(self.instances.keys().map(|id| Location { instance: Some(*id), depth: 0 }));
+
+        let (collision, tick, tick_rate, speed) = (self.collision, self.tick, self.tick_rate, self.speed());
+        let paces: HashMap<Uuid, i32> = self.effects.iter()
+            .map(|(id, effects)| (*id, effects.pace_percent()))
+            .collect();
+        for at in locations {
+            let nearby: Vec<(Uuid, Position)> = players.iter()
+                .filter(|(location, _, _)| *location == at)
+                .map(|(_, id, position)| (*id, *position))
+                .collect();
+            let Some(floor) = self.floor_mut(at) else {
+                continue;
+            };
+            for act in floor.think(&collision, &nearby, tick, tick_rate, speed, &paces) {
+                match act {
+                    Act::OpenDoor(x, y) => {
+                        if self.tile_under(at, collision.tile_center(x, y)) == TileType::Door {
+                            self.change_door(at, x, y, DoorState::Open, None);
+                        }
+                    }
+                    Act::Attack { npc, target } => self.npc_attack(at, npc, target),
+                    Act::Moved(_) => {}
+                }
+            }
+        }
+    }
+
+    /// Sends every player their view of a tick's snapshot
+    ///
+    /// Players are first told about tiles that came into their field of
+    /// view, then about entities that entered or left their area of
+    /// interest, and finally receive the snapshot restricted to it. Only
+    /// entities in sight on the same floor of the same dungeon or instance
+    /// are ever in a player's area of interest.
+    ///
+    /// # Arguments
+    /// * `snapshot` - Snapshot returned by `advance_tick`
+    pub fn publish_snapshot(&mut self, snapshot: Arc<WorldSnapshot>) {
+        let ids: Vec<Uuid> = self.players.keys().copied().collect();
+        for id in ids {
+            let (tiles, items) = self.update_sight(id);
+            if !tiles.is_empty() {
+                self.sessions.send_to(id, &ServerMessage::TilesRevealed { tiles });
             }
-            if movement.left {
-                new_pos.x -= PLAYER_SPEED;
+            for item in items {
+                self.sessions.send_to(id, &item);
             }
-            if movement.right {
-                new_pos.x += PLAYER_SPEED;
+        }
+
+        let collision = self.collision;
+        let instances = self.instances.values_mut().map(|instance| &mut instance.floor);
+        for floor in self.floors.iter_mut().chain(instances) {
+            let Floor { interest, sight, population, .. } = floor;
+            let in_sight = |observer: Uuid, position: Position| {
+                sight.get(&observer).is_some_and(|view| view.sees(collision.tile_of(position)))
+            };
+            let describe = |id: Uuid| match (self.players.get(&id), population.npcs.get(&id)) {
+                (Some(player), _) => Some((player.name.clone(), EntityKind::Player)),
+                (None, Some(npc)) if npc.hostile => Some((npc.name.clone(), EntityKind::Monster)),
+                (None, Some(npc)) => Some((npc.name.clone(), EntityKind::Npc)),
+                (None, None) => None,
+            };
+            for change in interest.refresh(in_sight) {
+                for id in change.entered {
+                    if let Some((name, kind)) = describe(id) {
+                        let entered = ServerMessage::EntityEntered { id, name, kind };
+                        self.sessions.send_to(change.observer, &entered);
+                    }
+                }
+                for id in change.left {
+                    self.sessions.send_to(change.observer, &ServerMessage::EntityLeft { id });
+                }
             }
+        }
 
-            // Validate new position
-            if self.is_valid_position(new_pos) {
-                player.position = new_pos;
-                self.broadcast_message(&format!("Player {} moved to ({}, {})", 
-                    player.name, new_pos.x, new_pos.y));
+        let empty = Default::default();
+        for (id, player) in &self.players {
+            let visible = self.floor(Location::of(player))
+                .and_then(|floor| floor.interest.visible_to(*id))
+                .unwrap_or(&empty);
+            self.sessions.send_snapshot(*id, Arc::new(snapshot.view(*id, visible)));
+        }
+    }
+
+    /// Captures the networked state of the world at the current tick,
+    /// including the status effects on every entity
+    pub fn snapshot(&self) -> WorldSnapshot {
+        let instances = self.instances.values().map(|instance| &instance.floor);
+        let npcs = self.floors.iter()
+            .chain(instances)
+            .flat_map(|floor| floor.population.npcs.values())
+            .map(EntitySnapshot::from);
+        let mut snapshot = WorldSnapshot::capture(self.tick, self.players.values().map(EntitySnapshot::from).chain(npcs));
+        for (id, effects) in &self.effects {
+            if let Some(entity) = snapshot.entities.get_mut(id) {
+                entity.effects = effects.icons();
             }
         }
+        snapshot
     }
 
-    /// Checks if a position is valid within the dungeon
-    /// 
-    /// # Arguments
-    /// * `pos` - Reference to the position to check
-    /// 
+    /// Captures the state of the world a player can see at the current tick
+    ///
     /// # Returns
-    /// Boolean indicating whether the position is valid (walkable tile)
-    fn is_valid_position(&self, pos: Position) -> bool {
-        // Convert position to tile coordinates
-        let tile_x = (pos.x / PLAYER_SPEED) as usize;
-        let tile_y = (pos.y / PLAYER_SPEED) as usize;
+    /// The player and the entities in their area of interest, or None when
+    /// the player does not exist
+    pub fn snapshot_for(&self, id: Uuid) -> Option<WorldSnapshot> {
+        let player = self.players.get(&id)?;
+        let empty = Default::default();
+        let visible = self.floor(Location::of(player))
+            .and_then(|floor| floor.interest.visible_to(id))
+            .unwrap_or(&empty);
+        Some(self.snapshot().view(id, visible))
+    }
 
-        // Check if the position is within bounds and not a wall
-        match self.dungeon.get_tile(tile_x, tile_y) {
-            TileType::Wall => false,
-            _ => true
+    /// Recomputes a player's field of view if they moved to another tile or
+    /// their floor changed, and records what they saw
+    ///
+    /// # Returns
+    /// Tiles the player sees for the first time or that changed since they
+    /// last saw them, row by row, and an `ItemDropped` for every item on the
+    /// ground that came into view
+    fn update_sight(&mut self, id: Uuid) -> (Vec<RevealedTile>, Vec<ServerMessage>) {
+        let Some(player) = self.players.get(&id) else {
+            return (Vec::new(), Vec::new());
+        };
+        let at = Location::of(player);
+        let origin = self.collision.tile_of(player.position);
+        let radius = self.sight_radius();
+        let Some(floor) = self.floor_mut(at) else {
+            return (Vec::new(), Vec::new());
+        };
+        if floor.sight.get(&id).is_some_and(|view| !view.is_stale(&floor.dungeon, origin)) {
+            return (Vec::new(), Vec::new());
         }
+
+        let view = FieldOfView::compute(&floor.dungeon, origin, radius);
+        let dungeon = &floor.dungeon;
+        let memory = floor.explored.entry(id).or_insert_with(|| vec![None; dungeon.tiles.len()]);
+        let mut revealed: Vec<RevealedTile> = view.visible.iter()
+            .filter_map(|&(x, y)| {
+                let index = y as usize * dungeon.width + x as usize;
+                let tile = dungeon.tiles[index];
+                (memory[index] != Some(tile)).then(|| {
+                    memory[index] = Some(tile);
+                    RevealedTile { x, y, tile }
+                })
+            })
+            .collect();
+        revealed.sort_by_key(|tile| (tile.y, tile.x));
+        let previous = floor.sight.get(&id);
+        let items = floor.ground.iter()
+            .filter(|(_, (tile, _))| view.sees(*tile) && !previous.is_some_and(|seen| seen.sees(*tile)))
+            .map(|(item_id, ((x, y), item))| ServerMessage::ItemDropped { id: *item_id, name: item.name.clone(), x: *x, y: *y })
+            .collect();
+        floor.sight.insert(id, view);
+        (revealed, items)
     }
 
-    /// Gets a reference to the current dungeon
-    /// 
-    /// # Returns
-    /// Reference to the current dungeon layout
-    pub fn get_dungeon(&self) -> &Dungeon {
-        &self.dungeon
+    /// Distance in tiles players see, matching their area of interest
+    fn sight_radius(&self) -> i32 {
+        VIEW_RADIUS / self.collision.tile_size
     }
 
-    /// Generates a new dungeon with rooms and corridors
+    /// Moves a player one tick's worth of distance, sliding along walls
     /// 
     /// # Arguments
-    /// * `width` - Width of the dungeon in tiles
-    /// * `height` - Height of the dungeon in tiles
+    /// * `id` - UUID of the player to move
+    /// * `movement` - Movement direction
     /// 
     /// # Returns
-    /// A new Dungeon instance with randomly generated rooms and connecting corridors
-    fn generate_dungeon(width: i32, height: i32) -> Dungeon {
-        let mut rng = rand::thread_rng();
-        let mut tiles = vec![vec![TileType::Wall; width as usize]; height as usize];
-        let mut rooms = Vec::new();
-        let num_rooms = rng.gen_range(5..11); // 5-10 rooms
+    /// The player's new position, or None if the player does not exist or the move was blocked
+    fn apply_movement(&mut self, id: Uuid, movement: Movement) -> Option<Position> {
+        let player = self.players.get(&id)?;
+        let (at, from) = (Location::of(player), player.position);
+        let step = self.step_of(id);
 
-        // Generate rooms
-        for _ in 0..100 {
-            if rooms.len() >= num_rooms {
-                break;
-            }
+        // Calculate the intended displacement based on movement
+        let (mut dx, mut dy) = (0, 0);
+        if movement.up {
+            dy -= step;
+        }
+        if movement.down {
+            dy += step;
+        }
+        if movement.left {
+            dx -= step;
+        }
+        if movement.right {
+            dx += step;
+        }
 
-            let room_width = rng.gen_range(4..8);
-            let room_height = rng.gen_range(4..8);
-            let x = rng.gen_range(1..width - room_width - 1);
-            let y = rng.gen_range(1..height - room_height - 1);
+        // Sweep the player's box along the displacement
+        let new_pos = self.collision.sweep(&self.floor(at)?.dungeon, from, dx, dy);
+        if new_pos == from {
+            return None;
+        }
 
-            let new_room = Room {
-                x,
-                y,
-                width: room_width,
-                height: room_height,
-            };
+        let player = self.players.get_mut(&id)?;
+        player.position = new_pos;
+        self.floor_mut(at)?.interest.update_position(id, new_pos);
+        Some(new_pos)
+    }
 
-            if !rooms.iter().any(|room| Self::do_rooms_overlap(&new_room, room)) {
-                Self::carve_room(&mut tiles, &new_room);
-                if !rooms.is_empty() {
-                    Self::create_corridor(&mut tiles, &rooms[rooms.len() - 1], &new_room);
-                }
-                rooms.push(new_room);
+    /// Moves a player one tick's worth of distance along their route
+    ///
+    /// The player heads for the center of each tile of the route in turn,
+    /// opening closed doors on the way as they reach them. A route that is
+    /// blocked, or whose last tile is reached, is dropped.
+    fn follow_route(&mut self, id: Uuid) {
+        let Some(mut route) = self.routes.remove(&id) else {
+            return;
+        };
+        let Some(player) = self.players.get(&id) else {
+            return;
+        };
+        let (at, from) = (Location::of(player), player.position);
+        if let Some(&(x, y)) = route.front() {
+            if self.tile_under(at, self.collision.tile_center(x, y)) == TileType::Door {
+                self.change_door(at, x, y, DoorState::Open, None);
             }
         }
+        let mut budget = self.step_of(id);
+        let Some(floor) = self.floor(at) else {
+            return;
+        };
 
-        // Add doors at corridor intersections
-        for y in 1..height as usize - 1 {
-            for x in 1..width as usize - 1 {
-                if tiles[y][x] == TileType::Floor {
-                    let is_horizontal = tiles[y][x - 1] == TileType::Floor && tiles[y][x + 1] == TileType::Floor;
-                    let is_vertical = tiles[y - 1][x] == TileType::Floor && tiles[y + 1][x] == TileType::Floor;
-                    
-                    if is_horizontal && is_vertical && rng.gen_bool(0.2) {
-                        tiles[y][x] = TileType::Door;
-                    }
-                }
+        let mut position = from;
+        while let Some(&(x, y)) = route.front() {
+            if budget == 0 {
+                break;
+            }
+            if floor.dungeon.get_tile(x as usize, y as usize) == TileType::Door {
+                // Opened once it is the next tile of the route
+                break;
+            }
+            let target = self.collision.tile_center(x, y);
+            let dx = (target.x - position.x).clamp(-budget, budget);
+            let dy = (target.y - position.y).clamp(-budget, budget);
+            let next = self.collision.sweep(&floor.dungeon, position, dx, dy);
+            if next == position {
+                route.clear();
+                break;
+            }
+            budget -= (next.x - position.x).abs().max((next.y - position.y).abs());
+            position = next;
+            if position == target {
+                route.pop_front();
             }
         }
 
-        Dungeon {
-            width,
-            height,
-            tiles,
-            rooms,
+        if position != from {
+            if let Some(player) = self.players.get_mut(&id) {
+                player.position = position;
+            }
+            if let Some(floor) = self.floor_mut(at) {
+                floor.interest.update_position(id, position);
+            }
+        }
+        if !route.is_empty() {
+            self.routes.insert(id, route);
         }
     }
 
-    /// Checks if two rooms overlap
-    /// 
+    /// Uses the door on a tile next to a player
+    ///
+    /// An open door is closed and a closed one opened. A locked door is
+    /// unlocked and opened when the player carries its key, which is used
+    /// up. Players near the door are told about its new state.
+    ///
     /// # Arguments
-    /// * `room1` - Reference to the first room
-    /// * `room2` - Reference to the second room
-    /// 
+    /// * `id` - UUID of the player
+    /// * `x` - X coordinate of the door's tile
+    /// * `y` - Y coordinate of the door's tile
+    ///
     /// # Returns
-    /// Boolean indicating whether the rooms overlap
-    fn do_rooms_overlap(room1: &Room, room2: &Room) -> bool {
-        !(room1.x + room1.width + 1 < room2.x ||
-          room2.x + room2.width + 1 < room1.x ||
-          room1.y + room1.height + 1 < room2.y ||
-          room2.y + room2.height + 1 < room1.y)
+    /// The door's new state
+    pub fn interact(&mut self, id: Uuid, x: i32, y: i32) -> Result<DoorState, GameError> {
+        let player = self.players.get(&id).ok_or(GameError::PlayerNotFound)?;
+        let at = Location::of(player);
+        let (tile_x, tile_y) = self.collision.tile_of(player.position);
+        if (tile_x - x).abs().max((tile_y - y).abs()) > INTERACT_REACH {
+            return Err(GameError::InvalidMovement("Too far away".to_string()));
+        }
+        let dungeon = &self.floor(at).ok_or(GameError::PlayerNotFound)?.dungeon;
+        let tile = if x >= 0 && y >= 0 { dungeon.get_tile(x as usize, y as usize) } else { TileType::Wall };
+        let (state, key) = match DoorState::of(tile) {
+            None => return Err(GameError::InvalidMovement("There is no door there".to_string())),
+            Some(DoorState::Locked) => {
+                let key = dungeon.door_key(x, y)
+                    .filter(|key| player.inventory.get_item(*key).is_some())
+                    .ok_or_else(|| GameError::InvalidMovement("The door is locked".to_string()))?;
+                (DoorState::Open, Some(key))
+            }
+            Some(DoorState::Closed) => (DoorState::Open, None),
+            Some(DoorState::Open) => (DoorState::Closed, None),
+        };
+        if state != DoorState::Open && self.door_blocked(at, x, y) {
+            return Err(GameError::InvalidMovement("Something is in the way".to_string()));
+        }
+
+        if let Some(key) = key {
+            if let Some(player) = self.players.get_mut(&id) {
+                player.inventory.remove_item(key, 1);
+            }
+            self.announce_inventory(id);
+        }
+        self.change_door(at, x, y, state, key);
+        Ok(state)
     }
 
-    /// Carves out a room in the dungeon by setting tiles to floor type
-    /// 
+    /// Checks whether a player stands in a doorway, keeping the door from closing
+    fn door_blocked(&self, at: Location, x: i32, y: i32) -> bool {
+        let (size, reach) = (self.collision.tile_size, self.collision.half_extent);
+        let (left, top) = (x * size, y * size);
+        self.players.values()
+            .filter(|player| Location::of(player) == at)
+            .any(|player| {
+                let Position { x: px, y: py } = player.position;
+                px + reach > left && px - reach < left + size && py + reach > top && py - reach < top + size
+            })
+    }
+
+    /// Puts a door in a new state and tells the players who can see it
+    ///
+    /// Their maps are updated, so the change is not revealed again on the
+    /// next tick.
+    ///
     /// # Arguments
-    /// * `tiles` - Mutable reference to the dungeon tiles
-    /// * `room` - Reference to the room to carve
-    fn carve_room(tiles: &mut Vec<Vec<TileType>>, room: &Room) {
-        for y in room.y..room.y + room.height {
-            for x in room.x..room.x + room.width {
-                if y >= 0 && y < tiles.len() as i32 && x >= 0 && x < tiles[0].len() as i32 {
-                    tiles[y as usize][x as usize] = TileType::Floor;
-                }
+    /// * `at` - Floor of the door
+    /// * `x` - X coordinate of the door's tile
+    /// * `y` - Y coordinate of the door's tile
+    /// * `state` - New state of the door
+    /// * `key` - Key unlocking the door when it is locked
+    fn change_door(&mut self, at: Location, x: i32, y: i32, state: DoorState, key: Option<Uuid>) {
+        let Some(floor) = self.floor(at) else {
+            return;
+        };
+        let watchers: Vec<Uuid> = floor.sight.keys()
+            .filter(|watcher| floor.sees(**watcher, (x, y)))
+            .copied()
+            .collect();
+
+        let Some(floor) = self.floor_mut(at) else {
+            return;
+        };
+        let dungeon = &mut floor.dungeon;
+        if let Some(key) = key {
+            dungeon.unlock_door(x, y, key);
+        }
+        dungeon.set_tile(x, y, state.tile());
+
+        let index = y as usize * dungeon.width + x as usize;
+        for watcher in &watchers {
+            if let Some(memory) = floor.explored.get_mut(watcher) {
+                memory[index] = Some(state.tile());
             }
         }
+        self.sessions.send_to_many(watchers, &ServerMessage::DoorChanged { x, y, state });
     }
 
-    /// Creates a corridor between two rooms
-    /// 
+    /// Moves a weapon or armor from a player's inventory into an equipment slot
+    ///
+    /// The player must meet the item's level, class and attribute
+    /// requirements, and the item must fit the slot.
+    ///
     /// # Arguments
-    /// * `tiles` - Mutable reference to the dungeon tiles
-    /// * `room1` - Reference to the first room
-    /// * `room2` - Reference to the second room
-    fn create_corridor(tiles: &mut Vec<Vec<TileType>>, room1: &Room, room2: &Room) {
-        let (x1, y1) = (
-            room1.x + room1.width / 2,
-            room1.y + room1.height / 2
-        );
-        let (x2, y2) = (
-            room2.x + room2.width / 2,
-            room2.y + room2.height / 2
-        );
-        
-        let mut rng = rand::thread_rng();
-        let horizontal_first = rng.gen_bool(0.5);
-
-        if horizontal_first {
-            for x in x1.min(x2)..=x1.max(x2) {
-                if x >= 0 && x < tiles[0].len() as i32 {
-                    tiles[y1 as usize][x as usize] = TileType::Floor;
-                }
-            }
-            for y in y1.min(y2)..=y1.max(y2) {
-                if y >= 0 && y < tiles.len() as i32 {
-                    tiles[y as usize][x2 as usize] = TileType::Floor;
-                }
-            }
-        } else {
-            for y in y1.min(y2)..=y1.max(y2) {
-                if y >= 0 && y < tiles.len() as i32 {
-                    tiles[y as usize][x1 as usize] = TileType::Floor;
-                }
-            }
-            for x in x1.min(x2)..=x1.max(x2) {
-                if x >= 0 && x < tiles[0].len() as i32 {
-                    tiles[y2 as usize][x as usize] = TileType::Floor;
-                }
-            }
+    /// * `id` - UUID of the player
+    /// * `item_id` - UUID of the item in their inventory
+    /// * `slot` - Equipment slot to put it in; the item already there goes
+    ///   back into the inventory
+    pub fn equip_item(&mut self, id: Uuid, item_id: Uuid, slot: EquipmentSlot) -> Result<(), GameError> {
+        let player = self.players.get_mut(&id).ok_or(GameError::PlayerNotFound)?;
+        if self.dead.contains_key(&id) {
+            return Err(GameError::InvalidAction("You are dead".to_string()));
         }
+        let item = player.inventory.get_item(item_id).ok_or(EquipError::NotCarried)?;
+        item.requirements.check(player)?;
+        player.inventory.equip_item(item_id, slot)?;
+        player.mana = player.mana.min(player.mana_capacity());
+        self.announce_inventory(id);
+        Ok(())
     }
 
-    pub fn subscribe<F>(&mut self, callback: F)
-    where
-        F: Fn(&str) + Send + 'static,
-    {
-        self.subscribers.push(Box::new(callback));
+    /// Moves the item in one of a player's equipment slots back into their inventory
+    ///
+    /// # Arguments
+    /// * `id` - UUID of the player
+    /// * `slot` - Equipment slot to empty
+    pub fn unequip_item(&mut self, id: Uuid, slot: EquipmentSlot) -> Result<(), GameError> {
+        let player = self.players.get_mut(&id).ok_or(GameError::PlayerNotFound)?;
+        if self.dead.contains_key(&id) {
+            return Err(GameError::InvalidAction("You are dead".to_string()));
+        }
+        player.inventory.unequip_item(slot)?;
+        player.mana = player.mana.min(player.mana_capacity());
+        self.announce_inventory(id);
+        Ok(())
+    }
+
+    /// Uses up one consumable from a player's stack, restoring the health
+    /// and mana it holds
+    ///
+    /// The item is kept when it would restore nothing, e.g. a healing potion
+    /// at full health.
+    ///
+    /// # Arguments
+    /// * `id` - UUID of the player
+    /// * `item_id` - UUID of the stack in their inventory
+    pub fn use_item(&mut self, id: Uuid, item_id: Uuid) -> Result<(), GameError> {
+        let player = self.players.get_mut(&id).ok_or(GameError::PlayerNotFound)?;
+        if self.dead.contains_key(&id) {
+            return Err(GameError::InvalidAction("You are dead".to_string()));
+        }
+        let item = player.inventory.get_item(item_id)
+            .ok_or_else(|| GameError::InvalidAction("You do not have that item".to_string()))?;
+        if !item.is_consumable() {
+            return Err(GameError::InvalidAction(format!("{} cannot be used", item.name)));
+        }
+        let (health, mana) = (item.stats.health, item.stats.mana);
+        let heals = health > 0 && player.health < player.max_health;
+        let restores_mana = mana > 0 && player.mana < player.mana_capacity();
+        if !heals && !restores_mana {
+            let reason = match (health > 0, mana > 0) {
+                (true, true) => "Your health and mana are already full".to_string(),
+                (true, false) => "Your health is already full".to_string(),
+                (false, true) => "Your mana is already full".to_string(),
+                (false, false) => format!("{} has no effect", item.name),
+            };
+            return Err(GameError::InvalidAction(reason));
+        }
+        player.inventory.remove_item(item_id, 1);
+        if mana > 0 {
+            player.mana = (player.mana + mana).min(player.mana_capacity());
+        }
+        let at = Location::of(player);
+        if health > 0 {
+            self.heal(at, id, health);
+        }
+        self.announce_inventory(id);
+        Ok(())
     }
 
-    fn broadcast_message(&self, message: &str) {
-        for subscriber in &self.subscribers {
-            subscriber(message);
+    /// Drops a whole stack from a player's inventory onto the tile they stand on
+    ///
+    /// # Arguments
+    /// * `id` - UUID of the player
+    /// * `item_id` - UUID of the stack in their inventory
+    pub fn drop_item(&mut self, id: Uuid, item_id: Uuid) -> Result<(), GameError> {
+        let player = self.players.get_mut(&id).ok_or(GameError::PlayerNotFound)?;
+        if self.dead.contains_key(&id) {
+            return Err(GameError::InvalidAction("You are dead".to_string()));
         }
+        let amount = player.inventory.get_item(item_id)
+            .map(|item| item.stack_size)
+            .ok_or_else(|| GameError::InvalidAction("You do not have that item".to_string()))?;
+        let item = player.inventory.remove_item(item_id, amount)
+            .ok_or_else(|| GameError::InvalidAction("You do not have that item".to_string()))?;
+        let (at, tile) = (Location::of(player), self.collision.tile_of(player.position));
+        self.place_item(at, tile, item);
+        self.announce_inventory(id);
+        Ok(())
     }
-} 
\ No newline at end of file
+
+    /// Picks up an item lying on the player's floor within reach
+    ///
+    /// # Arguments
+    /// * `id` - UUID of the player
+    /// * `item_id` - UUID of the item on the ground
+    pub fn pick_up_item(&mut self, id: Uuid, item_id: Uuid) -> Result<(), GameError> {
+        let player = self.players.get(&id).ok_or(GameError::PlayerNotFound)?;
+        if self.dead.contains_key(&id) {
+            return Err(GameError::InvalidAction("You are dead".to_string()));
+        }
+        let (at, from) = (Location::of(player), self.collision.tile_of(player.position));
+        let floor = self.floor(at).ok_or(GameError::PlayerNotFound)?;
+        let (tile, _) = floor.ground.get(&item_id)
+            .ok_or_else(|| GameError::InvalidAction("There is no such item here".to_string()))?;
+        if (tile.0 - from.0).abs().max((tile.1 - from.1).abs()) > INTERACT_REACH {
+            return Err(GameError::InvalidAction("Too far away".to_string()));
+        }
+
+        let watchers = self.watchers(at, *tile);
+        let Some((tile, item)) = self.floor_mut(at).and_then(|floor| floor.ground.remove(&item_id)) else {
+            return Err(GameError::InvalidAction("There is no such item here".to_string()));
+        };
+        let player = self.players.get_mut(&id).ok_or(GameError::PlayerNotFound)?;
+        if let Some(item) = player.inventory.add_item(item) {
+            if let Some(floor) = self.floor_mut(at) {
+                floor.ground.insert(item_id, (tile, item));
+            }
+            return Err(GameError::InvalidAction("Inventory is full".to_string()));
+        }
+        self.sessions.send_to_many(watchers, &ServerMessage::ItemPickedUp { id: item_id });
+        self.announce_inventory(id);
+        Ok(())
+    }
+
+    /// Leaves an item on a tile and tells the players who can see it
+    fn place_item(&mut self, at: Location, tile: (i32, i32), item: Item) {
+        let dropped = ServerMessage::ItemDropped { id: item.id, name: item.name.clone(), x: tile.0, y: tile.1 };
+        let Some(floor) = self.floor_mut(at) else {
+            return;
+        };
+        floor.ground.insert(item.id, (tile, item));
+        self.sessions.send_to_many(self.watchers(at, tile), &dropped);
+    }
+
+    /// Lists the players on a floor who can currently see a tile
+    fn watchers(&self, at: Location, tile: (i32, i32)) -> Vec<Uuid> {
+        self.floor(at)
+            .map(|floor| floor.sight.keys().filter(|watcher| floor.sees(**watcher, tile)).copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Sends a player their inventory and equipment
+    fn announce_inventory(&self, id: Uuid) {
+        if let Some(player) = self.players.get(&id) {
+            self.sessions.send_to(id, &ServerMessage::InventoryChanged { inventory: player.inventory.clone() });
+        }
+    }
+
+    /// Attacks an NPC on the player's floor
+    ///
+    /// The target must be within reach of the attack, and in line of sight
+    /// for ranged attacks; the player then has to wait out the attack's
+    /// cooldown. Players who can see the fight are told its outcome.
+    ///
+    /// # Arguments
+    /// * `id` - UUID of the attacking player
+    /// * `target` - UUID of the NPC
+    /// * `kind` - How the attack is delivered
+    ///
+    /// # Returns
+    /// Whether the attack hit, and for how much damage
+    pub fn attack(&mut self, id: Uuid, target: Uuid, kind: AttackKind) -> Result<AttackOutcome, GameError> {
+        let player = self.players.get(&id).ok_or(GameError::PlayerNotFound)?;
+        if self.dead.contains_key(&id) {
+            return Err(GameError::InvalidAction("You are dead".to_string()));
+        }
+        if self.is_stunned(id) {
+            return Err(GameError::InvalidAction("You are stunned".to_string()));
+        }
+        if self.cooldowns.get(&id).is_some_and(|ready| self.tick < *ready) {
+            return Err(GameError::InvalidAction("Not ready to attack yet".to_string()));
+        }
+        let at = Location::of(player);
+        let floor = self.floor(at).ok_or(GameError::PlayerNotFound)?;
+        let npc = floor.population.npcs.get(&target)
+            .ok_or_else(|| GameError::InvalidAction("There is nothing to attack there".to_string()))?;
+        let (from, to) = (self.collision.tile_of(player.position), self.collision.tile_of(npc.position));
+        if (from.0 - to.0).abs().max((from.1 - to.1).abs()) > kind.range() {
+            return Err(GameError::InvalidAction("Target is out of reach".to_string()));
+        }
+        if kind == AttackKind::Ranged && !line_of_sight(&floor.dungeon, from, to) {
+            return Err(GameError::InvalidAction("Target is not in sight".to_string()));
+        }
+
+        let attacker = self.modified(id, CombatStats::of_player(player));
+        let defender = self.modified(target, CombatStats::of_npc(npc));
+        let outcome = roll_attack(&attacker, &defender, kind, &mut self.rng);
+        self.cooldowns.insert(id, self.tick + self.ticks_for(kind.cooldown_ms()));
+        self.resolve_attack(at, id, target, kind, outcome);
+        Ok(outcome)
+    }
+
+    /// Lets an NPC attack the player it is after
+    ///
+    /// The attack is melee when the NPC stands next to the player and
+    /// ranged otherwise; the NPC's behavior decides when it is in reach.
+    fn npc_attack(&mut self, at: Location, npc: Uuid, target: Uuid) {
+        let Some(npc) = self.floor(at).and_then(|floor| floor.population.npcs.get(&npc)) else {
+            return;
+        };
+        let Some(player) = self.players.get(&target).filter(|player| Location::of(player) == at) else {
+            return;
+        };
+        if self.dead.contains_key(&target) {
+            return;
+        }
+        let (from, to) = (self.collision.tile_of(npc.position), self.collision.tile_of(player.position));
+        let kind = if (from.0 - to.0).abs().max((from.1 - to.1).abs()) <= 1 {
+            AttackKind::Melee
+        } else {
+            AttackKind::Ranged
+        };
+
+        let id = npc.id;
+        let attacker = self.modified(id, CombatStats::of_npc(npc));
+        let defender = self.modified(target, CombatStats::of_player(player));
+        let outcome = roll_attack(&attacker, &defender, kind, &mut self.rng);
+        self.resolve_attack(at, id, target, kind, outcome);
+    }
+
+    /// Applies the damage of an attack and tells the players who can see
+    /// the fight, killing the target when its health runs out
+    fn resolve_attack(&mut self, at: Location, attacker: Uuid, target: Uuid, kind: AttackKind, outcome: AttackOutcome) {
+        let damage = match outcome {
+            AttackOutcome::Hit { damage, .. } => damage,
+            AttackOutcome::Miss => 0,
+        };
+        let Some(health) = self.wound(at, target, damage) else {
+            return;
+        };
+
+        let audience = self.audience(at, &[attacker, target]);
+        let combat = ServerMessage::Combat { attacker, target, kind, outcome, health };
+        self.sessions.send_to_many(audience.iter().copied(), &combat);
+        if health == 0 {
+            self.sessions.send_to_many(audience, &ServerMessage::Died { id: target, killer: attacker });
+            self.kill(at, target, attacker);
+        }
+    }
+
+    /// Takes health from a player or an NPC on a floor, after its shields
+    ///
+    /// # Returns
+    /// The health the entity has left, or None if it is not there
+    fn wound(&mut self, at: Location, id: Uuid, damage: i32) -> Option<i32> {
+        let damage = match self.effects.get_mut(&id) {
+            Some(effects) => effects.absorb(damage),
+            None => damage,
+        };
+        if let Some(player) = self.players.get_mut(&id) {
+            player.health = (player.health - damage).max(0);
+            return Some(player.health);
+        }
+        let npc = self.floor_mut(at)?.population.npcs.get_mut(&id)?;
+        npc.health = (npc.health - damage).max(0);
+        Some(npc.health)
+    }
+
+    /// Gives health back to a player or an NPC on a floor, up to its maximum
+    ///
+    /// # Returns
+    /// The health the entity has now, or None if it is not there
+    fn heal(&mut self, at: Location, id: Uuid, health: i32) -> Option<i32> {
+        if let Some(player) = self.players.get_mut(&id) {
+            player.health = (player.health + health).min(player.max_health);
+            return Some(player.health);
+        }
+        let npc = self.floor_mut(at)?.population.npcs.get_mut(&id)?;
+        npc.health = (npc.health + health).min(npc.max_health);
+        Some(npc.health)
+    }
+
+    /// Applies the buffs and debuffs on an entity to its combat stats
+    fn modified(&self, id: Uuid, stats: CombatStats) -> CombatStats {
+        self.effects.get(&id).map_or(stats, |effects| effects.modify(stats))
+    }
+
+    /// Checks whether a player or an NPC is stunned
+    fn is_stunned(&self, id: Uuid) -> bool {
+        self.effects.get(&id).is_some_and(StatusEffects::is_stunned)
+    }
+
+    /// Distance a player covers this tick under haste, slow and stun
+    ///
+    /// Fractions of a unit left over are carried over to the player's next
+    /// step, so a second of movement covers the same distance at any tick rate.
+    fn step_of(&mut self, id: Uuid) -> i32 {
+        let pace = self.effects.get(&id).map_or(100, StatusEffects::pace_percent);
+        let distance = self.speed() as i64 * pace as i64;
+        let per = self.tick_rate as i64 * 100;
+        self.strides.entry(id).or_default().advance(distance, per)
+    }
+
+    /// Finds the floor a player or an NPC is on
+    fn locate(&self, id: Uuid) -> Option<Location> {
+        if let Some(player) = self.players.get(&id) {
+            return Some(Location::of(player));
+        }
+        let floors = (0..self.floors.len() as u32).map(|depth| Location { instance: None, depth });
+        let instances = self.instances.keys().map(|id| Location { instance: Some(*id), depth: 0 });
+        floors.chain(instances)
+            .find(|at| self.floor(*at).is_some_and(|floor| floor.population.npcs.contains_key(&id)))
+    }
+
+    /// Applies the health changes of poison and regeneration due this tick
+    /// and wears off expired status effects
+    ///
+    /// Poison that kills credits whoever applied it. Effects on entities
+    /// that are gone are dropped.
+    fn pulse_effects(&mut self) {
+        let tick = self.tick;
+        let mut ids: Vec<Uuid> = self.effects.keys().copied().collect();
+        ids.sort();
+        for id in ids {
+            let (Some(at), Some(effects)) = (self.locate(id), self.effects.get_mut(&id)) else {
+                self.effects.remove(&id);
+                continue;
+            };
+            let pulses = effects.advance(tick);
+            if effects.is_empty() {
+                self.effects.remove(&id);
+            }
+            for pulse in pulses {
+                if pulse.health > 0 {
+                    self.heal(at, id, pulse.health);
+                } else if self.wound(at, id, -pulse.health) == Some(0) {
+                    let audience = self.audience(at, &[id, pulse.source]);
+                    self.sessions.send_to_many(audience, &ServerMessage::Died { id, killer: pulse.source });
+                    self.kill(at, id, pulse.source);
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Finds the players who can see a fight between entities on a floor:
+    /// the fighters themselves and everyone whose view radius holds one of them
+    fn audience(&self, at: Location, fighters: &[Uuid]) -> Vec<Uuid> {
+        let Some(floor) = self.floor(at) else {
+            return Vec::new();
+        };
+        let mut audience: Vec<Uuid> = fighters.iter()
+            .filter(|id| self.players.contains_key(id))
+            .copied()
+            .chain(fighters.iter().flat_map(|id| floor.interest.observers_of(*id)))
+            .collect();
+        audience.sort();
+        audience.dedup();
+        audience
+    }
+
+    /// Handles the death of a player or NPC
+    ///
+    /// A dead player stops moving and comes back after
+    /// `PLAYER_RESPAWN_SECS`. A dead NPC is removed from the floor, where its
+    /// spawner brings it back later, and its loot goes to the player who
+    /// killed it, along with the experience it was worth.
+    fn kill(&mut self, at: Location, id: Uuid, killer: Uuid) {
+        self.effects.remove(&id);
+        if self.players.contains_key(&id) {
+            self.inputs.remove(&id);
+            self.routes.remove(&id);
+            self.casts.remove(&id);
+            self.dead.insert(id, self.tick + PLAYER_RESPAWN_SECS * self.tick_rate as u64);
+            return;
+        }
+
+        let Some(floor) = self.floor_mut(at) else {
+            return;
+        };
+        let Some(npc) = floor.population.npcs.remove(&id) else {
+            return;
+        };
+        let observers = floor.interest.remove(id);
+        self.sessions.send_to_many(observers, &ServerMessage::EntityLeft { id });
+        if self.players.contains_key(&killer) && !npc.loot.is_empty() {
+            let tile = self.collision.tile_of(npc.position);
+            let mut received = Vec::new();
+            for item in npc.loot {
+                let Some(player) = self.players.get_mut(&killer) else {
+                    break;
+                };
+                match player.inventory.add_item(item.clone()) {
+                    None => received.push(item),
+                    Some(item) => self.place_item(at, tile, item),
+                }
+            }
+            if !received.is_empty() {
+                self.sessions.send_to(killer, &ServerMessage::LootReceived { items: received });
+                self.announce_inventory(killer);
+            }
+        }
+        if npc.experience > 0 {
+            self.grant_experience(killer, npc.experience, ExperienceSource::Kill);
+        }
+        self.count_kill(killer, &npc.name);
+    }
+
+    /// Counts a kill toward the killer's quests, completing those that reach
+    /// their goal
+    ///
+    /// # Arguments
+    /// * `killer` - UUID of the entity that made the kill
+    /// * `target` - Name of the NPC killed
+    fn count_kill(&mut self, killer: Uuid, target: &str) {
+        let Some(player) = self.players.get_mut(&killer) else {
+            return;
+        };
+        for progress in player.quests.record_kill(&self.quests, target) {
+            let experience = progress.is_complete().then(|| self.quests.quests[&progress.quest].experience);
+            self.sessions.send_to(killer, &ServerMessage::QuestProgress { quest: progress.quest, progress: progress.progress, goal: progress.goal });
+            if let Some(experience) = experience {
+                self.grant_experience(killer, experience, ExperienceSource::Quest);
+            }
+        }
+    }
+
+    /// Gives a player experience, if the ID is a player's
+    ///
+    /// The player is told how much they gained; when they level up, they and
+    /// the players near them are told their new level.
+    fn grant_experience(&mut self, id: Uuid, amount: i32, source: ExperienceSource) {
+        let Some(player) = self.players.get_mut(&id) else {
+            return;
+        };
+        let gained = self.progression.award(player, amount);
+        let (exp, max_exp, level, at) = (player.exp, player.max_exp, player.level, Location::of(player));
+        self.sessions.send_to(id, &ServerMessage::ExperienceGained { amount, source, exp, max_exp });
+        if gained > 0 {
+            self.sessions.send_to_many(self.audience(at, &[id]), &ServerMessage::LeveledUp { id, level });
+            self.announce_attributes(id);
+        }
+    }
+
+    /// Spends a player's stat points on their attributes
+    ///
+    /// Maximum health and mana grow with the attributes raised.
+    ///
+    /// # Arguments
+    /// * `id` - UUID of the player
+    /// * `allocation` - Points to add to each attribute
+    ///
+    /// # Returns
+    /// The player after spending the points
+    pub fn allocate_attributes(&mut self, id: Uuid, allocation: AttributeAllocation) -> Result<&Player, GameError> {
+        let player = self.players.get_mut(&id).ok_or(GameError::PlayerNotFound)?;
+        let total = allocation.total();
+        if total == 0 {
+            return Err(GameError::InvalidAction("No stat points to spend".to_string()));
+        }
+        if total > player.stat_points.max(0) as u32 {
+            return Err(GameError::InvalidAction(format!("Only {} stat points left", player.stat_points)));
+        }
+        player.stat_points -= total as i32;
+        player.attributes.strength += allocation.strength as i32;
+        player.attributes.dexterity += allocation.dexterity as i32;
+        player.attributes.intelligence += allocation.intelligence as i32;
+        self.progression.recalculate(player);
+        self.announce_attributes(id);
+        Ok(&self.players[&id])
+    }
+
+    /// Tells a player their attributes, unspent stat points and maximum
+    /// health and mana
+    fn announce_attributes(&self, id: Uuid) {
+        if let Some(player) = self.players.get(&id) {
+            self.sessions.send_to(id, &ServerMessage::AttributesChanged {
+                attributes: player.attributes,
+                stat_points: player.stat_points,
+                max_health: player.max_health,
+                max_mana: player.max_mana,
+            });
+        }
+    }
+
+    /// Brings dead players whose respawn delay is over back to life at the
+    /// start of the dungeon or instance they died in, with full health and mana
+    fn respawn_players(&mut self) {
+        let tick = self.tick;
+        let due: Vec<Uuid> = self.dead.iter()
+            .filter(|(_, respawn)| **respawn <= tick)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in due {
+            self.dead.remove(&id);
+            let Some(player) = self.players.get_mut(&id) else {
+                continue;
+            };
+            player.health = player.max_health;
+            player.mana = player.mana_capacity();
+            let to = Location { instance: player.instance, depth: 0 };
+            let Some(position) = self.floor(to).map(|floor| self.spawn_position_in(&floor.dungeon)) else {
+                continue;
+            };
+            self.move_to(id, to, position);
+            self.sessions.send_to(id, &ServerMessage::Respawned { position });
+        }
+    }
+
+    /// Binds a skill to a slot of a player's hotbar
+    ///
+    /// # Arguments
+    /// * `id` - UUID of the player
+    /// * `slot` - Index of the slot
+    /// * `skill` - Name of the skill, or None to clear the slot
+    ///
+    /// # Returns
+    /// The player's hotbar after the change
+    pub fn bind_skill(&mut self, id: Uuid, slot: usize, skill: Option<String>) -> Result<Vec<Option<String>>, GameError> {
+        if slot >= HOTBAR_SLOTS {
+            return Err(GameError::InvalidAction(format!("The hotbar has only {} slots", HOTBAR_SLOTS)));
+        }
+        if let Some(name) = skill.as_ref().filter(|name| !self.skills.skills.contains_key(*name)) {
+            return Err(GameError::InvalidAction(format!("There is no skill called {}", name)));
+        }
+        let player = self.players.get_mut(&id).ok_or(GameError::PlayerNotFound)?;
+        player.hotbar.resize(HOTBAR_SLOTS, None);
+        player.hotbar[slot] = skill;
+        Ok(player.hotbar.clone())
+    }
+
+    /// Casts the skill in a slot of a player's hotbar
+    ///
+    /// The skill must have cooled down, the player must have the mana for
+    /// it, and whatever it is aimed at must be in range and in sight. A
+    /// skill without a cast time takes effect at once; otherwise the players
+    /// nearby are told the cast started, and it takes effect once the cast
+    /// time is up, provided the player did not move and it is still in
+    /// range. Mana is spent and the cooldown starts when the skill takes
+    /// effect.
+    ///
+    /// # Arguments
+    /// * `id` - UUID of the casting player
+    /// * `slot` - Index of the hotbar slot
+    /// * `target` - Entity to aim at, for skills aimed at entities
+    /// * `position` - Position to aim at, for skills aimed at the ground
+    pub fn cast_skill(&mut self, id: Uuid, slot: usize, target: Option<Uuid>, position: Option<Position>) -> Result<(), GameError> {
+        let player = self.players.get(&id).ok_or(GameError::PlayerNotFound)?;
+        if self.dead.contains_key(&id) {
+            return Err(GameError::InvalidAction("You are dead".to_string()));
+        }
+        if self.is_stunned(id) {
+            return Err(GameError::InvalidAction("You are stunned".to_string()));
+        }
+        if self.casts.contains_key(&id) {
+            return Err(GameError::InvalidAction("You are already casting".to_string()));
+        }
+        let name = player.hotbar.get(slot)
+            .cloned()
+            .flatten()
+            .ok_or_else(|| GameError::InvalidAction("Nothing is bound to that slot".to_string()))?;
+        let skill = self.skills.skills.get(&name)
+            .cloned()
+            .ok_or_else(|| GameError::InvalidAction(format!("There is no skill called {}", name)))?;
+        if self.skill_cooldowns.get(&(id, name.clone())).is_some_and(|ready| self.tick < *ready) {
+            return Err(GameError::InvalidAction(format!("{} is not ready yet", skill.name)));
+        }
+        let aim = self.check_cast(id, &skill, target, position)?;
+
+        if skill.cast_ms == 0 {
+            self.take_effect(id, &name, &skill, target, aim);
+            return Ok(());
+        }
+        let at = Location::of(&self.players[&id]);
+        self.inputs.remove(&id);
+        self.routes.remove(&id);
+        let finishes = self.tick + self.ticks_for(skill.cast_ms);
+        self.casts.insert(id, Cast { skill: name.clone(), target, position, finishes });
+        let started = ServerMessage::CastStarted { caster: id, skill: name, cast_ms: skill.cast_ms };
+        self.sessions.send_to_many(self.audience(at, &[id]), &started);
+        Ok(())
+    }
+
+    /// Checks that a player can cast a skill at a target right now
+    ///
+    /// # Returns
+    /// The point the skill is aimed at, or the reason it cannot be cast
+    fn check_cast(&self, id: Uuid, skill: &Skill, target: Option<Uuid>, position: Option<Position>) -> Result<Position, GameError> {
+        let player = self.players.get(&id).ok_or(GameError::PlayerNotFound)?;
+        if player.mana < skill.mana_cost {
+            return Err(GameError::InvalidAction(format!("Not enough mana for {}", skill.name)));
+        }
+        let at = Location::of(player);
+        let aim = match skill.targeting {
+            Targeting::Caster => return Ok(player.position),
+            Targeting::Entity => {
+                let target = target
+                    .ok_or_else(|| GameError::InvalidAction(format!("{} needs a target", skill.name)))?;
+                let (position, is_npc) = self.entity_at(at, target)
+                    .ok_or_else(|| GameError::InvalidAction("No such target".to_string()))?;
+                if is_npc != skill.effect.is_hostile() {
+                    return Err(GameError::InvalidAction(format!("{} cannot be cast on that target", skill.name)));
+                }
+                position
+            }
+            Targeting::Ground => {
+                let position = position
+                    .ok_or_else(|| GameError::InvalidAction(format!("{} needs a position", skill.name)))?;
+                if self.tile_under(at, position).is_solid() {
+                    return Err(GameError::InvalidAction("Cannot aim into a wall".to_string()));
+                }
+                position
+            }
+        };
+
+        let floor = self.floor(at).ok_or(GameError::PlayerNotFound)?;
+        let (from, to) = (self.collision.tile_of(player.position), self.collision.tile_of(aim));
+        if (from.0 - to.0).abs().max((from.1 - to.1).abs()) > skill.range {
+            return Err(GameError::InvalidAction("Target is out of range".to_string()));
+        }
+        if !line_of_sight(&floor.dungeon, from, to) {
+            return Err(GameError::InvalidAction("Target is not in sight".to_string()));
+        }
+        Ok(aim)
+    }
+
+    /// Finds a living player or an NPC on a floor
+    ///
+    /// # Returns
+    /// The entity's position and whether it is an NPC
+    fn entity_at(&self, at: Location, id: Uuid) -> Option<(Position, bool)> {
+        if let Some(player) = self.players.get(&id) {
+            let alive = Location::of(player) == at && !self.dead.contains_key(&id);
+            return alive.then_some((player.position, false));
+        }
+        self.floor(at)?.population.npcs.get(&id).map(|npc| (npc.position, true))
+    }
+
+    /// Lets the casts whose cast time is up take effect, interrupting those
+    /// that can no longer be cast
+    fn finish_casts(&mut self) {
+        let tick = self.tick;
+        let mut due: Vec<(Uuid, Cast)> = self.casts.iter()
+            .filter(|(_, cast)| cast.finishes <= tick)
+            .map(|(id, cast)| (*id, cast.clone()))
+            .collect();
+        due.sort_by_key(|(id, _)| *id);
+        for (id, cast) in due {
+            self.casts.remove(&id);
+            let Some(skill) = self.skills.skills.get(&cast.skill).cloned() else {
+                continue;
+            };
+            if let Ok(aim) = self.check_cast(id, &skill, cast.target, cast.position) {
+                self.take_effect(id, &cast.skill, &skill, cast.target, aim);
+            } else if let Some(at) = self.players.get(&id).map(Location::of) {
+                let interrupted = ServerMessage::CastInterrupted { caster: id, skill: cast.skill };
+                self.sessions.send_to_many(self.audience(at, &[id]), &interrupted);
+            }
+        }
+    }
+
+    /// Stops a player's cast before it takes effect and tells the players nearby
+    fn interrupt_cast(&mut self, id: Uuid) {
+        let Some(cast) = self.casts.remove(&id) else {
+            return;
+        };
+        if let Some(at) = self.players.get(&id).map(Location::of) {
+            let interrupted = ServerMessage::CastInterrupted { caster: id, skill: cast.skill };
+            self.sessions.send_to_many(self.audience(at, &[id]), &interrupted);
+        }
+    }
+
+    /// Spends the mana for a skill, starts its cooldown and applies its
+    /// effect to everything it affects, telling the players nearby
+    ///
+    /// The skill must have passed `check_cast`, which found the point `aim`
+    /// it is aimed at.
+    fn take_effect(&mut self, id: Uuid, name: &str, skill: &Skill, target: Option<Uuid>, aim: Position) {
+        let Some(player) = self.players.get(&id) else {
+            return;
+        };
+        let at = Location::of(player);
+        let caster = self.modified(id, CombatStats::of_player(player));
+        if let Some(player) = self.players.get_mut(&id) {
+            player.mana -= skill.mana_cost;
+        }
+        let ready = self.tick + self.ticks_for(skill.cooldown_ms);
+        self.skill_cooldowns.insert((id, name.to_string()), ready);
+
+        if skill.effect == Effect::Teleport {
+            let (tile_x, tile_y) = self.collision.tile_of(aim);
+            let position = self.collision.tile_center(tile_x, tile_y);
+            self.move_to(id, at, position);
+            self.announce_effect(at, id, name, id, SkillOutcome::Teleported { position });
+            return;
+        }
+
+        for affected in self.affected_by(at, skill, target, aim) {
+            let outcome = match skill.effect {
+                Effect::Damage { power, scaling_percent } => {
+                    let amount = roll_spell(power, scaling_percent, &caster, &mut self.rng);
+                    let Some(health) = self.wound(at, affected, amount) else {
+                        continue;
+                    };
+                    SkillOutcome::Damaged { amount, health }
+                }
+                Effect::Heal { power, scaling_percent } => {
+                    let amount = roll_spell(power, scaling_percent, &caster, &mut self.rng);
+                    let Some(health) = self.heal(at, affected, amount) else {
+                        continue;
+                    };
+                    SkillOutcome::Healed { amount, health }
+                }
+                Effect::Status { status, duration_ms } => {
+                    let duration = self.ticks_for(duration_ms);
+                    let (tick, tick_rate) = (self.tick, self.tick_rate);
+                    self.effects.entry(affected).or_default().apply(status, id, tick, duration, tick_rate);
+                    if status == Status::Stun {
+                        self.interrupt_cast(affected);
+                    }
+                    SkillOutcome::StatusApplied { status: status.kind(), duration_ms }
+                }
+                Effect::Teleport => continue,
+            };
+            let audience = self.announce_effect(at, id, name, affected, outcome);
+            if let SkillOutcome::Damaged { health: 0, .. } = outcome {
+                self.sessions.send_to_many(audience, &ServerMessage::Died { id: affected, killer: id });
+                self.kill(at, affected, id);
+            }
+        }
+    }
+
+    /// Finds the entities a skill affects: the entity it is aimed at for
+    /// single-target skills, or every entity within its area otherwise,
+    /// keeping only NPCs for hostile skills and only living players for
+    /// the others
+    fn affected_by(&self, at: Location, skill: &Skill, target: Option<Uuid>, aim: Position) -> Vec<Uuid> {
+        let Some(floor) = self.floor(at) else {
+            return Vec::new();
+        };
+        let center = self.collision.tile_of(aim);
+        let mut affected: Vec<Uuid> = if skill.effect.is_hostile() {
+            floor.population.npcs.values()
+                .filter(|npc| skill.area.covers(center, self.collision.tile_of(npc.position)))
+                .map(|npc| npc.id)
+                .collect()
+        } else {
+            self.players.values()
+                .filter(|player| Location::of(player) == at && !self.dead.contains_key(&player.id))
+                .filter(|player| skill.area.covers(center, self.collision.tile_of(player.position)))
+                .map(|player| player.id)
+                .collect()
+        };
+        if skill.area == Area::Single && skill.targeting == Targeting::Entity {
+            affected.retain(|id| Some(*id) == target);
+        }
+        affected.sort();
+        affected
+    }
+
+    /// Tells the players who can see it what a skill did to an entity
+    ///
+    /// # Returns
+    /// The players told
+    fn announce_effect(&self, at: Location, caster: Uuid, skill: &str, target: Uuid, outcome: SkillOutcome) -> Vec<Uuid> {
+        let audience = self.audience(at, &[caster, target]);
+        let effect = ServerMessage::SkillEffect { caster, skill: skill.to_string(), target, outcome };
+        self.sessions.send_to_many(audience.iter().copied(), &effect);
+        audience
+    }
+
+    /// Gives every living player back some mana, more the more intelligent
+    /// they are, up to what they can hold
+    fn regenerate_mana(&mut self) {
+        let regen: Vec<(Uuid, i32)> = self.players.values()
+            .filter(|player| !self.dead.contains_key(&player.id))
+            .map(|player| {
+                let intelligence = self.modified(player.id, CombatStats::of_player(player)).intelligence;
+                (player.id, MANA_REGEN_PER_SEC + intelligence.max(0) / INTELLIGENCE_PER_MANA_REGEN)
+            })
+            .collect();
+        for (id, amount) in regen {
+            if let Some(player) = self.players.get_mut(&id).filter(|player| player.mana < player.mana_capacity()) {
+                player.mana = (player.mana + amount).min(player.mana_capacity());
+            }
+        }
+    }
+
+    /// Converts a duration to a number of ticks at the current tick rate,
+    /// rounding up
+    fn ticks_for(&self, ms: u64) -> u64 {
+        (ms * self.tick_rate as u64).div_ceil(1000)
+    }
+
+    /// Distance a player covers in one second at the current tile size
+    fn speed(&self) -> i32 {
+        PLAYER_SPEED * REFERENCE_TICK_RATE as i32 * self.collision.tile_size / DEFAULT_TILE_SIZE
+    }
+
+    /// Gets the tile under the center of a position
+    ///
+    /// # Returns
+    /// The tile, or a wall when the floor does not exist
+    fn tile_under(&self, at: Location, pos: Position) -> TileType {
+        let (tile_x, tile_y) = self.collision.tile_of(pos);
+        match self.floor(at) {
+            Some(floor) if tile_x >= 0 && tile_y >= 0 => floor.dungeon.get_tile(tile_x as usize, tile_y as usize),
+            _ => TileType::Wall,
+        }
+    }
+
+    /// Gets a player's map of the floor they are on
+    ///
+    /// # Arguments
+    /// * `id` - UUID of the player
+    ///
+    /// # Returns
+    /// The tiles of the floor the player has seen
+    pub fn player_map(&self, id: Uuid) -> Result<DungeonView, GameError> {
+        let player = self.players.get(&id).ok_or(GameError::PlayerNotFound)?;
+        let floor = self.floor(Location::of(player)).ok_or(GameError::FloorNotFound)?;
+        Ok(floor.map_for(player.id))
+    }
+
+    /// Gets a player's map of a floor of the shared dungeon
+    ///
+    /// # Arguments
+    /// * `id` - UUID of the player
+    /// * `depth` - Depth of the floor, 0 being the top floor
+    ///
+    /// # Returns
+    /// The tiles of the floor the player has seen
+    pub fn floor_map(&self, id: Uuid, depth: u32) -> Result<DungeonView, GameError> {
+        let player = self.players.get(&id).ok_or(GameError::PlayerNotFound)?;
+        let floor = self.floors.get(depth as usize).ok_or(GameError::FloorNotFound)?;
+        Ok(floor.map_for(player.id))
+    }
+
+    /// Gets a player's map of a party instance
+    ///
+    /// # Arguments
+    /// * `id` - UUID of the player
+    /// * `instance` - ID of the instance
+    ///
+    /// # Returns
+    /// The tiles of the instance the player has seen
+    pub fn instance_map(&self, id: Uuid, instance: Uuid) -> Result<DungeonView, GameError> {
+        let player = self.players.get(&id).ok_or(GameError::PlayerNotFound)?;
+        let instance = self.instances.get(&instance).ok_or(GameError::InstanceNotFound)?;
+        Ok(instance.floor.map_for(player.id))
+    }
+
+    /// Moves a player through the stairs they are standing on
+    ///
+    /// The floor below is generated the first time anyone reaches it. The
+    /// player arrives on the matching stairs of the other floor, is told
+    /// about the new floor, and stops seeing everyone on the old one.
+    ///
+    /// # Arguments
+    /// * `id` - UUID of the player
+    ///
+    /// # Returns
+    /// Depth of the floor the player arrived on
+    pub fn take_stairs(&mut self, id: Uuid) -> Result<u32, GameError> {
+        let player = self.players.get(&id).ok_or(GameError::PlayerNotFound)?;
+        let (at, depth) = (Location::of(player), player.floor);
+        // Instances have no stairs
+        let (target, arrival) = match self.tile_under(at, player.position) {
+            TileType::StairsDown => (depth + 1, TileType::StairsUp),
+            TileType::StairsUp if depth > 0 => (depth - 1, TileType::StairsDown),
+            _ => return Err(GameError::InvalidMovement("Not standing on stairs".to_string())),
+        };
+
+        if self.floors.len() <= target as usize {
+            let top = &self.floors[0].dungeon;
+            let dungeon = self.dungeon_generator.generate_floor(top.width, top.height, self.seed, target);
+            self.floors.push(Floor::new(dungeon, &self.bestiary));
+        }
+
+        let dungeon = &self.floors[target as usize].dungeon;
+        let (x, y) = dungeon.find_tile(arrival)
+            .or_else(|| dungeon.spawn_tile())
+            .ok_or_else(|| GameError::InternalError(format!("Floor {} has no walkable tile", target)))?;
+        let to = Location { instance: None, depth: target };
+        self.move_to(id, to, self.collision.tile_center(x, y));
+        self.sessions.send_to(id, &ServerMessage::FloorChanged { depth: target });
+        Ok(target)
+    }
+
+    /// Waits on the portal a player is standing on for another player to
+    /// take them into an instance
+    ///
+    /// Only characters can enter instances: guests get a new ID on every
+    /// connection, so they could never get back into an instance after
+    /// reconnecting.
+    ///
+    /// # Arguments
+    /// * `id` - UUID of the player waiting
+    /// * `leader` - UUID of the player to enter with
+    pub fn wait_at_portal(&mut self, id: Uuid, leader: Uuid) -> Result<(), GameError> {
+        let player = self.players.get(&id).ok_or(GameError::PlayerNotFound)?;
+        let (at, name) = (Location::of(player), player.name.clone());
+        if self.roster.get(id).is_none() {
+            return Err(GameError::InvalidAction("Guests cannot enter instances".to_string()));
+        }
+        if at.instance.is_some() || self.tile_under(at, player.position) != TileType::Portal {
+            return Err(GameError::InvalidMovement("Not standing on a portal".to_string()));
+        }
+        if leader == id || !self.players.contains_key(&leader) {
+            return Err(GameError::PlayerNotFound);
+        }
+        self.portal_waits.insert(id, leader);
+        self.sessions.send_to(leader, &ServerMessage::WaitingAtPortal { id, name });
+        Ok(())
+    }
+
+    /// Moves a player through the portal they are standing on
+    ///
+    /// From the shared dungeon this spins up a private instance from a seed
+    /// and takes the player inside together with the listed party members
+    /// waiting for them on the same portal; members who are elsewhere or
+    /// did not agree to come are left behind. Guests cannot enter. From
+    /// inside an instance it takes the player back out to the portal on the
+    /// top floor, and out of the instance's party.
+    ///
+    /// # Arguments
+    /// * `id` - UUID of the player
+    /// * `party` - Other players to take into the instance, if they wait for the player
+    /// * `seed` - Seed of the instance's dungeon
+    ///
+    /// # Returns
+    /// ID of the instance entered, or None when the player left one
+    pub fn enter_portal(&mut self, id: Uuid, party: &[Uuid], seed: u64) -> Result<Option<Uuid>, GameError> {
+        let player = self.players.get(&id).ok_or(GameError::PlayerNotFound)?;
+        let (at, origin) = (Location::of(player), player.position);
+        if self.tile_under(at, origin) != TileType::Portal {
+            return Err(GameError::InvalidMovement("Not standing on a portal".to_string()));
+        }
+        if let Some(instance) = at.instance {
+            self.leave_instance(id, instance);
+            return Ok(None);
+        }
+
+        if self.roster.get(id).is_none() {
+            return Err(GameError::InvalidAction("Guests cannot enter instances".to_string()));
+        }
+
+        let portal = self.collision.tile_of(origin);
+        let mut entrants = vec![id];
+        for member in party.iter().filter_map(|member| self.players.get(member)) {
+            let on_portal = Location::of(member) == at && self.collision.tile_of(member.position) == portal;
+            let waiting = self.portal_waits.get(&member.id) == Some(&id);
+            if on_portal && waiting && !self.dead.contains_key(&member.id) && !entrants.contains(&member.id) {
+                entrants.push(member.id);
+            }
+        }
+        for entrant in &entrants {
+            self.portal_waits.remove(entrant);
+        }
+
+        let dungeon = self.dungeon_generator.generate_instance(INSTANCE_SIZE, INSTANCE_SIZE, seed);
+        let (instance_id, seed) = (Uuid::new_v4(), dungeon.seed);
+        let spawn = self.spawn_position_in(&dungeon);
+        self.instances.insert(instance_id, Instance {
+            floor: Floor::new(dungeon, &self.bestiary),
+            members: entrants.iter().copied().collect(),
+            players: HashSet::new(),
+            created_tick: self.tick,
+            empty_since: None,
+        });
+        info!("Created instance {} from seed {} for {} players", instance_id, seed, entrants.len());
+
+        let to = Location { instance: Some(instance_id), depth: 0 };
+        for entrant in entrants {
+            self.move_to(entrant, to, spawn);
+            self.sessions.send_to(entrant, &ServerMessage::InstanceEntered { id: instance_id });
+        }
+        Ok(Some(instance_id))
+    }
+
+    /// Takes a player out of an instance to the portal on the top floor
+    ///
+    /// The player leaves the instance's party; an instance whose whole party
+    /// left is torn down at once.
+    fn leave_instance(&mut self, id: Uuid, instance_id: Uuid) {
+        let top = &self.floors[0].dungeon;
+        let position = match top.find_tile(TileType::Portal) {
+            Some((x, y)) => self.collision.tile_center(x, y),
+            None => self.spawn_position(),
+        };
+        self.move_to(id, Location::TOP, position);
+        self.sessions.send_to(id, &ServerMessage::FloorChanged { depth: 0 });
+
+        if let Some(instance) = self.instances.get_mut(&instance_id) {
+            instance.members.remove(&id);
+            if instance.members.is_empty() && instance.players.is_empty() {
+                self.instances.remove(&instance_id);
+                info!("Tearing down abandoned instance {}", instance_id);
+            }
+        }
+    }
+
+    /// Records that a player is no longer inside an instance
+    fn vacate_instance(&mut self, instance: Option<Uuid>, id: Uuid) {
+        let tick = self.tick;
+        if let Some(instance) = instance.and_then(|instance| self.instances.get_mut(&instance)) {
+            instance.players.remove(&id);
+            if instance.players.is_empty() {
+                instance.empty_since = Some(tick);
+            }
+        }
+    }
+
+    /// Moves a player to a position, possibly on another floor or instance
+    ///
+    /// When the floor changes, players left behind are told the player left
+    /// and the player is told everyone they could see left; the new floor's
+    /// players come into view on the next refresh.
+    ///
+    /// # Arguments
+    /// * `id` - UUID of the player
+    /// * `to` - Destination floor, which must exist
+    /// * `position` - Destination in world space
+    fn move_to(&mut self, id: Uuid, to: Location, position: Position) {
+        let Some(player) = self.players.get_mut(&id) else {
+            return;
+        };
+        let from = Location::of(player);
+        player.floor = to.depth;
+        player.instance = to.instance;
+        player.position = position;
+        self.inputs.remove(&id);
+        self.routes.remove(&id);
+
+        if from != to {
+            self.casts.remove(&id);
+            let (seen, observers) = match self.floor_mut(from) {
+                Some(floor) => (
+                    floor.interest.visible_to(id)
+                        .map(|seen| seen.iter().copied().collect())
+                        .unwrap_or_default(),
+                    {
+                        floor.sight.remove(&id);
+                        floor.interest.remove(id)
+                    },
+                ),
+                None => (Vec::new(), Vec::new()),
+            };
+            self.sessions.send_to_many(observers, &ServerMessage::EntityLeft { id });
+            for other in seen {
+                self.sessions.send_to(id, &ServerMessage::EntityLeft { id: other });
+            }
+
+            self.vacate_instance(from.instance, id);
+            if let Some(instance) = to.instance.and_then(|instance| self.instances.get_mut(&instance)) {
+                instance.players.insert(id);
+                instance.empty_since = None;
+            }
+            if let Some(floor) = self.floor_mut(to) {
+                floor.interest.add_observer(id);
+            }
+        }
+        if let Some(floor) = self.floor_mut(to) {
+            floor.interest.update_position(id, position);
+        }
+    }
+
+    /// Lists the live party instances
+    pub fn instances(&self) -> Vec<InstanceInfo> {
+        let grace = INSTANCE_GRACE_SECS * self.tick_rate as u64;
+        self.instances.iter()
+            .map(|(id, instance)| InstanceInfo {
+                id: *id,
+                players: instance.players.iter().copied().collect(),
+                members: instance.members.iter().copied().collect(),
+                created_tick: instance.created_tick,
+                expires_in_secs: instance.empty_since.map(|since| {
+                    (since + grace).saturating_sub(self.tick).div_ceil(self.tick_rate as u64)
+                }),
+            })
+            .collect()
+    }
+
+    /// Replaces the dungeon with the layout generated from a seed
+    ///
+    /// Deeper floors are discarded and every player in the shared dungeon is
+    /// moved to the new spawn point on the top floor; everyone is told about
+    /// the new layout. Party instances are left alone.
+    ///
+    /// # Arguments
+    /// * `seed` - Seed of the new layout
+    /// * `layout` - Layout to generate from now on, or None to keep the current one
+    ///
+    /// # Returns
+    /// Reference to the new dungeon
+    pub fn regenerate_dungeon(&mut self, seed: u64, layout: Option<DungeonLayout>) -> &Dungeon {
+        if let Some(layout) = layout {
+            self.dungeon_generator = DungeonGenerator::new(layout);
+        }
+        let top = &self.floors[0].dungeon;
+        let dungeon = self.dungeon_generator.generate_floor(top.width, top.height, seed, 0);
+        self.floors[0].replace(dungeon, &self.bestiary);
+        self.seed = seed;
+
+        let spawn = self.spawn_position();
+        let ids: Vec<Uuid> = self.players.values()
+            .filter(|player| player.instance.is_none())
+            .map(|player| player.id)
+            .collect();
+        for id in ids {
+            self.move_to(id, Location::TOP, spawn);
+        }
+        self.floors.truncate(1);
+        self.inputs.clear();
+        self.routes.clear();
+
+        self.sessions.broadcast(&ServerMessage::DungeonChanged);
+        &self.floors[0].dungeon
+    }
+
+    /// Registers the websocket session serving a player
+    ///
+    /// # Arguments
+    /// * `id` - UUID of the player
+    /// * `addr` - Address of the player's session actor
+    pub fn register_session(&mut self, id: Uuid, addr: Addr<WsGameSession>) {
+        self.sessions.register(id, addr);
+    }
+
+    /// Removes the websocket session serving a player
+    ///
+    /// # Arguments
+    /// * `id` - UUID of the player
+    pub fn unregister_session(&mut self, id: Uuid) {
+        self.sessions.unregister(id);
+    }
+
+    /// Gets the registry used to push messages to connected players
+    pub fn sessions(&self) -> &SessionRegistry {
+        &self.sessions
+    }
+
+    /// Finds the dungeon room a player is standing in
+    ///
+    /// # Arguments
+    /// * `id` - UUID of the player
+    ///
+    /// # Returns
+    /// Index into the room list of the player's floor, or None when in a corridor
+    pub fn room_of_player(&self, id: Uuid) -> Option<usize> {
+        let player = self.players.get(&id)?;
+        let (tile_x, tile_y) = self.collision.tile_of(player.position);
+        self.floor(Location::of(player))?.dungeon.room_at(tile_x, tile_y)
+    }
+
+    /// Sends a message to every player standing in the same dungeon room as
+    /// a player
+    ///
+    /// # Arguments
+    /// * `id` - UUID of the player whose room receives the message
+    /// * `message` - Message to deliver
+    ///
+    /// # Returns
+    /// Index of the room, or None when the player is not in a room and
+    /// nothing was sent
+    pub fn send_to_room(&self, id: Uuid, message: &ServerMessage) -> Option<usize> {
+        let at = Location::of(self.players.get(&id)?);
+        let room = self.room_of_player(id)?;
+        let occupants = self.players.values()
+            .filter(|player| Location::of(player) == at && self.room_of_player(player.id) == Some(room))
+            .map(|player| player.id);
+        self.sessions.send_to_many(occupants, message);
+        Some(room)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wallet::tests::{personal_sign, test_wallet};
+
+    /// Game state around the single open room of `Dungeon::new`
+    fn open_state() -> GameState {
+        GameState::with_dungeon(DungeonGenerator::default(), Dungeon::new(100, 100))
+    }
+
+    const RIGHT: Movement = Movement { up: false, down: false, left: false, right: true };
+
+    impl GameState {
+        /// Puts a player directly at a position on their floor
+        ///
+        /// The path is not checked; the player's box only has to fit at the
+        /// target. Players walk with `move_player_to`.
+        fn update_player_position(&mut self, id: Uuid, position: Position) -> Result<(), GameError> {
+            let at = self.players.get(&id)
+                .map(Location::of)
+                .ok_or(GameError::PlayerNotFound)?;
+            if !self.floor(at).is_some_and(|floor| self.collision.fits(&floor.dungeon, position)) {
+                return Err(GameError::InvalidPosition("Position out of bounds or in wall".to_string()));
+            }
+            if let Some(player) = self.players.get_mut(&id) {
+                player.position = position;
+            }
+            if let Some(floor) = self.floor_mut(at) {
+                floor.interest.update_position(id, position);
+            }
+            Ok(())
+        }
+
+        /// Gets the layout of the top floor
+        fn get_dungeon(&self) -> &Dungeon {
+            &self.floors[0].dungeon
+        }
+
+        /// Gets the layout of a floor, or None when no player has reached it yet
+        fn get_floor(&self, depth: u32) -> Option<&Dungeon> {
+            self.floors.get(depth as usize).map(|floor| &floor.dungeon)
+        }
+
+        /// Gets the layout of a party instance, or None when it is not live
+        fn get_instance(&self, id: Uuid) -> Option<&Dungeon> {
+            self.instances.get(&id).map(|instance| &instance.floor.dungeon)
+        }
+    }
+
+    #[test]
+    fn input_rate_does_not_change_speed() {
+        let mut state = open_state();
+        let spammer = state.add_player(Uuid::new_v4(), "spammer".to_string()).unwrap();
+        let casual = state.add_player(Uuid::new_v4(), "casual".to_string()).unwrap();
+
+        for _ in 0..3 {
+            for _ in 0..40 {
+                state.queue_movement(spammer.id, RIGHT);
+            }
+            state.queue_movement(casual.id, RIGHT);
+            state.advance_tick();
+        }
+
+        let spammer_x = state.get_player(spammer.id).unwrap().position.x;
+        let casual_x = state.get_player(casual.id).unwrap().position.x;
+        assert_eq!(spammer_x, casual_x);
+        assert_eq!(spammer_x, spammer.position.x + 3 * PLAYER_SPEED);
+    }
+
+    #[test]
+    fn held_input_applies_every_tick_until_it_expires() {
+        let mut state = open_state();
+        let player = state.add_player(Uuid::new_v4(), "walker".to_string()).unwrap();
+        state.queue_movement(player.id, RIGHT);
+
+        for _ in 0..20 {
+            state.advance_tick();
+        }
+
+        // 250ms of input at 30 Hz
+        assert_eq!(state.get_player(player.id).unwrap().position.x, player.position.x + 8 * PLAYER_SPEED);
+        assert_eq!(state.snapshot().tick, 20);
+    }
+
+    #[test]
+    fn distance_per_second_is_independent_of_tick_rate() {
+        let per_second = PLAYER_SPEED * REFERENCE_TICK_RATE as i32;
+        for rate in [20, 30, 45, 60, 64] {
+            // Room wide enough to walk for a second without reaching a wall
+            let mut state = GameState::with_dungeon(DungeonGenerator::default(), Dungeon::new(200, 200));
+            state.set_tick_rate(rate);
+            let player = state.add_player(Uuid::new_v4(), "walker".to_string()).unwrap();
+            for _ in 0..rate {
+                state.queue_movement(player.id, RIGHT);
+                state.advance_tick();
+            }
+            let covered = state.get_player(player.id).unwrap().position.x - player.position.x;
+            assert_eq!(covered, per_second, "{} Hz", rate);
+        }
+    }
+
+    #[test]
+    fn tick_snapshot_is_stamped_and_reflects_movement() {
+        let mut state = open_state();
+        let player = state.add_player(Uuid::new_v4(), "jumper".to_string()).unwrap();
+        let target = Position::new(player.position.x + 20, player.position.y);
+        state.update_player_position(player.id, target).unwrap();
+
+        let snapshot = state.advance_tick();
+        assert_eq!(snapshot.tick, 1);
+        assert_eq!(snapshot.entities[&player.id].position, target);
+    }
+
+    #[test]
+    fn regenerating_moves_players_to_the_new_spawn() {
+        let mut state = GameState::new(DungeonGenerator::default(), 42);
+        let player = state.add_player(Uuid::new_v4(), "explorer".to_string()).unwrap();
+        assert_eq!(state.get_dungeon().seed, 42);
+
+        let expected = DungeonGenerator::default().generate_floor(DUNGEON_SIZE, DUNGEON_SIZE, 7, 0);
+        let dungeon = state.regenerate_dungeon(7, None);
+        assert_eq!(dungeon.seed, 7);
+        assert_eq!(dungeon.tiles, expected.tiles);
+
+        let (x, y) = expected.rooms[0].center();
+        let position = state.get_player(player.id).unwrap().position;
+        assert_eq!(position, state.collision.tile_center(x, y));
+    }
+
+    #[test]
+    fn roomless_layouts_spawn_on_floor() {
+        let mut state = GameState::new(DungeonGenerator::default(), 1);
+        let layout = DungeonLayout::parse("cellularCaves").unwrap();
+        assert!(state.regenerate_dungeon(3, Some(layout)).rooms.is_empty());
+
+        let player = state.add_player(Uuid::new_v4(), "spelunker".to_string()).unwrap();
+        assert!(state.collision.fits(state.get_dungeon(), player.position));
+    }
+
+    /// Moves a player onto the stairs of their floor and takes them
+    fn descend(state: &mut GameState, id: Uuid, stairs: TileType) -> Result<u32, GameError> {
+        let depth = state.get_player(id).unwrap().floor;
+        let (x, y) = state.get_floor(depth).unwrap().find_tile(stairs).unwrap();
+        state.update_player_position(id, state.collision.tile_center(x, y)).unwrap();
+        state.take_stairs(id)
+    }
+
+    /// Runs a tick and refreshes every player's view
+    fn tick(state: &mut GameState) {
+        let snapshot = Arc::new(state.advance_tick());
+        state.publish_snapshot(snapshot);
+    }
+
+    fn sees(state: &GameState, observer: Uuid, id: Uuid) -> bool {
+        let at = Location::of(state.get_player(observer).unwrap());
+        state.floor(at).unwrap().interest.visible_to(observer).is_some_and(|seen| seen.contains(&id))
+    }
+
+    #[test]
+    fn stairs_move_players_between_floors() {
+        let mut state = GameState::new(DungeonGenerator::default(), 42);
+        let player = state.add_player(Uuid::new_v4(), "delver".to_string()).unwrap();
+        assert!(matches!(state.take_stairs(player.id), Err(GameError::InvalidMovement(_))));
+
+        assert_eq!(descend(&mut state, player.id, TileType::StairsDown).unwrap(), 1);
+        let floor = state.get_floor(1).unwrap();
+        assert_eq!(floor.seed, crate::core::game::dungeon::floor_seed(42, 1));
+        let (x, y) = floor.find_tile(TileType::StairsUp).unwrap();
+        let moved = state.get_player(player.id).unwrap();
+        assert_eq!((moved.floor, moved.position), (1, state.collision.tile_center(x, y)));
+
+        assert_eq!(state.take_stairs(player.id).unwrap(), 0);
+        let (x, y) = state.get_dungeon().find_tile(TileType::StairsDown).unwrap();
+        assert_eq!(state.get_player(player.id).unwrap().position, state.collision.tile_center(x, y));
+        assert_eq!(state.floors.len(), 2);
+    }
+
+    #[test]
+    fn players_on_other_floors_are_invisible() {
+        let mut state = GameState::new(DungeonGenerator::default(), 42);
+        let stayer = state.add_player(Uuid::new_v4(), "stayer".to_string()).unwrap();
+        let delver = state.add_player(Uuid::new_v4(), "delver".to_string()).unwrap();
+        tick(&mut state);
+        assert!(sees(&state, stayer.id, delver.id));
+
+        descend(&mut state, delver.id, TileType::StairsDown).unwrap();
+        // Wait by the stairs the delver took
+        let (x, y) = state.get_dungeon().find_tile(TileType::StairsDown).unwrap();
+        state.update_player_position(stayer.id, state.collision.tile_center(x, y)).unwrap();
+        tick(&mut state);
+
+        assert!(!sees(&state, stayer.id, delver.id));
+        assert!(!sees(&state, delver.id, stayer.id));
+        let snapshot = state.snapshot();
+        let view = snapshot.view(stayer.id, state.floors[0].interest.visible_to(stayer.id).unwrap());
+        assert!(!view.entities.contains_key(&delver.id));
+    }
+
+    #[test]
+    fn regenerating_brings_everyone_back_to_the_top_floor() {
+        let mut state = GameState::new(DungeonGenerator::default(), 42);
+        let player = state.add_player(Uuid::new_v4(), "delver".to_string()).unwrap();
+        descend(&mut state, player.id, TileType::StairsDown).unwrap();
+
+        state.regenerate_dungeon(9, None);
+        assert_eq!(state.get_player(player.id).unwrap().floor, 0);
+        assert!(state.get_floor(1).is_none());
+        assert_eq!(state.get_player(player.id).unwrap().position, state.spawn_position());
+    }
+
+    /// Moves a player onto the portal of the floor or instance they are in
+    fn step_on_portal(state: &mut GameState, id: Uuid) {
+        let player = state.get_player(id).unwrap();
+        let (x, y) = state.floor(Location::of(player)).unwrap().dungeon.find_tile(TileType::Portal).unwrap();
+        state.update_player_position(id, state.collision.tile_center(x, y)).unwrap();
+    }
+
+    /// Creates a character and brings it into the game
+    fn join_as(state: &mut GameState, name: &str) -> Player {
+        let character = state.create_character("0xabc", name, "warrior").unwrap();
+        state.join_character(character.id, "0xabc").unwrap()
+    }
+
+    #[test]
+    fn parties_enter_private_instances() {
+        let mut state = GameState::new(DungeonGenerator::default(), 42);
+        let leader = join_as(&mut state, "leader");
+        let follower = join_as(&mut state, "follower");
+        let bystander = join_as(&mut state, "bystander");
+        let stranger = join_as(&mut state, "stranger");
+        step_on_portal(&mut state, leader.id);
+        step_on_portal(&mut state, follower.id);
+        step_on_portal(&mut state, bystander.id);
+        state.wait_at_portal(follower.id, leader.id).unwrap();
+        state.wait_at_portal(stranger.id, leader.id).unwrap_err();
+
+        // The bystander is at the portal but did not agree to come, and the
+        // stranger is listed but not at the portal, so both are left behind
+        let instance = state.enter_portal(leader.id, &[follower.id, bystander.id, stranger.id], 7).unwrap().unwrap();
+        assert_eq!(state.get_player(leader.id).unwrap().instance, Some(instance));
+        assert_eq!(state.get_player(follower.id).unwrap().instance, Some(instance));
+        assert_eq!(state.get_player(bystander.id).unwrap().instance, None);
+        assert_eq!(state.get_player(stranger.id).unwrap().instance, None);
+        assert_eq!(state.get_instance(instance).unwrap().tiles, DungeonGenerator::default().generate_instance(INSTANCE_SIZE, INSTANCE_SIZE, 7).tiles);
+
+        // Meet the stranger where the party used to be
+        step_on_portal(&mut state, stranger.id);
+        tick(&mut state);
+        assert!(sees(&state, leader.id, follower.id));
+        assert!(!sees(&state, stranger.id, leader.id));
+
+        let info = state.instances();
+        assert_eq!(info.len(), 1);
+        assert_eq!(info[0].players.len(), 2);
+        assert!(!info[0].members.contains(&stranger.id));
+        assert_eq!(info[0].expires_in_secs, None);
+
+        // Guests would lose their place on reconnecting, so they stay out
+        let guest = state.add_player(Uuid::new_v4(), "guest".to_string()).unwrap();
+        step_on_portal(&mut state, guest.id);
+        assert!(matches!(state.enter_portal(guest.id, &[], 7), Err(GameError::InvalidAction(_))));
+        assert!(matches!(state.wait_at_portal(guest.id, stranger.id), Err(GameError::InvalidAction(_))));
+    }
+
+    #[test]
+    fn empty_instances_wait_for_reconnects_then_close() {
+        let mut state = GameState::new(DungeonGenerator::default(), 42);
+        let player = join_as(&mut state, "solo");
+        step_on_portal(&mut state, player.id);
+        let instance = state.enter_portal(player.id, &[], 3).unwrap().unwrap();
+
+        state.remove_player(player.id);
+        assert_eq!(state.instances()[0].expires_in_secs, Some(INSTANCE_GRACE_SECS));
+        for _ in 0..REFERENCE_TICK_RATE * 10 {
+            state.advance_tick();
+        }
+
+        // Sharing the name of a party member is not enough to get in
+        let impostor = state.add_player(Uuid::new_v4(), "SOLO".to_string());
+        assert!(impostor.is_err());
+
+        let rejoined = state.join_character(player.id, "0xabc").unwrap();
+        assert_eq!(rejoined.instance, Some(instance));
+        assert_eq!(state.instances()[0].expires_in_secs, None);
+
+        state.remove_player(rejoined.id);
+        for _ in 0..INSTANCE_GRACE_SECS * REFERENCE_TICK_RATE as u64 {
+            state.advance_tick();
+        }
+        assert!(state.instances().is_empty());
+        assert_eq!(state.join_character(player.id, "0xabc").unwrap().instance, None);
+    }
+
+    #[test]
+    fn leaving_through_the_portal_abandons_the_instance() {
+        let mut state = GameState::new(DungeonGenerator::default(), 42);
+        let player = join_as(&mut state, "quitter");
+        step_on_portal(&mut state, player.id);
+        let portal = state.get_player(player.id).unwrap().position;
+        state.enter_portal(player.id, &[], 3).unwrap();
+
+        step_on_portal(&mut state, player.id);
+        assert_eq!(state.enter_portal(player.id, &[], 3).unwrap(), None);
+        let player = state.get_player(player.id).unwrap();
+        assert_eq!((player.instance, player.position), (None, portal));
+        assert!(state.instances().is_empty());
+    }
+
+    #[test]
+    fn click_to_move_walks_the_path_over_ticks() {
+        let mut state = open_state();
+        let player = state.add_player(Uuid::new_v4(), "walker".to_string()).unwrap();
+        let (x, y) = state.collision.tile_of(player.position);
+        let target = state.collision.tile_center(x + 3, y + 2);
+
+        let waypoints = state.move_player_to(player.id, target).unwrap();
+        assert_eq!(waypoints.len(), 3);
+        assert_eq!(waypoints.last(), Some(&target));
+        for _ in 0..3 {
+            tick(&mut state);
+        }
+        assert_eq!(state.get_player(player.id).unwrap().position, target);
+        assert!(!state.routes.contains_key(&player.id));
+
+        assert!(matches!(
+            state.move_player_to(player.id, Position::new(0, 0)),
+            Err(GameError::InvalidMovement(_))
+        ));
+    }
+
+    #[test]
+    fn movement_keys_cancel_click_to_move() {
+        let mut state = open_state();
+        let player = state.add_player(Uuid::new_v4(), "walker".to_string()).unwrap();
+        let (x, y) = state.collision.tile_of(player.position);
+        state.move_player_to(player.id, state.collision.tile_center(x + 10, y)).unwrap();
+        tick(&mut state);
+
+        let up = Movement { up: true, down: false, left: false, right: false };
+        state.queue_movement(player.id, up);
+        tick(&mut state);
+        let position = state.get_player(player.id).unwrap().position;
+        assert_eq!(position, Position::new(player.position.x + 5, player.position.y - 5));
+    }
+
+    #[test]
+    fn doors_open_and_close() {
+        let mut state = open_state();
+        // The room's top door is at (50, 24)
+        let owner = state.add_player(Uuid::new_v4(), "owner".to_string()).unwrap();
+        let other = state.add_player(Uuid::new_v4(), "other".to_string()).unwrap();
+        for id in [owner.id, other.id] {
+            state.update_player_position(id, state.collision.tile_center(50, 25)).unwrap();
+        }
+
+        assert_eq!(state.interact(owner.id, 50, 24).unwrap(), DoorState::Open);
+        assert_eq!(state.get_dungeon().get_tile(50, 24), TileType::OpenDoor);
+        assert_eq!(state.interact(other.id, 50, 24).unwrap(), DoorState::Closed);
+        assert_eq!(state.interact(owner.id, 50, 24).unwrap(), DoorState::Open);
+
+        assert!(state.interact(owner.id, 50, 20).is_err());
+        assert!(state.interact(owner.id, 49, 24).is_err());
+    }
+
+    #[test]
+    fn generated_locked_doors_open_with_the_key_on_their_floor() {
+        let mut state = GameState::new(DungeonGenerator::default(), 42);
+        let lock = state.get_dungeon().locks[0];
+        let keeper = state.add_player(Uuid::new_v4(), "keeper".to_string()).unwrap();
+        let other = state.add_player(Uuid::new_v4(), "other".to_string()).unwrap();
+        let beside = [(1, 0), (-1, 0), (0, 1), (0, -1)].into_iter()
+            .map(|(dx, dy)| state.collision.tile_center(lock.x + dx, lock.y + dy))
+            .find(|position| state.collision.fits(state.get_dungeon(), *position))
+            .unwrap();
+
+        state.update_player_position(other.id, beside).unwrap();
+        assert!(matches!(state.interact(other.id, lock.x, lock.y), Err(GameError::InvalidMovement(_))));
+
+        state.update_player_position(keeper.id, state.collision.tile_center(lock.key_at.0, lock.key_at.1)).unwrap();
+        tick(&mut state);
+        state.pick_up_item(keeper.id, lock.key).unwrap();
+        state.update_player_position(keeper.id, beside).unwrap();
+        assert_eq!(state.interact(keeper.id, lock.x, lock.y).unwrap(), DoorState::Open);
+        assert_eq!(state.get_dungeon().get_tile(lock.x as usize, lock.y as usize), TileType::OpenDoor);
+        assert!(state.get_player(keeper.id).unwrap().inventory.get_item(lock.key).is_none());
+
+        // Doors cannot be locked again by players
+        assert_eq!(state.interact(other.id, lock.x, lock.y).unwrap(), DoorState::Closed);
+        assert_eq!(state.interact(other.id, lock.x, lock.y).unwrap(), DoorState::Open);
+        assert_eq!(state.get_dungeon().door_key(lock.x, lock.y), None);
+    }
+
+    #[test]
+    fn doors_do_not_close_on_players() {
+        let mut state = open_state();
+        let player = state.add_player(Uuid::new_v4(), "doorman".to_string()).unwrap();
+        state.update_player_position(player.id, state.collision.tile_center(50, 25)).unwrap();
+        state.interact(player.id, 50, 24).unwrap();
+
+        state.update_player_position(player.id, state.collision.tile_center(50, 24)).unwrap();
+        assert!(state.interact(player.id, 50, 24).is_err());
+        assert_eq!(state.get_dungeon().get_tile(50, 24), TileType::OpenDoor);
+    }
+
+    #[test]
+    fn click_to_move_opens_closed_doors_on_the_way() {
+        let mut state = open_state();
+        let player = state.add_player(Uuid::new_v4(), "walker".to_string()).unwrap();
+        state.update_player_position(player.id, state.collision.tile_center(50, 26)).unwrap();
+        let target = state.collision.tile_center(50, 21);
+
+        state.move_player_to(player.id, target).unwrap();
+        for _ in 0..10 {
+            tick(&mut state);
+        }
+        assert_eq!(state.get_player(player.id).unwrap().position, target);
+        assert_eq!(state.get_dungeon().get_tile(50, 24), TileType::OpenDoor);
+    }
+
+    #[test]
+    fn maps_fill_in_as_players_look_around_and_survive_reconnects() {
+        let mut state = open_state();
+        let scout = state.add_player(Uuid::new_v4(), "scout".to_string()).unwrap();
+        state.update_player_position(scout.id, state.collision.tile_center(50, 26)).unwrap();
+        tick(&mut state);
+
+        let tile = |map: &DungeonView, x: usize, y: usize| map.tiles[y * map.width + x];
+        let map = state.player_map(scout.id).unwrap();
+        assert_eq!(tile(&map, 50, 26), Some(TileType::Floor));
+        assert_eq!(tile(&map, 50, 24), Some(TileType::Door));
+        assert_eq!(tile(&map, 50, 20), None);
+        assert_eq!(tile(&map, 50, 90), None);
+
+        state.update_player_position(scout.id, state.collision.tile_center(50, 25)).unwrap();
+        state.interact(scout.id, 50, 24).unwrap();
+        tick(&mut state);
+        assert_eq!(tile(&state.player_map(scout.id).unwrap(), 50, 20), Some(TileType::Floor));
+
+        state.remove_player(scout.id);
+        // Taking the name of a player who left does not reveal their map
+        let impostor = state.add_player(Uuid::new_v4(), "scout".to_string()).unwrap();
+        assert!(state.player_map(impostor.id).unwrap().tiles.iter().all(Option::is_none));
+        state.remove_player(impostor.id);
+
+        let again = state.add_player(scout.id, "scout".to_string()).unwrap();
+        let map = state.player_map(again.id).unwrap();
+        assert_eq!(tile(&map, 50, 24), Some(TileType::OpenDoor));
+        assert_eq!(tile(&map, 50, 20), Some(TileType::Floor));
+    }
+
+    #[test]
+    fn closed_doors_hide_players_behind_them() {
+        let mut state = open_state();
+        let inside = state.add_player(Uuid::new_v4(), "inside".to_string()).unwrap();
+        let outside = state.add_player(Uuid::new_v4(), "outside".to_string()).unwrap();
+        state.update_player_position(inside.id, state.collision.tile_center(50, 26)).unwrap();
+        state.update_player_position(outside.id, state.collision.tile_center(50, 21)).unwrap();
+        tick(&mut state);
+        assert!(!sees(&state, inside.id, outside.id));
+        assert!(!state.snapshot_for(inside.id).unwrap().entities.contains_key(&outside.id));
+
+        state.update_player_position(inside.id, state.collision.tile_center(50, 25)).unwrap();
+        state.interact(inside.id, 50, 24).unwrap();
+        tick(&mut state);
+        assert!(sees(&state, inside.id, outside.id));
+        assert!(sees(&state, outside.id, inside.id));
+    }
+
+    #[test]
+    fn npcs_spawn_on_the_tick_and_are_streamed_to_players_who_see_them() {
+        let mut state = GameState::new(DungeonGenerator::default(), 42);
+        let player = state.add_player(Uuid::new_v4(), "hunter".to_string()).unwrap();
+        assert!(state.floors[0].population.npcs.is_empty());
+        tick(&mut state);
+
+        let npcs = &state.floors[0].population.npcs;
+        assert!(!npcs.is_empty());
+        assert_eq!(state.snapshot().entities.len(), npcs.len() + 1);
+        let (npc, position) = npcs.values().next().map(|npc| (npc.id, npc.position)).unwrap();
+        assert!(!sees(&state, player.id, npc));
+
+        state.update_player_position(player.id, position).unwrap();
+        tick(&mut state);
+        assert!(sees(&state, player.id, npc));
+        assert!(state.snapshot_for(player.id).unwrap().entities.contains_key(&npc));
+
+        state.regenerate_dungeon(43, None);
+        assert!(state.floors[0].population.npcs.is_empty());
+        tick(&mut state);
+        assert!(!sees(&state, player.id, npc));
+        assert!(!state.floors[0].population.npcs.contains_key(&npc));
+    }
+
+    #[test]
+    fn monsters_from_a_custom_bestiary_chase_players_they_see() {
+        let mut state = GameState::new(DungeonGenerator::default(), 42);
+        state.set_bestiary(Bestiary::parse(r#"{
+            "behaviors": { "stalker": { "node": "sequence", "children": [
+                { "node": "seePlayer", "range": 30 }, { "node": "chase" }
+            ] } },
+            "npcs": [{ "name": "Stalker", "hostile": true, "health": 10, "stats": { "damage": 1, "defense": 0, "magic": 0 },
+                       "minDepth": 0, "weight": 1, "speed": 50, "behavior": "stalker" }]
+        }"#).unwrap());
+        let player = state.add_player(Uuid::new_v4(), "prey".to_string()).unwrap();
+        tick(&mut state);
+
+        let collision = state.collision;
+        let floor = &state.floors[0];
+        assert!(floor.population.npcs.values().all(|npc| npc.name == "Stalker"));
+        let (npc, start) = floor.population.npcs.values().next().map(|npc| (npc.id, collision.tile_of(npc.position))).unwrap();
+        let room = &floor.dungeon.rooms[floor.dungeon.room_at(start.0, start.1).unwrap()];
+        let corner = [(room.x, room.y), (room.x + room.width - 1, room.y), (room.x, room.y + room.height - 1), (room.x + room.width - 1, room.y + room.height - 1)]
+            .into_iter()
+            .max_by_key(|&(x, y)| (x - start.0).abs().max((y - start.1).abs()))
+            .unwrap();
+        state.update_player_position(player.id, collision.tile_center(corner.0, corner.1)).unwrap();
+
+        for _ in 0..60 {
+            tick(&mut state);
+        }
+        let end = collision.tile_of(state.floors[0].population.npcs[&npc].position);
+        assert!((end.0 - corner.0).abs().max((end.1 - corner.1).abs()) <= 1, "stopped at {:?}, player at {:?}", end, corner);
+        assert!(sees(&state, player.id, npc));
+    }
+
+    /// Moves a player onto an open tile next to an NPC of the top floor
+    fn stand_next_to(state: &mut GameState, player: Uuid) -> Uuid {
+        let collision = state.collision;
+        let floor = &state.floors[0];
+        let npc = floor.population.npcs.values().min_by_key(|npc| npc.id).unwrap();
+        let (x, y) = collision.tile_of(npc.position);
+        let next = [(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)]
+            .into_iter()
+            .find(|&(x, y)| !floor.dungeon.get_tile(x as usize, y as usize).is_solid())
+            .unwrap();
+        let id = npc.id;
+        state.update_player_position(player, collision.tile_center(next.0, next.1)).unwrap();
+        id
+    }
+
+    #[test]
+    fn players_kill_npcs_and_take_their_loot() {
+        let mut state = GameState::new(DungeonGenerator::default(), 42);
+        state.set_bestiary(Bestiary::parse(r#"{
+            "behaviors": { "still": { "node": "idle" } },
+            "npcs": [{ "name": "Training Dummy", "hostile": true, "health": 30, "stats": { "damage": 0, "defense": 0, "magic": 0 },
+                       "minDepth": 0, "weight": 1, "speed": 0, "behavior": "still",
+                       "loot": [{ "name": "Straw", "itemType": "Quest", "rarity": "Common", "value": 1,
+                                  "stats": { "damage": 0, "defense": 0, "magic": 0 }, "chance": 1.0 }] }]
+        }"#).unwrap());
+        let player = state.add_player(Uuid::new_v4(), "fighter".to_string()).unwrap();
+        let sword = Item {
+            id: Uuid::new_v4(),
+            name: "Greatsword".to_string(),
+            item_type: ItemType::Weapon,
+            rarity: Rarity::Rare,
+            value: 0,
+            attributes: Vec::new(),
+            stats: Stats { damage: 20, ..Default::default() },
+            stack_size: 1,
+            slots: vec![EquipmentSlot::MainHand],
+            two_handed: true,
+            requirements: Requirements::default(),
+        };
+        let sword_id = sword.id;
+        state.players.get_mut(&player.id).unwrap().inventory.add_item(sword);
+        state.equip_item(player.id, sword_id, EquipmentSlot::MainHand).unwrap();
+        tick(&mut state);
+        let target = stand_next_to(&mut state, player.id);
+
+        let mut swings = 0;
+        while state.floors[0].population.npcs.contains_key(&target) {
+            assert!(swings < 20, "the dummy survived {} swings", swings);
+            state.attack(player.id, target, AttackKind::Melee).unwrap();
+            assert!(matches!(state.attack(player.id, target, AttackKind::Melee), Err(GameError::InvalidAction(_))));
+            swings += 1;
+            for _ in 0..(AttackKind::Melee.cooldown_ms() * REFERENCE_TICK_RATE as u64).div_ceil(1000) {
+                tick(&mut state);
+            }
+        }
+
+        assert!(state.get_player(player.id).unwrap().inventory.items().any(|item| item.name == "Straw"));
+        assert!(matches!(state.attack(player.id, target, AttackKind::Ranged), Err(GameError::InvalidAction(_))));
+    }
+
+    #[test]
+    fn players_killed_by_npcs_respawn_at_the_start() {
+        let mut state = GameState::new(DungeonGenerator::default(), 42);
+        state.set_bestiary(Bestiary::parse(r#"{
+            "behaviors": { "butcher": { "node": "sequence", "children": [
+                { "node": "seePlayer", "range": 30 }, { "node": "targetInRange", "range": 1 }, { "node": "attack", "cooldownMs": 100 }
+            ] } },
+            "npcs": [{ "name": "Butcher", "hostile": true, "health": 10, "stats": { "damage": 500, "defense": 0, "magic": 0 },
+                       "minDepth": 0, "weight": 1, "speed": 0, "behavior": "butcher" }]
+        }"#).unwrap());
+        let player = state.add_player(Uuid::new_v4(), "victim".to_string()).unwrap();
+        let start = player.position;
+        tick(&mut state);
+        stand_next_to(&mut state, player.id);
+
+        let mut ticks = 0;
+        while !state.dead.contains_key(&player.id) {
+            assert!(ticks < 60, "the player survived {} ticks", ticks);
+            tick(&mut state);
+            ticks += 1;
+        }
+        assert_eq!(state.get_player(player.id).unwrap().health, 0);
+        assert!(matches!(state.move_player_to(player.id, start), Err(GameError::InvalidAction(_))));
+
+        for _ in 0..PLAYER_RESPAWN_SECS * REFERENCE_TICK_RATE as u64 {
+            tick(&mut state);
+        }
+        let player = state.get_player(player.id).unwrap();
+        assert!(!state.dead.contains_key(&player.id));
+        assert_eq!(player.health, player.max_health);
+        assert_eq!(player.floor, 0);
+        let spawn = state.collision.tile_of(start);
+        assert_eq!(state.collision.tile_of(player.position), spawn);
+    }
+
+    #[test]
+    fn kills_and_quests_level_players_up_to_spend_stat_points() {
+        let mut state = GameState::new(DungeonGenerator::default(), 42);
+        state.set_bestiary(Bestiary::parse(r#"{
+            "behaviors": { "still": { "node": "idle" } },
+            "npcs": [{ "name": "Practice Dummy", "hostile": true, "health": 1, "experience": 150, "stats": { "damage": 0, "defense": 0, "magic": 0 },
+                       "minDepth": 0, "weight": 1, "speed": 0, "behavior": "still" }]
+        }"#).unwrap());
+        let player = state.add_player(Uuid::new_v4(), "hero".to_string()).unwrap();
+        assert_eq!((player.level, player.stat_points), (1, 0));
+        tick(&mut state);
+        let target = stand_next_to(&mut state, player.id);
+        while state.floors[0].population.npcs.contains_key(&target) {
+            state.attack(player.id, target, AttackKind::Melee).unwrap();
+            for _ in 0..(AttackKind::Melee.cooldown_ms() * REFERENCE_TICK_RATE as u64).div_ceil(1000) {
+                tick(&mut state);
+            }
+        }
+
+        let leveled = state.get_player(player.id).unwrap();
+        assert_eq!((leveled.level, leveled.exp, leveled.stat_points), (2, 150, 3));
+        assert_eq!(leveled.max_exp, state.progression.experience[2]);
+        assert!(leveled.max_health > player.max_health);
+
+        let max_health = leveled.max_health + 3 * state.progression.health.per_attribute;
+        let strong = state.allocate_attributes(player.id, AttributeAllocation { strength: 3, ..Default::default() }).unwrap();
+        assert_eq!((strong.attributes.strength, strong.stat_points), (player.attributes.strength + 3, 0));
+        assert_eq!(strong.max_health, max_health);
+        let more = AttributeAllocation { dexterity: 1, ..Default::default() };
+        assert!(matches!(state.allocate_attributes(player.id, more), Err(GameError::InvalidAction(_))));
+
+        state.grant_experience(player.id, 100, ExperienceSource::Quest);
+        let quested = state.get_player(player.id).unwrap();
+        assert_eq!((quested.level, quested.stat_points), (3, 3));
+    }
+
+    #[test]
+    fn kills_complete_quests_once() {
+        let mut state = GameState::new(DungeonGenerator::default(), 42);
+        state.set_bestiary(Bestiary::parse(r#"{
+            "behaviors": { "still": { "node": "idle" } },
+            "npcs": [{ "name": "Practice Dummy", "hostile": true, "health": 1, "experience": 10, "stats": { "damage": 0, "defense": 0, "magic": 0 },
+                       "minDepth": 0, "weight": 1, "speed": 0, "behavior": "still" }]
+        }"#).unwrap());
+        state.set_quests(QuestBook::parse(r#"{ "quests": {
+            "dummies": { "name": "Dummies", "target": "Practice Dummy", "count": 2, "experience": 50 }
+        } }"#).unwrap());
+        let id = state.add_player(Uuid::new_v4(), "hero".to_string()).unwrap().id;
+        tick(&mut state);
+        for kills in 1..=3 {
+            let target = stand_next_to(&mut state, id);
+            while state.floors[0].population.npcs.contains_key(&target) {
+                state.attack(id, target, AttackKind::Melee).unwrap();
+                for _ in 0..(AttackKind::Melee.cooldown_ms() * REFERENCE_TICK_RATE as u64).div_ceil(1000) {
+                    tick(&mut state);
+                }
+            }
+            let player = state.get_player(id).unwrap();
+            let quest_experience = if kills >= 2 { 50 } else { 0 };
+            assert_eq!(player.exp, 10 * kills + quest_experience);
+            assert_eq!(player.quests.is_completed("dummies"), kills >= 2);
+        }
+    }
+
+    #[test]
+    fn characters_are_created_from_classes_and_keep_their_progress() {
+        let mut state = GameState::new(DungeonGenerator::default(), 42);
+        let (wallet, address) = test_wallet();
+        assert!(matches!(state.account_challenge("0xabc"), Err(GameError::InvalidAction(_))));
+        assert!(matches!(state.register_account(&address, "0x00"), Err(GameError::InvalidAction(_))));
+        let challenge = state.account_challenge(&address).unwrap();
+        let stale = personal_sign(&wallet, &challenge);
+        let challenge = state.account_challenge(&address).unwrap();
+        assert!(matches!(state.register_account(&address, &stale), Err(GameError::Unauthorized(_))));
+        assert!(matches!(state.register_account(&address, &personal_sign(&wallet, &challenge)), Err(GameError::InvalidAction(_))));
+        let challenge = state.account_challenge(&address).unwrap();
+        let key = state.register_account(&address, &personal_sign(&wallet, &challenge)).unwrap();
+        state.authenticate(&address, key).unwrap();
+        assert!(matches!(state.authenticate(&address, Uuid::new_v4()), Err(GameError::Unauthorized(_))));
+        assert!(matches!(state.authenticate("0xdef", key), Err(GameError::Unauthorized(_))));
+        let mage = state.create_character("0xabc", "Merlin", "mage").unwrap();
+        let warrior = state.create_character("0xabc", "Conan", "warrior").unwrap();
+        assert_eq!(mage.class.as_deref(), Some("mage"));
+        assert!(mage.attributes.intelligence > warrior.attributes.intelligence);
+        assert!(mage.max_mana > warrior.max_mana && warrior.max_health > mage.max_health);
+        assert!(mage.inventory.items().any(|item| item.name == "Apprentice Staff"));
+        assert_eq!(mage.hotbar[0].as_deref(), Some("firebolt"));
+        assert_eq!(warrior.hotbar[0].as_deref(), Some("concuss"));
+
+        assert!(matches!(state.create_character("0xabc", "merlin", "ranger"), Err(GameError::InvalidName(_))));
+        assert!(matches!(state.create_character("0xabc", "X", "ranger"), Err(GameError::InvalidName(_))));
+        assert!(matches!(state.create_character("0xabc", "Robin", "bard"), Err(GameError::InvalidAction(_))));
+        assert!(matches!(state.add_player(Uuid::new_v4(), "MERLIN".to_string()), Err(GameError::InvalidName(_))));
+        assert!(matches!(state.add_player(Uuid::new_v4(), "Dark Lord".to_string()), Err(GameError::InvalidName(_))));
+        for name in ["Robin", "Marian", "Tuck"] {
+            state.create_character("0xabc", name, "ranger").unwrap();
+        }
+        assert!(matches!(state.create_character("0xabc", "John", "ranger"), Err(GameError::InvalidAction(_))));
+        assert!(state.create_character("0xdef", "John", "cleric").is_ok());
+        let names: Vec<&str> = state.characters("0xabc").iter().map(|character| character.name.as_str()).collect();
+        assert_eq!(names, ["Conan", "Marian", "Merlin", "Robin", "Tuck"]);
+
+        let joined = state.join_character(mage.id, "0xabc").unwrap();
+        assert_eq!(joined.position, state.spawn_position());
+        assert!(matches!(state.join_character(mage.id, "0xabc"), Err(GameError::InvalidAction(_))));
+        assert!(matches!(state.join_character(Uuid::new_v4(), "0xabc"), Err(GameError::CharacterNotFound)));
+        assert!(matches!(state.join_character(warrior.id, "0xdef"), Err(GameError::Unauthorized(_))));
+        state.grant_experience(mage.id, 120, ExperienceSource::Quest);
+        state.remove_player(mage.id);
+
+        let rejoined = state.join_character(mage.id, "0xabc").unwrap();
+        assert_eq!((rejoined.level, rejoined.exp), (2, 120));
+        assert_eq!(rejoined.inventory.items().count(), mage.inventory.items().count());
+    }
+
+    /// Brings a warrior into the game and kills them, returning their ID and
+    /// the IDs of their sword and vest
+    fn dead_warrior(state: &mut GameState) -> (Uuid, Uuid, Uuid) {
+        let id = join_as(state, "Conan").id;
+        let find = |state: &GameState, name: &str| {
+            state.get_player(id).unwrap().inventory.items().find(|item| item.name == name).unwrap().id
+        };
+        let (sword, vest) = (find(state, "Iron Sword"), find(state, "Leather Vest"));
+        state.equip_item(id, vest, EquipmentSlot::Chest).unwrap();
+        state.players.get_mut(&id).unwrap().health = 0;
+        state.dead.insert(id, u64::MAX);
+        (id, sword, vest)
+    }
+
+    #[test]
+    fn dead_players_cannot_equip_items() {
+        let mut state = GameState::new(DungeonGenerator::default(), 42);
+        let (id, sword, _) = dead_warrior(&mut state);
+        assert!(matches!(state.equip_item(id, sword, EquipmentSlot::MainHand), Err(GameError::InvalidAction(reason)) if reason == "You are dead"));
+        assert!(state.get_player(id).unwrap().inventory.equipment().get(&EquipmentSlot::MainHand).is_none());
+    }
+
+    #[test]
+    fn dead_players_cannot_unequip_items() {
+        let mut state = GameState::new(DungeonGenerator::default(), 42);
+        let (id, _, vest) = dead_warrior(&mut state);
+        assert!(matches!(state.unequip_item(id, EquipmentSlot::Chest), Err(GameError::InvalidAction(reason)) if reason == "You are dead"));
+        assert_eq!(state.get_player(id).unwrap().inventory.equipment().get(&EquipmentSlot::Chest).map(|item| item.id), Some(vest));
+    }
+
+    #[test]
+    fn dead_players_cannot_drop_items() {
+        let mut state = GameState::new(DungeonGenerator::default(), 42);
+        let (id, sword, _) = dead_warrior(&mut state);
+        assert!(matches!(state.drop_item(id, sword), Err(GameError::InvalidAction(reason)) if reason == "You are dead"));
+        assert!(state.get_player(id).unwrap().inventory.get_item(sword).is_some());
+        assert!(!state.floors[0].ground.contains_key(&sword));
+    }
+
+    #[test]
+    fn items_are_equipped_used_dropped_and_picked_up() {
+        let mut state = GameState::new(DungeonGenerator::default(), 42);
+        let warrior = state.create_character("0xabc", "Conan", "warrior").unwrap();
+        let id = state.join_character(warrior.id, "0xabc").unwrap().id;
+        let find = |state: &GameState, name: &str| {
+            state.get_player(id).unwrap().inventory.items().find(|item| item.name == name).map(|item| item.id)
+        };
+        let (sword, vest, potion) = (find(&state, "Iron Sword").unwrap(), find(&state, "Leather Vest").unwrap(), find(&state, "Healing Potion").unwrap());
+
+        state.equip_item(id, sword, EquipmentSlot::MainHand).unwrap();
+        assert_eq!(CombatStats::of_player(state.get_player(id).unwrap()).damage, 6);
+        assert!(matches!(state.equip_item(id, potion, EquipmentSlot::Chest), Err(GameError::InvalidEquipment(EquipError::NotEquippable { .. }))));
+        assert!(matches!(state.equip_item(id, vest, EquipmentSlot::Feet), Err(GameError::InvalidEquipment(EquipError::WrongSlot { .. }))));
+        state.unequip_item(id, EquipmentSlot::MainHand).unwrap();
+        assert!(find(&state, "Iron Sword").is_some());
+        assert!(matches!(state.unequip_item(id, EquipmentSlot::MainHand), Err(GameError::InvalidEquipment(EquipError::NothingEquipped))));
+
+        let mut axe = state.get_player(id).unwrap().inventory.get_item(sword).unwrap().clone();
+        axe.id = Uuid::new_v4();
+        axe.requirements = Requirements { level: 3, classes: vec!["warrior".to_string()], ..Default::default() };
+        let axe_id = axe.id;
+        state.players.get_mut(&id).unwrap().inventory.add_item(axe);
+        assert!(matches!(state.equip_item(id, axe_id, EquipmentSlot::MainHand), Err(GameError::InvalidEquipment(EquipError::LevelTooLow { required: 3 }))));
+        let third_level = state.progression.experience[2];
+        state.grant_experience(id, third_level, ExperienceSource::Quest);
+        state.equip_item(id, axe_id, EquipmentSlot::MainHand).unwrap();
+
+        assert!(matches!(state.use_item(id, potion), Err(GameError::InvalidAction(reason)) if reason == "Your health is already full"));
+        assert!(find(&state, "Healing Potion").is_some());
+        state.players.get_mut(&id).unwrap().health = 10;
+        state.use_item(id, potion).unwrap();
+        assert_eq!(state.get_player(id).unwrap().health, 40);
+        assert!(find(&state, "Healing Potion").is_none());
+
+        let mut elixir = state.get_player(id).unwrap().inventory.get_item(vest).unwrap().clone();
+        elixir.id = Uuid::new_v4();
+        elixir.name = "Mana Potion".to_string();
+        elixir.item_type = ItemType::Consumable;
+        elixir.slots.clear();
+        elixir.stats = Stats { mana: 25, ..Default::default() };
+        let elixir_id = elixir.id;
+        state.players.get_mut(&id).unwrap().inventory.add_item(elixir);
+        assert!(matches!(state.use_item(id, elixir_id), Err(GameError::InvalidAction(reason)) if reason == "Your mana is already full"));
+        let capacity = state.get_player(id).unwrap().mana_capacity();
+        state.players.get_mut(&id).unwrap().mana = capacity - 40;
+        state.use_item(id, elixir_id).unwrap();
+        assert_eq!(state.get_player(id).unwrap().mana, capacity - 15);
+        assert!(find(&state, "Mana Potion").is_none());
+        assert!(matches!(state.use_item(id, potion), Err(GameError::InvalidAction(_))));
+        assert!(matches!(state.use_item(id, vest), Err(GameError::InvalidAction(_))));
+
+        state.drop_item(id, vest).unwrap();
+        assert!(find(&state, "Leather Vest").is_none());
+        assert!(state.floors[0].ground.contains_key(&vest));
+        state.pick_up_item(id, vest).unwrap();
+        assert!(find(&state, "Leather Vest").is_some());
+        assert!(!state.floors[0].ground.contains_key(&vest));
+        assert!(matches!(state.pick_up_item(id, vest), Err(GameError::InvalidAction(_))));
+    }
+
+    fn spellbook() -> SkillBook {
+        SkillBook::parse(r#"{
+            "skills": {
+                "zap": { "name": "Zap", "manaCost": 30, "cooldownMs": 2000, "castMs": 100, "range": 5, "targeting": "entity",
+                         "effect": { "effect": "damage", "power": 5, "scalingPercent": 50 } },
+                "blink": { "name": "Blink", "manaCost": 10, "cooldownMs": 0, "range": 3, "targeting": "ground",
+                           "effect": { "effect": "teleport" } }
+            },
+            "hotbar": ["zap", "blink"]
+        }"#).unwrap()
+    }
+
+    #[test]
+    fn spells_spend_mana_when_their_cast_completes() {
+        let mut state = GameState::new(DungeonGenerator::default(), 42);
+        state.set_bestiary(Bestiary::parse(r#"{
+            "behaviors": { "still": { "node": "idle" } },
+            "npcs": [{ "name": "Training Dummy", "hostile": true, "health": 500, "stats": { "damage": 0, "defense": 50, "magic": 0 },
+                       "minDepth": 0, "weight": 1, "speed": 0, "behavior": "still" }]
+        }"#).unwrap());
+        state.set_skills(spellbook());
+        let player = state.add_player(Uuid::new_v4(), "mage".to_string()).unwrap();
+        assert_eq!(player.hotbar[0].as_deref(), Some("zap"));
+        tick(&mut state);
+        let target = stand_next_to(&mut state, player.id);
+        state.players.get_mut(&player.id).unwrap().mana = 50;
+
+        state.cast_skill(player.id, 0, Some(target), None).unwrap();
+        assert!(matches!(state.cast_skill(player.id, 0, Some(target), None), Err(GameError::InvalidAction(_))));
+        assert_eq!(state.get_player(player.id).unwrap().mana, 50);
+        for _ in 0..3 {
+            tick(&mut state);
+        }
+        assert_eq!(state.get_player(player.id).unwrap().mana, 20);
+        let health = state.floors[0].population.npcs[&target].health;
+        assert!(health < 500 - 5, "armor soaked up the spell: {}", health);
+        assert!(matches!(state.cast_skill(player.id, 0, Some(target), None), Err(GameError::InvalidAction(_))));
+
+        for _ in 0..60 {
+            tick(&mut state);
+        }
+        assert!(matches!(state.cast_skill(player.id, 0, Some(target), None), Err(GameError::InvalidAction(_))));
+        state.players.get_mut(&player.id).unwrap().mana = 50;
+        state.cast_skill(player.id, 0, Some(target), None).unwrap();
+        state.queue_movement(player.id, RIGHT);
+        for _ in 0..3 {
+            tick(&mut state);
+        }
+        assert_eq!(state.floors[0].population.npcs[&target].health, health);
+        assert_eq!(state.get_player(player.id).unwrap().mana, 50);
+    }
+
+    #[test]
+    fn blink_teleports_within_range_and_sight() {
+        let mut state = open_state();
+        state.set_skills(spellbook());
+        let player = state.add_player(Uuid::new_v4(), "blinker".to_string()).unwrap();
+        let (x, y) = state.collision.tile_of(player.position);
+
+        let far = state.collision.tile_center(x + 4, y);
+        assert!(matches!(state.cast_skill(player.id, 1, None, Some(far)), Err(GameError::InvalidAction(_))));
+        assert!(matches!(state.cast_skill(player.id, 1, None, None), Err(GameError::InvalidAction(_))));
+        let near = state.collision.tile_center(x + 3, y);
+        state.cast_skill(player.id, 1, None, Some(near)).unwrap();
+        let player = state.get_player(player.id).unwrap();
+        assert_eq!(player.position, near);
+        assert_eq!(player.mana, player.max_mana - 10);
+    }
+
+    #[test]
+    fn hotbar_slots_only_hold_known_skills() {
+        let mut state = open_state();
+        state.set_skills(spellbook());
+        let player = state.add_player(Uuid::new_v4(), "binder".to_string()).unwrap();
+        let hotbar = state.bind_skill(player.id, 5, Some("zap".to_string())).unwrap();
+        assert_eq!(hotbar[5].as_deref(), Some("zap"));
+        assert_eq!(state.bind_skill(player.id, 0, None).unwrap()[0], None);
+        assert!(state.bind_skill(player.id, 5, Some("meteor".to_string())).is_err());
+        assert!(state.bind_skill(player.id, HOTBAR_SLOTS, Some("zap".to_string())).is_err());
+        assert!(matches!(state.cast_skill(player.id, 0, None, None), Err(GameError::InvalidAction(_))));
+    }
+
+    #[test]
+    fn stunned_npcs_hold_still_while_poison_kills_them() {
+        let mut state = GameState::new(DungeonGenerator::default(), 42);
+        state.set_bestiary(Bestiary::parse(r#"{
+            "behaviors": { "butcher": { "node": "sequence", "children": [
+                { "node": "seePlayer", "range": 30 }, { "node": "targetInRange", "range": 1 }, { "node": "attack", "cooldownMs": 100 }
+            ] } },
+            "npcs": [{ "name": "Butcher", "hostile": true, "health": 40, "stats": { "damage": 500, "defense": 0, "magic": 0 },
+                       "minDepth": 0, "weight": 1, "speed": 0, "behavior": "butcher" }]
+        }"#).unwrap());
+        state.set_skills(SkillBook::parse(r#"{
+            "skills": {
+                "daze": { "name": "Daze", "manaCost": 0, "cooldownMs": 0, "range": 1, "targeting": "entity",
+                          "effect": { "effect": "status", "status": { "status": "stun" }, "durationMs": 2000 } },
+                "venom": { "name": "Venom", "manaCost": 0, "cooldownMs": 0, "range": 1, "targeting": "entity",
+                           "effect": { "effect": "status", "status": { "status": "poison", "damage": 10, "intervalMs": 200 }, "durationMs": 5000 } }
+            },
+            "hotbar": ["daze", "venom"]
+        }"#).unwrap());
+        let player = state.add_player(Uuid::new_v4(), "alchemist".to_string()).unwrap();
+        tick(&mut state);
+        let target = stand_next_to(&mut state, player.id);
+
+        state.cast_skill(player.id, 0, Some(target), None).unwrap();
+        state.cast_skill(player.id, 1, Some(target), None).unwrap();
+        state.cast_skill(player.id, 1, Some(target), None).unwrap();
+        let effects = &state.snapshot().entities[&target].effects;
+        assert_eq!(effects.iter().map(|effect| (effect.kind, effect.stacks)).collect::<Vec<_>>(),
+                   vec![(StatusKind::Poison, 2), (StatusKind::Stun, 1)]);
+
+        for _ in 0..30 {
+            tick(&mut state);
+        }
+        assert!(!state.floors[0].population.npcs.contains_key(&target));
+        assert!(!state.effects.contains_key(&target));
+        assert!(!state.dead.contains_key(&player.id));
+        assert_eq!(state.get_player(player.id).unwrap().health, 100);
+    }
+}
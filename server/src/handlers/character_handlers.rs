@@ -0,0 +1,79 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use std::sync::Arc;
+use parking_lot::RwLock;
+use uuid::Uuid;
+
+use crate::game::GameState;
+use crate::types::{AccountChallenge, AccountKey, AccountQuery, CreateCharacterRequest, RegisterAccountRequest};
+use crate::error::GameError;
+use crate::handlers::bearer_token;
+
+/// Gets the account key sent as a bearer token with a request
+fn account_key(request: &HttpRequest) -> Result<Uuid, GameError> {
+    bearer_token(request)
+        .and_then(|token| Uuid::parse_str(token).ok())
+        .ok_or_else(|| GameError::Unauthorized("Send the account's key as a bearer token".to_string()))
+}
+
+/// Issues the challenge the wallet of an account signs to register it
+pub async fn account_challenge(
+    game_state: web::Data<Arc<RwLock<GameState>>>,
+    request: web::Json<AccountQuery>,
+) -> Result<HttpResponse, GameError> {
+    let mut state = game_state.write();
+    let message = state.account_challenge(&request.account)?;
+
+    Ok(HttpResponse::Ok().json(AccountChallenge { account: request.account.clone(), message }))
+}
+
+/// Registers an account whose wallet signed its challenge
+///
+/// Responds with the account's secret key; listing, creating and joining
+/// the account's characters require it. Registering again replaces the key.
+pub async fn register_account(
+    game_state: web::Data<Arc<RwLock<GameState>>>,
+    request: web::Json<RegisterAccountRequest>,
+) -> Result<HttpResponse, GameError> {
+    let mut state = game_state.write();
+    let key = state.register_account(&request.account, &request.signature)?;
+
+    Ok(HttpResponse::Created().json(AccountKey { account: request.account.clone(), key }))
+}
+
+/// Lists the classes characters can be created with, keyed by the name
+/// to create them with
+pub async fn get_classes(
+    game_state: web::Data<Arc<RwLock<GameState>>>,
+) -> Result<HttpResponse, GameError> {
+    let state = game_state.read();
+
+    Ok(HttpResponse::Ok().json(&state.classes().classes))
+}
+
+/// Lists the characters of an account
+pub async fn list_characters(
+    game_state: web::Data<Arc<RwLock<GameState>>>,
+    http_request: HttpRequest,
+    query: web::Query<AccountQuery>,
+) -> Result<HttpResponse, GameError> {
+    let state = game_state.read();
+    state.authenticate(&query.account, account_key(&http_request)?)?;
+
+    Ok(HttpResponse::Ok().json(state.characters(&query.account)))
+}
+
+/// Creates a character of a class for an account
+///
+/// The character joins the game when a client sends `join` with its ID
+/// and the account's key.
+pub async fn create_character(
+    game_state: web::Data<Arc<RwLock<GameState>>>,
+    http_request: HttpRequest,
+    request: web::Json<CreateCharacterRequest>,
+) -> Result<HttpResponse, GameError> {
+    let mut state = game_state.write();
+    state.authenticate(&request.account, account_key(&http_request)?)?;
+    let character = state.create_character(&request.account, &request.name, &request.class)?;
+
+    Ok(HttpResponse::Created().json(character))
+}
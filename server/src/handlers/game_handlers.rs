@@ -1,26 +1,98 @@
-use actix_web::{web, HttpResponse};
+use actix_web::{web, HttpRequest, HttpResponse};
 use std::sync::Arc;
 use parking_lot::RwLock;
 use serde_json::json;
+use uuid::Uuid;
 
+use crate::core::game::dungeon::random_seed;
 use crate::game::GameState;
+use crate::types::{PlayerQuery, RegenerateDungeonRequest};
 use crate::error::GameError;
+use crate::handlers::AdminKey;
 
+/// Gets the game as a player sees it: the entities in their area of
+/// interest and their map of the floor they are on
 pub async fn get_game_state(
     game_state: web::Data<Arc<RwLock<GameState>>>,
+    query: web::Query<PlayerQuery>,
 ) -> Result<HttpResponse, GameError> {
     let state = game_state.read();
     
+    let snapshot = state.snapshot_for(query.player)
+        .ok_or(GameError::PlayerNotFound)?;
+
     Ok(HttpResponse::Ok().json(json!({
-        "players": state.get_players(),
-        "dungeon": state.get_dungeon()
+        "tick": snapshot.tick,
+        "entities": snapshot.entities.values().collect::<Vec<_>>(),
+        "dungeon": state.player_map(query.player)?
     })))
 }
 
+/// Gets a player's map of the floor they are on
 pub async fn get_dungeon(
     game_state: web::Data<Arc<RwLock<GameState>>>,
+    query: web::Query<PlayerQuery>,
 ) -> Result<HttpResponse, GameError> {
     let state = game_state.read();
     
-    Ok(HttpResponse::Ok().json(state.get_dungeon()))
-} 
\ No newline at end of file
+    Ok(HttpResponse::Ok().json(state.player_map(query.player)?))
+}
+
+/// Gets a player's map of a floor of the dungeon by depth, 0 being the top floor
+///
+/// Floors below the top one exist once a player has reached them.
+pub async fn get_floor(
+    game_state: web::Data<Arc<RwLock<GameState>>>,
+    path: web::Path<u32>,
+    query: web::Query<PlayerQuery>,
+) -> Result<HttpResponse, GameError> {
+    let state = game_state.read();
+    let floor = state.floor_map(query.player, path.into_inner())?;
+
+    Ok(HttpResponse::Ok().json(floor))
+}
+
+/// Lists the live party instances and how long empty ones have left
+pub async fn get_instances(
+    game_state: web::Data<Arc<RwLock<GameState>>>,
+) -> Result<HttpResponse, GameError> {
+    let state = game_state.read();
+    let instances = state.instances();
+
+    Ok(HttpResponse::Ok().json(json!({
+        "live": instances.len(),
+        "instances": instances
+    })))
+}
+
+/// Gets a player's map of a live party instance
+pub async fn get_instance_dungeon(
+    game_state: web::Data<Arc<RwLock<GameState>>>,
+    path: web::Path<Uuid>,
+    query: web::Query<PlayerQuery>,
+) -> Result<HttpResponse, GameError> {
+    let state = game_state.read();
+    let dungeon = state.instance_map(query.player, path.into_inner())?;
+
+    Ok(HttpResponse::Ok().json(dungeon))
+}
+
+/// Regenerates the dungeon from a seed, or from a random one if none is given
+///
+/// Only operators sending the admin key may regenerate the dungeon. A
+/// layout in the request switches the dungeon to that algorithm and its
+/// parameters; otherwise the current layout is kept. Players fetch their
+/// maps of the new layout after being told it changed.
+pub async fn regenerate_dungeon(
+    game_state: web::Data<Arc<RwLock<GameState>>>,
+    admin_key: web::Data<AdminKey>,
+    http_request: HttpRequest,
+    request: web::Json<RegenerateDungeonRequest>,
+) -> Result<HttpResponse, GameError> {
+    admin_key.authorize(&http_request)?;
+    let request = request.into_inner();
+    let seed = request.seed.unwrap_or_else(random_seed);
+    game_state.write().regenerate_dungeon(seed, request.layout);
+
+    Ok(HttpResponse::NoContent().finish())
+}
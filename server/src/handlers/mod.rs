@@ -1,2 +1,66 @@
 pub mod player_handlers;
-pub mod game_handlers; 
\ No newline at end of file
+pub mod game_handlers;
+pub mod character_handlers;
+
+use actix_web::{http::header, HttpRequest};
+use log::warn;
+use crate::error::GameError;
+
+/// Gets the bearer token sent in a request's `Authorization` header
+pub fn bearer_token(request: &HttpRequest) -> Option<&str> {
+    request.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+}
+
+/// Key operators send to use the admin routes, such as regenerating the dungeon
+///
+/// Without a key every admin request is refused.
+pub struct AdminKey(Option<String>);
+
+impl AdminKey {
+    pub fn new(key: Option<String>) -> Self {
+        Self(key.filter(|key| !key.is_empty()))
+    }
+
+    /// Checks that a request sends the admin key as a bearer token
+    pub fn authorize(&self, request: &HttpRequest) -> Result<(), GameError> {
+        match (&self.0, bearer_token(request)) {
+            (Some(key), Some(token)) if key == token => Ok(()),
+            _ => Err(GameError::Unauthorized("Admin key required".to_string())),
+        }
+    }
+}
+
+/// Reads the admin key from the `ADMIN_KEY` environment variable
+///
+/// # Returns
+/// The configured key, or a key refusing every request when unset
+pub fn admin_key_from_env() -> AdminKey {
+    let key = std::env::var("ADMIN_KEY").ok();
+    if key.as_deref().is_none_or(str::is_empty) {
+        warn!("ADMIN_KEY is not set; admin routes are disabled");
+    }
+    AdminKey::new(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn admin_routes_need_the_configured_key() {
+        let with = |token: &str| TestRequest::default()
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+            .to_http_request();
+        let key = AdminKey::new(Some("s3cret".to_string()));
+        assert!(key.authorize(&with("s3cret")).is_ok());
+        assert!(key.authorize(&with("guess")).is_err());
+        assert!(key.authorize(&TestRequest::default().to_http_request()).is_err());
+        assert!(AdminKey::new(None).authorize(&with("")).is_err());
+        assert!(AdminKey::new(Some(String::new())).authorize(&with("")).is_err());
+    }
+}
@@ -22,6 +22,10 @@ pub async fn get_player(
     Ok(HttpResponse::Ok().json(player))
 }
 
+/// Starts walking a player to a position along a path found by the server
+///
+/// The player moves on the following ticks at normal speed; the response
+/// lists the centers of the tiles they will walk through.
 pub async fn move_player(
     game_state: web::Data<Arc<RwLock<GameState>>>,
     path: web::Path<String>,
@@ -31,14 +35,14 @@ pub async fn move_player(
         .map_err(|_| GameError::InvalidPosition("Invalid player ID".to_string()))?;
         
     let new_pos = Position {
-        x: move_req.x,
-        y: move_req.y,
+        x: move_req.x as i32,
+        y: move_req.y as i32,
     };
     
     let mut state = game_state.write();
-    state.update_player_position(id, new_pos)?;
+    let waypoints = state.move_player_to(id, new_pos)?;
     
-    Ok(HttpResponse::Ok().finish())
+    Ok(HttpResponse::Ok().json(json!({ "waypoints": waypoints })))
 }
 
 pub async fn get_inventory(
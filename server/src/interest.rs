@@ -0,0 +1,330 @@
+/// Area-of-interest management
+///
+/// Entities are bucketed into a uniform [`SpatialGrid`] over world space so
+/// each observer only has to look at the cells around it. The
+/// [`InterestManager`] keeps the set of entities every observer can see and
+/// reports who entered or left each observer's view since the last refresh.
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+use crate::types::Position;
+
+/// Distance in world units within which an observer sees other entities
+pub const VIEW_RADIUS: i32 = 160;
+
+/// Extra distance an entity must move past `VIEW_RADIUS` before it leaves
+/// view, so entities walking along the boundary do not flicker in and out
+pub const VIEW_MARGIN: i32 = 20;
+
+/// Uniform grid bucketing entities by position
+#[derive(Debug)]
+pub struct SpatialGrid {
+    /// Side length of a cell in world units
+    cell_size: i32,
+    /// Entities in each occupied cell
+    cells: HashMap<(i32, i32), HashSet<Uuid>>,
+    /// Last known position of every entity
+    positions: HashMap<Uuid, Position>,
+}
+
+impl SpatialGrid {
+    pub fn new(cell_size: i32) -> Self {
+        Self {
+            cell_size: cell_size.max(1),
+            cells: HashMap::new(),
+            positions: HashMap::new(),
+        }
+    }
+
+    fn cell_of(&self, position: Position) -> (i32, i32) {
+        (position.x.div_euclid(self.cell_size), position.y.div_euclid(self.cell_size))
+    }
+
+    /// Inserts an entity or moves it to a new position
+    pub fn update(&mut self, id: Uuid, position: Position) {
+        let cell = self.cell_of(position);
+        if let Some(old) = self.positions.insert(id, position) {
+            let old_cell = self.cell_of(old);
+            if old_cell == cell {
+                return;
+            }
+            self.remove_from_cell(old_cell, id);
+        }
+        self.cells.entry(cell).or_default().insert(id);
+    }
+
+    /// Removes an entity from the grid
+    pub fn remove(&mut self, id: Uuid) {
+        if let Some(position) = self.positions.remove(&id) {
+            self.remove_from_cell(self.cell_of(position), id);
+        }
+    }
+
+    fn remove_from_cell(&mut self, cell: (i32, i32), id: Uuid) {
+        if let Some(entities) = self.cells.get_mut(&cell) {
+            entities.remove(&id);
+            if entities.is_empty() {
+                self.cells.remove(&cell);
+            }
+        }
+    }
+
+    /// Gets the last known position of an entity
+    pub fn position(&self, id: Uuid) -> Option<Position> {
+        self.positions.get(&id).copied()
+    }
+
+    /// Finds every entity within a distance of a point
+    ///
+    /// # Arguments
+    /// * `center` - Point to search around
+    /// * `radius` - Maximum distance in world units, inclusive
+    pub fn query(&self, center: Position, radius: i32) -> Vec<Uuid> {
+        let (min_x, min_y) = self.cell_of(Position::new(center.x - radius, center.y - radius));
+        let (max_x, max_y) = self.cell_of(Position::new(center.x + radius, center.y + radius));
+
+        let mut found = Vec::new();
+        for cell_y in min_y..=max_y {
+            for cell_x in min_x..=max_x {
+                let Some(entities) = self.cells.get(&(cell_x, cell_y)) else {
+                    continue;
+                };
+                found.extend(entities.iter().copied().filter(|id| {
+                    within(self.positions[id], center, radius)
+                }));
+            }
+        }
+        found
+    }
+}
+
+/// Checks whether two points are at most `radius` apart
+fn within(a: Position, b: Position, radius: i32) -> bool {
+    let dx = (a.x - b.x) as i64;
+    let dy = (a.y - b.y) as i64;
+    dx * dx + dy * dy <= radius as i64 * radius as i64
+}
+
+/// Entities that entered or left an observer's view during a refresh
+#[derive(Debug, Clone, PartialEq)]
+pub struct VisibilityChange {
+    /// Player whose view changed
+    pub observer: Uuid,
+    /// Entities that came into view
+    pub entered: Vec<Uuid>,
+    /// Entities that went out of view
+    pub left: Vec<Uuid>,
+}
+
+/// Tracks which entities each observer can see
+#[derive(Debug)]
+pub struct InterestManager {
+    /// Positions of every entity
+    grid: SpatialGrid,
+    /// Distance within which entities come into view
+    view_radius: i32,
+    /// Entities currently visible to each observer, excluding itself
+    visible: HashMap<Uuid, HashSet<Uuid>>,
+}
+
+impl InterestManager {
+    /// Creates an empty manager
+    ///
+    /// # Arguments
+    /// * `view_radius` - Distance in world units within which entities are visible
+    pub fn new(view_radius: i32) -> Self {
+        Self {
+            grid: SpatialGrid::new(view_radius),
+            view_radius,
+            visible: HashMap::new(),
+        }
+    }
+
+    /// Starts tracking an observer with an initially empty view
+    ///
+    /// The observer's surroundings are reported as entered on the next refresh.
+    pub fn add_observer(&mut self, id: Uuid) {
+        self.visible.entry(id).or_default();
+    }
+
+    /// Inserts an entity or moves it to a new position
+    pub fn update_position(&mut self, id: Uuid, position: Position) {
+        self.grid.update(id, position);
+    }
+
+    /// Stops tracking an entity, and the observer if it is one
+    ///
+    /// # Returns
+    /// Observers that could see the entity
+    pub fn remove(&mut self, id: Uuid) -> Vec<Uuid> {
+        self.grid.remove(id);
+        self.visible.remove(&id);
+        self.visible.iter_mut()
+            .filter_map(|(observer, seen)| seen.remove(&id).then_some(*observer))
+            .collect()
+    }
+
+    /// Finds the observers whose view radius contains an entity
+    ///
+    /// Unlike [`InterestManager::refresh`] this does not record anything.
+    pub fn observers_of(&self, id: Uuid) -> Vec<Uuid> {
+        let Some(position) = self.grid.position(id) else {
+            return Vec::new();
+        };
+        self.grid.query(position, self.view_radius)
+            .into_iter()
+            .filter(|observer| *observer != id && self.visible.contains_key(observer))
+            .collect()
+    }
+
+    /// Marks an entity as already visible to an observer, e.g. because the
+    /// observer was told about it directly
+    pub fn mark_visible(&mut self, observer: Uuid, id: Uuid) {
+        if observer != id {
+            if let Some(seen) = self.visible.get_mut(&observer) {
+                seen.insert(id);
+            }
+        }
+    }
+
+    /// Entities currently visible to an observer, excluding itself
+    pub fn visible_to(&self, observer: Uuid) -> Option<&HashSet<Uuid>> {
+        self.visible.get(&observer)
+    }
+
+    /// Recomputes every observer's view from the current positions
+    ///
+    /// Entities enter view within `view_radius` and leave it only once they
+    /// are more than `VIEW_MARGIN` further away. On top of that, an entity
+    /// is only in view while the observer has line of sight to it.
+    ///
+    /// # Arguments
+    /// * `in_sight` - Whether an observer has line of sight to a position
+    ///
+    /// # Returns
+    /// The observers whose view changed
+    pub fn refresh(&mut self, in_sight: impl Fn(Uuid, Position) -> bool) -> Vec<VisibilityChange> {
+        let mut changes = Vec::new();
+        for (observer, seen) in &mut self.visible {
+            let Some(center) = self.grid.position(*observer) else {
+                continue;
+            };
+
+            let entered: Vec<Uuid> = self.grid.query(center, self.view_radius)
+                .into_iter()
+                .filter(|id| id != observer && !seen.contains(id))
+                .filter(|id| self.grid.position(*id).is_some_and(|position| in_sight(*observer, position)))
+                .collect();
+            let left: Vec<Uuid> = seen.iter()
+                .copied()
+                .filter(|id| match self.grid.position(*id) {
+                    Some(position) => {
+                        !within(position, center, self.view_radius + VIEW_MARGIN) || !in_sight(*observer, position)
+                    }
+                    None => true,
+                })
+                .collect();
+
+            if entered.is_empty() && left.is_empty() {
+                continue;
+            }
+            seen.extend(entered.iter().copied());
+            for id in &left {
+                seen.remove(id);
+            }
+            changes.push(VisibilityChange { observer: *observer, entered, left });
+        }
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grid_query_respects_radius_across_cells() {
+        let mut grid = SpatialGrid::new(10);
+        let (near, far, negative) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        grid.update(near, Position::new(15, 3));
+        grid.update(far, Position::new(40, 40));
+        grid.update(negative, Position::new(-5, 0));
+
+        let mut found = grid.query(Position::new(5, 0), 12);
+        found.sort();
+        let mut expected = vec![near, negative];
+        expected.sort();
+        assert_eq!(found, expected);
+
+        grid.update(far, Position::new(6, 1));
+        grid.remove(near);
+        let mut found = grid.query(Position::new(5, 0), 12);
+        found.sort();
+        let mut expected = vec![far, negative];
+        expected.sort();
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn refresh_reports_enter_and_leave_with_margin() {
+        let mut interest = InterestManager::new(100);
+        let (observer, walker) = (Uuid::new_v4(), Uuid::new_v4());
+        interest.add_observer(observer);
+        interest.update_position(observer, Position::new(0, 0));
+        interest.update_position(walker, Position::new(150, 0));
+        assert!(interest.refresh(|_, _| true).is_empty());
+
+        interest.update_position(walker, Position::new(90, 0));
+        assert_eq!(interest.refresh(|_, _| true), vec![VisibilityChange {
+            observer,
+            entered: vec![walker],
+            left: Vec::new(),
+        }]);
+
+        // Stepping just past the radius stays in view
+        interest.update_position(walker, Position::new(100 + VIEW_MARGIN, 0));
+        assert!(interest.refresh(|_, _| true).is_empty());
+
+        interest.update_position(walker, Position::new(101 + VIEW_MARGIN, 0));
+        assert_eq!(interest.refresh(|_, _| true), vec![VisibilityChange {
+            observer,
+            entered: Vec::new(),
+            left: vec![walker],
+        }]);
+    }
+
+    #[test]
+    fn entities_out_of_sight_stay_hidden() {
+        let mut interest = InterestManager::new(100);
+        let (observer, walker) = (Uuid::new_v4(), Uuid::new_v4());
+        interest.add_observer(observer);
+        interest.update_position(observer, Position::new(0, 0));
+        interest.update_position(walker, Position::new(50, 0));
+
+        // Everything right of x = 40 is behind a wall
+        let behind_wall = |_: Uuid, position: Position| position.x <= 40;
+        assert!(interest.refresh(behind_wall).is_empty());
+
+        interest.update_position(walker, Position::new(30, 0));
+        assert_eq!(interest.refresh(behind_wall)[0].entered, vec![walker]);
+
+        interest.update_position(walker, Position::new(45, 0));
+        assert_eq!(interest.refresh(behind_wall)[0].left, vec![walker]);
+    }
+
+    #[test]
+    fn removal_reports_observers_that_saw_the_entity() {
+        let mut interest = InterestManager::new(100);
+        let (a, b, c) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        for (id, x) in [(a, 0), (b, 50), (c, 500)] {
+            interest.add_observer(id);
+            interest.update_position(id, Position::new(x, 0));
+        }
+        interest.refresh(|_, _| true);
+        assert_eq!(interest.observers_of(b).len(), 1);
+        assert_eq!(interest.observers_of(c), Vec::<Uuid>::new());
+
+        assert_eq!(interest.remove(b), vec![a]);
+        assert!(interest.visible_to(a).unwrap().is_empty());
+        assert!(interest.visible_to(b).is_none());
+    }
+}
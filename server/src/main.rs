@@ -1,29 +1,64 @@
+mod characters;
+mod wallet;
+mod core;
 mod game;
 mod types;
 mod error;
 mod handlers;
+mod protocol;
+mod sessions;
+mod snapshot;
+mod interest;
+mod tick;
+mod socketio;
 mod ws;
+mod wire;
 
+use actix::Actor;
 use actix_web::{web, App, HttpServer};
 use actix_cors::Cors;
 use std::sync::Arc;
 use parking_lot::RwLock;
 use log::info;
 
+use crate::core::game::classes::classes_from_env;
+use crate::core::game::collision::tile_size_from_env;
+use crate::core::game::dungeon::{layout_from_env, seed_from_env, DungeonGenerator};
+use crate::core::game::npcs::bestiary_from_env;
+use crate::core::game::progression::progression_from_env;
+use crate::core::game::quests::quests_from_env;
+use crate::core::game::skills::skills_from_env;
 use crate::game::GameState;
 use crate::handlers::{
+    admin_key_from_env,
     player_handlers,
     game_handlers,
+    character_handlers,
 };
+use crate::tick::{tick_rate_from_env, GameLoop};
 use crate::ws::ws_index;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
+    // Load configuration from .env
+    dotenv::dotenv().ok();
+
     // Initialize logging
     env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
     
     // Initialize game state
-    let game_state = Arc::new(RwLock::new(GameState::new()));
+    let mut game_state = GameState::new(DungeonGenerator::new(layout_from_env()), seed_from_env());
+    game_state.set_tile_size(tile_size_from_env());
+    game_state.set_bestiary(bestiary_from_env());
+    game_state.set_skills(skills_from_env());
+    game_state.set_progression(progression_from_env());
+    game_state.set_classes(classes_from_env());
+    game_state.set_quests(quests_from_env());
+    let game_state = Arc::new(RwLock::new(game_state));
+    let admin_key = web::Data::new(admin_key_from_env());
+
+    // Start the simulation loop
+    GameLoop::new(game_state.clone(), tick_rate_from_env()).start();
     
     info!("Starting game server on 127.0.0.1:3000");
     
@@ -38,6 +73,7 @@ async fn main() -> std::io::Result<()> {
         App::new()
             .wrap(cors)
             .app_data(web::Data::new(game_state.clone()))
+            .app_data(admin_key.clone())
             // WebSocket route
             .route("/socket.io/", web::get().to(ws_index))
             // Player routes
@@ -46,10 +82,23 @@ async fn main() -> std::io::Result<()> {
                 .route("/{id}/move", web::post().to(player_handlers::move_player))
                 .route("/{id}/inventory", web::get().to(player_handlers::get_inventory))
                 .route("/{id}/wallet", web::get().to(player_handlers::get_wallet)))
+            // Account routes
+            .service(web::scope("/api/accounts")
+                .route("", web::post().to(character_handlers::register_account))
+                .route("/challenge", web::post().to(character_handlers::account_challenge)))
+            // Character routes
+            .service(web::scope("/api/characters")
+                .route("", web::get().to(character_handlers::list_characters))
+                .route("", web::post().to(character_handlers::create_character))
+                .route("/classes", web::get().to(character_handlers::get_classes)))
             // Game routes
             .service(web::scope("/api/game")
                 .route("/state", web::get().to(game_handlers::get_game_state))
-                .route("/dungeon", web::get().to(game_handlers::get_dungeon)))
+                .route("/dungeon", web::get().to(game_handlers::get_dungeon))
+                .route("/dungeon", web::post().to(game_handlers::regenerate_dungeon))
+                .route("/dungeon/floors/{depth}", web::get().to(game_handlers::get_floor))
+                .route("/instances", web::get().to(game_handlers::get_instances))
+                .route("/instances/{id}/dungeon", web::get().to(game_handlers::get_instance_dungeon)))
     })
     .bind("127.0.0.1:3000")?
     .run()
@@ -0,0 +1,376 @@
+/// Versioned client/server message contract for the game gateway
+///
+/// Every Socket.IO event exchanged with a client maps onto one variant of
+/// [`ClientMessage`] or [`ServerMessage`]; the event name is the variant name
+/// in camelCase and the first event argument is the variant's data.
+use serde::{Serialize, Deserialize};
+use serde_json::{json, Value};
+use uuid::Uuid;
+use crate::socketio::{SocketPacket, DEFAULT_NAMESPACE};
+use crate::snapshot::SnapshotDelta;
+use crate::core::game::inventory::{EquipError, Inventory};
+use crate::types::{AttackKind, AttackOutcome, AttributeAllocation, Attributes, DoorState, EntityKind, EquipmentSlot, ExperienceSource, Item, Player, Movement, Position, RevealedTile, SkillOutcome};
+
+/// Protocol version spoken by this server
+///
+/// Version 1 was the bare `{"type", "data"}` JSON protocol sent over a raw
+/// WebSocket; version 2 carries the same messages as Socket.IO events.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Oldest protocol version still accepted
+pub const MIN_PROTOCOL_VERSION: u32 = 2;
+
+/// Messages sent by clients
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum ClientMessage {
+    /// Enter the game as a created character of the account, signed in
+    /// with the account's key, or as a guest under the given name
+    Join {
+        #[serde(default)]
+        name: String,
+        #[serde(default)]
+        character: Option<Uuid>,
+        #[serde(default)]
+        account: String,
+        #[serde(default)]
+        key: Option<Uuid>,
+    },
+    /// Update the held movement keys
+    Move(Movement),
+    /// Walk to a position on the player's floor along a path found by the server
+    MoveTo { x: i32, y: i32 },
+    /// Send a chat message to other players
+    Chat {
+        message: String,
+        #[serde(default)]
+        channel: ChatChannel,
+    },
+    /// Equip an inventory item into a slot
+    EquipItem { item_id: Uuid, slot: EquipmentSlot },
+    /// Move the item in a slot back into the inventory
+    UnequipItem { slot: EquipmentSlot },
+    /// Use a consumable item
+    UseItem { item_id: Uuid },
+    /// Drop a stack of items on the ground
+    DropItem { item_id: Uuid },
+    /// Pick up an item lying on the ground next to the player
+    PickUpItem { item_id: Uuid },
+    /// Confirm receipt of the snapshot of a tick
+    AckSnapshot { tick: u64 },
+    /// Open or close the door on a tile next to the player, unlocking it
+    /// with its key from the inventory when it is locked
+    Interact { x: i32, y: i32 },
+    /// Attack an NPC, within reach of the attack and, for ranged attacks,
+    /// in line of sight
+    Attack {
+        target: Uuid,
+        #[serde(default)]
+        kind: AttackKind,
+    },
+    /// Bind a skill to a hotbar slot, or clear the slot
+    BindSkill {
+        slot: usize,
+        #[serde(default)]
+        skill: Option<String>,
+    },
+    /// Cast the skill in a hotbar slot, aimed at an entity or a position
+    /// depending on the skill
+    CastSkill {
+        slot: usize,
+        #[serde(default)]
+        target: Option<Uuid>,
+        #[serde(default)]
+        position: Option<Position>,
+    },
+    /// Spend unspent stat points on the player's attributes
+    AllocateAttributes(AttributeAllocation),
+    /// Take the stairs the player is standing on to the next floor
+    TakeStairs,
+    /// Enter the portal the player is standing on together with the listed
+    /// party members waiting there for the player, or leave the instance
+    /// through it; with a leader, wait on the portal for that player to
+    /// take the player along instead
+    EnterPortal {
+        #[serde(default)]
+        party: Vec<Uuid>,
+        #[serde(default)]
+        leader: Option<Uuid>,
+    },
+}
+
+/// Audience of a chat message
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ChatChannel {
+    /// Every player in the game
+    #[default]
+    Global,
+    /// Players in the same dungeon room as the sender
+    Room,
+}
+
+impl ClientMessage {
+    /// Builds a message from a Socket.IO event name and its first argument
+    ///
+    /// # Arguments
+    /// * `event` - Event name, e.g. `"equipItem"`
+    /// * `data` - First event argument
+    ///
+    /// # Returns
+    /// The typed message or the reason the event was rejected
+    pub fn from_event(event: &str, data: Value) -> Result<Self, serde_json::Error> {
+        serde_json::from_value(json!({ "type": event, "data": data }))
+    }
+}
+
+/// Messages sent by the server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum ServerMessage {
+    /// Confirms the negotiated protocol version and encoding after a namespace connect
+    Connected { protocol_version: u32, encoding: WireEncoding },
+    /// Sent to a player after joining, with their own character
+    Welcome { player: Box<Player> },
+    /// Another player entered the game
+    PlayerJoined { id: Uuid, name: String },
+    /// A player left the game
+    PlayerLeft { id: Uuid },
+    /// An entity came within view; its state follows in the next snapshot
+    EntityEntered { id: Uuid, name: String, kind: EntityKind },
+    /// An entity went out of view
+    EntityLeft { id: Uuid },
+    /// World state at the end of a tick, relative to the last acknowledged snapshot
+    Snapshot(SnapshotDelta),
+    /// The dungeon was regenerated; maps of the old layout no longer apply
+    DungeonChanged,
+    /// The player took the stairs; fetch their map of the new floor from
+    /// `/api/game/dungeon/floors/{depth}?player={id}`
+    FloorChanged { depth: u32 },
+    /// The player entered a party instance; fetch their map of it from
+    /// `/api/game/instances/{id}/dungeon?player={id}`
+    InstanceEntered { id: Uuid },
+    /// A player is waiting on the portal for the player to take them into
+    /// an instance
+    WaitingAtPortal { id: Uuid, name: String },
+    /// Tiles that came into the player's field of view for the first time,
+    /// or changed since the player last saw them
+    TilesRevealed { tiles: Vec<RevealedTile> },
+    /// The player started walking to a destination through these tile
+    /// centers, in order
+    PathPlanned { waypoints: Vec<Position> },
+    /// A door on the player's floor near them was opened, closed, locked or
+    /// unlocked
+    DoorChanged { x: i32, y: i32, state: DoorState },
+    /// An entity near the player attacked another; `health` is what the
+    /// target has left
+    Combat {
+        attacker: Uuid,
+        target: Uuid,
+        kind: AttackKind,
+        outcome: AttackOutcome,
+        health: i32,
+    },
+    /// An entity near the player was killed
+    Died { id: Uuid, killer: Uuid },
+    /// The player's hotbar changed
+    HotbarChanged { hotbar: Vec<Option<String>> },
+    /// An entity near the player started casting a skill that takes
+    /// effect after `cast_ms`
+    CastStarted { caster: Uuid, skill: String, cast_ms: u64 },
+    /// A cast near the player was interrupted before it took effect
+    CastInterrupted { caster: Uuid, skill: String },
+    /// A skill cast near the player affected an entity
+    SkillEffect {
+        caster: Uuid,
+        skill: String,
+        target: Uuid,
+        outcome: SkillOutcome,
+    },
+    /// The player came back to life at the start of their dungeon
+    Respawned { position: Position },
+    /// Items the player looted from an NPC they killed, now in their inventory
+    LootReceived { items: Vec<Item> },
+    /// The player's inventory or equipment changed
+    InventoryChanged { inventory: Inventory },
+    /// The player's equipment stayed as it was, for `reason`
+    EquipRejected { reason: EquipError },
+    /// An item was left on a tile the player can see, or an item on the
+    /// ground came into their view
+    ItemDropped { id: Uuid, name: String, x: i32, y: i32 },
+    /// An item the player saw on the ground was picked up
+    ItemPickedUp { id: Uuid },
+    /// The player gained experience; `exp` is their total and `max_exp`
+    /// what they need for the next level
+    ExperienceGained { amount: i32, source: ExperienceSource, exp: i32, max_exp: i32 },
+    /// A kill counted toward one of the player's quests; the quest is
+    /// completed once `progress` reaches `goal`
+    QuestProgress { quest: String, progress: u32, goal: u32 },
+    /// A player near the player, or the player themselves, reached a new level
+    LeveledUp { id: Uuid, level: i32 },
+    /// The player's attributes, unspent stat points or maximum health and
+    /// mana changed after leveling up or spending stat points
+    AttributesChanged {
+        attributes: Attributes,
+        stat_points: i32,
+        max_health: i32,
+        max_mana: i32,
+    },
+    /// A chat message from a player
+    ChatMessage { sender: String, message: String },
+    /// A request could not be processed
+    Error { message: String },
+}
+
+/// Encoding of high-frequency messages on a connection
+///
+/// Movement inputs, snapshot acks and snapshots use the compact layout from
+/// [`crate::wire`] when `Binary` is selected; every other event is JSON.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum WireEncoding {
+    /// JSON events only
+    #[default]
+    Json,
+    /// Binary frames for movement and snapshots
+    Binary,
+}
+
+impl ServerMessage {
+    /// Encodes the message as a Socket.IO event on the default namespace
+    pub fn to_packet(&self) -> SocketPacket {
+        let mut value = serde_json::to_value(self).unwrap_or(Value::Null);
+        let event = value["type"].as_str().unwrap_or_default().to_string();
+        let data = value.get_mut("data").map(Value::take).unwrap_or(Value::Null);
+        SocketPacket::event(DEFAULT_NAMESPACE, &event, data)
+    }
+}
+
+/// Picks the protocol version for a connection from its connect payload
+///
+/// Clients announce their version as `{"protocol": n}` in the Socket.IO
+/// `auth` option; clients announcing no version get the oldest one still
+/// supported.
+///
+/// # Returns
+/// The accepted version or the reason the connection is refused
+pub fn negotiate_version(auth: Option<&Value>) -> Result<u32, String> {
+    let requested = match auth.and_then(|auth| auth.get("protocol")) {
+        None | Some(Value::Null) => MIN_PROTOCOL_VERSION,
+        Some(version) => version.as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or_else(|| format!("Invalid protocol version {}", version))?,
+    };
+
+    if requested < MIN_PROTOCOL_VERSION {
+        Err(format!(
+            "Protocol version {} is no longer supported, please update to version {}",
+            requested, PROTOCOL_VERSION
+        ))
+    } else if requested > PROTOCOL_VERSION {
+        Err(format!(
+            "Protocol version {} is not supported, this server speaks version {}",
+            requested, PROTOCOL_VERSION
+        ))
+    } else {
+        Ok(requested)
+    }
+}
+
+/// Picks the wire encoding for a connection from its connect payload
+///
+/// Clients opt into binary frames with `{"encoding": "binary"}` next to
+/// their protocol version; without it the connection stays on JSON.
+///
+/// # Returns
+/// The requested encoding or the reason the connection is refused
+pub fn negotiate_encoding(auth: Option<&Value>) -> Result<WireEncoding, String> {
+    match auth.and_then(|auth| auth.get("encoding")) {
+        None | Some(Value::Null) => Ok(WireEncoding::default()),
+        Some(encoding) => serde_json::from_value(encoding.clone())
+            .map_err(|_| format!("Unsupported encoding {}, use \"json\" or \"binary\"", encoding)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_every_client_event() {
+        let item_id = Uuid::new_v4();
+        let cases = [
+            ("join", json!({ "name": "Alice" })),
+            ("join", json!({ "character": item_id, "account": "0xabc", "key": item_id })),
+            ("move", json!({ "up": true, "down": false, "left": false, "right": true })),
+            ("moveTo", json!({ "x": 120, "y": 64 })),
+            ("chat", json!({ "message": "hi" })),
+            ("chat", json!({ "message": "hi", "channel": "room" })),
+            ("equipItem", json!({ "itemId": item_id, "slot": "MainHand" })),
+            ("unequipItem", json!({ "slot": "MainHand" })),
+            ("useItem", json!({ "itemId": item_id })),
+            ("dropItem", json!({ "itemId": item_id })),
+            ("pickUpItem", json!({ "itemId": item_id })),
+            ("ackSnapshot", json!({ "tick": 42 })),
+            ("interact", json!({ "x": 3, "y": 4 })),
+            ("attack", json!({ "target": item_id })),
+            ("attack", json!({ "target": item_id, "kind": "ranged" })),
+            ("bindSkill", json!({ "slot": 2, "skill": "firebolt" })),
+            ("bindSkill", json!({ "slot": 2 })),
+            ("castSkill", json!({ "slot": 0, "target": item_id })),
+            ("castSkill", json!({ "slot": 1, "position": { "x": 120, "y": 64 } })),
+            ("allocateAttributes", json!({ "strength": 2, "intelligence": 1 })),
+            ("takeStairs", Value::Null),
+            ("enterPortal", json!({ "party": [item_id] })),
+            ("enterPortal", json!({ "leader": item_id })),
+            ("enterPortal", json!({})),
+        ];
+        for (event, data) in cases {
+            assert!(ClientMessage::from_event(event, data).is_ok(), "failed to decode {}", event);
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_events_and_bad_payloads() {
+        assert!(ClientMessage::from_event("teleport", json!({})).is_err());
+        assert!(ClientMessage::from_event("useItem", json!({ "itemId": "not-a-uuid" })).is_err());
+    }
+
+    #[test]
+    fn encodes_server_messages_as_events() {
+        let id = Uuid::nil();
+        let packet = ServerMessage::PlayerLeft { id }.to_packet();
+        assert_eq!(packet.encode().0, format!(r#"42["playerLeft",{{"id":"{}"}}]"#, id));
+
+        let snapshot = SnapshotDelta {
+            tick: 7,
+            baseline_tick: Some(5),
+            added: Vec::new(),
+            changed: Vec::new(),
+            removed: vec![id],
+        };
+        assert_eq!(
+            ServerMessage::Snapshot(snapshot).to_packet().encode().0,
+            format!(r#"42["snapshot",{{"baselineTick":5,"removed":["{}"],"tick":7}}]"#, id)
+        );
+    }
+
+    #[test]
+    fn negotiates_supported_versions_only() {
+        assert_eq!(negotiate_version(Some(&json!({ "protocol": PROTOCOL_VERSION }))), Ok(PROTOCOL_VERSION));
+        assert_eq!(negotiate_version(None), Ok(MIN_PROTOCOL_VERSION));
+        assert_eq!(negotiate_version(Some(&json!({}))), Ok(MIN_PROTOCOL_VERSION));
+        assert!(negotiate_version(Some(&json!({ "protocol": (1u64 << 32) + PROTOCOL_VERSION as u64 }))).is_err());
+        assert!(negotiate_version(Some(&json!({ "protocol": "2" }))).is_err());
+        assert!(negotiate_version(Some(&json!({ "protocol": 1 }))).is_err());
+        assert!(negotiate_version(Some(&json!({ "protocol": PROTOCOL_VERSION + 1 }))).is_err());
+    }
+
+    #[test]
+    fn negotiates_wire_encoding() {
+        assert_eq!(negotiate_encoding(None), Ok(WireEncoding::Json));
+        assert_eq!(negotiate_encoding(Some(&json!({ "protocol": 2 }))), Ok(WireEncoding::Json));
+        assert_eq!(negotiate_encoding(Some(&json!({ "encoding": "binary" }))), Ok(WireEncoding::Binary));
+        assert!(negotiate_encoding(Some(&json!({ "encoding": "msgpack" }))).is_err());
+    }
+}
@@ -0,0 +1,81 @@
+/// Registry of connected websocket sessions
+///
+/// Maps player IDs to the address of the session actor serving them so
+/// that game logic can push typed messages to one, some, or all players.
+use std::collections::HashMap;
+use std::sync::Arc;
+use actix::Addr;
+use uuid::Uuid;
+use crate::protocol::ServerMessage;
+use crate::snapshot::WorldSnapshot;
+use crate::ws::{WorldUpdate, WsGameSession, WsMessage};
+
+/// Addresses of all sessions whose player has joined the game
+#[derive(Default)]
+pub struct SessionRegistry {
+    sessions: HashMap<Uuid, Addr<WsGameSession>>,
+}
+
+impl SessionRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the session serving a player, replacing any previous one
+    pub fn register(&mut self, player_id: Uuid, addr: Addr<WsGameSession>) {
+        self.sessions.insert(player_id, addr);
+    }
+
+    /// Removes the session serving a player
+    pub fn unregister(&mut self, player_id: Uuid) {
+        self.sessions.remove(&player_id);
+    }
+
+    /// Sends a message to a single player
+    pub fn send_to(&self, player_id: Uuid, message: &ServerMessage) {
+        if let Some(addr) = self.sessions.get(&player_id) {
+            addr.do_send(WsMessage(encode(message)));
+        }
+    }
+
+    /// Sends a message to every listed player that has a session
+    pub fn send_to_many<I>(&self, player_ids: I, message: &ServerMessage)
+    where
+        I: IntoIterator<Item = Uuid>,
+    {
+        let frame = encode(message);
+        for player_id in player_ids {
+            if let Some(addr) = self.sessions.get(&player_id) {
+                addr.do_send(WsMessage(frame.clone()));
+            }
+        }
+    }
+
+    /// Sends a message to every connected player except one
+    pub fn broadcast_except(&self, except: Option<Uuid>, message: &ServerMessage) {
+        let frame = encode(message);
+        for (player_id, addr) in &self.sessions {
+            if Some(*player_id) != except {
+                addr.do_send(WsMessage(frame.clone()));
+            }
+        }
+    }
+
+    /// Sends a message to every connected player
+    pub fn broadcast(&self, message: &ServerMessage) {
+        self.broadcast_except(None, message);
+    }
+
+    /// Hands a player their view of the world, which their session
+    /// encodes against the baseline its client acknowledged
+    pub fn send_snapshot(&self, player_id: Uuid, snapshot: Arc<WorldSnapshot>) {
+        if let Some(addr) = self.sessions.get(&player_id) {
+            addr.do_send(WorldUpdate(snapshot));
+        }
+    }
+}
+
+/// Encodes a message as the Socket.IO event frame delivered by `WsMessage`
+fn encode(message: &ServerMessage) -> String {
+    message.to_packet().encode().0
+}
@@ -0,0 +1,355 @@
+/// Delta-compressed world snapshots
+///
+/// Each tick the server captures a [`WorldSnapshot`] of the networked state
+/// of every entity. Sessions keep a [`SnapshotHistory`] of what they sent and
+/// encode each new snapshot against the last one their client acknowledged,
+/// so only changed fields travel over the wire. When no acknowledged baseline
+/// is available the client receives a full snapshot instead.
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+use crate::types::{ActiveStatus, Npc, Player, Position};
+
+/// Number of sent snapshots a session remembers while waiting for acks
+///
+/// About two seconds at the default tick rate; a client that has not
+/// acknowledged anything within that window gets a full snapshot.
+pub const SNAPSHOT_HISTORY: usize = 64;
+
+/// Networked state of a single entity
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EntitySnapshot {
+    /// UUID of the entity
+    pub id: Uuid,
+    /// Position in world space
+    pub position: Position,
+    /// Current health points
+    pub health: i32,
+    /// Current mana points
+    pub mana: i32,
+    /// Status effects on the entity
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub effects: Vec<ActiveStatus>,
+}
+
+impl From<&Player> for EntitySnapshot {
+    fn from(player: &Player) -> Self {
+        Self {
+            id: player.id,
+            position: player.position,
+            health: player.health,
+            mana: player.mana,
+            effects: Vec::new(),
+        }
+    }
+}
+
+impl From<&Npc> for EntitySnapshot {
+    fn from(npc: &Npc) -> Self {
+        Self {
+            id: npc.id,
+            position: npc.position,
+            health: npc.health,
+            mana: 0,
+            effects: Vec::new(),
+        }
+    }
+}
+
+/// Networked state of the whole world at the end of a tick
+#[derive(Debug, Clone, Default)]
+pub struct WorldSnapshot {
+    /// Tick the snapshot was captured at
+    pub tick: u64,
+    /// State of every entity, keyed by entity ID
+    pub entities: HashMap<Uuid, EntitySnapshot>,
+}
+
+/// Fields of an entity that changed since the baseline
+///
+/// Unchanged fields are left out of the serialized message.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EntityDelta {
+    /// UUID of the entity
+    pub id: Uuid,
+    /// New position, if it changed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position: Option<Position>,
+    /// New health, if it changed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health: Option<i32>,
+    /// New mana, if it changed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mana: Option<i32>,
+    /// New status effects, if they changed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub effects: Option<Vec<ActiveStatus>>,
+}
+
+/// Snapshot encoded against a baseline the client acknowledged
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotDelta {
+    /// Tick the snapshot was captured at; clients acknowledge this value
+    pub tick: u64,
+    /// Tick of the baseline the delta applies to, or None for a full snapshot
+    pub baseline_tick: Option<u64>,
+    /// Entities missing from the baseline, with their full state
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub added: Vec<EntitySnapshot>,
+    /// Entities whose state changed since the baseline
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub changed: Vec<EntityDelta>,
+    /// Entities present in the baseline that no longer exist
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub removed: Vec<Uuid>,
+}
+
+impl SnapshotDelta {
+    /// Checks whether applying the delta would change nothing
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+}
+
+impl WorldSnapshot {
+    /// Captures the networked state of a set of entities
+    ///
+    /// # Arguments
+    /// * `tick` - Tick the state belongs to
+    /// * `entities` - Players, NPCs or anything else to include
+    pub fn capture<I, E>(tick: u64, entities: I) -> Self
+    where
+        I: IntoIterator<Item = E>,
+        E: Into<EntitySnapshot>,
+    {
+        Self {
+            tick,
+            entities: entities.into_iter()
+                .map(|entity| {
+                    let entity = entity.into();
+                    (entity.id, entity)
+                })
+                .collect(),
+        }
+    }
+
+    /// Restricts the snapshot to what one observer can see
+    ///
+    /// # Arguments
+    /// * `observer` - Entity the view belongs to, always included
+    /// * `visible` - Other entities in the observer's area of interest
+    pub fn view(&self, observer: Uuid, visible: &HashSet<Uuid>) -> WorldSnapshot {
+        Self {
+            tick: self.tick,
+            entities: self.entities.iter()
+                .filter(|(id, _)| **id == observer || visible.contains(id))
+                .map(|(id, entity)| (*id, entity.clone()))
+                .collect(),
+        }
+    }
+
+    /// Encodes this snapshot against a baseline
+    ///
+    /// # Arguments
+    /// * `baseline` - Snapshot the client already has, or None to encode everything
+    ///
+    /// # Returns
+    /// The entities added, changed and removed since the baseline
+    pub fn diff(&self, baseline: Option<&WorldSnapshot>) -> SnapshotDelta {
+        let empty = HashMap::new();
+        let previous = baseline.map(|snapshot| &snapshot.entities).unwrap_or(&empty);
+
+        let mut delta = SnapshotDelta {
+            tick: self.tick,
+            baseline_tick: baseline.map(|snapshot| snapshot.tick),
+            added: Vec::new(),
+            changed: Vec::new(),
+            removed: previous.keys()
+                .filter(|id| !self.entities.contains_key(id))
+                .copied()
+                .collect(),
+        };
+
+        for entity in self.entities.values() {
+            match previous.get(&entity.id) {
+                None => delta.added.push(entity.clone()),
+                Some(old) if old != entity => delta.changed.push(EntityDelta {
+                    id: entity.id,
+                    position: (old.position != entity.position).then_some(entity.position),
+                    health: (old.health != entity.health).then_some(entity.health),
+                    mana: (old.mana != entity.mana).then_some(entity.mana),
+                    effects: (old.effects != entity.effects).then(|| entity.effects.clone()),
+                }),
+                Some(_) => {}
+            }
+        }
+
+        delta
+    }
+}
+
+/// Snapshots sent to one client and the last one it acknowledged
+#[derive(Debug, Default)]
+pub struct SnapshotHistory {
+    /// Snapshots sent and not yet superseded by an ack, oldest first
+    sent: VecDeque<Arc<WorldSnapshot>>,
+    /// Last snapshot the client acknowledged
+    acked: Option<Arc<WorldSnapshot>>,
+}
+
+impl SnapshotHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records that the client received the snapshot of a tick
+    ///
+    /// Acks for unknown or older ticks are ignored.
+    pub fn acknowledge(&mut self, tick: u64) {
+        if self.acked.as_ref().is_some_and(|acked| acked.tick >= tick) {
+            return;
+        }
+        if let Some(index) = self.sent.iter().position(|snapshot| snapshot.tick == tick) {
+            self.acked = self.sent.drain(..=index).next_back();
+        }
+    }
+
+    /// Encodes a snapshot for this client and remembers it was sent
+    ///
+    /// # Returns
+    /// The delta against the acknowledged baseline, or None when nothing
+    /// changed since that baseline and there is nothing to send
+    pub fn encode(&mut self, snapshot: Arc<WorldSnapshot>) -> Option<SnapshotDelta> {
+        let delta = snapshot.diff(self.acked.as_deref());
+        if delta.is_empty() && self.acked.is_some() {
+            return None;
+        }
+
+        self.sent.push_back(snapshot);
+        if self.sent.len() > SNAPSHOT_HISTORY {
+            // The client has not acked anything for too long; assume the
+            // baseline is lost and start over with full snapshots
+            self.sent.pop_front();
+            self.acked = None;
+        }
+        Some(delta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entity(id: Uuid, x: i32, health: i32) -> EntitySnapshot {
+        EntitySnapshot { id, position: Position::new(x, 0), health, mana: 10, effects: Vec::new() }
+    }
+
+    fn world(tick: u64, entities: &[EntitySnapshot]) -> Arc<WorldSnapshot> {
+        Arc::new(WorldSnapshot {
+            tick,
+            entities: entities.iter().map(|e| (e.id, e.clone())).collect(),
+        })
+    }
+
+    #[test]
+    fn diff_reports_only_changed_fields() {
+        let (a, b, c) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let baseline = world(1, &[entity(a, 0, 100), entity(b, 0, 100)]);
+        let current = world(2, &[entity(a, 5, 100), entity(c, 0, 100)]);
+
+        let delta = current.diff(Some(&baseline));
+        assert_eq!(delta.baseline_tick, Some(1));
+        assert_eq!(delta.added, vec![entity(c, 0, 100)]);
+        assert_eq!(delta.removed, vec![b]);
+        assert_eq!(delta.changed, vec![EntityDelta {
+            id: a,
+            position: Some(Position::new(5, 0)),
+            health: None,
+            mana: None,
+            effects: None,
+        }]);
+
+        let json = serde_json::to_value(&delta.changed[0]).unwrap();
+        assert!(json.get("health").is_none());
+        assert!(json.get("position").is_some());
+    }
+
+    #[test]
+    fn view_keeps_observer_and_visible_entities() {
+        let (me, near, far) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let snapshot = world(3, &[entity(me, 0, 100), entity(near, 1, 100), entity(far, 2, 100)]);
+
+        let view = snapshot.view(me, &HashSet::from([near]));
+        assert_eq!(view.tick, 3);
+        assert_eq!(view.entities.len(), 2);
+        assert!(view.entities.contains_key(&me) && view.entities.contains_key(&near));
+    }
+
+    #[test]
+    fn full_snapshot_until_first_ack() {
+        let a = Uuid::new_v4();
+        let mut history = SnapshotHistory::new();
+
+        let first = history.encode(world(1, &[entity(a, 0, 100)])).unwrap();
+        assert_eq!(first.baseline_tick, None);
+        assert_eq!(first.added.len(), 1);
+
+        let second = history.encode(world(2, &[entity(a, 5, 100)])).unwrap();
+        assert_eq!(second.baseline_tick, None);
+        assert_eq!(second.added.len(), 1);
+
+        history.acknowledge(2);
+        let third = history.encode(world(3, &[entity(a, 5, 90)])).unwrap();
+        assert_eq!(third.baseline_tick, Some(2));
+        assert!(third.added.is_empty());
+        assert_eq!(third.changed[0].health, Some(90));
+        assert_eq!(third.changed[0].position, None);
+    }
+
+    #[test]
+    fn deltas_stay_against_last_ack_when_acks_are_lost() {
+        let a = Uuid::new_v4();
+        let mut history = SnapshotHistory::new();
+        history.encode(world(1, &[entity(a, 0, 100)]));
+        history.acknowledge(1);
+
+        history.encode(world(2, &[entity(a, 5, 100)]));
+        let delta = history.encode(world(3, &[entity(a, 10, 100)])).unwrap();
+        assert_eq!(delta.baseline_tick, Some(1));
+        assert_eq!(delta.changed[0].position, Some(Position::new(10, 0)));
+
+        // Stale and unknown acks are ignored
+        history.acknowledge(1);
+        history.acknowledge(99);
+        assert_eq!(history.encode(world(4, &[entity(a, 15, 100)])).unwrap().baseline_tick, Some(1));
+    }
+
+    #[test]
+    fn unchanged_world_sends_nothing_after_ack() {
+        let a = Uuid::new_v4();
+        let mut history = SnapshotHistory::new();
+        history.encode(world(1, &[entity(a, 0, 100)]));
+        history.acknowledge(1);
+        assert_eq!(history.encode(world(2, &[entity(a, 0, 100)])), None);
+    }
+
+    #[test]
+    fn falls_back_to_full_snapshot_when_baseline_expires() {
+        let a = Uuid::new_v4();
+        let mut history = SnapshotHistory::new();
+        history.encode(world(0, &[entity(a, 0, 100)]));
+        history.acknowledge(0);
+
+        let mut last = None;
+        for tick in 1..=SNAPSHOT_HISTORY as u64 + 1 {
+            last = history.encode(world(tick, &[entity(a, tick as i32, 100)]));
+        }
+        assert_eq!(last.unwrap().baseline_tick, Some(0));
+
+        let full = history.encode(world(100, &[entity(a, 100, 100)])).unwrap();
+        assert_eq!(full.baseline_tick, None);
+        assert_eq!(full.added.len(), 1);
+    }
+}
@@ -0,0 +1,574 @@
+/// Engine.IO v4 / Socket.IO v5 packet codec
+///
+/// Implements the wire format spoken by `socket.io-client` over a plain
+/// WebSocket transport. Every text frame is an Engine.IO packet; Engine.IO
+/// `message` packets carry Socket.IO packets, which in turn may announce
+/// binary attachments that arrive as the following binary frames.
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use thiserror::Error;
+
+/// Engine.IO protocol revision implemented by this codec
+pub const ENGINE_IO_VERSION: u8 = 4;
+
+/// Namespace every client joins unless it asks for another one
+pub const DEFAULT_NAMESPACE: &str = "/";
+
+/// Most binary attachments a client packet may announce
+pub const MAX_ATTACHMENTS: usize = 8;
+
+/// Errors raised while decoding Engine.IO or Socket.IO frames
+#[derive(Error, Debug, PartialEq)]
+pub enum PacketError {
+    /// The frame did not contain a packet type
+    #[error("Empty packet")]
+    Empty,
+
+    /// The packet type is not defined by the protocol
+    ///
+    /// # Arguments
+    /// * char - The unrecognised packet type
+    #[error("Unknown packet type: {0}")]
+    UnknownType(char),
+
+    /// The packet payload could not be parsed
+    ///
+    /// # Arguments
+    /// * String - Description of why the payload was rejected
+    #[error("Invalid payload: {0}")]
+    InvalidPayload(String),
+
+    /// A binary frame arrived without a packet announcing it
+    #[error("Unexpected binary attachment")]
+    UnexpectedAttachment,
+
+    /// A binary packet announced more attachments than are accepted
+    ///
+    /// # Arguments
+    /// * usize - The number of attachments announced
+    #[error("Too many binary attachments: {0}")]
+    TooManyAttachments(usize),
+}
+
+/// Handshake data sent in the Engine.IO `open` packet
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Handshake {
+    /// Engine.IO session id, unique per connection
+    pub sid: String,
+    /// Transports the client may upgrade to
+    pub upgrades: Vec<String>,
+    /// Milliseconds between server pings
+    pub ping_interval: u64,
+    /// Milliseconds the client has to answer a ping
+    pub ping_timeout: u64,
+    /// Largest payload in bytes the server accepts
+    pub max_payload: u64,
+}
+
+/// Packet of the Engine.IO transport layer
+#[derive(Debug, Clone, PartialEq)]
+pub enum EnginePacket {
+    /// Opens the session (`0`)
+    Open(Handshake),
+    /// Closes the session (`1`)
+    Close,
+    /// Heartbeat request, optionally carrying a probe payload (`2`)
+    Ping(Option<String>),
+    /// Heartbeat response, echoing the ping payload (`3`)
+    Pong(Option<String>),
+    /// Application data, usually a Socket.IO packet (`4`)
+    Message(String),
+    /// Transport upgrade confirmation (`5`)
+    Upgrade,
+    /// No-op, used to flush polling transports (`6`)
+    Noop,
+}
+
+impl EnginePacket {
+    /// Decodes a text WebSocket frame into an Engine.IO packet
+    ///
+    /// # Arguments
+    /// * `frame` - Raw text of the frame
+    ///
+    /// # Returns
+    /// The decoded packet or the reason the frame is malformed
+    pub fn decode(frame: &str) -> Result<Self, PacketError> {
+        let mut chars = frame.chars();
+        let kind = chars.next().ok_or(PacketError::Empty)?;
+        let data = chars.as_str();
+        let optional = || (!data.is_empty()).then(|| data.to_string());
+
+        match kind {
+            '0' => serde_json::from_str(data)
+                .map(EnginePacket::Open)
+                .map_err(|e| PacketError::InvalidPayload(e.to_string())),
+            '1' => Ok(EnginePacket::Close),
+            '2' => Ok(EnginePacket::Ping(optional())),
+            '3' => Ok(EnginePacket::Pong(optional())),
+            '4' => Ok(EnginePacket::Message(data.to_string())),
+            '5' => Ok(EnginePacket::Upgrade),
+            '6' => Ok(EnginePacket::Noop),
+            other => Err(PacketError::UnknownType(other)),
+        }
+    }
+
+    /// Encodes the packet as the text of a WebSocket frame
+    pub fn encode(&self) -> String {
+        match self {
+            EnginePacket::Open(handshake) => {
+                format!("0{}", serde_json::to_string(handshake).unwrap_or_default())
+            }
+            EnginePacket::Close => "1".to_string(),
+            EnginePacket::Ping(data) => format!("2{}", data.as_deref().unwrap_or_default()),
+            EnginePacket::Pong(data) => format!("3{}", data.as_deref().unwrap_or_default()),
+            EnginePacket::Message(data) => format!("4{}", data),
+            EnginePacket::Upgrade => "5".to_string(),
+            EnginePacket::Noop => "6".to_string(),
+        }
+    }
+}
+
+/// Type of a Socket.IO packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacketKind {
+    /// Namespace connection request or acceptance (`0`)
+    Connect = 0,
+    /// Namespace disconnection (`1`)
+    Disconnect = 1,
+    /// Event carrying `["name", ...args]` (`2`)
+    Event = 2,
+    /// Acknowledgement of an event with an id (`3`)
+    Ack = 3,
+    /// Namespace connection refusal (`4`)
+    ConnectError = 4,
+    /// Event with binary attachments (`5`)
+    BinaryEvent = 5,
+    /// Acknowledgement with binary attachments (`6`)
+    BinaryAck = 6,
+}
+
+impl PacketKind {
+    fn from_char(c: char) -> Result<Self, PacketError> {
+        match c {
+            '0' => Ok(PacketKind::Connect),
+            '1' => Ok(PacketKind::Disconnect),
+            '2' => Ok(PacketKind::Event),
+            '3' => Ok(PacketKind::Ack),
+            '4' => Ok(PacketKind::ConnectError),
+            '5' => Ok(PacketKind::BinaryEvent),
+            '6' => Ok(PacketKind::BinaryAck),
+            other => Err(PacketError::UnknownType(other)),
+        }
+    }
+
+    fn is_binary(self) -> bool {
+        matches!(self, PacketKind::BinaryEvent | PacketKind::BinaryAck)
+    }
+}
+
+/// Packet of the Socket.IO layer
+///
+/// Binary attachments are kept out of the JSON payload: the payload holds
+/// `{"_placeholder": true, "num": n}` markers and the buffers are stored
+/// in `attachments` in the same order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SocketPacket {
+    /// Type of the packet
+    pub kind: PacketKind,
+    /// Namespace the packet belongs to
+    pub namespace: String,
+    /// Acknowledgement id, present when the sender expects a reply
+    pub id: Option<u64>,
+    /// JSON payload
+    pub data: Option<Value>,
+    /// Binary attachments referenced by placeholders in `data`
+    pub attachments: Vec<Vec<u8>>,
+}
+
+impl SocketPacket {
+    fn new(kind: PacketKind, namespace: &str, id: Option<u64>, data: Option<Value>) -> Self {
+        Self {
+            kind,
+            namespace: namespace.to_string(),
+            id,
+            data,
+            attachments: Vec::new(),
+        }
+    }
+
+    /// Creates a packet accepting a namespace connection
+    ///
+    /// # Arguments
+    /// * `namespace` - Namespace being joined
+    /// * `sid` - Socket id assigned to the client in that namespace
+    pub fn connect(namespace: &str, sid: &str) -> Self {
+        Self::new(PacketKind::Connect, namespace, None, Some(serde_json::json!({ "sid": sid })))
+    }
+
+    /// Creates a packet refusing a namespace connection
+    ///
+    /// # Arguments
+    /// * `namespace` - Namespace that was requested
+    /// * `message` - Reason shown to the client
+    pub fn connect_error(namespace: &str, message: &str) -> Self {
+        Self::new(PacketKind::ConnectError, namespace, None, Some(serde_json::json!({ "message": message })))
+    }
+
+    /// Creates an event packet
+    ///
+    /// # Arguments
+    /// * `namespace` - Namespace the event is emitted on
+    /// * `event` - Event name
+    /// * `payload` - Event argument
+    pub fn event(namespace: &str, event: &str, payload: Value) -> Self {
+        Self::new(PacketKind::Event, namespace, None, Some(Value::Array(vec![event.into(), payload])))
+    }
+
+    /// Creates an acknowledgement for an event received with an id
+    ///
+    /// # Arguments
+    /// * `namespace` - Namespace the event arrived on
+    /// * `id` - Acknowledgement id of the event
+    /// * `payload` - Argument passed to the client's callback
+    pub fn ack(namespace: &str, id: u64, payload: Value) -> Self {
+        Self::new(PacketKind::Ack, namespace, Some(id), Some(Value::Array(vec![payload])))
+    }
+
+    /// Attaches binary buffers, turning the packet into its binary variant
+    ///
+    /// The payload must already contain one placeholder per buffer.
+    pub fn with_attachments(mut self, attachments: Vec<Vec<u8>>) -> Self {
+        self.kind = match self.kind {
+            PacketKind::Event => PacketKind::BinaryEvent,
+            PacketKind::Ack => PacketKind::BinaryAck,
+            kind => kind,
+        };
+        self.attachments = attachments;
+        self
+    }
+
+    /// Returns the event name of an event packet
+    pub fn event_name(&self) -> Option<&str> {
+        match self.kind {
+            PacketKind::Event | PacketKind::BinaryEvent => {
+                self.data.as_ref()?.as_array()?.first()?.as_str()
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the arguments following the event name of an event packet
+    pub fn event_args(&self) -> &[Value] {
+        match (self.kind, self.data.as_ref().and_then(Value::as_array)) {
+            (PacketKind::Event | PacketKind::BinaryEvent, Some(array)) if !array.is_empty() => &array[1..],
+            _ => &[],
+        }
+    }
+
+    /// Decodes the text part of a Socket.IO packet
+    ///
+    /// Binary packets are returned with an empty `attachments` list; use
+    /// [`PacketDecoder`] to collect the buffers that follow them.
+    ///
+    /// # Arguments
+    /// * `text` - Payload of an Engine.IO `message` packet
+    pub fn decode(text: &str) -> Result<(Self, usize), PacketError> {
+        let mut rest = text;
+        let kind = PacketKind::from_char(rest.chars().next().ok_or(PacketError::Empty)?)?;
+        rest = &rest[1..];
+
+        let mut attachment_count = 0;
+        if kind.is_binary() {
+            let dash = rest.find('-')
+                .ok_or_else(|| PacketError::InvalidPayload("missing attachment count".to_string()))?;
+            attachment_count = rest[..dash].parse()
+                .map_err(|_| PacketError::InvalidPayload("invalid attachment count".to_string()))?;
+            if attachment_count > MAX_ATTACHMENTS {
+                return Err(PacketError::TooManyAttachments(attachment_count));
+            }
+            rest = &rest[dash + 1..];
+        }
+
+        let mut namespace = DEFAULT_NAMESPACE;
+        if rest.starts_with('/') {
+            let end = rest.find(',').unwrap_or(rest.len());
+            namespace = &rest[..end];
+            rest = rest.get(end + 1..).unwrap_or_default();
+        }
+
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        let id = if digits > 0 {
+            Some(rest[..digits].parse()
+                .map_err(|_| PacketError::InvalidPayload("invalid ack id".to_string()))?)
+        } else {
+            None
+        };
+        rest = &rest[digits..];
+
+        let data = if rest.is_empty() {
+            None
+        } else {
+            Some(serde_json::from_str(rest).map_err(|e| PacketError::InvalidPayload(e.to_string()))?)
+        };
+
+        Ok((Self::new(kind, namespace, id, data), attachment_count))
+    }
+
+    /// Encodes the packet into an Engine.IO `message` text frame
+    /// followed by one binary frame per attachment
+    pub fn encode(&self) -> (String, Vec<Vec<u8>>) {
+        let mut text = String::from("4");
+        text.push(char::from(b'0' + self.kind as u8));
+
+        if self.kind.is_binary() {
+            text.push_str(&format!("{}-", self.attachments.len()));
+        }
+        if self.namespace != DEFAULT_NAMESPACE {
+            text.push_str(&self.namespace);
+            text.push(',');
+        }
+        if let Some(id) = self.id {
+            text.push_str(&id.to_string());
+        }
+        if let Some(data) = &self.data {
+            text.push_str(&data.to_string());
+        }
+
+        (text, self.attachments.clone())
+    }
+}
+
+/// Reassembles Socket.IO packets split across text and binary frames
+#[derive(Debug, Default)]
+pub struct PacketDecoder {
+    /// Binary packet waiting for attachments, with the number still expected
+    pending: Option<(SocketPacket, usize)>,
+}
+
+impl PacketDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds the payload of an Engine.IO `message` packet
+    ///
+    /// # Returns
+    /// The packet once it is complete, or `None` while attachments are missing
+    pub fn decode_text(&mut self, text: &str) -> Result<Option<SocketPacket>, PacketError> {
+        let (packet, attachment_count) = SocketPacket::decode(text)?;
+        if attachment_count == 0 {
+            self.pending = None;
+            return Ok(Some(packet));
+        }
+        self.pending = Some((packet, attachment_count));
+        Ok(None)
+    }
+
+    /// Checks whether a binary packet is still waiting for attachments
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Feeds a binary frame carrying the next expected attachment
+    ///
+    /// # Returns
+    /// The packet once its last attachment arrived, or `None` while more are missing
+    pub fn decode_binary(&mut self, data: &[u8]) -> Result<Option<SocketPacket>, PacketError> {
+        let (mut packet, expected) = self.pending.take().ok_or(PacketError::UnexpectedAttachment)?;
+        packet.attachments.push(data.to_vec());
+        if packet.attachments.len() < expected {
+            self.pending = Some((packet, expected));
+            return Ok(None);
+        }
+        Ok(Some(packet))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Decodes a frame as sent by the client and checks that encoding the
+    /// result reproduces the exact same frame
+    fn round_trip(frame: &str) -> SocketPacket {
+        let payload = match EnginePacket::decode(frame).unwrap() {
+            EnginePacket::Message(payload) => payload,
+            other => panic!("expected message packet, got {:?}", other),
+        };
+        let mut decoder = PacketDecoder::new();
+        let packet = decoder.decode_text(&payload).unwrap().unwrap();
+        assert_eq!(packet.encode().0, frame);
+        packet
+    }
+
+    #[test]
+    fn engine_open_round_trip() {
+        let frame = r#"0{"sid":"lv_VI97HAXpY6yYWAAAC","upgrades":[],"pingInterval":25000,"pingTimeout":5000,"maxPayload":1000000}"#;
+        let packet = EnginePacket::decode(frame).unwrap();
+        match &packet {
+            EnginePacket::Open(handshake) => {
+                assert_eq!(handshake.sid, "lv_VI97HAXpY6yYWAAAC");
+                assert_eq!(handshake.ping_interval, 25000);
+                assert_eq!(handshake.ping_timeout, 5000);
+            }
+            other => panic!("expected open packet, got {:?}", other),
+        }
+        assert_eq!(packet.encode(), frame);
+    }
+
+    #[test]
+    fn engine_heartbeat_round_trip() {
+        assert_eq!(EnginePacket::decode("2").unwrap(), EnginePacket::Ping(None));
+        assert_eq!(EnginePacket::decode("3").unwrap(), EnginePacket::Pong(None));
+        assert_eq!(EnginePacket::decode("2probe").unwrap(), EnginePacket::Ping(Some("probe".to_string())));
+        assert_eq!(EnginePacket::Pong(Some("probe".to_string())).encode(), "3probe");
+        assert_eq!(EnginePacket::decode("1").unwrap(), EnginePacket::Close);
+        assert_eq!(EnginePacket::decode("6").unwrap(), EnginePacket::Noop);
+    }
+
+    #[test]
+    fn engine_rejects_malformed_frames() {
+        assert_eq!(EnginePacket::decode(""), Err(PacketError::Empty));
+        assert_eq!(EnginePacket::decode("9"), Err(PacketError::UnknownType('9')));
+        assert!(matches!(EnginePacket::decode("0{"), Err(PacketError::InvalidPayload(_))));
+    }
+
+    #[test]
+    fn connect_default_namespace() {
+        let packet = round_trip("40");
+        assert_eq!(packet.kind, PacketKind::Connect);
+        assert_eq!(packet.namespace, "/");
+        assert_eq!(packet.data, None);
+    }
+
+    #[test]
+    fn connect_with_auth_payload() {
+        let packet = round_trip(r#"40{"token":"123"}"#);
+        assert_eq!(packet.kind, PacketKind::Connect);
+        assert_eq!(packet.data, Some(json!({ "token": "123" })));
+    }
+
+    #[test]
+    fn connect_custom_namespace() {
+        let packet = round_trip(r#"40/admin,{"token":"123"}"#);
+        assert_eq!(packet.namespace, "/admin");
+        assert_eq!(packet.data, Some(json!({ "token": "123" })));
+    }
+
+    #[test]
+    fn disconnect_packets() {
+        assert_eq!(round_trip("41").kind, PacketKind::Disconnect);
+        let packet = round_trip("41/admin,");
+        assert_eq!(packet.kind, PacketKind::Disconnect);
+        assert_eq!(packet.namespace, "/admin");
+    }
+
+    #[test]
+    fn event_without_ack() {
+        let packet = round_trip(r#"42["chat",{"message":"hello"}]"#);
+        assert_eq!(packet.kind, PacketKind::Event);
+        assert_eq!(packet.id, None);
+        assert_eq!(packet.event_name(), Some("chat"));
+        assert_eq!(packet.event_args(), &[json!({ "message": "hello" })]);
+    }
+
+    #[test]
+    fn event_with_ack_id() {
+        let packet = round_trip(r#"4213["equipItem",{"itemId":"6a1b","slot":"MainHand"}]"#);
+        assert_eq!(packet.id, Some(13));
+        assert_eq!(packet.event_name(), Some("equipItem"));
+        assert_eq!(packet.event_args()[0]["slot"], "MainHand");
+    }
+
+    #[test]
+    fn event_on_namespace_with_ack_id() {
+        let packet = round_trip(r#"42/admin,7["kick",{"id":"abc"}]"#);
+        assert_eq!(packet.namespace, "/admin");
+        assert_eq!(packet.id, Some(7));
+        assert_eq!(packet.event_name(), Some("kick"));
+    }
+
+    #[test]
+    fn ack_packets() {
+        let packet = round_trip(r#"4313[{"success":true}]"#);
+        assert_eq!(packet.kind, PacketKind::Ack);
+        assert_eq!(packet.id, Some(13));
+        assert_eq!(
+            SocketPacket::ack("/", 13, json!({ "success": true })).encode().0,
+            r#"4313[{"success":true}]"#
+        );
+    }
+
+    #[test]
+    fn connect_and_error_responses() {
+        assert_eq!(SocketPacket::connect("/", "abc").encode().0, r#"40{"sid":"abc"}"#);
+        assert_eq!(
+            SocketPacket::connect_error("/admin", "Invalid namespace").encode().0,
+            r#"44/admin,{"message":"Invalid namespace"}"#
+        );
+    }
+
+    #[test]
+    fn binary_event_reassembly() {
+        let mut decoder = PacketDecoder::new();
+        let text = r#"52-["upload",{"_placeholder":true,"num":0},{"_placeholder":true,"num":1}]"#;
+        assert_eq!(decoder.decode_text(text).unwrap(), None);
+        assert_eq!(decoder.decode_binary(&[1, 2, 3]).unwrap(), None);
+        let packet = decoder.decode_binary(&[4, 5]).unwrap().unwrap();
+
+        assert_eq!(packet.kind, PacketKind::BinaryEvent);
+        assert_eq!(packet.event_name(), Some("upload"));
+        assert_eq!(packet.attachments, vec![vec![1, 2, 3], vec![4, 5]]);
+
+        let (encoded, buffers) = packet.encode();
+        assert_eq!(encoded, format!("4{}", text));
+        assert_eq!(buffers, vec![vec![1, 2, 3], vec![4, 5]]);
+    }
+
+    #[test]
+    fn binary_ack_with_namespace_and_id() {
+        let mut decoder = PacketDecoder::new();
+        let text = r#"61-/admin,4[{"_placeholder":true,"num":0}]"#;
+        assert_eq!(decoder.decode_text(text).unwrap(), None);
+        let packet = decoder.decode_binary(&[9]).unwrap().unwrap();
+        assert_eq!(packet.kind, PacketKind::BinaryAck);
+        assert_eq!(packet.namespace, "/admin");
+        assert_eq!(packet.id, Some(4));
+        assert_eq!(packet.encode().0, format!("4{}", text));
+    }
+
+    #[test]
+    fn packets_announcing_too_many_attachments_are_rejected() {
+        let mut decoder = PacketDecoder::new();
+        let text = |count: usize| format!(r#"5{}-["upload",{{"_placeholder":true,"num":0}}]"#, count);
+        assert_eq!(decoder.decode_text(&text(MAX_ATTACHMENTS + 1)), Err(PacketError::TooManyAttachments(MAX_ATTACHMENTS + 1)));
+        assert_eq!(decoder.decode_text("599999999999-[\"upload\"]"), Err(PacketError::TooManyAttachments(99999999999)));
+        assert!(!decoder.is_pending());
+        assert_eq!(decoder.decode_text(&text(MAX_ATTACHMENTS)), Ok(None));
+    }
+
+    #[test]
+    fn outgoing_binary_event() {
+        let packet = SocketPacket::event("/", "snapshot", json!({ "_placeholder": true, "num": 0 }))
+            .with_attachments(vec![vec![0xde, 0xad]]);
+        let (text, buffers) = packet.encode();
+        assert_eq!(text, r#"451-["snapshot",{"_placeholder":true,"num":0}]"#);
+        assert_eq!(buffers, vec![vec![0xde, 0xad]]);
+    }
+
+    #[test]
+    fn stray_binary_frame_is_rejected() {
+        let mut decoder = PacketDecoder::new();
+        assert_eq!(decoder.decode_binary(&[1]), Err(PacketError::UnexpectedAttachment));
+    }
+
+    #[test]
+    fn socket_rejects_malformed_packets() {
+        assert_eq!(SocketPacket::decode("").unwrap_err(), PacketError::Empty);
+        assert_eq!(SocketPacket::decode("7").unwrap_err(), PacketError::UnknownType('7'));
+        assert!(matches!(SocketPacket::decode("2[\"x\""), Err(PacketError::InvalidPayload(_))));
+        assert!(matches!(SocketPacket::decode("5[\"x\"]"), Err(PacketError::InvalidPayload(_))));
+    }
+}
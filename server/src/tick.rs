@@ -0,0 +1,66 @@
+/// Fixed-rate authoritative simulation loop
+///
+/// Client inputs are only buffered by the websocket sessions; this actor
+/// advances the game state at a fixed rate and hands every session its
+/// view of the resulting world snapshot once per tick.
+use actix::{Actor, AsyncContext, Context};
+use std::sync::Arc;
+use std::time::Duration;
+use parking_lot::RwLock;
+use log::info;
+use crate::game::GameState;
+
+/// Tick rate used when `TICK_RATE` is not set
+pub const DEFAULT_TICK_RATE: u32 = 30;
+/// Lowest accepted tick rate
+pub const MIN_TICK_RATE: u32 = 20;
+/// Highest accepted tick rate
+pub const MAX_TICK_RATE: u32 = 60;
+
+/// Reads the tick rate from the `TICK_RATE` environment variable
+///
+/// # Returns
+/// The configured rate clamped to `MIN_TICK_RATE..=MAX_TICK_RATE`,
+/// or `DEFAULT_TICK_RATE` when unset or invalid
+pub fn tick_rate_from_env() -> u32 {
+    std::env::var("TICK_RATE")
+        .ok()
+        .and_then(|rate| rate.parse::<u32>().ok())
+        .map(|rate| rate.clamp(MIN_TICK_RATE, MAX_TICK_RATE))
+        .unwrap_or(DEFAULT_TICK_RATE)
+}
+
+/// Actor driving the simulation at a fixed rate
+pub struct GameLoop {
+    /// Shared game state advanced on every tick
+    game_state: Arc<RwLock<GameState>>,
+    /// Simulation ticks per second
+    tick_rate: u32,
+}
+
+impl GameLoop {
+    pub fn new(game_state: Arc<RwLock<GameState>>, tick_rate: u32) -> Self {
+        game_state.write().set_tick_rate(tick_rate);
+        Self {
+            game_state,
+            tick_rate,
+        }
+    }
+
+    /// Advances the simulation and broadcasts what changed
+    fn tick(&mut self) {
+        let mut game_state = self.game_state.write();
+        let snapshot = Arc::new(game_state.advance_tick());
+        game_state.publish_snapshot(snapshot);
+    }
+}
+
+impl Actor for GameLoop {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        info!("Starting game loop at {} Hz", self.tick_rate);
+        let interval = Duration::from_secs_f64(1.0 / self.tick_rate as f64);
+        ctx.run_interval(interval, |act, _ctx| act.tick());
+    }
+}
@@ -1,11 +1,16 @@
 /// Core type definitions for the game
 use serde::{Serialize, Deserialize};
 use uuid::Uuid;
+use crate::core::game::combat::BASE_ATTRIBUTE;
+use crate::core::game::dungeon::DungeonLayout;
+use crate::core::game::inventory::Inventory;
+use crate::core::game::quests::QuestLog;
+use crate::core::game::skills::HOTBAR_SLOTS;
 
 /// Represents a 2D position in the game world
 /// 
 /// Uses floating-point coordinates for smooth movement
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Position {
     /// X coordinate in world space
     pub x: i32,
@@ -30,6 +35,10 @@ pub struct Player {
     pub name: String,
     /// Current position in the game world
     pub position: Position,
+    /// Depth of the dungeon floor the player is on, 0 being the top floor
+    pub floor: u32,
+    /// Party instance the player is in, or None in the shared dungeon
+    pub instance: Option<Uuid>,
     /// Current health points
     pub health: i32,
     /// Maximum health points
@@ -44,10 +53,20 @@ pub struct Player {
     pub max_exp: i32,
     /// Current player level
     pub level: i32,
+    /// Attribute points earned by leveling up and not spent yet
+    pub stat_points: i32,
     /// Player's faction alignment
     pub faction: String,
-    /// Player's inventory items
-    pub inventory: Vec<Item>,
+    /// Class picked when the character was created, or None for guests
+    pub class: Option<String>,
+    /// Base attributes, before equipment bonuses
+    pub attributes: Attributes,
+    /// Items the player carries and wears
+    pub inventory: Inventory,
+    /// Names of the skills bound to each hotbar slot
+    pub hotbar: Vec<Option<String>>,
+    /// Progress on the quests of the quest book
+    pub quests: QuestLog,
     /// Cryptocurrency wallet address
     pub wallet_address: String,
     /// Current cryptocurrency balance
@@ -60,19 +79,201 @@ impl Player {
             id,
             name,
             position,
-            health: 0,
-            max_health: 0,
-            mana: 0,
-            max_mana: 0,
+            floor: 0,
+            instance: None,
+            health: 100,
+            max_health: 100,
+            mana: 100,
+            max_mana: 100,
             exp: 0,
             max_exp: 0,
-            level: 0,
+            level: 1,
+            stat_points: 0,
             faction: String::new(),
-            inventory: Vec::new(),
+            class: None,
+            attributes: Attributes::default(),
+            inventory: Inventory::new(),
+            hotbar: vec![None; HOTBAR_SLOTS],
+            quests: QuestLog::default(),
             wallet_address: String::new(),
             balance: 0.0,
         }
     }
+
+    /// Gets the most mana the player can hold, equipment bonuses included
+    pub fn mana_capacity(&self) -> i32 {
+        self.max_mana + self.inventory.equipment().values().map(|item| item.stats.mana).sum::<i32>()
+    }
+}
+
+/// Attributes of a character, each raising different combat stats
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct Attributes {
+    /// Raises melee damage
+    pub strength: i32,
+    /// Raises ranged damage and the chance to hit, dodge and land critical hits
+    pub dexterity: i32,
+    /// Raises magic
+    pub intelligence: i32,
+}
+
+impl Default for Attributes {
+    fn default() -> Self {
+        Self {
+            strength: BASE_ATTRIBUTE,
+            dexterity: BASE_ATTRIBUTE,
+            intelligence: BASE_ATTRIBUTE,
+        }
+    }
+}
+
+/// Slot an item is equipped in
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum EquipmentSlot {
+    MainHand,
+    OffHand,
+    Head,
+    Chest,
+    Legs,
+    Feet,
+    Hands,
+    Neck,
+    Ring1,
+    Ring2,
+}
+
+/// Way an attack is delivered
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum AttackKind {
+    /// Strike at an adjacent target, scaling with strength
+    #[default]
+    Melee,
+    /// Shoot at a target in line of sight, scaling with dexterity
+    Ranged,
+}
+
+impl AttackKind {
+    /// Reach of the attack in tiles, measured along the longer axis
+    pub fn range(self) -> i32 {
+        match self {
+            AttackKind::Melee => 1,
+            AttackKind::Ranged => 6,
+        }
+    }
+
+    /// Time a player waits after this attack before attacking again
+    pub fn cooldown_ms(self) -> u64 {
+        match self {
+            AttackKind::Melee => 800,
+            AttackKind::Ranged => 1200,
+        }
+    }
+}
+
+/// Result of an attack roll
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "result", rename_all = "camelCase")]
+pub enum AttackOutcome {
+    /// The attack missed
+    Miss,
+    /// The attack hit for damage, after armor
+    Hit { damage: i32, critical: bool },
+}
+
+/// Kind of a status effect, as shown to clients
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "camelCase")]
+#[repr(u8)]
+pub enum StatusKind {
+    /// Loses health every interval
+    Poison = 0,
+    /// Regains health every interval
+    Regeneration = 1,
+    /// Moves faster
+    Haste = 2,
+    /// Moves slower
+    Slow = 3,
+    /// Cannot move, attack or cast
+    Stun = 4,
+    /// Damage is absorbed before it reaches health
+    Shield = 5,
+    /// Combat stats are raised
+    Buff = 6,
+    /// Combat stats are lowered
+    Debuff = 7,
+}
+
+/// Status effect currently on an entity, for clients to draw as an icon
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct ActiveStatus {
+    /// Kind of the effect
+    pub kind: StatusKind,
+    /// Number of effects of this kind on the entity
+    pub stacks: u8,
+    /// Tick at which the last of them wears off
+    pub expires: u64,
+}
+
+/// What a skill did to an entity it affected
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "result", rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum SkillOutcome {
+    /// The entity took damage and has `health` left
+    Damaged { amount: i32, health: i32 },
+    /// The entity was healed and now has `health`
+    Healed { amount: i32, health: i32 },
+    /// A status effect was put on the entity for a while
+    StatusApplied { status: StatusKind, duration_ms: u64 },
+    /// The entity was moved to a position
+    Teleported { position: Position },
+}
+
+/// Why a player gained experience
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum ExperienceSource {
+    /// The player killed an NPC
+    Kill,
+    /// The player completed a quest
+    Quest,
+}
+
+/// Represents a monster or other non-player character
+///
+/// NPCs are spawned by the spawners of a floor and live on that floor only.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Npc {
+    /// Unique identifier for the NPC
+    pub id: Uuid,
+    /// Name of the template the NPC was spawned from, e.g. "Skeleton"
+    pub name: String,
+    /// Whether the NPC is a monster that fights players
+    pub hostile: bool,
+    /// Current position in the game world
+    pub position: Position,
+    /// Current health points
+    pub health: i32,
+    /// Maximum health points
+    pub max_health: i32,
+    /// Combat stats
+    pub stats: Stats,
+    /// Experience awarded to the player who kills the NPC
+    pub experience: i32,
+    /// Items dropped when the NPC dies, rolled from its loot table at spawn
+    pub loot: Vec<Item>,
+}
+
+/// Kind of an entity in the game world
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum EntityKind {
+    /// Another player
+    Player,
+    /// A hostile NPC
+    Monster,
+    /// A peaceful NPC
+    Npc,
 }
 
 /// Represents an item in the game
@@ -94,6 +295,65 @@ pub struct Item {
     pub attributes: Vec<ItemAttribute>,
     /// Additional stats for the item
     pub stats: Stats,
+    /// Number of items in the stack, for items that stack
+    #[serde(default = "Item::single")]
+    pub stack_size: u32,
+    /// Equipment slots the item fits; empty for items that cannot be equipped
+    #[serde(default)]
+    pub slots: Vec<EquipmentSlot>,
+    /// Whether the item takes both hands, leaving the off hand empty
+    #[serde(default)]
+    pub two_handed: bool,
+    /// What a player needs to equip the item
+    #[serde(default)]
+    pub requirements: Requirements,
+}
+
+/// What a player needs to equip an item
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Requirements {
+    /// Lowest level of the player
+    pub level: i32,
+    /// Names of the classes that may equip the item; any class when empty
+    pub classes: Vec<String>,
+    /// Lowest base strength of the player
+    pub strength: i32,
+    /// Lowest base dexterity of the player
+    pub dexterity: i32,
+    /// Lowest base intelligence of the player
+    pub intelligence: i32,
+}
+
+impl Item {
+    /// Stack size of a single item
+    fn single() -> u32 {
+        1
+    }
+
+    /// Whether the item can be worn or wielded
+    pub fn is_equippable(&self) -> bool {
+        !self.slots.is_empty()
+    }
+
+    /// Whether the item is used up when used
+    pub fn is_consumable(&self) -> bool {
+        self.item_type == ItemType::Consumable
+    }
+
+    /// Whether items of this kind share an inventory slot
+    pub fn is_stackable(&self) -> bool {
+        matches!(self.item_type, ItemType::Consumable | ItemType::Quest)
+    }
+
+    /// Whether another item can go on this item's stack
+    pub fn stacks_with(&self, other: &Item) -> bool {
+        self.is_stackable()
+            && self.name == other.name
+            && self.item_type == other.item_type
+            && self.rarity == other.rarity
+            && self.stats == other.stats
+    }
 }
 
 /// Represents a special attribute of an item
@@ -131,8 +391,98 @@ pub enum TileType {
     Floor = 0,
     /// Solid wall tile that blocks movement
     Wall = 1,
-    /// Door tile that can be opened/closed
+    /// Closed door that blocks movement and sight until opened
     Door = 2,
+    /// Walkable tile leading to the floor above
+    StairsUp = 3,
+    /// Walkable tile leading to the floor below
+    StairsDown = 4,
+    /// Walkable tile leading into a party instance, or out of one
+    Portal = 5,
+    /// Open door that can be walked and seen through
+    OpenDoor = 6,
+    /// Closed door that only opens for the matching key
+    LockedDoor = 7,
+}
+
+impl TileType {
+    /// Whether the tile blocks both movement and line of sight
+    pub fn is_solid(self) -> bool {
+        matches!(self, TileType::Wall | TileType::Door | TileType::LockedDoor)
+    }
+
+    /// Whether the tile is a door in any state
+    pub fn is_door(self) -> bool {
+        DoorState::of(self).is_some()
+    }
+}
+
+/// State of a door
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum DoorState {
+    /// Can be walked and seen through
+    Open,
+    /// Blocks movement and sight until opened
+    Closed,
+    /// Closed and only opens for the matching key
+    Locked,
+}
+
+impl DoorState {
+    /// Gets the state of the door on a tile
+    ///
+    /// # Returns
+    /// The door's state, or None when the tile is not a door
+    pub fn of(tile: TileType) -> Option<Self> {
+        match tile {
+            TileType::OpenDoor => Some(DoorState::Open),
+            TileType::Door => Some(DoorState::Closed),
+            TileType::LockedDoor => Some(DoorState::Locked),
+            _ => None,
+        }
+    }
+
+    /// Gets the tile of a door in this state
+    pub fn tile(self) -> TileType {
+        match self {
+            DoorState::Open => TileType::OpenDoor,
+            DoorState::Closed => TileType::Door,
+            DoorState::Locked => TileType::LockedDoor,
+        }
+    }
+}
+
+/// Key required to open a locked door
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct Lock {
+    /// X coordinate of the door's tile
+    pub x: i32,
+    /// Y coordinate of the door's tile
+    pub y: i32,
+    /// ID of the key item that opens the door
+    pub key: Uuid,
+    /// Tile the key lies on when the floor is generated
+    pub key_at: (i32, i32),
+}
+
+impl Lock {
+    /// Creates the key item that opens the door
+    pub fn key_item(&self) -> Item {
+        Item {
+            id: self.key,
+            name: "Iron Key".to_string(),
+            item_type: ItemType::Key,
+            rarity: Rarity::Common,
+            value: 0,
+            attributes: Vec::new(),
+            stats: Stats::default(),
+            stack_size: 1,
+            slots: Vec::new(),
+            two_handed: false,
+            requirements: Requirements::default(),
+        }
+    }
 }
 
 /// Represents a room in the dungeon
@@ -150,6 +500,38 @@ pub struct Room {
     pub height: i32,
 }
 
+impl Room {
+    pub fn new(x: i32, y: i32, width: i32, height: i32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Gets the tile at the center of the room
+    pub fn center(&self) -> (i32, i32) {
+        (
+            self.x + self.width / 2,
+            self.y + self.height / 2,
+        )
+    }
+
+    /// Checks whether two rooms overlap or touch, leaving no wall between them
+    pub fn intersects(&self, other: &Room) -> bool {
+        !(self.x + self.width + 1 < other.x ||
+          other.x + other.width + 1 < self.x ||
+          self.y + self.height + 1 < other.y ||
+          other.y + other.height + 1 < self.y)
+    }
+
+    /// Checks whether a tile lies inside the room
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+}
+
 /// Represents the complete dungeon layout
 /// 
 /// Contains the tile map and room information
@@ -163,35 +545,68 @@ pub struct Dungeon {
     pub tiles: Vec<TileType>,
     /// List of rooms in the dungeon
     pub rooms: Vec<Room>,
+    /// Seed the layout was generated from
+    pub seed: u64,
+    /// Depth of the floor in the dungeon stack, 0 being the top floor
+    pub depth: u32,
+    /// Algorithm and parameters the layout was generated with, or None
+    /// for a fixed layout
+    pub layout: Option<DungeonLayout>,
+    /// Number of tile changes made through `set_tile`, used to drop
+    /// cached paths
+    #[serde(skip)]
+    pub revision: u64,
+    /// Keys of the locked doors, kept from clients
+    #[serde(skip)]
+    pub locks: Vec<Lock>,
 }
 
 impl Dungeon {
     pub fn new(width: usize, height: usize) -> Self {
-        let mut tiles = vec![TileType::Wall; width * height];
-        
+        let mut dungeon = Self::solid(width, height, 0);
+
         // Create a simple room in the center
-        let room_x = width / 4;
-        let room_y = height / 4;
-        let room_w = width / 2;
-        let room_h = height / 2;
-
-        for y in room_y..(room_y + room_h) {
-            for x in room_x..(room_x + room_w) {
-                tiles[y * width + x] = TileType::Floor;
+        let room = Room::new(width as i32 / 4, height as i32 / 4, width as i32 / 2, height as i32 / 2);
+        let (mid_x, mid_y) = room.center();
+        let (right, bottom) = (room.x + room.width, room.y + room.height);
+        let (max_x, max_y) = (width as i32 - 2, height as i32 - 2);
+        dungeon.add_room(room.clone());
+
+        // Put a door in the middle of each wall, opening onto a corridor that
+        // runs to the edge of the map, where there is space for one
+        let exits = [
+            (room.y - 1 > 1, (mid_x, room.y - 1), (mid_x, 1)),
+            (bottom < max_y, (mid_x, bottom), (mid_x, max_y)),
+            (room.x - 1 > 1, (room.x - 1, mid_y), (1, mid_y)),
+            (right < max_x, (right, mid_y), (max_x, mid_y)),
+        ];
+        for (fits, door, end) in exits {
+            if fits {
+                dungeon.create_corridor(door, end, true);
+                dungeon.set_tile(door.0, door.1, TileType::Door);
             }
         }
 
-        // Add some doors
-        tiles[room_y * width + (room_x + room_w/2)] = TileType::Door;
-        tiles[(room_y + room_h) * width + (room_x + room_w/2)] = TileType::Door;
-        tiles[(room_y + room_h/2) * width + room_x] = TileType::Door;
-        tiles[(room_y + room_h/2) * width + (room_x + room_w)] = TileType::Door;
+        dungeon
+    }
 
+    /// Creates a dungeon made only of walls
+    ///
+    /// # Arguments
+    /// * `width` - Width in tiles
+    /// * `height` - Height in tiles
+    /// * `seed` - Seed the layout will be generated from
+    pub fn solid(width: usize, height: usize, seed: u64) -> Self {
         Self {
             width,
             height,
-            tiles,
+            tiles: vec![TileType::Wall; width * height],
             rooms: Vec::new(),
+            seed,
+            depth: 0,
+            layout: None,
+            revision: 0,
+            locks: Vec::new(),
         }
     }
 
@@ -202,6 +617,185 @@ impl Dungeon {
             self.tiles[y * self.width + x]
         }
     }
+
+    /// Changes a tile, ignoring coordinates outside the dungeon
+    ///
+    /// # Returns
+    /// Whether the tile was inside the dungeon
+    pub fn set_tile(&mut self, x: i32, y: i32, tile: TileType) -> bool {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            return false;
+        }
+        let index = y as usize * self.width + x as usize;
+        if self.tiles[index] != tile {
+            self.tiles[index] = tile;
+            self.revision += 1;
+        }
+        true
+    }
+
+    /// Locks a closed door so only one key opens it
+    ///
+    /// # Arguments
+    /// * `x` - X coordinate of the door's tile
+    /// * `y` - Y coordinate of the door's tile
+    /// * `key` - ID of the key item that will open the door
+    /// * `key_at` - Tile the key lies on
+    ///
+    /// # Returns
+    /// Whether there was a closed door to lock
+    pub fn lock_door(&mut self, x: i32, y: i32, key: Uuid, key_at: (i32, i32)) -> bool {
+        if x < 0 || y < 0 || self.get_tile(x as usize, y as usize) != TileType::Door {
+            return false;
+        }
+        self.set_tile(x, y, TileType::LockedDoor);
+        self.locks.push(Lock { x, y, key, key_at });
+        true
+    }
+
+    /// Unlocks a locked door, leaving it closed
+    ///
+    /// # Arguments
+    /// * `x` - X coordinate of the door's tile
+    /// * `y` - Y coordinate of the door's tile
+    /// * `key` - ID of the key item tried on the door
+    ///
+    /// # Returns
+    /// Whether the door was locked with that key
+    pub fn unlock_door(&mut self, x: i32, y: i32, key: Uuid) -> bool {
+        let Some(index) = self.locks.iter().position(|lock| (lock.x, lock.y, lock.key) == (x, y, key)) else {
+            return false;
+        };
+        self.locks.swap_remove(index);
+        self.set_tile(x, y, TileType::Door);
+        true
+    }
+
+    /// Gets the key that opens a locked door
+    ///
+    /// # Returns
+    /// ID of the key item, or None when the tile is not a locked door
+    pub fn door_key(&self, x: i32, y: i32) -> Option<Uuid> {
+        self.locks.iter()
+            .find(|lock| lock.x == x && lock.y == y)
+            .map(|lock| lock.key)
+    }
+
+    /// Carves out a room and adds it to the room list
+    pub fn add_room(&mut self, room: Room) {
+        for y in room.y..room.y + room.height {
+            for x in room.x..room.x + room.width {
+                self.set_tile(x, y, TileType::Floor);
+            }
+        }
+        self.rooms.push(room);
+    }
+
+    /// Carves an L-shaped corridor between two tiles
+    ///
+    /// # Arguments
+    /// * `from` - Start tile
+    /// * `to` - End tile
+    /// * `horizontal_first` - Whether to run along the row of `from` before turning
+    pub fn create_corridor(&mut self, from: (i32, i32), to: (i32, i32), horizontal_first: bool) {
+        let ((x1, y1), (x2, y2)) = (from, to);
+        let corner = if horizontal_first { (x2, y1) } else { (x1, y2) };
+
+        for (a, b) in [(from, corner), (corner, to)] {
+            for x in a.0.min(b.0)..=a.0.max(b.0) {
+                for y in a.1.min(b.1)..=a.1.max(b.1) {
+                    self.set_tile(x, y, TileType::Floor);
+                }
+            }
+        }
+    }
+
+    /// Finds the tile players spawn on
+    ///
+    /// # Returns
+    /// The center of the first room or, for layouts without rooms, the floor
+    /// tile closest to the center of the dungeon
+    pub fn spawn_tile(&self) -> Option<(i32, i32)> {
+        match self.rooms.first() {
+            Some(room) => Some(room.center()),
+            None => self.find_floor_near(self.width as i32 / 2, self.height as i32 / 2),
+        }
+    }
+
+    /// Finds the first tile of a type, scanning row by row
+    ///
+    /// # Returns
+    /// Coordinates of the tile, or None when the dungeon has no such tile
+    pub fn find_tile(&self, tile: TileType) -> Option<(i32, i32)> {
+        self.tiles.iter()
+            .position(|t| *t == tile)
+            .map(|index| ((index % self.width) as i32, (index / self.width) as i32))
+    }
+
+    /// Finds the walkable tile closest to a tile
+    ///
+    /// Searches rings of increasing distance around the tile.
+    ///
+    /// # Returns
+    /// Coordinates of a floor tile, or None when the dungeon has no floor
+    pub fn find_floor_near(&self, x: i32, y: i32) -> Option<(i32, i32)> {
+        let max_radius = self.width.max(self.height) as i32;
+        for radius in 0..=max_radius {
+            for ty in y - radius..=y + radius {
+                for tx in x - radius..=x + radius {
+                    let on_ring = (tx - x).abs() == radius || (ty - y).abs() == radius;
+                    if on_ring && tx >= 0 && ty >= 0
+                        && self.get_tile(tx as usize, ty as usize) == TileType::Floor
+                    {
+                        return Some((tx, ty));
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Finds the room containing a tile
+    ///
+    /// # Returns
+    /// Index into `rooms`, or None when the tile is outside every room
+    pub fn room_at(&self, x: i32, y: i32) -> Option<usize> {
+        self.rooms.iter().position(|room| room.contains(x, y))
+    }
+}
+
+/// A player's map of a dungeon floor
+///
+/// Only the tiles the player has seen are filled in, as they looked when
+/// the player last saw them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DungeonView {
+    /// Width of the dungeon in tiles
+    pub width: usize,
+    /// Height of the dungeon in tiles
+    pub height: usize,
+    /// Depth of the floor in the dungeon stack, 0 being the top floor
+    pub depth: u32,
+    /// 2D grid of tile types, None where the player has not been able to see
+    pub tiles: Vec<Option<TileType>>,
+}
+
+/// A tile that came into a player's view, or changed while in view
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct RevealedTile {
+    /// X coordinate of the tile
+    pub x: i32,
+    /// Y coordinate of the tile
+    pub y: i32,
+    /// Type of the tile
+    pub tile: TileType,
+}
+
+/// Query parameters naming the player whose view of the game is requested
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayerQuery {
+    /// UUID of the player
+    pub player: Uuid,
 }
 
 /// Request structure for player movement
@@ -213,8 +807,102 @@ pub struct MoveRequest {
     pub y: f32,
 }
 
-/// Additional stats for an item
+/// Query parameters naming an account
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountQuery {
+    /// Wallet address of the account
+    pub account: String,
+}
+
+/// Challenge the wallet of an account signs to register it
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountChallenge {
+    /// Wallet address of the account
+    pub account: String,
+    /// Message to sign with the wallet
+    pub message: String,
+}
+
+/// Request structure for registering an account
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegisterAccountRequest {
+    /// Wallet address of the account
+    pub account: String,
+    /// Signature of the account's challenge by the wallet, as hex
+    pub signature: String,
+}
+
+/// Secret key given to an account when it is registered
+///
+/// Requests acting for the account send the key as a bearer token.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountKey {
+    /// Wallet address of the account
+    pub account: String,
+    /// Secret key of the account
+    pub key: Uuid,
+}
+
+/// Request structure for creating a character
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateCharacterRequest {
+    /// Wallet address of the account the character belongs to
+    pub account: String,
+    /// Display name of the character
+    pub name: String,
+    /// Name of the character's class, e.g. "warrior"
+    pub class: String,
+}
+
+/// Request structure for spending stat points on attributes
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AttributeAllocation {
+    /// Points added to strength
+    pub strength: u32,
+    /// Points added to dexterity
+    pub dexterity: u32,
+    /// Points added to intelligence
+    pub intelligence: u32,
+}
+
+impl AttributeAllocation {
+    /// Gets the number of stat points the allocation spends
+    pub fn total(&self) -> u32 {
+        self.strength + self.dexterity + self.intelligence
+    }
+}
+
+/// Request structure for regenerating the dungeon
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegenerateDungeonRequest {
+    /// Seed of the new layout; a random seed is used when missing
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// Algorithm and parameters of the new layout; the current ones are
+    /// used when missing
+    #[serde(default)]
+    pub layout: Option<DungeonLayout>,
+}
+
+/// Lifecycle state of a party dungeon instance
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstanceInfo {
+    /// Unique identifier of the instance
+    pub id: Uuid,
+    /// Players currently inside
+    pub players: Vec<Uuid>,
+    /// IDs of the party members allowed back in after reconnecting
+    pub members: Vec<Uuid>,
+    /// Tick at which the instance was created
+    pub created_tick: u64,
+    /// Seconds left before the empty instance is torn down, or None while
+    /// players are inside
+    pub expires_in_secs: Option<u64>,
+}
+
+/// Additional stats for an item
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
 pub struct Stats {
     /// Damage bonus provided by the item
     pub damage: i32,
@@ -222,6 +910,22 @@ pub struct Stats {
     pub defense: i32,
     /// Magic bonus provided by the item
     pub magic: i32,
+    /// Strength bonus provided by the item
+    #[serde(default)]
+    pub strength: i32,
+    /// Dexterity bonus provided by the item
+    #[serde(default)]
+    pub dexterity: i32,
+    /// Intelligence bonus provided by the item
+    #[serde(default)]
+    pub intelligence: i32,
+    /// Maximum mana bonus provided by the item when worn, or mana restored
+    /// when it is consumed
+    #[serde(default)]
+    pub mana: i32,
+    /// Health restored when the item is consumed
+    #[serde(default)]
+    pub health: i32,
 }
 
 /// Type of item
@@ -235,6 +939,8 @@ pub enum ItemType {
     Consumable,
     /// Quest item
     Quest,
+    /// Key that opens a specific locked door
+    Key,
 }
 
 /// Additional movement structure
@@ -0,0 +1,97 @@
+/// Proof that a player owns the wallet they register an account for
+///
+/// Players sign a server-issued challenge with their wallet the way
+/// Ethereum wallets sign messages (`personal_sign`); the address recovered
+/// from the signature must be the wallet address of the account.
+use libsecp256k1::{recover, Message, RecoveryId, Signature};
+use sha3::{Digest, Keccak256};
+
+/// Checks whether a string is an Ethereum wallet address, `0x` followed by
+/// 40 hexadecimal digits
+pub fn is_address(address: &str) -> bool {
+    address.strip_prefix("0x")
+        .is_some_and(|digits| digits.len() == 40 && digits.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// Builds the message a player signs to prove they own a wallet
+///
+/// # Arguments
+/// * `address` - Wallet address of the account
+/// * `nonce` - Single-use challenge issued by the server
+pub fn challenge_message(address: &str, nonce: &str) -> String {
+    format!("Sign in to Crypto MMORPG as {} with nonce {}", address, nonce)
+}
+
+/// Recovers the wallet address that signed a message
+///
+/// # Arguments
+/// * `message` - Message that was signed
+/// * `signature` - 65-byte signature as hex, with or without `0x`, its last
+///   byte the recovery ID (0, 1, 27 or 28)
+///
+/// # Returns
+/// The lowercase address of the signer, or the reason the signature is invalid
+pub fn recover_signer(message: &str, signature: &str) -> Result<String, String> {
+    let bytes = hex::decode(signature.trim_start_matches("0x"))
+        .map_err(|_| "Signatures are hex encoded".to_string())?;
+    if bytes.len() != 65 {
+        return Err("Signatures are 65 bytes long".to_string());
+    }
+    let recovery = RecoveryId::parse(bytes[64] % 27)
+        .map_err(|_| "Invalid signature recovery ID".to_string())?;
+    let signature = Signature::parse_standard_slice(&bytes[..64])
+        .map_err(|_| "Invalid signature".to_string())?;
+
+    let prefixed = format!("\x19Ethereum Signed Message:\n{}{}", message.len(), message);
+    let digest: [u8; 32] = Keccak256::digest(prefixed.as_bytes()).into();
+    let key = recover(&Message::parse(&digest), &signature, &recovery)
+        .map_err(|_| "Invalid signature".to_string())?;
+    let hash = Keccak256::digest(&key.serialize()[1..]);
+    Ok(format!("0x{}", hex::encode(&hash[12..])))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use libsecp256k1::{sign, PublicKey, SecretKey};
+
+    /// Well-known test key and its wallet address
+    pub fn test_wallet() -> (SecretKey, String) {
+        let mut secret = [0u8; 32];
+        secret[31] = 1;
+        (SecretKey::parse(&secret).unwrap(), "0x7e5f4552091a69125d5dfcb7b8c2659029395bdf".to_string())
+    }
+
+    /// Signs a message the way wallets do for `personal_sign`
+    pub fn personal_sign(key: &SecretKey, message: &str) -> String {
+        let prefixed = format!("\x19Ethereum Signed Message:\n{}{}", message.len(), message);
+        let digest: [u8; 32] = Keccak256::digest(prefixed.as_bytes()).into();
+        let (signature, recovery) = sign(&Message::parse(&digest), key);
+        let mut bytes = signature.serialize().to_vec();
+        bytes.push(recovery.serialize() + 27);
+        format!("0x{}", hex::encode(bytes))
+    }
+
+    #[test]
+    fn signatures_recover_the_signing_address() {
+        let (key, address) = test_wallet();
+        let derived = Keccak256::digest(&PublicKey::from_secret_key(&key).serialize()[1..]);
+        assert_eq!(format!("0x{}", hex::encode(&derived[12..])), address);
+        assert!(is_address(&address));
+
+        let message = challenge_message(&address, "abc123");
+        let signature = personal_sign(&key, &message);
+        assert_eq!(recover_signer(&message, &signature), Ok(address.clone()));
+        assert_ne!(recover_signer(&challenge_message(&address, "other"), &signature), Ok(address));
+        assert!(recover_signer(&message, "0x1234").is_err());
+        assert!(recover_signer(&message, "not hex").is_err());
+    }
+
+    #[test]
+    fn only_full_addresses_are_accepted() {
+        assert!(is_address("0x7E5F4552091A69125d5DfCb7b8C2659029395Bdf"));
+        assert!(!is_address("0xabc"));
+        assert!(!is_address("7e5f4552091a69125d5dfcb7b8c2659029395bdf"));
+        assert!(!is_address("0x7e5f4552091a69125d5dfcb7b8c2659029395bdz"));
+    }
+}
@@ -0,0 +1,433 @@
+/// Compact binary encoding for high-frequency messages
+///
+/// Clients that connect with `{"encoding": "binary"}` in their auth payload
+/// exchange movement inputs, snapshot acks and snapshots as fixed-layout
+/// binary frames instead of JSON. Everything else stays JSON.
+///
+/// Inputs travel as raw Engine.IO binary messages (`socket.io.engine.send`),
+/// snapshots as a `snapshot` event whose only argument is the frame buffer.
+/// All integers are little-endian and UUIDs are their 16 raw bytes.
+///
+/// | Frame      | Layout                                                      |
+/// |------------|-------------------------------------------------------------|
+/// | `MOVE`     | `u8 opcode, u8 keys` (bits: up, down, left, right)          |
+/// | `ACK`      | `u8 opcode, u64 tick`                                       |
+/// | `SNAPSHOT` | `u8 opcode, u64 tick, u8 has_baseline, [u64 baseline],`     |
+/// |            | `u16 n, n × added, u16 n, n × changed, u16 n, n × removed`  |
+///
+/// An added entity is `uuid, i32 x, i32 y, i32 health, i32 mana, effects`;
+/// a changed entity is `uuid, u8 fields` followed by the fields whose bit is
+/// set (position `x, y`, health, mana, effects, in that order); a removed
+/// entity is its `uuid`. Status effects are `u8 n, n × (u8 kind, u8 stacks,
+/// u64 expires)`, with the kind numbered as in [`crate::types::StatusKind`]. A
+/// position-only update therefore costs 25 bytes.
+use serde_json::json;
+use thiserror::Error;
+use crate::protocol::ClientMessage;
+use crate::snapshot::SnapshotDelta;
+use crate::socketio::{SocketPacket, DEFAULT_NAMESPACE};
+use crate::types::{ActiveStatus, Movement, Position};
+
+/// Opcode of a movement input frame
+pub const OP_MOVE: u8 = 0x01;
+/// Opcode of a snapshot acknowledgement frame
+pub const OP_ACK: u8 = 0x02;
+/// Opcode of a snapshot frame
+pub const OP_SNAPSHOT: u8 = 0x03;
+
+const KEY_UP: u8 = 1 << 0;
+const KEY_DOWN: u8 = 1 << 1;
+const KEY_LEFT: u8 = 1 << 2;
+const KEY_RIGHT: u8 = 1 << 3;
+
+const FIELD_POSITION: u8 = 1 << 0;
+const FIELD_HEALTH: u8 = 1 << 1;
+const FIELD_MANA: u8 = 1 << 2;
+const FIELD_EFFECTS: u8 = 1 << 3;
+
+/// Errors raised while decoding a binary frame
+#[derive(Error, Debug, PartialEq)]
+pub enum WireError {
+    #[error("Frame ends before the {0} field")]
+    Truncated(&'static str),
+
+    #[error("Unknown opcode {0:#04x}")]
+    UnknownOpcode(u8),
+
+    #[error("{0} trailing bytes after frame")]
+    TrailingBytes(usize),
+
+    #[error("Too many entries for one frame: {0}")]
+    TooManyEntries(usize),
+}
+
+/// Decodes an input frame sent by a client
+///
+/// # Arguments
+/// * `frame` - Raw binary frame
+///
+/// # Returns
+/// The equivalent client message or the reason the frame was rejected
+pub fn decode_input(frame: &[u8]) -> Result<ClientMessage, WireError> {
+    let mut reader = Reader::new(frame);
+    let message = match reader.u8("opcode")? {
+        OP_MOVE => {
+            let keys = reader.u8("keys")?;
+            ClientMessage::Move(Movement {
+                up: keys & KEY_UP != 0,
+                down: keys & KEY_DOWN != 0,
+                left: keys & KEY_LEFT != 0,
+                right: keys & KEY_RIGHT != 0,
+            })
+        }
+        OP_ACK => ClientMessage::AckSnapshot { tick: reader.u64("tick")? },
+        opcode => return Err(WireError::UnknownOpcode(opcode)),
+    };
+    reader.finish()?;
+    Ok(message)
+}
+
+/// Encodes a snapshot frame
+///
+/// # Returns
+/// The frame, or an error when a section holds more than `u16::MAX` entries
+pub fn encode_snapshot(delta: &SnapshotDelta) -> Result<Vec<u8>, WireError> {
+    let mut frame = Vec::with_capacity(
+        21 + delta.added.len() * 32 + delta.changed.len() * 29 + delta.removed.len() * 16,
+    );
+    frame.push(OP_SNAPSHOT);
+    frame.extend_from_slice(&delta.tick.to_le_bytes());
+    match delta.baseline_tick {
+        Some(baseline) => {
+            frame.push(1);
+            frame.extend_from_slice(&baseline.to_le_bytes());
+        }
+        None => frame.push(0),
+    }
+
+    put_count(&mut frame, delta.added.len())?;
+    for entity in &delta.added {
+        frame.extend_from_slice(entity.id.as_bytes());
+        put_position(&mut frame, entity.position);
+        frame.extend_from_slice(&entity.health.to_le_bytes());
+        frame.extend_from_slice(&entity.mana.to_le_bytes());
+        put_effects(&mut frame, &entity.effects)?;
+    }
+
+    put_count(&mut frame, delta.changed.len())?;
+    for entity in &delta.changed {
+        frame.extend_from_slice(entity.id.as_bytes());
+        let mut fields = 0;
+        if entity.position.is_some() {
+            fields |= FIELD_POSITION;
+        }
+        if entity.health.is_some() {
+            fields |= FIELD_HEALTH;
+        }
+        if entity.mana.is_some() {
+            fields |= FIELD_MANA;
+        }
+        if entity.effects.is_some() {
+            fields |= FIELD_EFFECTS;
+        }
+        frame.push(fields);
+        if let Some(position) = entity.position {
+            put_position(&mut frame, position);
+        }
+        if let Some(health) = entity.health {
+            frame.extend_from_slice(&health.to_le_bytes());
+        }
+        if let Some(mana) = entity.mana {
+            frame.extend_from_slice(&mana.to_le_bytes());
+        }
+        if let Some(effects) = &entity.effects {
+            put_effects(&mut frame, effects)?;
+        }
+    }
+
+    put_count(&mut frame, delta.removed.len())?;
+    for id in &delta.removed {
+        frame.extend_from_slice(id.as_bytes());
+    }
+
+    Ok(frame)
+}
+
+/// Wraps a snapshot frame into the `snapshot` event sent to binary clients
+pub fn snapshot_packet(delta: &SnapshotDelta) -> Result<SocketPacket, WireError> {
+    let frame = encode_snapshot(delta)?;
+    Ok(SocketPacket::event(DEFAULT_NAMESPACE, "snapshot", json!({ "_placeholder": true, "num": 0 }))
+        .with_attachments(vec![frame]))
+}
+
+fn put_count(frame: &mut Vec<u8>, count: usize) -> Result<(), WireError> {
+    let count = u16::try_from(count).map_err(|_| WireError::TooManyEntries(count))?;
+    frame.extend_from_slice(&count.to_le_bytes());
+    Ok(())
+}
+
+fn put_position(frame: &mut Vec<u8>, position: Position) {
+    frame.extend_from_slice(&position.x.to_le_bytes());
+    frame.extend_from_slice(&position.y.to_le_bytes());
+}
+
+fn put_effects(frame: &mut Vec<u8>, effects: &[ActiveStatus]) -> Result<(), WireError> {
+    let count = u8::try_from(effects.len()).map_err(|_| WireError::TooManyEntries(effects.len()))?;
+    frame.push(count);
+    for effect in effects {
+        frame.push(effect.kind as u8);
+        frame.push(effect.stacks);
+        frame.extend_from_slice(&effect.expires.to_le_bytes());
+    }
+    Ok(())
+}
+
+/// Cursor over a frame being decoded
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    fn take<const N: usize>(&mut self, field: &'static str) -> Result<[u8; N], WireError> {
+        if self.data.len() < N {
+            return Err(WireError::Truncated(field));
+        }
+        let (bytes, rest) = self.data.split_at(N);
+        self.data = rest;
+        Ok(bytes.try_into().expect("split at N"))
+    }
+
+    fn u8(&mut self, field: &'static str) -> Result<u8, WireError> {
+        Ok(self.take::<1>(field)?[0])
+    }
+
+    fn u64(&mut self, field: &'static str) -> Result<u64, WireError> {
+        Ok(u64::from_le_bytes(self.take(field)?))
+    }
+
+    /// Fails when bytes are left over after the last field
+    fn finish(self) -> Result<(), WireError> {
+        match self.data.len() {
+            0 => Ok(()),
+            extra => Err(WireError::TrailingBytes(extra)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::ServerMessage;
+    use crate::snapshot::{EntityDelta, EntitySnapshot};
+    use crate::types::StatusKind;
+    use uuid::Uuid;
+
+    impl Reader<'_> {
+        fn u16(&mut self, field: &'static str) -> Result<u16, WireError> {
+            Ok(u16::from_le_bytes(self.take(field)?))
+        }
+
+        fn i32(&mut self, field: &'static str) -> Result<i32, WireError> {
+            Ok(i32::from_le_bytes(self.take(field)?))
+        }
+
+        fn uuid(&mut self) -> Result<Uuid, WireError> {
+            Ok(Uuid::from_bytes(self.take("id")?))
+        }
+
+        fn position(&mut self) -> Result<Position, WireError> {
+            Ok(Position::new(self.i32("x")?, self.i32("y")?))
+        }
+
+        fn effects(&mut self) -> Result<Vec<ActiveStatus>, WireError> {
+            const KINDS: [StatusKind; 8] = [
+                StatusKind::Poison,
+                StatusKind::Regeneration,
+                StatusKind::Haste,
+                StatusKind::Slow,
+                StatusKind::Stun,
+                StatusKind::Shield,
+                StatusKind::Buff,
+                StatusKind::Debuff,
+            ];
+            (0..self.u8("effect count")?)
+                .map(|_| Ok(ActiveStatus {
+                    kind: KINDS.get(self.u8("effect kind")? as usize).copied().ok_or(WireError::Truncated("effect kind"))?,
+                    stacks: self.u8("effect stacks")?,
+                    expires: self.u64("effect expiry")?,
+                }))
+                .collect()
+        }
+    }
+
+    /// Encodes a movement input frame as a client would
+    fn encode_movement(movement: &Movement) -> Vec<u8> {
+        let mut keys = 0;
+        for (held, bit) in [
+            (movement.up, KEY_UP),
+            (movement.down, KEY_DOWN),
+            (movement.left, KEY_LEFT),
+            (movement.right, KEY_RIGHT),
+        ] {
+            if held {
+                keys |= bit;
+            }
+        }
+        vec![OP_MOVE, keys]
+    }
+
+    /// Encodes a snapshot acknowledgement frame as a client would
+    fn encode_ack(tick: u64) -> Vec<u8> {
+        let mut frame = vec![OP_ACK];
+        frame.extend_from_slice(&tick.to_le_bytes());
+        frame
+    }
+
+    /// Decodes a snapshot frame as a client would
+    fn decode_snapshot(frame: &[u8]) -> Result<SnapshotDelta, WireError> {
+        let mut reader = Reader::new(frame);
+        let opcode = reader.u8("opcode")?;
+        if opcode != OP_SNAPSHOT {
+            return Err(WireError::UnknownOpcode(opcode));
+        }
+
+        let tick = reader.u64("tick")?;
+        let baseline_tick = match reader.u8("baseline flag")? {
+            0 => None,
+            _ => Some(reader.u64("baseline tick")?),
+        };
+
+        let mut added = Vec::new();
+        for _ in 0..reader.u16("added count")? {
+            added.push(EntitySnapshot {
+                id: reader.uuid()?,
+                position: reader.position()?,
+                health: reader.i32("health")?,
+                mana: reader.i32("mana")?,
+                effects: reader.effects()?,
+            });
+        }
+
+        let mut changed = Vec::new();
+        for _ in 0..reader.u16("changed count")? {
+            let id = reader.uuid()?;
+            let fields = reader.u8("field mask")?;
+            changed.push(EntityDelta {
+                id,
+                position: if fields & FIELD_POSITION != 0 { Some(reader.position()?) } else { None },
+                health: if fields & FIELD_HEALTH != 0 { Some(reader.i32("health")?) } else { None },
+                mana: if fields & FIELD_MANA != 0 { Some(reader.i32("mana")?) } else { None },
+                effects: if fields & FIELD_EFFECTS != 0 { Some(reader.effects()?) } else { None },
+            });
+        }
+
+        let mut removed = Vec::new();
+        for _ in 0..reader.u16("removed count")? {
+            removed.push(reader.uuid()?);
+        }
+
+        reader.finish()?;
+        Ok(SnapshotDelta { tick, baseline_tick, added, changed, removed })
+    }
+
+    fn delta(changed: Vec<EntityDelta>) -> SnapshotDelta {
+        SnapshotDelta {
+            tick: 42,
+            baseline_tick: Some(40),
+            added: Vec::new(),
+            changed,
+            removed: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn movement_and_ack_round_trip() {
+        let movement = Movement { up: true, down: false, left: false, right: true };
+        match decode_input(&encode_movement(&movement)).unwrap() {
+            ClientMessage::Move(decoded) => {
+                assert!(decoded.up && decoded.right && !decoded.down && !decoded.left);
+            }
+            other => panic!("expected move, got {:?}", other),
+        }
+        assert_eq!(encode_movement(&movement), vec![OP_MOVE, 0b1001]);
+
+        match decode_input(&encode_ack(7)).unwrap() {
+            ClientMessage::AckSnapshot { tick } => assert_eq!(tick, 7),
+            other => panic!("expected ack, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn snapshot_round_trip() {
+        let (a, b, c) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let poisoned = ActiveStatus { kind: StatusKind::Poison, stacks: 3, expires: 120 };
+        let stunned = ActiveStatus { kind: StatusKind::Stun, stacks: 1, expires: u64::MAX };
+        let original = SnapshotDelta {
+            tick: 9,
+            baseline_tick: None,
+            added: vec![EntitySnapshot { id: a, position: Position::new(-3, 8), health: 100, mana: 50, effects: vec![poisoned] }],
+            changed: vec![
+                EntityDelta { id: b, position: Some(Position::new(1, 2)), health: None, mana: None, effects: None },
+                EntityDelta { id: c, position: None, health: Some(0), mana: Some(-1), effects: Some(Vec::new()) },
+                EntityDelta { id: a, position: None, health: None, mana: None, effects: Some(vec![poisoned, stunned]) },
+            ],
+            removed: vec![Uuid::nil()],
+        };
+        assert_eq!(decode_snapshot(&encode_snapshot(&original).unwrap()).unwrap(), original);
+
+        let mut with_baseline = original.clone();
+        with_baseline.baseline_tick = Some(u64::MAX);
+        assert_eq!(decode_snapshot(&encode_snapshot(&with_baseline).unwrap()).unwrap(), with_baseline);
+    }
+
+    #[test]
+    fn position_updates_cost_a_fraction_of_json() {
+        let moved = |count: usize| delta((0..count).map(|_| EntityDelta {
+            id: Uuid::new_v4(),
+            position: Some(Position::new(1234, 5678)),
+            health: None,
+            mana: None,
+            effects: None,
+        }).collect());
+        let binary_size = |count| {
+            let (text, attachments) = snapshot_packet(&moved(count)).unwrap().encode();
+            text.len() + attachments.iter().map(Vec::len).sum::<usize>()
+        };
+        let json_size = |count| ServerMessage::Snapshot(moved(count)).to_packet().encode().0.len();
+
+        assert_eq!(binary_size(1) - binary_size(0), 25);
+        let (binary, json) = (binary_size(20), json_size(20));
+        assert!(binary * 2 < json, "binary {} bytes vs json {} bytes", binary, json);
+    }
+
+    #[test]
+    fn snapshot_packet_carries_frame_as_attachment() {
+        let update = delta(Vec::new());
+        let (text, attachments) = snapshot_packet(&update).unwrap().encode();
+        assert_eq!(text, r#"451-["snapshot",{"_placeholder":true,"num":0}]"#);
+        assert_eq!(attachments, vec![encode_snapshot(&update).unwrap()]);
+    }
+
+    #[test]
+    fn rejects_malformed_frames() {
+        assert_eq!(decode_input(&[]).unwrap_err(), WireError::Truncated("opcode"));
+        assert_eq!(decode_input(&[OP_MOVE]).unwrap_err(), WireError::Truncated("keys"));
+        assert_eq!(decode_input(&[OP_ACK, 1, 2]).unwrap_err(), WireError::Truncated("tick"));
+        assert_eq!(decode_input(&[OP_MOVE, 0, 0]).unwrap_err(), WireError::TrailingBytes(1));
+        assert_eq!(decode_input(&[0x7f]).unwrap_err(), WireError::UnknownOpcode(0x7f));
+        assert_eq!(decode_input(&[OP_SNAPSHOT]).unwrap_err(), WireError::UnknownOpcode(OP_SNAPSHOT));
+
+        let frame = encode_snapshot(&delta(vec![EntityDelta {
+            id: Uuid::new_v4(),
+            position: None,
+            health: Some(1),
+            mana: None,
+            effects: None,
+        }])).unwrap();
+        assert_eq!(decode_snapshot(&frame[..frame.len() - 3]), Err(WireError::Truncated("health")));
+    }
+}
@@ -1,154 +1,469 @@
-+
-/// WebSocket module for handling real-time game communication
-use actix::{Actor, StreamHandler, ActorContext};
+/// WebSocket gateway for handling real-time game communication
+///
+/// Every client connects through the same Socket.IO endpoint. The protocol
+/// version is negotiated when the client joins the default namespace and all
+/// events are dispatched through the typed [`ClientMessage`] contract.
+use actix::{Actor, StreamHandler, Handler, Message, ActorContext, AsyncContext};
 use actix_web::{web, Error, HttpRequest, HttpResponse};
 use actix_web_actors::ws;
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use parking_lot::RwLock;
-use serde_json::json;
+use serde_json::{json, Value};
+use uuid::Uuid;
+use log::{debug, info, warn};
+use crate::error::GameError;
 use crate::game::GameState;
+use crate::types::Position;
+use crate::core::game::dungeon::random_seed;
+use crate::snapshot::{SnapshotHistory, WorldSnapshot};
+use crate::protocol::{
+    negotiate_encoding, negotiate_version, ChatChannel, ClientMessage, ServerMessage, WireEncoding,
+    PROTOCOL_VERSION,
+};
+use crate::socketio::{
+    EnginePacket, Handshake, PacketDecoder, PacketKind, SocketPacket, DEFAULT_NAMESPACE,
+    ENGINE_IO_VERSION,
+};
+use crate::wire;
 
-/// WebSocket connection handler for Socket.IO protocol
-pub struct GameWebSocket {
+/// Interval between server-sent heartbeat pings
+const PING_INTERVAL: Duration = Duration::from_millis(25000);
+/// Time a client has to answer a ping before the connection is dropped
+const PING_TIMEOUT: Duration = Duration::from_millis(5000);
+/// Largest payload in bytes accepted from a client
+const MAX_PAYLOAD: u64 = 1_000_000;
+
+/// Pre-encoded text frame pushed to a session by other parts of the server
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct WsMessage(pub String);
+
+/// World snapshot of the latest tick, encoded by each session for its client
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct WorldUpdate(pub Arc<WorldSnapshot>);
+
+/// WebSocket session of a single connected client
+pub struct WsGameSession {
+    /// Player ID used once the client joins the game; replaced by the ID of
+    /// the character the client joins as, if any
+    pub id: Uuid,
     /// Shared game state accessible across all connections
-    game_state: Arc<RwLock<GameState>>,
-    /// Player ID associated with this connection
-    player_id: Option<String>,
+    pub game_state: Arc<RwLock<GameState>>,
+    /// Engine.IO session id advertised in the handshake
+    sid: String,
+    /// Whether the client asked for the Engine.IO revision we implement
+    engine_supported: bool,
+    /// Protocol version negotiated when the client joined the default namespace
+    protocol_version: Option<u32>,
+    /// Encoding of movement inputs and snapshots negotiated with the client
+    encoding: WireEncoding,
+    /// Whether the player has joined the game
+    joined: bool,
+    /// Reassembles packets split across text and binary frames
+    decoder: PacketDecoder,
+    /// Last time the client answered a heartbeat
+    last_pong: Instant,
+    /// Snapshots sent to the client and the last one it acknowledged
+    snapshots: SnapshotHistory,
 }
 
-impl Actor for GameWebSocket {
+impl Actor for WsGameSession {
     /// Specifies the context type for the WebSocket actor
     type Context = ws::WebsocketContext<Self>;
 
     fn started(&mut self, ctx: &mut Self::Context) {
-        // Send Socket.IO handshake
-        let handshake = json!({
-            "type": "open",
-            "sid": "server-generated-session-id",
-            "upgrades": [],
-            "pingInterval": 25000,
-            "pingTimeout": 5000
-        });
-        ctx.text(format!("0{}", handshake.to_string()));
+        if !self.engine_supported {
+            self.close_with_reason(
+                ctx,
+                ws::CloseCode::Protocol,
+                format!(
+                    "Unsupported transport, connect with socket.io-client (Engine.IO v{}, protocol version {})",
+                    ENGINE_IO_VERSION, PROTOCOL_VERSION
+                ),
+            );
+            return;
+        }
+
+        // Send Engine.IO handshake
+        let handshake = Handshake {
+            sid: self.sid.clone(),
+            upgrades: Vec::new(),
+            ping_interval: PING_INTERVAL.as_millis() as u64,
+            ping_timeout: PING_TIMEOUT.as_millis() as u64,
+            max_payload: MAX_PAYLOAD,
+        };
+        ctx.text(EnginePacket::Open(handshake).encode());
+        self.start_heartbeat(ctx);
+        debug!("WebSocket session {} established", self.sid);
+    }
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        debug!("WebSocket session {} closed", self.sid);
+        if self.joined {
+            let mut game_state = self.game_state.write();
+            game_state.unregister_session(self.id);
+            let observers = game_state.remove_player(self.id);
+            game_state.sessions().send_to_many(observers, &ServerMessage::PlayerLeft { id: self.id });
+        }
     }
 }
 
-impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for GameWebSocket {
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsGameSession {
     /// Handles incoming WebSocket messages from clients
-    /// 
+    ///
     /// # Arguments
     /// * `msg` - The incoming message wrapped in a Result
     /// * `ctx` - The WebSocket context for sending responses
-    /// 
+    ///
     /// # Message Types
     /// * `Ping` - Responds with a pong to keep the connection alive
-    /// * `Text` - Handles text-based game commands and chat messages
-    /// * `Binary` - Processes binary data (currently echoes back)
+    /// * `Text` - Decodes Engine.IO packets and the Socket.IO packets they carry
+    /// * `Binary` - Collects attachments of binary Socket.IO packets, or
+    ///   decodes a [`wire`] input frame on binary connections
     fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
         match msg {
             Ok(ws::Message::Ping(msg)) => {
                 ctx.pong(&msg);
             }
             Ok(ws::Message::Text(text)) => {
-                // Handle Socket.IO messages
-                if text.starts_with("2") {
-                    // Socket.IO ping
-                    ctx.text("3");
-                } else if text.starts_with("4") {
-                    // Socket.IO message
-                    let payload = &text[1..];
-                    if let Ok(value) = serde_json::from_str(payload) {
-                        self.handle_socket_message(value, ctx);
+                match EnginePacket::decode(&text) {
+                    Ok(EnginePacket::Pong(_)) => {
+                        self.last_pong = Instant::now();
+                    }
+                    Ok(EnginePacket::Ping(data)) => {
+                        ctx.text(EnginePacket::Pong(data).encode());
+                    }
+                    Ok(EnginePacket::Message(payload)) => {
+                        match self.decoder.decode_text(&payload) {
+                            Ok(Some(packet)) => self.handle_socket_packet(packet, ctx),
+                            Ok(None) => {}
+                            Err(e) => warn!("Dropping malformed Socket.IO packet from {}: {}", self.sid, e),
+                        }
                     }
+                    Ok(EnginePacket::Close) => {
+                        ctx.close(None);
+                        ctx.stop();
+                    }
+                    Ok(_) => {}
+                    Err(_) if text.starts_with('{') => {
+                        // Version 1 clients sent bare JSON messages
+                        self.close_with_reason(
+                            ctx,
+                            ws::CloseCode::Policy,
+                            format!(
+                                "Protocol version 1 is no longer supported, please update to version {}",
+                                PROTOCOL_VERSION
+                            ),
+                        );
+                    }
+                    Err(e) => warn!("Dropping malformed Engine.IO packet from {}: {}", self.sid, e),
+                }
+            }
+            Ok(ws::Message::Binary(bin)) if self.encoding == WireEncoding::Binary && !self.decoder.is_pending() => {
+                let result = wire::decode_input(&bin)
+                    .map_err(|e| e.to_string())
+                    .and_then(|message| self.handle_client_message(message, ctx));
+                if let Err(e) = result {
+                    warn!("Dropping binary input from {}: {}", self.sid, e);
                 }
             }
             Ok(ws::Message::Binary(bin)) => {
-                ctx.binary(bin);
+                match self.decoder.decode_binary(&bin) {
+                    Ok(Some(packet)) => self.handle_socket_packet(packet, ctx),
+                    Ok(None) => {}
+                    Err(e) => warn!("Dropping binary frame from {}: {}", self.sid, e),
+                }
             }
             Ok(ws::Message::Close(reason)) => {
                 ctx.close(reason);
                 ctx.stop();
             }
+            Err(e) => {
+                warn!("WebSocket protocol error on session {}: {}", self.sid, e);
+                ctx.stop();
+            }
             _ => {}
         }
     }
 }
 
-impl GameWebSocket {
-    fn handle_socket_message(&mut self, message: serde_json::Value, ctx: &mut ws::WebsocketContext<Self>) {
-        if let Some(event) = message.get("event").and_then(|v| v.as_str()) {
-            match event {
-                "equipItem" => {
-                    // Handle equip item event
-                    if let Some(data) = message.get("data") {
-                        // Process equip item logic here
-                        let response = json!({
-                            "type": "message",
-                            "event": "equipItem",
-                            "data": {
-                                "success": true
-                            }
-                        });
-                        ctx.text(format!("42{}", response.to_string()));
-                    }
+impl Handler<WsMessage> for WsGameSession {
+    type Result = ();
+
+    fn handle(&mut self, msg: WsMessage, ctx: &mut Self::Context) {
+        ctx.text(msg.0);
+    }
+}
+
+impl Handler<WorldUpdate> for WsGameSession {
+    type Result = ();
+
+    fn handle(&mut self, msg: WorldUpdate, ctx: &mut Self::Context) {
+        let Some(delta) = self.snapshots.encode(msg.0) else {
+            return;
+        };
+        match self.encoding {
+            WireEncoding::Json => self.send(&ServerMessage::Snapshot(delta), ctx),
+            WireEncoding::Binary => match wire::snapshot_packet(&delta) {
+                Ok(packet) => self.send_packet(&packet, ctx),
+                Err(e) => warn!("Could not encode snapshot {} for {}: {}", delta.tick, self.sid, e),
+            },
+        }
+    }
+}
+
+impl WsGameSession {
+    pub fn new(game_state: Arc<RwLock<GameState>>, engine_supported: bool) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            game_state,
+            sid: Uuid::new_v4().simple().to_string(),
+            engine_supported,
+            protocol_version: None,
+            encoding: WireEncoding::Json,
+            joined: false,
+            decoder: PacketDecoder::new(),
+            last_pong: Instant::now(),
+            snapshots: SnapshotHistory::new(),
+        }
+    }
+
+    /// Pings the client every `PING_INTERVAL` and drops the connection
+    /// when no pong arrived within the advertised timeout
+    fn start_heartbeat(&self, ctx: &mut ws::WebsocketContext<Self>) {
+        ctx.run_interval(PING_INTERVAL, |act, ctx| {
+            if Instant::now().duration_since(act.last_pong) > PING_INTERVAL + PING_TIMEOUT {
+                debug!("Heartbeat timed out for session {}", act.sid);
+                ctx.close(None);
+                ctx.stop();
+                return;
+            }
+            ctx.text(EnginePacket::Ping(None).encode());
+        });
+    }
+
+    /// Closes the connection with a close frame explaining why
+    fn close_with_reason(&self, ctx: &mut ws::WebsocketContext<Self>, code: ws::CloseCode, description: String) {
+        info!("Closing session {}: {}", self.sid, description);
+        ctx.close(Some(ws::CloseReason { code, description: Some(description) }));
+        ctx.stop();
+    }
+
+    /// Sends a Socket.IO packet followed by its binary attachments
+    fn send_packet(&self, packet: &SocketPacket, ctx: &mut ws::WebsocketContext<Self>) {
+        let (text, attachments) = packet.encode();
+        ctx.text(text);
+        for attachment in attachments {
+            ctx.binary(attachment);
+        }
+    }
+
+    /// Sends a typed message to this client
+    fn send(&self, message: &ServerMessage, ctx: &mut ws::WebsocketContext<Self>) {
+        self.send_packet(&message.to_packet(), ctx);
+    }
+
+    /// Tells the client why their equipment change was refused, if it was
+    ///
+    /// # Returns
+    /// The reason shown to the client for any other error
+    fn reject_equipment(&self, result: Result<(), GameError>, ctx: &mut ws::WebsocketContext<Self>) -> Result<(), String> {
+        match result {
+            Err(GameError::InvalidEquipment(reason)) => {
+                self.send(&ServerMessage::EquipRejected { reason }, ctx);
+                Ok(())
+            }
+            result => result.map_err(|e| e.to_string()),
+        }
+    }
+
+    fn handle_socket_packet(&mut self, packet: SocketPacket, ctx: &mut ws::WebsocketContext<Self>) {
+        match packet.kind {
+            PacketKind::Connect => {
+                if packet.namespace != DEFAULT_NAMESPACE {
+                    self.send_packet(&SocketPacket::connect_error(&packet.namespace, "Invalid namespace"), ctx);
+                    return;
                 }
-                "unequipItem" => {
-                    // Handle unequip item event
-                    if let Some(data) = message.get("data") {
-                        // Process unequip item logic here
-                        let response = json!({
-                            "type": "message",
-                            "event": "unequipItem",
-                            "data": {
-                                "success": true
-                            }
-                        });
-                        ctx.text(format!("42{}", response.to_string()));
+                let auth = packet.data.as_ref();
+                match negotiate_version(auth).and_then(|version| Ok((version, negotiate_encoding(auth)?))) {
+                    Ok((version, encoding)) => {
+                        let socket_sid = Uuid::new_v4().simple().to_string();
+                        self.send_packet(&SocketPacket::connect(&packet.namespace, &socket_sid), ctx);
+                        self.send(&ServerMessage::Connected { protocol_version: version, encoding }, ctx);
+                        self.protocol_version = Some(version);
+                        self.encoding = encoding;
+                    }
+                    Err(reason) => {
+                        self.send_packet(&SocketPacket::connect_error(&packet.namespace, &reason), ctx);
+                        self.close_with_reason(ctx, ws::CloseCode::Policy, reason);
                     }
                 }
-                "useItem" => {
-                    // Handle use item event
-                    if let Some(data) = message.get("data") {
-                        // Process use item logic here
-                        let response = json!({
-                            "type": "message",
-                            "event": "useItem",
-                            "data": {
-                                "success": true
-                            }
-                        });
-                        ctx.text(format!("42{}", response.to_string()));
+            }
+            PacketKind::Disconnect if packet.namespace == DEFAULT_NAMESPACE => {
+                self.protocol_version = None;
+            }
+            PacketKind::Event | PacketKind::BinaryEvent => {
+                if self.protocol_version.is_none() || packet.namespace != DEFAULT_NAMESPACE {
+                    return;
+                }
+                let Some(event) = packet.event_name() else {
+                    return;
+                };
+                let data = packet.event_args().first().cloned().unwrap_or(Value::Null);
+                let result = ClientMessage::from_event(event, data)
+                    .map_err(|e| format!("Invalid message format: {}", e))
+                    .and_then(|message| self.handle_client_message(message, ctx));
+
+                match (packet.id, result) {
+                    (Some(id), Ok(())) => {
+                        self.send_packet(&SocketPacket::ack(&packet.namespace, id, json!({ "success": true })), ctx);
+                    }
+                    (Some(id), Err(message)) => {
+                        let response = json!({ "success": false, "message": message });
+                        self.send_packet(&SocketPacket::ack(&packet.namespace, id, response), ctx);
                     }
+                    (None, Ok(())) => {}
+                    (None, Err(message)) => self.send(&ServerMessage::Error { message }, ctx),
                 }
-                "dropItem" => {
-                    // Handle drop item event
-                    if let Some(data) = message.get("data") {
-                        // Process drop item logic here
-                        let response = json!({
-                            "type": "message",
-                            "event": "dropItem",
-                            "data": {
-                                "success": true
-                            }
-                        });
-                        ctx.text(format!("42{}", response.to_string()));
+            }
+            _ => {}
+        }
+    }
+
+    /// Applies a message sent by the client
+    ///
+    /// # Returns
+    /// Result indicating success or the reason shown to the client
+    fn handle_client_message(&mut self, message: ClientMessage, ctx: &mut ws::WebsocketContext<Self>) -> Result<(), String> {
+        if !self.joined && !matches!(message, ClientMessage::Join { .. }) {
+            return Err("Join the game first".to_string());
+        }
+
+        match message {
+            ClientMessage::Join { name, character, account, key } => {
+                if self.joined {
+                    return Err("Already joined".to_string());
+                }
+                let mut game_state = self.game_state.write();
+                let player = match character {
+                    Some(character) => {
+                        let key = key.ok_or("Sign in with the account's key to play a character")?;
+                        game_state.authenticate(&account, key).map_err(|e| e.to_string())?;
+                        let player = game_state.join_character(character, &account).map_err(|e| e.to_string())?;
+                        self.id = player.id;
+                        player
                     }
+                    None => game_state.add_player(self.id, name).map_err(|e| e.to_string())?,
+                };
+                game_state.register_session(self.id, ctx.address());
+                self.joined = true;
+
+                // Announce the new player to everyone who can see them
+                let joined = ServerMessage::PlayerJoined { id: player.id, name: player.name.clone() };
+                let observers = game_state.reveal_player(self.id);
+                game_state.sessions().send_to_many(observers, &joined);
+
+                // Send welcome message to new player
+                game_state.sessions().send_to(self.id, &ServerMessage::Welcome { player: Box::new(player) });
+            }
+            ClientMessage::Move(movement) => {
+                self.game_state.write().queue_movement(self.id, movement);
+            }
+            ClientMessage::MoveTo { x, y } => {
+                let waypoints = self.game_state.write()
+                    .move_player_to(self.id, Position::new(x, y))
+                    .map_err(|e| e.to_string())?;
+                self.send(&ServerMessage::PathPlanned { waypoints }, ctx);
+            }
+            ClientMessage::Chat { message, channel } => {
+                let game_state = self.game_state.read();
+                let player = game_state.get_player(self.id)
+                    .ok_or_else(|| "Player not found".to_string())?;
+                let chat = ServerMessage::ChatMessage {
+                    sender: player.name.clone(),
+                    message,
+                };
+                match channel {
+                    ChatChannel::Global => game_state.sessions().broadcast(&chat),
+                    ChatChannel::Room => {
+                        game_state.send_to_room(self.id, &chat)
+                            .ok_or_else(|| "You are not in a room".to_string())?;
+                    }
+                }
+            }
+            ClientMessage::EquipItem { item_id, slot } => {
+                let result = self.game_state.write().equip_item(self.id, item_id, slot);
+                self.reject_equipment(result, ctx)?;
+            }
+            ClientMessage::UnequipItem { slot } => {
+                let result = self.game_state.write().unequip_item(self.id, slot);
+                self.reject_equipment(result, ctx)?;
+            }
+            ClientMessage::UseItem { item_id } => {
+                self.game_state.write().use_item(self.id, item_id).map_err(|e| e.to_string())?;
+            }
+            ClientMessage::DropItem { item_id } => {
+                self.game_state.write().drop_item(self.id, item_id).map_err(|e| e.to_string())?;
+            }
+            ClientMessage::PickUpItem { item_id } => {
+                self.game_state.write().pick_up_item(self.id, item_id).map_err(|e| e.to_string())?;
+            }
+            ClientMessage::AckSnapshot { tick } => {
+                self.snapshots.acknowledge(tick);
+            }
+            ClientMessage::Interact { x, y } => {
+                self.game_state.write().interact(self.id, x, y).map_err(|e| e.to_string())?;
+            }
+            ClientMessage::Attack { target, kind } => {
+                self.game_state.write().attack(self.id, target, kind).map_err(|e| e.to_string())?;
+            }
+            ClientMessage::BindSkill { slot, skill } => {
+                let hotbar = self.game_state.write()
+                    .bind_skill(self.id, slot, skill)
+                    .map_err(|e| e.to_string())?;
+                self.send(&ServerMessage::HotbarChanged { hotbar }, ctx);
+            }
+            ClientMessage::AllocateAttributes(allocation) => {
+                self.game_state.write()
+                    .allocate_attributes(self.id, allocation)
+                    .map_err(|e| e.to_string())?;
+            }
+            ClientMessage::CastSkill { slot, target, position } => {
+                self.game_state.write()
+                    .cast_skill(self.id, slot, target, position)
+                    .map_err(|e| e.to_string())?;
+            }
+            ClientMessage::TakeStairs => {
+                self.game_state.write().take_stairs(self.id).map_err(|e| e.to_string())?;
+            }
+            ClientMessage::EnterPortal { party, leader } => {
+                let mut game_state = self.game_state.write();
+                match leader {
+                    Some(leader) => game_state.wait_at_portal(self.id, leader),
+                    None => game_state.enter_portal(self.id, &party, random_seed()).map(|_| ()),
                 }
-                _ => {}
+                .map_err(|e| e.to_string())?;
             }
         }
+        Ok(())
     }
 }
 
 /// WebSocket connection handler
-/// 
+///
 /// Establishes a new WebSocket connection for a client and sets up the communication channel.
-/// 
+///
+/// Clients that do not speak Engine.IO v4 (`EIO=4` in the query string) are
+/// upgraded and then closed with a close frame explaining why.
+///
 /// # Arguments
 /// * `req` - The HTTP request that initiated the WebSocket connection
 /// * `stream` - The payload stream for the WebSocket connection
 /// * `game_state` - Shared game state accessible by all connections
-/// 
+///
 /// # Returns
 /// * `Result<HttpResponse, Error>` - The result of establishing the WebSocket connection
 pub async fn ws_index(
@@ -156,9 +471,11 @@ pub async fn ws_index(
     stream: web::Payload,
     game_state: web::Data<Arc<RwLock<GameState>>>,
 ) -> Result<HttpResponse, Error> {
-    let ws = GameWebSocket {
-        game_state: game_state.get_ref().clone(),
-        player_id: None,
-    };
-    ws::start(ws, &req, stream)
-} 
\ No newline at end of file
+    let engine_version = web::Query::<HashMap<String, String>>::from_query(req.query_string())
+        .ok()
+        .and_then(|query| query.get("EIO").cloned());
+    let engine_supported = engine_version.as_deref() == Some(ENGINE_IO_VERSION.to_string().as_str());
+
+    let session = WsGameSession::new(game_state.get_ref().clone(), engine_supported);
+    ws::start(session, &req, stream)
+}